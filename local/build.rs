@@ -1,6 +1,11 @@
 use std::io;
 
 fn main() -> io::Result<()> {
+    // the server needs to know which artifact to send back when updating
+    println!(
+        "cargo:rustc-env=PITSU_TARGET={}",
+        std::env::var("TARGET").map_err(io::Error::other)?
+    );
     if std::env::var("CARGO_CFG_TARGET_OS").map_err(io::Error::other)? == "windows" {
         let mut res = winresource::WindowsResource::new();
         res.set_icon("assets/p51-03.ico");
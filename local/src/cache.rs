@@ -1,18 +1,21 @@
 use std::{
     collections::HashMap,
-    path::PathBuf,
+    io::Write as _,
+    path::{Path, PathBuf},
     sync::{Arc, mpsc},
 };
 
 use pitsu_lib::{
-    ChangeType, CreateRemoteRepository, FileUpload, Pitignore, RemoteRepository, ThisUser, UploadFile, User,
-    UserWithAccess, VersionNumber,
+    ActionType, ArchiveFormat, ChangelogEntry, CreateRemoteRepository, FilePart, FileUpload, RemoteRepository,
+    ThisUser, TransferEncoding, UpdateRemoteRepository, UploadFile, User, UserWithAccess, VersionNumber, api,
 };
 use uuid::Uuid;
 
 use crate::{
     Repository,
-    config::{CONFIG, PUBLIC_URL, delete_request, delete_request_with_body, get_request, post_request},
+    config::{CONFIG, client},
+    throttle::{Direction, Throttle},
+    trash::TrashSession,
 };
 
 pub struct RequestCache {
@@ -23,11 +26,15 @@ pub struct RequestCache {
     latest_upload_progress: Option<Progress>,
     latest_download_progress: Option<Progress>,
     remote_version_number: Option<PendingRequest<Arc<VersionNumber>>>,
-    remote_update_bytes: Option<PendingRequest<Arc<[u8]>>>,
+    changelog: Option<PendingRequest<Arc<[ChangelogEntry]>>>,
+    // (exe, signature)
+    remote_update_bytes: Option<PendingRequest<(Arc<[u8]>, Arc<str>)>>,
     create_repository: Option<PendingRequest<Arc<RemoteRepository>>>,
     repositories: HashMap<Uuid, PendingRequest<Arc<RemoteRepository>>>,
     stored_repositories: HashMap<Uuid, PendingRequest<Option<Arc<Repository>>>>,
     user_action: Option<PendingRequest<Uuid>>,
+    // the repository to reload once the transfer finishes, set for imports
+    archive_transfer: Option<(Option<Uuid>, PendingRequest<Arc<str>>)>,
     pub new_repository_name: String,
     pub new_repository_path: Option<PathBuf>,
 }
@@ -65,9 +72,11 @@ impl RequestCache {
             latest_download_progress: None,
             remote_version_number: None,
             remote_update_bytes: None,
+            changelog: None,
             repositories: HashMap::new(),
             stored_repositories: HashMap::new(),
             user_action: None,
+            archive_transfer: None,
             new_repository_name: String::new(),
             new_repository_path: None,
             create_repository: None,
@@ -81,54 +90,27 @@ impl RequestCache {
                 }
                 let (sender, receiver) = mpsc::channel();
                 let path = self.new_repository_path.clone();
-                ehttp::fetch(
-                    post_request(
-                        &format!("{PUBLIC_URL}/api/repository"),
-                        serde_json::to_value(CreateRemoteRepository {
+                client().fetch(
+                    api::CreateRepository {
+                        repository: CreateRemoteRepository {
                             name: self.new_repository_name.clone().into(),
-                        })
-                        .expect("Failed to serialize repository creation request"),
-                    ),
-                    move |response| {
-                        let response = match response {
-                            Ok(resp) => resp,
-                            Err(e) => {
-                                sender
-                                    .send(Err(Arc::from(format!("Failed to create repository: {e}"))))
-                                    .unwrap_or_else(|e| {
-                                        log::error!("Failed to send error response: {e}");
-                                    });
-                                return;
+                        },
+                    },
+                    move |response| match response {
+                        Ok(repo) => {
+                            if let Some(path) = path {
+                                CONFIG.add_stored(repo.uuid, path).ok();
                             }
-                        };
-                        if response.status != 201 {
+                            sender.send(Ok(Arc::new(repo))).unwrap_or_else(|e| {
+                                log::error!("Failed to send repository creation response: {e}");
+                            });
+                        }
+                        Err(e) => {
                             sender
-                                .send(Err(Arc::from(format!(
-                                    "Failed to create repository: {}",
-                                    response.status
-                                ))))
+                                .send(Err(Arc::from(format!("Failed to create repository: {e}"))))
                                 .unwrap_or_else(|e| {
                                     log::error!("Failed to send error response: {e}");
                                 });
-                            return;
-                        }
-                        let repo: Result<RemoteRepository, _> = response.json();
-                        match repo {
-                            Ok(repo) => {
-                                if let Some(path) = path {
-                                    CONFIG.add_stored(repo.uuid, path).ok();
-                                }
-                                sender.send(Ok(Arc::new(repo))).unwrap_or_else(|e| {
-                                    log::error!("Failed to send repository creation response: {e}");
-                                });
-                            }
-                            Err(e) => {
-                                sender
-                                    .send(Err(Arc::from(format!("Failed to parse repository: {e}"))))
-                                    .unwrap_or_else(|e| {
-                                        log::error!("Failed to send error response: {e}");
-                                    });
-                            }
                         }
                     },
                 );
@@ -150,31 +132,12 @@ impl RequestCache {
         let new_state = match &self.remote_version_number {
             None => {
                 let (sender, receiver) = mpsc::channel();
-                ehttp::fetch(
-                    get_request(&format!("{PUBLIC_URL}/api/local/version")),
-                    move |response| {
-                        let response = match response {
-                            Ok(resp) => resp,
-                            Err(e) => {
-                                sender
-                                    .send(Err(Arc::from(format!("Failed to fetch commit hash: {e}"))))
-                                    .unwrap_or_else(|e| {
-                                        log::error!("Failed to send error response: {e}");
-                                    });
-                                return;
-                            }
-                        };
-                        if response.status != 200 {
-                            sender
-                                .send(Err(Arc::from(format!(
-                                    "Failed to fetch commit hash: {}",
-                                    response.status
-                                ))))
-                                .unwrap_or_else(|e| {
-                                    log::error!("Failed to send error response: {e}");
-                                });
-                            return;
-                        }
+                client().fetch(
+                    api::LatestVersion {
+                        channel: CONFIG.update_channel(),
+                        target: env!("PITSU_TARGET").into(),
+                    },
+                    move |version_number| {
                         // let version_number: Result<Arc<str>, Arc<str>> = match response.text() {
                         //     Some(text) => Ok(Arc::from(text.trim())),
                         //     None => Err(Arc::from("Failed to read response: No text found")),
@@ -195,7 +158,6 @@ impl RequestCache {
                         //             });
                         //     }
                         // }
-                        let version_number = response.json::<VersionNumber>();
                         match version_number {
                             Ok(version_number) => {
                                 sender.send(Ok(Arc::from(version_number))).unwrap_or_else(|e| {
@@ -204,7 +166,7 @@ impl RequestCache {
                             }
                             Err(e) => {
                                 sender
-                                    .send(Err(Arc::from(format!("Failed to parse commit hash: {e}"))))
+                                    .send(Err(Arc::from(format!("Failed to fetch commit hash: {e}"))))
                                     .unwrap_or_else(|e| {
                                         log::error!("Failed to send error response: {e}");
                                     });
@@ -230,39 +192,33 @@ impl RequestCache {
         self.remote_version_number = Some(new_state);
         Ok(None)
     }
-    pub fn remote_update_bytes(&mut self, only_check: bool) -> PendingResponse<Arc<[u8]>> {
+    pub fn remote_update_bytes(&mut self, only_check: bool) -> PendingResponse<(Arc<[u8]>, Arc<str>)> {
         let new_state = match &self.remote_update_bytes {
             None => {
                 if only_check {
                     return Ok(None);
                 }
                 let (sender, receiver) = mpsc::channel();
-                ehttp::fetch(
-                    get_request(&format!("{PUBLIC_URL}/api/local/update")),
-                    move |response| {
-                        let response = match response {
-                            Ok(resp) => resp,
-                            Err(e) => {
-                                sender
-                                    .send(Err(Arc::from(format!("Failed to fetch update: {e}"))))
-                                    .unwrap_or_else(|e| {
-                                        log::error!("Failed to send error response: {e}");
-                                    });
-                                return;
-                            }
-                        };
-                        if response.status != 200 {
+                client().fetch(
+                    api::DownloadUpdate {
+                        channel: CONFIG.update_channel(),
+                        target: env!("PITSU_TARGET").into(),
+                    },
+                    move |response| match response {
+                        Ok(update) => {
+                            sender
+                                .send(Ok((update.executable.into(), update.signature)))
+                                .unwrap_or_else(|e| {
+                                    log::error!("Failed to send update file: {e}");
+                                });
+                        }
+                        Err(e) => {
                             sender
-                                .send(Err(Arc::from(format!("Failed to fetch update: {}", response.status))))
+                                .send(Err(Arc::from(format!("Failed to fetch update: {e}"))))
                                 .unwrap_or_else(|e| {
                                     log::error!("Failed to send error response: {e}");
                                 });
-                            return;
                         }
-                        let file = response.bytes;
-                        sender.send(Ok(file.into())).unwrap_or_else(|e| {
-                            log::error!("Failed to send update file: {e}");
-                        });
                     },
                 );
                 PendingRequest::Pending(receiver)
@@ -283,44 +239,73 @@ impl RequestCache {
         self.remote_update_bytes = Some(new_state);
         Ok(None)
     }
-    pub fn this_user(&mut self) -> PendingResponse<Arc<ThisUser>> {
-        let new_state = match &self.this_user {
+    pub fn reset_update_bytes(&mut self) {
+        self.remote_update_bytes = None;
+    }
+    // forgets the offered version, used when the update channel changes
+    pub fn reset_version_check(&mut self) {
+        self.remote_version_number = None;
+        self.changelog = None;
+    }
+    // notes between the running version and the one on offer
+    pub fn changelog(&mut self, offered: &VersionNumber) -> PendingResponse<Arc<[ChangelogEntry]>> {
+        let new_state = match &self.changelog {
             None => {
                 let (sender, receiver) = mpsc::channel();
-                ehttp::fetch(get_request(&format!("{PUBLIC_URL}/api/user")), move |response| {
-                    let response = match response {
-                        Ok(resp) => resp,
+                client().fetch(
+                    api::Changelog {
+                        from: crate::config::VERSION_NUMBER.clone(),
+                        to: offered.clone(),
+                    },
+                    move |response| match response {
+                        Ok(entries) => {
+                            sender.send(Ok(Arc::from(entries))).unwrap_or_else(|e| {
+                                log::error!("Failed to send changelog response: {e}");
+                            });
+                        }
                         Err(e) => {
                             sender
-                                .send(Err(Arc::from(format!("Failed to fetch user: {e}"))))
+                                .send(Err(Arc::from(format!("Failed to fetch changelog: {e}"))))
                                 .unwrap_or_else(|e| {
                                     log::error!("Failed to send error response: {e}");
                                 });
-                            return;
                         }
-                    };
-                    if response.status != 200 {
+                    },
+                );
+                PendingRequest::Pending(receiver)
+            }
+            Some(PendingRequest::Pending(pending)) => match pending.try_recv() {
+                Ok(result) => PendingRequest::Response(result),
+                Err(mpsc::TryRecvError::Empty) => {
+                    return Ok(None);
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    PendingRequest::Response(Err(Arc::from("Request channel disconnected unexpectedly".to_string())))
+                }
+            },
+            Some(PendingRequest::Response(result)) => {
+                return result.clone().map(Some);
+            }
+        };
+        self.changelog = Some(new_state);
+        Ok(None)
+    }
+    pub fn this_user(&mut self) -> PendingResponse<Arc<ThisUser>> {
+        let new_state = match &self.this_user {
+            None => {
+                let (sender, receiver) = mpsc::channel();
+                client().fetch(api::GetSelf, move |response| match response {
+                    Ok(user) => {
+                        sender.send(Ok(Arc::new(user))).unwrap_or_else(|e| {
+                            log::error!("Failed to send user response: {e}");
+                        });
+                    }
+                    Err(e) => {
                         sender
-                            .send(Err(Arc::from(format!("Failed to fetch user: {}", response.status))))
+                            .send(Err(Arc::from(format!("Failed to fetch user: {e}"))))
                             .unwrap_or_else(|e| {
                                 log::error!("Failed to send error response: {e}");
                             });
-                        return;
-                    }
-                    let user: Result<ThisUser, _> = response.json();
-                    match user {
-                        Ok(user) => {
-                            sender.send(Ok(Arc::new(user))).unwrap_or_else(|e| {
-                                log::error!("Failed to send user response: {e}");
-                            });
-                        }
-                        Err(e) => {
-                            sender
-                                .send(Err(Arc::from(format!("Failed to parse user: {e}"))))
-                                .unwrap_or_else(|e| {
-                                    log::error!("Failed to send error response: {e}");
-                                });
-                        }
                     }
                 });
                 PendingRequest::Pending(receiver)
@@ -345,40 +330,18 @@ impl RequestCache {
         let new_state = match &self.users {
             None => {
                 let (sender, receiver) = mpsc::channel();
-                ehttp::fetch(get_request(&format!("{PUBLIC_URL}/api/users")), move |response| {
-                    let response = match response {
-                        Ok(resp) => resp,
-                        Err(e) => {
-                            sender
-                                .send(Err(Arc::from(format!("Failed to fetch users: {e}"))))
-                                .unwrap_or_else(|e| {
-                                    log::error!("Failed to send error response: {e}");
-                                });
-                            return;
-                        }
-                    };
-                    if response.status != 200 {
+                client().fetch(api::GetUsers, move |response| match response {
+                    Ok(users) => {
+                        sender.send(Ok(users)).unwrap_or_else(|e| {
+                            log::error!("Failed to send users response: {e}");
+                        });
+                    }
+                    Err(e) => {
                         sender
-                            .send(Err(Arc::from(format!("Failed to fetch users: {}", response.status))))
+                            .send(Err(Arc::from(format!("Failed to fetch users: {e}"))))
                             .unwrap_or_else(|e| {
                                 log::error!("Failed to send error response: {e}");
                             });
-                        return;
-                    }
-                    let users: Result<Vec<User>, _> = response.json();
-                    match users {
-                        Ok(users) => {
-                            sender.send(Ok(users)).unwrap_or_else(|e| {
-                                log::error!("Failed to send users response: {e}");
-                            });
-                        }
-                        Err(e) => {
-                            sender
-                                .send(Err(Arc::from(format!("Failed to parse users: {e}"))))
-                                .unwrap_or_else(|e| {
-                                    log::error!("Failed to send error response: {e}");
-                                });
-                        }
                     }
                 });
                 PendingRequest::Pending(receiver)
@@ -403,43 +366,18 @@ impl RequestCache {
         match self.repositories.entry(uuid) {
             std::collections::hash_map::Entry::Vacant(entry) => {
                 let (sender, receiver) = mpsc::channel();
-                ehttp::fetch(get_request(&format!("{PUBLIC_URL}/{uuid}")), move |response| {
-                    let response = match response {
-                        Ok(resp) => resp,
-                        Err(e) => {
-                            sender
-                                .send(Err(Arc::from(format!("Failed to fetch repository: {e}"))))
-                                .unwrap_or_else(|e| {
-                                    log::error!("Failed to send error response: {e}");
-                                });
-                            return;
-                        }
-                    };
-                    if response.status != 200 {
+                client().fetch(api::GetRepository { uuid }, move |response| match response {
+                    Ok(repo) => {
+                        sender.send(Ok(Arc::new(repo))).unwrap_or_else(|e| {
+                            log::error!("Failed to send repository response: {e}");
+                        });
+                    }
+                    Err(e) => {
                         sender
-                            .send(Err(Arc::from(format!(
-                                "Failed to fetch repository: {}",
-                                response.status
-                            ))))
+                            .send(Err(Arc::from(format!("Failed to fetch repository: {e}"))))
                             .unwrap_or_else(|e| {
                                 log::error!("Failed to send error response: {e}");
                             });
-                        return;
-                    }
-                    let repo: Result<RemoteRepository, _> = response.json();
-                    match repo {
-                        Ok(repo) => {
-                            sender.send(Ok(Arc::new(repo))).unwrap_or_else(|e| {
-                                log::error!("Failed to send repository response: {e}");
-                            });
-                        }
-                        Err(e) => {
-                            sender
-                                .send(Err(Arc::from(format!("Failed to parse repository: {e}"))))
-                                .unwrap_or_else(|e| {
-                                    log::error!("Failed to send error response: {e}");
-                                });
-                        }
                     }
                 });
                 entry.insert(PendingRequest::Pending(receiver));
@@ -478,10 +416,8 @@ impl RequestCache {
                     let stored_repo = CONFIG.get_stored(uuid);
                     match stored_repo {
                         Ok(Some(repo)) => {
-                            // let mut diff = remote.files.diff(&repo.folder);
-                            let diff = Arc::from(repo.folder.diff(&remote.files));
-                            let pitignore = match Pitignore::from_repository(repo.path.clone()) {
-                                Ok(pitignore) => pitignore,
+                            let local = match Repository::new(repo, remote) {
+                                Ok(local) => local,
                                 Err(e) => {
                                     log::error!("Failed to get .pitignore for repository: {e}");
                                     sender
@@ -492,16 +428,6 @@ impl RequestCache {
                                     return;
                                 }
                             };
-                            let local_pitignore_diff = pitignore.apply_patterns(&diff);
-                            let remote_pitignore_diff = remote.pitignore.apply_patterns(&diff);
-                            let local = Repository {
-                                local: repo,
-                                // remote: Arc::clone(&remote),
-                                local_pitignore_diff,
-                                remote_pitignore_diff,
-                                local_pitignore: Arc::from(pitignore),
-                                remote_pitignore: Arc::from(remote.pitignore.clone()),
-                            };
                             // println!("Loaded stored repository: {local:#?}");
                             sender.send(Ok(Some(Arc::from(local)))).unwrap_or_else(|e| {
                                 log::error!("Failed to send stored repository response: {e}");
@@ -717,37 +643,24 @@ impl RequestCache {
             None => {
                 let (sender, receiver) = mpsc::channel();
                 // std::thread::spawn(move || {
-                ehttp::fetch(
-                    post_request(
-                        &format!("{PUBLIC_URL}/{repository_uuid}/.pit/user/access"),
-                        serde_json::to_value(user).expect("Failed to serialize user"),
-                    ),
-                    move |response| {
-                        let response = match response {
-                            Ok(resp) => resp,
-                            Err(e) => {
-                                sender
-                                    .send(Err(Arc::from(format!("Failed to set user access level: {e}"))))
-                                    .unwrap_or_else(|e| {
-                                        log::error!("Failed to send error response: {e}");
-                                    });
-                                return;
-                            }
-                        };
-                        if response.status != 200 {
+                client().fetch(
+                    api::SetAccessLevel {
+                        uuid: repository_uuid,
+                        user,
+                    },
+                    move |response| match response {
+                        Ok(()) => {
+                            sender.send(Ok(repository_uuid)).unwrap_or_else(|e| {
+                                log::error!("Failed to send user access level set response: {e}");
+                            });
+                        }
+                        Err(e) => {
                             sender
-                                .send(Err(Arc::from(format!(
-                                    "Failed to set user access level: {}",
-                                    response.status
-                                ))))
+                                .send(Err(Arc::from(format!("Failed to set user access level: {e}"))))
                                 .unwrap_or_else(|e| {
                                     log::error!("Failed to send error response: {e}");
                                 });
-                            return;
                         }
-                        sender.send(Ok(repository_uuid)).unwrap_or_else(|e| {
-                            log::error!("Failed to send user access level set response: {e}");
-                        });
                     },
                 );
                 // });
@@ -767,6 +680,28 @@ impl RequestCache {
             Some(PendingRequest::Response(_)) => {}
         }
     }
+    pub fn update_repository(&mut self, repository_uuid: Uuid, update: UpdateRemoteRepository) {
+        if self.user_action.is_some() {
+            return;
+        }
+        let (sender, receiver) = mpsc::channel();
+        self.user_action = Some(PendingRequest::Pending(receiver));
+        client().fetch(
+            api::UpdateRepository {
+                uuid: repository_uuid,
+                update,
+            },
+            move |response| {
+                let result = match response {
+                    Ok(()) => Ok(repository_uuid),
+                    Err(e) => Err(Arc::from(format!("Failed to update repository: {e}"))),
+                };
+                sender.send(result).unwrap_or_else(|e| {
+                    log::error!("Failed to send repository update response: {e}");
+                });
+            },
+        );
+    }
     pub fn resolve_user_action(&mut self) -> PendingResponse<Uuid> {
         if let Some(user_action) = &mut self.user_action {
             match user_action {
@@ -795,37 +730,24 @@ impl RequestCache {
             None => {
                 let (sender, receiver) = mpsc::channel();
                 self.user_action = Some(PendingRequest::Pending(receiver));
-                ehttp::fetch(
-                    delete_request_with_body(
-                        &format!("{PUBLIC_URL}/{repository_uuid}/.pit/user/access"),
-                        serde_json::to_value(user_uuid).expect("Failed to serialize user UUID"),
-                    ),
-                    move |response| {
-                        let response = match response {
-                            Ok(resp) => resp,
-                            Err(e) => {
-                                sender
-                                    .send(Err(Arc::from(format!("Failed to remove user access level: {e}"))))
-                                    .unwrap_or_else(|e| {
-                                        log::error!("Failed to send error response: {e}");
-                                    });
-                                return;
-                            }
-                        };
-                        if response.status != 200 {
+                client().fetch(
+                    api::RemoveAccessLevel {
+                        uuid: repository_uuid,
+                        user: user_uuid,
+                    },
+                    move |response| match response {
+                        Ok(()) => {
+                            sender.send(Ok(repository_uuid)).unwrap_or_else(|e| {
+                                log::error!("Failed to send user access level removed response: {e}");
+                            });
+                        }
+                        Err(e) => {
                             sender
-                                .send(Err(Arc::from(format!(
-                                    "Failed to remove user access level: {}",
-                                    response.status
-                                ))))
+                                .send(Err(Arc::from(format!("Failed to remove user access level: {e}"))))
                                 .unwrap_or_else(|e| {
                                     log::error!("Failed to send error response: {e}");
                                 });
-                            return;
                         }
-                        sender.send(Ok(repository_uuid)).unwrap_or_else(|e| {
-                            log::error!("Failed to send user access level removed response: {e}");
-                        });
                     },
                 );
             }
@@ -853,6 +775,86 @@ impl RequestCache {
         self.user_action = None;
     }
 
+    pub fn export_archive(&mut self, uuid: Uuid, format: ArchiveFormat, path: PathBuf) {
+        let (sender, receiver) = mpsc::channel();
+        self.archive_transfer = Some((None, PendingRequest::Pending(receiver)));
+        client().fetch(api::DownloadArchive { uuid, format }, move |response| {
+            let result = match response {
+                Ok(bytes) => match std::fs::write(&path, bytes) {
+                    Ok(()) => Ok(Arc::from(format!("Archive saved to {}", path.display()))),
+                    Err(e) => Err(Arc::from(format!("Failed to write archive to {}: {e}", path.display()))),
+                },
+                Err(e) => Err(Arc::from(format!("Failed to download archive: {e}"))),
+            };
+            sender.send(result).unwrap_or_else(|e| {
+                log::error!("Failed to send archive response: {e}");
+            });
+        });
+    }
+    pub fn import_archive(&mut self, uuid: Uuid, path: PathBuf, strip_components: usize) {
+        let (sender, receiver) = mpsc::channel();
+        self.archive_transfer = Some((Some(uuid), PendingRequest::Pending(receiver)));
+        // archives can be large, so they're read off the ui thread
+        std::thread::spawn(move || {
+            let bytes = match std::fs::read(&path) {
+                Ok(bytes) if bytes.len() > pitsu_lib::MAX_UPLOAD_SIZE => {
+                    sender
+                        .send(Err(Arc::from(format!(
+                            "Archive is too large to import ({} max)",
+                            pitsu_lib::readable_size(pitsu_lib::MAX_UPLOAD_SIZE as u64)
+                        ))))
+                        .ok();
+                    return;
+                }
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    sender
+                        .send(Err(Arc::from(format!("Failed to read {}: {e}", path.display()))))
+                        .ok();
+                    return;
+                }
+            };
+            log::info!("Importing {} into repository {uuid}", path.display());
+            client().fetch(
+                api::ImportArchive {
+                    uuid,
+                    archive: bytes,
+                    strip_components,
+                },
+                move |response| {
+                    let result = match response {
+                        Ok(message) => Ok(message),
+                        Err(e) => Err(Arc::from(format!("Failed to import archive: {e}"))),
+                    };
+                    sender.send(result).unwrap_or_else(|e| {
+                        log::error!("Failed to send archive response: {e}");
+                    });
+                },
+            );
+        });
+    }
+    pub fn archive_transfer_in_progress(&self) -> bool {
+        matches!(self.archive_transfer, Some((_, PendingRequest::Pending(_))))
+    }
+    // returns the finished transfer's message and the repository it changed once, then forgets it
+    pub fn take_archive_transfer(&mut self) -> PendingResponse<(Arc<str>, Option<Uuid>)> {
+        if let Some((reload, PendingRequest::Pending(pending))) = &self.archive_transfer {
+            match pending.try_recv() {
+                Ok(result) => {
+                    let reload = *reload;
+                    self.archive_transfer = None;
+                    return result.map(|message| Some((message, reload)));
+                }
+                Err(mpsc::TryRecvError::Empty) => return Ok(None),
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.archive_transfer = None;
+                    return Err(Arc::from("Request channel disconnected unexpectedly".to_string()));
+                }
+            }
+        }
+        Ok(None)
+    }
+
     pub fn reset_sync_response(&mut self) {
         self.upload = None;
         self.download = None;
@@ -946,60 +948,9 @@ fn sync_request(
     upload: bool,
     progress_sender: mpsc::Sender<Option<Progress>>,
 ) -> Result<(), Arc<str>> {
-    let mut actions = Vec::new();
-    let diffs = if upload {
-        repository.local_pitignore_diff.iter()
-    } else {
-        repository.remote_pitignore_diff.iter()
-    };
-    for diff in diffs {
-        match diff.change_type {
-            ChangeType::Modified => {
-                if upload {
-                    actions.push(SyncAction {
-                        action_type: ActionType::Upload,
-                        full_path: diff.full_path.clone(),
-                    });
-                } else {
-                    actions.push(SyncAction {
-                        action_type: ActionType::Download,
-                        full_path: diff.full_path.clone(),
-                    });
-                }
-            }
-            ChangeType::OnServer => {
-                if upload {
-                    actions.push(SyncAction {
-                        action_type: ActionType::DeleteFromRemote,
-                        full_path: diff.full_path.clone(),
-                    });
-                } else {
-                    actions.push(SyncAction {
-                        action_type: ActionType::Download,
-                        full_path: diff.full_path.clone(),
-                    });
-                }
-            }
-            ChangeType::OnClient => {
-                if upload {
-                    actions.push(SyncAction {
-                        action_type: ActionType::Upload,
-                        full_path: diff.full_path.clone(),
-                    });
-                } else {
-                    actions.push(SyncAction {
-                        action_type: ActionType::DeleteFromDisk,
-                        full_path: diff.full_path.clone(),
-                    });
-                }
-            }
-        }
-    }
+    let actions = repository.plan(upload).actions;
     let mut progress = Progress {
-        total: actions
-            .iter()
-            .filter(|a| a.action_type == ActionType::Upload || a.action_type == ActionType::Download)
-            .count(),
+        total: actions.iter().filter(|a| a.action_type.is_transfer()).count(),
         batched: 0,
         completed: 0,
         // current_progress: 0.0,
@@ -1009,7 +960,12 @@ fn sync_request(
         .map_err(|e| Arc::from(format!("Failed to send initial progress: {e}")))?;
     let (snd, rcv) = mpsc::channel::<ProgressType>();
     let mut pending_batched_uploads = Vec::new();
-    let url_prefix = format!("{PUBLIC_URL}/{}", repository.local.uuid);
+    let uuid = repository.local.uuid;
+    let trash = TrashSession::new(repository.local.uuid)
+        .map_err(|e| Arc::from(format!("Failed to prepare trash for sync: {e}")))?;
+    let mut upload_throttle = Throttle::new(Direction::Upload);
+    let mut download_throttle = Throttle::new(Direction::Download);
+    let (compression, compression_level) = CONFIG.compression();
     for action in actions {
         let mut changed = false;
         while let Ok(progress_made) = rcv.try_recv() {
@@ -1030,21 +986,21 @@ fn sync_request(
         }
         let mut local_path = repository.local.path.clone();
         local_path.push(action.full_path.strip_prefix("/").unwrap_or(&*action.full_path));
-        let remote_path = format!(
-            "{url_prefix}/{}",
-            action.full_path.strip_prefix("/").unwrap_or(&*action.full_path)
-        );
         if action.action_type == ActionType::Download {
             std::fs::create_dir_all(local_path.parent().unwrap())
                 .map_err(|e| Arc::from(format!("Failed to create directory: {e}")))?;
         }
-        // Delete if necessary
+        // Move to the trash if necessary, nothing a sync removes or overwrites is deleted outright
         if (action.action_type == ActionType::DeleteFromDisk || action.action_type == ActionType::Download)
             && local_path.exists()
         {
             // this function has side effects so call it here specifically
-            if let Err(e) = std::fs::remove_file(&local_path) {
-                log::warn!("Failed to delete file {}: {e}", local_path.display());
+            if let Err(e) = trash.move_file(&local_path, &action.full_path) {
+                log::error!("Failed to move file {} to trash: {e}", local_path.display());
+                return Err(Arc::from(format!(
+                    "Failed to move {} to trash, stopping sync so it isn't lost: {e}",
+                    local_path.display()
+                )));
             }
         }
         if action.action_type == ActionType::DeleteFromDisk {
@@ -1078,6 +1034,8 @@ fn sync_request(
             }
         }
         let (await_sender, await_receiver) = mpsc::channel::<Result<(), Arc<str>>>();
+        // those are recorded against the throttle piece by piece
+        let mut downloaded_in_parts = false;
         match action.action_type {
             ActionType::DeleteFromDisk => {
                 await_sender.send(Ok(())).unwrap_or_else(|e| {
@@ -1085,18 +1043,29 @@ fn sync_request(
                 });
             }
             ActionType::Upload => {
-                // Add to pending uploads
-                pending_batched_uploads.push(
-                    UploadFile::new(
-                        action.full_path.clone(),
-                        std::fs::read(&local_path).map_err(|e| Arc::from(format!("Failed to read file: {e}")))?,
-                    )
-                    .map_err(|e| Arc::from(format!("Failed to create upload file: {e}")))?,
-                );
+                let bytes = std::fs::read(&local_path).map_err(|e| Arc::from(format!("Failed to read file: {e}")))?;
                 snd.send(ProgressType::Batched(1))
                     .map_err(|e| Arc::from(format!("Failed to send batched upload progress: {e}")))?;
-                if pending_batched_uploads.iter().map(|f| f.size()).sum::<usize>() as f64
-                    > (pitsu_lib::MAX_UPLOAD_SIZE as f64) * 0.10
+                if upload_throttle
+                    .part_size()
+                    .is_some_and(|part_size| bytes.len() as u64 > part_size)
+                {
+                    // too large for one request at the limit, goes up on its own a piece at a time
+                    if let Err(e) = upload_in_parts(uuid, action.full_path.clone(), bytes, &mut upload_throttle) {
+                        log::error!("Failed to upload file in parts: {e}");
+                        return Err(e);
+                    }
+                    snd.send(ProgressType::Transferred(1))
+                        .map_err(|e| Arc::from(format!("Failed to send upload progress: {e}")))?;
+                } else {
+                    // Add to pending uploads
+                    pending_batched_uploads.push(
+                        UploadFile::new(action.full_path.clone(), bytes, compression, compression_level)
+                            .map_err(|e| Arc::from(format!("Failed to create upload file: {e}")))?,
+                    );
+                }
+                if pending_batched_uploads.iter().map(|f| f.size()).sum::<usize>()
+                    > upload_throttle.batch_size((pitsu_lib::MAX_UPLOAD_SIZE as f64 * 0.10) as usize)
                 {
                     let mut new_uploads = if pending_batched_uploads.len() == 1 {
                         vec![]
@@ -1109,7 +1078,8 @@ fn sync_request(
                     };
                     std::mem::swap(&mut pending_batched_uploads, &mut new_uploads);
                     let size = new_uploads.len();
-                    if let Err(e) = upload_batched_files(&url_prefix, FileUpload { files: new_uploads }) {
+                    if let Err(e) = upload_batched_files(uuid, FileUpload { files: new_uploads }, &mut upload_throttle)
+                    {
                         log::error!("Failed to upload files: {e}");
                         return Err(e);
                     }
@@ -1121,72 +1091,93 @@ fn sync_request(
                 }
             }
             ActionType::DeleteFromRemote => {
-                ehttp::fetch(delete_request(&remote_path), move |response| {
-                    // log::warn!("Deleted file: {remote_path}");
-                    let response = match response {
-                        Ok(resp) => resp,
+                client().fetch(
+                    api::DeleteFile {
+                        uuid,
+                        path: action.full_path.clone(),
+                    },
+                    move |response| match response {
+                        Ok(()) => {
+                            await_sender.send(Ok(())).unwrap_or_else(|e| {
+                                log::error!("Failed to send delete completion: {e}");
+                            });
+                        }
                         Err(e) => {
                             await_sender
                                 .send(Err(Arc::from(format!("Failed to delete file: {e}"))))
                                 .unwrap_or_else(|e| {
                                     log::error!("Failed to send error response: {e}");
                                 });
-                            return;
                         }
-                    };
-                    if response.status != 200 {
-                        await_sender
-                            .send(Err(Arc::from(format!("Failed to delete file: {}", response.status))))
-                            .unwrap_or_else(|e| {
-                                log::error!("Failed to send error response: {e}");
-                            });
-                        return;
-                    }
-                    await_sender.send(Ok(())).unwrap_or_else(|e| {
-                        log::error!("Failed to send delete completion: {e}");
-                    });
+                    },
+                );
+            }
+            ActionType::Download
+                if download_throttle
+                    .part_size()
+                    .is_some_and(|part_size| action.size > part_size) =>
+            {
+                // too large for one request at the limit, comes down a range at a time
+                let result = download_in_parts(
+                    uuid,
+                    &action.full_path,
+                    &local_path,
+                    action.size,
+                    &mut download_throttle,
+                );
+                if result.is_ok() {
+                    snd.send(ProgressType::Transferred(1)).ok();
+                    downloaded_in_parts = true;
+                }
+                await_sender.send(result).unwrap_or_else(|e| {
+                    log::error!("Failed to send download completion: {e}");
                 });
             }
             ActionType::Download => {
+                download_throttle.wait_for_focus();
                 let snd = snd.clone();
-                ehttp::fetch(get_request(&remote_path), move |response| {
-                    let response = match response {
-                        Ok(resp) => resp,
-                        Err(e) => {
+                let path = action.full_path.clone();
+                client().fetch(
+                    api::DownloadFile {
+                        uuid,
+                        path: path.clone(),
+                        accept: compression,
+                        range: None,
+                    },
+                    move |response| {
+                        let bytes = match response {
+                            Ok(bytes) => bytes,
+                            Err(e) => {
+                                await_sender
+                                    .send(Err(Arc::from(format!("Failed to download {path}: {e}"))))
+                                    .unwrap_or_else(|e| {
+                                        log::error!("Failed to send error response: {e}");
+                                    });
+                                return;
+                            }
+                        };
+                        if let Err(e) = std::fs::write(&local_path, bytes) {
                             await_sender
-                                .send(Err(Arc::from(format!("Failed to download file: {e}"))))
+                                .send(Err(Arc::from(format!("Failed to write to {local_path:?}: {e}"))))
                                 .unwrap_or_else(|e| {
                                     log::error!("Failed to send error response: {e}");
                                 });
                             return;
                         }
-                    };
-                    if response.status != 200 {
-                        await_sender
-                            .send(Err(Arc::from(format!("Failed to download file: {}", response.status))))
-                            .unwrap_or_else(|e| {
-                                log::error!("Failed to send error response: {e}");
-                            });
-                        return;
-                    }
-                    if let Err(e) = std::fs::write(&local_path, response.bytes) {
-                        await_sender
-                            .send(Err(Arc::from(format!("Failed to write to {local_path:?}: {e}"))))
-                            .unwrap_or_else(|e| {
-                                log::error!("Failed to send error response: {e}");
-                            });
-                        return;
-                    }
-                    await_sender.send(Ok(())).unwrap_or_else(|e| {
-                        log::error!("Failed to send download completion: {e}");
-                    });
-                    snd.send(ProgressType::Transferred(1)).ok();
-                })
+                        await_sender.send(Ok(())).unwrap_or_else(|e| {
+                            log::error!("Failed to send download completion: {e}");
+                        });
+                        snd.send(ProgressType::Transferred(1)).ok();
+                    },
+                )
             }
         }
         match await_receiver.recv() {
             Ok(Ok(())) => {
                 // Successfully completed the action
+                if action.action_type == ActionType::Download && !downloaded_in_parts {
+                    download_throttle.record(action.size);
+                }
             }
             Ok(Err(e)) => {
                 log::error!("Failed to complete action: {e}");
@@ -1201,71 +1192,109 @@ fn sync_request(
     if !pending_batched_uploads.is_empty() {
         // This function has side effects
         if let Err(e) = upload_batched_files(
-            &url_prefix,
+            uuid,
             FileUpload {
                 files: pending_batched_uploads,
             },
+            &mut upload_throttle,
         ) {
             log::error!("Failed to upload remaining files: {e}");
             return Err(e);
         }
     }
+    if let Err(e) = crate::trash::purge_expired(CONFIG.trash_retention_days()) {
+        log::warn!("Failed to purge expired trash: {e}");
+    }
     progress_sender
         .send(None)
         .map_err(|e| Arc::from(format!("Failed to send final progress update: {e}")))?;
     Ok(())
 }
 
-#[derive(Debug, Clone)]
-struct SyncAction {
-    action_type: ActionType,
-    full_path: Arc<str>,
+// one piece per request, the server puts the file together once the last one arrives
+fn upload_in_parts(uuid: Uuid, path: Arc<str>, bytes: Vec<u8>, throttle: &mut Throttle) -> Result<(), Arc<str>> {
+    let (compression, compression_level) = CONFIG.compression();
+    let total = bytes.len() as u64;
+    let mut offset = 0;
+    while offset < total {
+        // the limit can change mid-file, without one the rest goes in a single request
+        let end = (offset + throttle.part_size().unwrap_or(total)).min(total);
+        let mut file = UploadFile::new(
+            path.clone(),
+            bytes[offset as usize..end as usize].to_vec(),
+            compression,
+            compression_level,
+        )
+        .map_err(|e| Arc::from(format!("Failed to create upload file: {e}")))?;
+        file.part = Some(FilePart { offset, total });
+        upload_batched_files(uuid, FileUpload { files: vec![file] }, throttle)?;
+        offset = end;
+    }
+    Ok(())
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum ActionType {
-    DeleteFromDisk,
-    DeleteFromRemote,
-    Upload,
-    Download,
+fn download_in_parts(
+    uuid: Uuid,
+    path: &Arc<str>,
+    local_path: &Path,
+    size: u64,
+    throttle: &mut Throttle,
+) -> Result<(), Arc<str>> {
+    let mut file =
+        std::fs::File::create(local_path).map_err(|e| Arc::from(format!("Failed to create {local_path:?}: {e}")))?;
+    let mut offset = 0;
+    while offset < size {
+        throttle.wait_for_focus();
+        let end = (offset + throttle.part_size().unwrap_or(size)).min(size);
+        let bytes = client()
+            .call(api::DownloadFile {
+                uuid,
+                path: path.clone(),
+                accept: TransferEncoding::None,
+                range: Some(offset..end),
+            })
+            .map_err(|e| Arc::from(format!("Failed to download {path}: {e}")))?;
+        let received = bytes.len() as u64;
+        // a server that ignores ranges sends the whole file, which is just as good on the first request
+        let complete = offset == 0 && received == size;
+        if !complete && received != end - offset {
+            return Err(Arc::from(format!(
+                "Server sent {received} bytes of {path} instead of {}",
+                end - offset
+            )));
+        }
+        file.write_all(&bytes)
+            .map_err(|e| Arc::from(format!("Failed to write to {local_path:?}: {e}")))?;
+        throttle.record(received);
+        offset = if complete { size } else { end };
+    }
+    Ok(())
 }
 
-fn upload_batched_files(url_prefix: &str, files: FileUpload) -> Result<(), Arc<str>> {
+fn upload_batched_files(uuid: Uuid, files: FileUpload, throttle: &mut Throttle) -> Result<(), Arc<str>> {
     if files.files.is_empty() {
         return Ok(());
     }
+    throttle.wait_for_focus();
+    let size = files.files.iter().map(|f| f.size()).sum::<usize>() as u64;
     let (sender, receiver) = mpsc::channel();
-    ehttp::fetch(
-        post_request(
-            &format!("{url_prefix}/.pit/upload"),
-            serde_json::to_value(&files).expect("Failed to serialize files"),
-        ),
-        move |response| {
-            let response = match response {
-                Ok(resp) => resp,
-                Err(e) => {
-                    sender
-                        .send(Err(Arc::from(format!("Failed to upload files: {e}"))))
-                        .unwrap_or_else(|e| {
-                            log::error!("Failed to send error response: {e}");
-                        });
-                    return;
-                }
-            };
-            if response.status != 200 {
-                sender
-                    .send(Err(Arc::from(format!("Failed to upload files: {}", response.status))))
-                    .unwrap_or_else(|e| {
-                        log::error!("Failed to send error response: {e}");
-                    });
-                return;
-            }
+    client().fetch(api::UploadFiles { uuid, files }, move |response| match response {
+        Ok(()) => {
             sender.send(Ok(())).unwrap_or_else(|e| {
                 log::error!("Failed to send upload completion: {e}");
             });
-        },
-    );
+        }
+        Err(e) => {
+            sender
+                .send(Err(Arc::from(format!("Failed to upload files: {e}"))))
+                .unwrap_or_else(|e| {
+                    log::error!("Failed to send error response: {e}");
+                });
+        }
+    });
     receiver
         .recv()
-        .map_err(|_| Arc::from("Upload channel disconnected unexpectedly".to_string()))?
+        .map_err(|_| Arc::from("Upload channel disconnected unexpectedly".to_string()))??;
+    throttle.record(size);
+    Ok(())
 }
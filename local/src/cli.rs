@@ -0,0 +1,99 @@
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use pitsu_lib::api::GetRepository;
+use uuid::Uuid;
+
+use crate::{
+    Repository,
+    config::{CONFIG, client},
+};
+
+#[derive(clap::Parser)]
+#[clap(name = "pitsu", version = env!("CARGO_PKG_VERSION"), author = "Ethan Conaway <you@willsh.art>", about = "Planet51 Internet Transfer and Synchronization Utility")]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+    /// Open the app on a repository, by UUID, alias or pitsu:// link, or redeem a pitsu://invite/ link
+    #[clap(long)]
+    pub open: Option<String>,
+    /// Start without loading downloaded repositories, for when something in them keeps crashing the app
+    #[clap(long)]
+    pub safe_mode: bool,
+}
+
+// release builds use the windows subsystem, so --help and argument errors would otherwise print nowhere
+pub fn parse() -> Cli {
+    let e = match <Cli as clap::Parser>::try_parse() {
+        Ok(cli) => return cli,
+        Err(e) => e,
+    };
+    // --help and bad arguments still mean the update runs
+    crate::update::mark_healthy();
+    match e {
+        e if cfg!(all(windows, not(debug_assertions))) => {
+            crate::dialogue::rfd_ok_dialogue(&e.render().to_string()).ok();
+            std::process::exit(e.exit_code());
+        }
+        e => e.exit(),
+    }
+}
+
+#[derive(clap::Subcommand)]
+pub enum Command {
+    /// Print what a sync would do without touching the disk or the server
+    Plan {
+        /// UUID or alias
+        repo: String,
+        /// Plan a download instead of an upload
+        #[clap(long)]
+        download: bool,
+        #[clap(long, value_enum, default_value = "text")]
+        format: PlanFormat,
+        /// Write the plan to a file instead of stdout (release builds have no console attached)
+        #[clap(long, short)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq)]
+pub enum PlanFormat {
+    Json,
+    Text,
+}
+
+impl Command {
+    pub fn run(self) -> Result<()> {
+        match self {
+            Command::Plan {
+                repo,
+                download,
+                format,
+                output,
+            } => {
+                let repository = load_repository(CONFIG.resolve_repository(&repo)?)?;
+                let plan = repository.plan(!download);
+                let contents = match format {
+                    PlanFormat::Json => plan.to_json()?,
+                    PlanFormat::Text => plan.to_text(),
+                };
+                match output {
+                    Some(path) => std::fs::write(path, contents)?,
+                    None => print!("{contents}"),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn load_repository(uuid: Uuid) -> Result<Repository> {
+    let remote = client()
+        .call(GetRepository { uuid })
+        .map(Arc::new)
+        .map_err(|e| anyhow::anyhow!("Failed to fetch repository: {e}"))?;
+    let local = CONFIG
+        .get_stored(uuid)?
+        .ok_or_else(|| anyhow::anyhow!("Repository {uuid} has no local path set"))?;
+    Repository::new(local, remote)
+}
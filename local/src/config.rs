@@ -1,45 +1,62 @@
 #![allow(unused)]
 use anyhow::Result;
 use core::panic;
-use ehttp::{Request, fetch};
 use lazy_static::lazy_static;
-use pitsu_lib::{Pitignore, RootFolder, ThisUser, VersionNumber};
-use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use serde_json::Value;
+use pitsu_lib::{
+    Pitignore, RootFolder, ThisUser, TransferEncoding, UpdateChannel, VersionNumber,
+    api::{Client, Endpoint, GetSelf, RedeemInvite},
+};
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
     fmt::{self, Debug},
     path::PathBuf,
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
 };
 use uuid::Uuid;
 
 use crate::dialogue;
 
-pub fn setup() {
+// open is --open, which is also how the OS hands over a pitsu://invite link
+pub fn setup(safe_mode: bool, open: Option<&str>) {
+    SAFE_MODE.store(safe_mode, Ordering::Relaxed);
     std::panic::set_hook(Box::new(crate::dialogue::rfd_panic_dialogue));
-    // let exe = std::env::current_exe().expect("Failed to get current executable path");
-    // let binary_name = exe
-    //     .file_name()
-    //     .and_then(|s| s.to_str())
-    //     .map(|s| s.to_string())
-    //     .expect("Failed to convert executable name to string");
-    // if let Some(api_key) = binary_name.strip_prefix("pitsu.") {
-    //     let api_key = api_key.strip_suffix(".exe").unwrap_or(api_key);
-    //     let api_key = api_key.split(' ').next().unwrap_or(api_key); // removes any accidental multi-file downloads (1) etc.
-    //     log::info!("Extracted API key from binary name: {api_key}");
-    //     let new_exe_name = exe.with_file_name("pitsu.exe");
-    //     std::fs::rename(exe, new_exe_name).expect("Failed to rename executable");
-    //     // std::env::set_var("PITSU_API_KEY", api_key.trim());
-    //     unsafe {
-    //         SET_API_KEY = Some(api_key.to_string());
-    //     }
-    // }
+    if let Some(code) = open.and_then(pitsu_lib::invite_code) {
+        match redeem_invite(code) {
+            Ok(api_key) => {
+                // read by the default profile when this is the first launch
+                INVITE_API_KEY.set(api_key.clone()).ok();
+                // an existing install picked up a new invite, make sure it's the profile we start on
+                if let Err(e) = CONFIG.use_invite_api_key(api_key) {
+                    log::error!("Failed to apply API key from invite: {e}");
+                }
+            }
+            Err(e) => {
+                log::error!("Failed to redeem invite: {e}");
+                dialogue::rfd_ok_dialogue(&format!("Failed to redeem the invite link:\n{e}")).ok();
+            }
+        }
+    }
+    CONFIG.remember_connection();
     unsafe {
         std::env::set_var("SEQ_API_KEY", env!("LOCAL_SEQ_API_KEY"));
         std::env::set_var("SEQ_API_URL", env!("SEQ_API_URL"));
     }
     datalust_logger::init(&format!("PITSU <{}>", CONFIG.uuid())).expect("Failed to initialize logger");
+    // a binary downloaded without an invite, ask for the key instead
+    if CONFIG.api_key().is_empty() {
+        match dialogue::get_api_key(PUBLIC_URL) {
+            Ok(api_key) => {
+                if let Err(e) = CONFIG.set_api_key(api_key) {
+                    log::error!("Failed to save API key: {e}");
+                }
+            }
+            Err(e) => log::error!("Failed to get API key: {e}"),
+        }
+    }
     if CONFIG.api_key().is_empty() {
         log::error!("PITSU_API_KEY is not set. Please try to download again.");
         panic!("PITSU_API_KEY is not set. Please try to download again.");
@@ -50,7 +67,27 @@ pub fn setup() {
     }
 }
 
-pub static PUBLIC_URL: &str = env!("PITSU_PUBLIC_URL");
+static SAFE_MODE: AtomicBool = AtomicBool::new(false);
+// what the panic hook needs to look for an update, kept outside CONFIG so it can't be poisoned by the crash
+static CRASH_CONNECTION: Mutex<Option<CrashConnection>> = Mutex::new(None);
+
+pub fn safe_mode() -> bool {
+    SAFE_MODE.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone)]
+pub struct CrashConnection {
+    pub url: Arc<str>,
+    pub api_key: Arc<str>,
+    pub channel: UpdateChannel,
+}
+
+pub fn crash_connection() -> Option<CrashConnection> {
+    CRASH_CONNECTION.lock().ok()?.clone()
+}
+
+// the server this binary was built for, only used to create the first profile
+static PUBLIC_URL: &str = env!("PITSU_PUBLIC_URL");
 pub const MAX_PATH_LENGTH: usize = 32;
 // pub const VERSION_NUMBER: &str = env!("VERSION_NUMBER");
 
@@ -70,18 +107,8 @@ lazy_static! {
         path
     };
     pub static ref VERSION_NUMBER: VersionNumber = {
-        // serde_json::from_str(env!("VERSION_NUMBER")).unwrap_or_else(|err| {
-        //     log::error!("Failed to parse version number: {err}");
-        //     panic!("Failed to parse version number: {err}");
-        // })
-        // Parse version number from the exe manifest
-
-        VersionNumber {
-            major: env!("VERSION_MAJOR").parse().expect("Failed to parse major version"),
-            minor: env!("VERSION_MINOR").parse().expect("Failed to parse minor version"),
-            patch: env!("VERSION_PATCH").parse().expect("Failed to parse patch version"),
-            folder_hash: env!("VERSION_HASH").parse().expect("Failed to parse folder hash"),
-        }
+        // the folder hash is handed in by the server when it builds us
+        VersionNumber::parse(env!("CARGO_PKG_VERSION"), env!("VERSION_HASH")).expect("Failed to parse version number")
     };
     pub static ref CONFIG: Config = load_config().unwrap_or_else(|err| {
         log::error!("Failed to load configuration: {err}");
@@ -106,12 +133,22 @@ fn load_config() -> Result<Config> {
     }
     log::debug!("Loading configuration from: {}", config_path.display());
     let config_str = std::fs::read_to_string(&config_path)?;
-    let config = ConfigVersion::load(&config_str)?;
+    let (config, read_only) = match ConfigVersion::load(&config_str) {
+        Ok(config) => (config, false),
+        Err(e) if safe_mode() => {
+            log::error!("Failed to parse configuration, continuing in safe mode without saving: {e}");
+            (salvage_config(&config_str), true)
+        }
+        Err(e) => return Err(e),
+    };
     log::debug!("Configuration loaded successfully");
     let config = Config {
         dir: config_path,
-        user_info: UserInfo::get(config.api_key.clone()),
-
+        read_only,
+        user_info: Arc::new(Mutex::new(UserInfo::get(
+            &config.profile().url,
+            config.profile().api_key.clone(),
+        ))),
         config: Arc::new(Mutex::new(config)),
     };
     if let Err(e) = config.save() {
@@ -124,20 +161,31 @@ fn load_config() -> Result<Config> {
 #[derive(Debug, Clone)]
 pub struct Config {
     dir: PathBuf,
-    user_info: Pending<UserInfo>,
-    config: Arc<Mutex<ConfigV1>>,
+    // set when safe mode had to salvage a config that doesn't parse, so the original is left alone
+    read_only: bool,
+    // replaced when the active profile changes
+    user_info: Arc<Mutex<Pending<UserInfo>>>,
+    config: Arc<Mutex<ConfigV2>>,
 }
 
 impl Config {
     fn new(dir: PathBuf) -> Self {
-        let config = ConfigV1::default();
+        let config = ConfigV2::default();
         Config {
             dir,
-            user_info: UserInfo::get(config.api_key.clone()),
+            read_only: false,
+            user_info: Arc::new(Mutex::new(UserInfo::get(
+                &config.profile().url,
+                config.profile().api_key.clone(),
+            ))),
             config: Arc::new(Mutex::new(config)),
         }
     }
     fn save(&self) -> Result<()> {
+        if self.read_only {
+            log::warn!("Not saving configuration, it was salvaged in safe mode");
+            return Ok(());
+        }
         let config_str = toml::to_string(
             &*self
                 .config
@@ -149,25 +197,159 @@ impl Config {
     }
     pub fn api_key(&self) -> Arc<str> {
         match self.config.lock() {
-            Ok(config) => config.api_key.clone(),
+            Ok(config) => config.profile().api_key.clone(),
             Err(e) => {
                 log::error!("Failed to lock config: {e}");
                 panic!("Failed to lock config: {}", e);
             }
         }
     }
+    fn user_info(&self) -> Pending<UserInfo> {
+        self.user_info.lock().expect("Failed to lock user info").clone()
+    }
     pub fn username(&self) -> Arc<str> {
-        self.user_info
+        self.user_info()
             .wait_ready()
             .expect("Failed to wait for user info")
             .username
             .clone()
     }
     pub fn uuid(&self) -> Uuid {
-        self.user_info.wait_ready().expect("Failed to wait for user info").uuid
+        self.user_info()
+            .wait_ready()
+            .expect("Failed to wait for user info")
+            .uuid
+    }
+    pub fn public_url(&self) -> Arc<str> {
+        let config = self.config.lock().expect("Failed to lock config");
+        config.profile().url.clone()
+    }
+    // (name, url) of every profile, in order
+    pub fn profiles(&self) -> Vec<(Arc<str>, Arc<str>)> {
+        let config = self.config.lock().expect("Failed to lock config");
+        config
+            .profiles
+            .iter()
+            .map(|profile| (profile.name.clone(), profile.url.clone()))
+            .collect()
+    }
+    pub fn active_profile(&self) -> usize {
+        let config = self.config.lock().expect("Failed to lock config");
+        config.active_profile
+    }
+    // anything cached from the previous server has to be thrown away by the caller
+    pub fn set_active_profile(&self, index: usize) -> Result<()> {
+        let (url, api_key) = {
+            let mut config = self
+                .config
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock config: {}", e))?;
+            let profile = config
+                .profiles
+                .get(index)
+                .ok_or(anyhow::anyhow!("Profile {index} does not exist"))?;
+            let connection = (profile.url.clone(), profile.api_key.clone());
+            config.active_profile = index;
+            connection
+        };
+        *self.user_info.lock().expect("Failed to lock user info") = UserInfo::get(&url, api_key);
+        self.remember_connection();
+        self.save()?;
+        log::info!("Switched to profile {index} ({url})");
+        Ok(())
+    }
+    fn remember_connection(&self) {
+        let connection = match self.config.lock() {
+            Ok(config) => CrashConnection {
+                url: config.profile().url.clone(),
+                api_key: config.profile().api_key.clone(),
+                channel: config.update_channel,
+            },
+            Err(e) => {
+                log::error!("Failed to lock config: {e}");
+                return;
+            }
+        };
+        if let Ok(mut crash_connection) = CRASH_CONNECTION.lock() {
+            *crash_connection = Some(connection);
+        }
+    }
+    fn use_invite_api_key(&self, api_key: Arc<str>) -> Result<()> {
+        let existing = {
+            let config = self
+                .config
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock config: {}", e))?;
+            config
+                .profiles
+                .iter()
+                .position(|profile| &*profile.url == PUBLIC_URL && profile.api_key == api_key)
+        };
+        // the same link opened again, the user may have switched away from that profile since
+        if existing.is_some() {
+            return Ok(());
+        }
+        self.add_profile(&default_profile_name(), PUBLIC_URL, &api_key)?;
+        self.set_active_profile(self.profiles().len() - 1)
+    }
+    // fills in the active profile, for a first launch that had to ask for the key
+    fn set_api_key(&self, api_key: Arc<str>) -> Result<()> {
+        let url = {
+            let mut config = self
+                .config
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock config: {}", e))?;
+            config.profile_mut().api_key = api_key.clone();
+            config.profile().url.clone()
+        };
+        *self.user_info.lock().expect("Failed to lock user info") = UserInfo::get(&url, api_key);
+        self.remember_connection();
+        self.save()
     }
-    pub fn public_url(&self) -> &'static str {
-        PUBLIC_URL
+    pub fn add_profile(&self, name: &str, url: &str, api_key: &str) -> Result<()> {
+        let (name, url, api_key) = (name.trim(), url.trim().trim_end_matches('/'), api_key.trim());
+        if name.is_empty() || api_key.is_empty() {
+            return Err(anyhow::anyhow!("Profiles need a name and an API key"));
+        }
+        if !(url.starts_with("http://") || url.starts_with("https://")) {
+            return Err(anyhow::anyhow!("Server URL must start with http:// or https://"));
+        }
+        {
+            let mut config = self
+                .config
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock config: {}", e))?;
+            if config
+                .profiles
+                .iter()
+                .any(|profile| &*profile.url == url && &*profile.api_key == api_key)
+            {
+                return Err(anyhow::anyhow!("A profile for this server and key already exists"));
+            }
+            config
+                .profiles
+                .push(Profile::new(name.into(), url.into(), api_key.into()));
+        }
+        self.save()
+    }
+    pub fn remove_profile(&self, index: usize) -> Result<()> {
+        {
+            let mut config = self
+                .config
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock config: {}", e))?;
+            if index == config.active_profile {
+                return Err(anyhow::anyhow!("Switch to another profile before removing this one"));
+            }
+            if index >= config.profiles.len() {
+                return Err(anyhow::anyhow!("Profile {index} does not exist"));
+            }
+            config.profiles.remove(index);
+            if config.active_profile > index {
+                config.active_profile -= 1;
+            }
+        }
+        self.save()
     }
     pub fn get_stored(&self, uuid: Uuid) -> Result<Option<Arc<LocalRepository>>> {
         let path = {
@@ -175,7 +357,7 @@ impl Config {
                 .config
                 .lock()
                 .map_err(|e| anyhow::anyhow!("Failed to lock config: {}", e))?;
-            match config.stored_repositories.get(&uuid) {
+            match config.profile().stored_repositories.get(&uuid) {
                 Some(repo) => repo.path.clone(),
                 None => return Ok(None),
             }
@@ -193,7 +375,7 @@ impl Config {
                 .config
                 .lock()
                 .map_err(|e| anyhow::anyhow!("Failed to lock config: {}", e))?;
-            match config.stored_repositories.get(&uuid) {
+            match config.profile().stored_repositories.get(&uuid) {
                 Some(repo) => repo.overrides.clone(),
                 None => Pitignore::default(),
             }
@@ -207,22 +389,185 @@ impl Config {
         Ok(Some(Arc::new(local_repo)))
     }
     pub fn add_stored(&self, uuid: Uuid, path: PathBuf) -> Result<()> {
-        let stored_repo = Arc::new(StoredRepository {
-            uuid,
-            path,
-            overrides: Pitignore::default(),
-        });
-        {
+        let stored_repo = {
             let mut config = self
                 .config
                 .lock()
                 .map_err(|e| anyhow::anyhow!("Failed to lock config: {}", e))?;
-            config.stored_repositories.insert(uuid, stored_repo.clone());
-        }
+            // keep personal overrides when the path is changed
+            let overrides = config
+                .profile()
+                .stored_repositories
+                .get(&uuid)
+                .map(|repo| repo.overrides.clone())
+                .unwrap_or_default();
+            let stored_repo = Arc::new(StoredRepository { uuid, path, overrides });
+            config
+                .profile_mut()
+                .stored_repositories
+                .insert(uuid, stored_repo.clone());
+            stored_repo
+        };
         self.save()?;
         log::info!("Stored repository added: {}", stored_repo.path.display());
         Ok(())
     }
+    pub fn set_overrides(&self, uuid: Uuid, overrides: Pitignore) -> Result<()> {
+        {
+            let mut config = self
+                .config
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock config: {}", e))?;
+            let stored_repo = config
+                .profile_mut()
+                .stored_repositories
+                .get_mut(&uuid)
+                .ok_or(anyhow::anyhow!("Repository {uuid} has no local path set"))?;
+            Arc::make_mut(stored_repo).overrides = overrides;
+        }
+        self.save()
+    }
+    pub fn alias(&self, uuid: Uuid) -> Option<RepositoryAlias> {
+        let config = self.config.lock().expect("Failed to lock config");
+        config.profile().aliases.get(&uuid).cloned()
+    }
+    // None removes the alias
+    pub fn set_alias(&self, uuid: Uuid, alias: Option<RepositoryAlias>) -> Result<()> {
+        {
+            let mut config = self
+                .config
+                .lock()
+                .map_err(|e| anyhow::anyhow!("Failed to lock config: {}", e))?;
+            match alias {
+                Some(alias) => {
+                    let name = alias.name.trim();
+                    if name.is_empty() {
+                        return Err(anyhow::anyhow!("Alias can't be empty"));
+                    }
+                    if Uuid::parse_str(name).is_ok() {
+                        return Err(anyhow::anyhow!("Alias can't be a UUID"));
+                    }
+                    if let Some((other, _)) = config
+                        .profile()
+                        .aliases
+                        .iter()
+                        .find(|(other, a)| **other != uuid && a.name.eq_ignore_ascii_case(name))
+                    {
+                        return Err(anyhow::anyhow!("{name} is already the alias of {other}"));
+                    }
+                    config.profile_mut().aliases.insert(
+                        uuid,
+                        RepositoryAlias {
+                            name: name.into(),
+                            color: alias.color,
+                        },
+                    );
+                }
+                None => {
+                    config.profile_mut().aliases.remove(&uuid);
+                }
+            }
+        }
+        self.save()
+    }
+    // accepts a UUID, an alias (case insensitive) or either as a pitsu:// link
+    pub fn resolve_repository(&self, name: &str) -> Result<Uuid> {
+        let name = name.trim().trim_start_matches("pitsu://").trim_matches('/');
+        if let Ok(uuid) = Uuid::parse_str(name) {
+            return Ok(uuid);
+        }
+        let config = self
+            .config
+            .lock()
+            .map_err(|e| anyhow::anyhow!("Failed to lock config: {}", e))?;
+        config
+            .profile()
+            .aliases
+            .iter()
+            .find(|(_, alias)| alias.name.eq_ignore_ascii_case(name))
+            .map(|(uuid, _)| *uuid)
+            .ok_or(anyhow::anyhow!("No repository with the UUID or alias {name}"))
+    }
+    pub fn trash_retention_days(&self) -> u64 {
+        let config = self.config.lock().expect("Failed to lock config");
+        config.trash_retention_days
+    }
+    pub fn set_trash_retention_days(&self, days: u64) {
+        {
+            let mut config = self.config.lock().expect("Failed to lock config");
+            config.trash_retention_days = days;
+        }
+        if let Err(e) = self.save() {
+            log::error!("Failed to save configuration after changing trash retention: {e}");
+        }
+    }
+    pub fn upload_limit(&self) -> u64 {
+        let config = self.config.lock().expect("Failed to lock config");
+        config.upload_limit
+    }
+    pub fn set_upload_limit(&self, limit: u64) {
+        {
+            let mut config = self.config.lock().expect("Failed to lock config");
+            config.upload_limit = limit;
+        }
+        if let Err(e) = self.save() {
+            log::error!("Failed to save configuration after changing upload limit: {e}");
+        }
+    }
+    pub fn download_limit(&self) -> u64 {
+        let config = self.config.lock().expect("Failed to lock config");
+        config.download_limit
+    }
+    pub fn set_download_limit(&self, limit: u64) {
+        {
+            let mut config = self.config.lock().expect("Failed to lock config");
+            config.download_limit = limit;
+        }
+        if let Err(e) = self.save() {
+            log::error!("Failed to save configuration after changing download limit: {e}");
+        }
+    }
+    pub fn pause_when_unfocused(&self) -> bool {
+        let config = self.config.lock().expect("Failed to lock config");
+        config.pause_when_unfocused
+    }
+    pub fn set_pause_when_unfocused(&self, pause: bool) {
+        {
+            let mut config = self.config.lock().expect("Failed to lock config");
+            config.pause_when_unfocused = pause;
+        }
+        if let Err(e) = self.save() {
+            log::error!("Failed to save configuration after toggling pause when unfocused: {e}");
+        }
+    }
+    pub fn update_channel(&self) -> UpdateChannel {
+        let config = self.config.lock().expect("Failed to lock config");
+        config.update_channel
+    }
+    pub fn set_update_channel(&self, channel: UpdateChannel) {
+        {
+            let mut config = self.config.lock().expect("Failed to lock config");
+            config.update_channel = channel;
+        }
+        self.remember_connection();
+        if let Err(e) = self.save() {
+            log::error!("Failed to save configuration after changing update channel: {e}");
+        }
+    }
+    pub fn compression(&self) -> (TransferEncoding, i32) {
+        let config = self.config.lock().expect("Failed to lock config");
+        (config.compression, config.compression_level)
+    }
+    pub fn set_compression(&self, encoding: TransferEncoding, level: i32) {
+        {
+            let mut config = self.config.lock().expect("Failed to lock config");
+            config.compression = encoding;
+            config.compression_level = level;
+        }
+        if let Err(e) = self.save() {
+            log::error!("Failed to save configuration after changing compression: {e}");
+        }
+    }
     pub fn skip_confirmation(&self) -> bool {
         let config = self
             .config
@@ -245,15 +590,21 @@ impl Config {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 enum ConfigVersion {
+    // newest first, untagged tries them in order
+    V2(ConfigV2),
     V1(ConfigV1),
 }
 
 impl ConfigVersion {
-    fn load(config_str: &str) -> Result<ConfigV1> {
+    fn load(config_str: &str) -> Result<ConfigV2> {
         let config: ConfigVersion = toml::from_str(config_str)?;
 
         match config {
-            ConfigVersion::V1(v1) => Ok(v1),
+            ConfigVersion::V2(v2) => Ok(v2.validated()),
+            ConfigVersion::V1(v1) => {
+                log::info!("Migrating configuration from V1 to V2");
+                Ok(v1.into())
+            }
         }
     }
 }
@@ -266,18 +617,188 @@ struct ConfigV1 {
     stored_repositories: HashMap<Uuid, Arc<StoredRepository>>,
     #[serde(default)]
     skip_confirmation: bool,
+    #[serde(default = "default_trash_retention_days")]
+    trash_retention_days: u64,
+    // KiB/s, 0 is unlimited
+    #[serde(default)]
+    upload_limit: u64,
+    #[serde(default)]
+    download_limit: u64,
+    #[serde(default)]
+    pause_when_unfocused: bool,
+    #[serde(default)]
+    aliases: HashMap<Uuid, RepositoryAlias>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ConfigV2 {
+    profiles: Vec<Profile>,
+    #[serde(default)]
+    active_profile: usize,
+    #[serde(default)]
+    skip_confirmation: bool,
+    #[serde(default = "default_trash_retention_days")]
+    trash_retention_days: u64,
+    // KiB/s, 0 is unlimited
+    #[serde(default)]
+    upload_limit: u64,
+    #[serde(default)]
+    download_limit: u64,
+    #[serde(default)]
+    pause_when_unfocused: bool,
+    #[serde(default)]
+    update_channel: UpdateChannel,
+    #[serde(default)]
+    compression: TransferEncoding,
+    // clamped to whatever range the encoding supports
+    #[serde(default = "default_compression_level")]
+    compression_level: i32,
+}
+
+fn default_compression_level() -> i32 {
+    TransferEncoding::default().default_level()
+}
+
+impl ConfigV2 {
+    // a hand edited config can leave no profiles or point past them, everything else relies on there being an active one
+    fn validated(mut self) -> Self {
+        if self.profiles.is_empty() {
+            log::warn!("Configuration has no profiles, adding one for {PUBLIC_URL}");
+            self.profiles
+                .push(Profile::new(default_profile_name(), PUBLIC_URL.into(), get_api_key()));
+        }
+        if self.active_profile >= self.profiles.len() {
+            log::warn!(
+                "Active profile {} does not exist, using the first one",
+                self.active_profile
+            );
+            self.active_profile = 0;
+        }
+        self
+    }
+    // validated() on load and remove_profile refusing the active one keep this from ever being empty
+    fn profile(&self) -> &Profile {
+        &self.profiles[self.active_profile]
+    }
+    fn profile_mut(&mut self) -> &mut Profile {
+        &mut self.profiles[self.active_profile]
+    }
 }
 
-impl Default for ConfigV1 {
+impl Default for ConfigV2 {
     fn default() -> Self {
-        ConfigV1 {
-            api_key: get_api_key(),
-            stored_repositories: HashMap::new(),
+        ConfigV2 {
+            profiles: vec![Profile::new(default_profile_name(), PUBLIC_URL.into(), get_api_key())],
+            active_profile: 0,
             skip_confirmation: false,
+            trash_retention_days: default_trash_retention_days(),
+            upload_limit: 0,
+            download_limit: 0,
+            pause_when_unfocused: false,
+            update_channel: UpdateChannel::default(),
+            compression: TransferEncoding::default(),
+            compression_level: default_compression_level(),
         }
     }
 }
 
+impl From<ConfigV1> for ConfigV2 {
+    fn from(v1: ConfigV1) -> Self {
+        ConfigV2 {
+            profiles: vec![Profile {
+                name: default_profile_name(),
+                url: PUBLIC_URL.into(),
+                api_key: v1.api_key,
+                stored_repositories: v1.stored_repositories,
+                aliases: v1.aliases,
+            }],
+            active_profile: 0,
+            skip_confirmation: v1.skip_confirmation,
+            trash_retention_days: v1.trash_retention_days,
+            upload_limit: v1.upload_limit,
+            download_limit: v1.download_limit,
+            pause_when_unfocused: v1.pause_when_unfocused,
+            update_channel: UpdateChannel::default(),
+            compression: TransferEncoding::default(),
+            compression_level: default_compression_level(),
+        }
+    }
+}
+
+// one server and the key used on it, repositories are stored per profile since uuids only mean something on their own server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Profile {
+    name: Arc<str>,
+    url: Arc<str>,
+    api_key: Arc<str>,
+    #[serde(default)]
+    stored_repositories: HashMap<Uuid, Arc<StoredRepository>>,
+    // personal nicknames, kept apart from stored_repositories so repositories that aren't downloaded can have one too
+    #[serde(default)]
+    aliases: HashMap<Uuid, RepositoryAlias>,
+}
+
+impl Profile {
+    fn new(name: Arc<str>, url: Arc<str>, api_key: Arc<str>) -> Self {
+        Profile {
+            name,
+            url,
+            api_key,
+            stored_repositories: HashMap::new(),
+            aliases: HashMap::new(),
+        }
+    }
+}
+
+// safe mode only, pulls whatever server connections can still be read out of a config that no longer parses
+fn salvage_config(config_str: &str) -> ConfigV2 {
+    let mut config = ConfigV2::default();
+    let Ok(table) = toml::from_str::<toml::Table>(config_str) else {
+        return config;
+    };
+    let string = |table: &toml::Table, key: &str| table.get(key).and_then(|value| value.as_str()).map(Arc::<str>::from);
+    let profiles = table
+        .get("profiles")
+        .and_then(|profiles| profiles.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|profile| profile.as_table())
+        .filter_map(|profile| {
+            Some(Profile::new(
+                string(profile, "name").unwrap_or_else(default_profile_name),
+                string(profile, "url")?,
+                string(profile, "api_key")?,
+            ))
+        })
+        .collect::<Vec<_>>();
+    if !profiles.is_empty() {
+        config.active_profile = table
+            .get("active_profile")
+            .and_then(|index| index.as_integer())
+            .and_then(|index| usize::try_from(index).ok())
+            .filter(|index| *index < profiles.len())
+            .unwrap_or(0);
+        config.profiles = profiles;
+    } else if let Some(api_key) = string(&table, "api_key") {
+        // V1
+        config.profile_mut().api_key = api_key;
+    }
+    config
+}
+
+fn default_profile_name() -> Arc<str> {
+    PUBLIC_URL
+        .split("://")
+        .last()
+        .unwrap_or(PUBLIC_URL)
+        .trim_end_matches('/')
+        .into()
+}
+
+fn default_trash_retention_days() -> u64 {
+    30
+}
+
 fn arc_str_empty(s: &Arc<str>) -> bool {
     s.is_empty()
 }
@@ -287,29 +808,24 @@ fn arc_str_empty(s: &Arc<str>) -> bool {
 //     resp.trim().to_string().into()
 // }
 
-pub static mut SET_API_KEY: Option<String> = None;
+static INVITE_API_KEY: OnceLock<Arc<str>> = OnceLock::new();
 
-#[inline(never)]
 fn get_api_key() -> Arc<str> {
-    // Arc::from(env!("PITSU_API_KEY_PLACEHOLDER"))
-    // if let Some(val) = unsafe {
-    //     #[allow(static_mut_refs)]
-    //     SET_API_KEY.clone()
-    // } {
-    //     log::info!("Using API key from environment variable");
-    //     Arc::from(val)
-    // } else {
-    //     log::info!("No API key found in binary name");
-    //     dialogue::get_api_key()
-    //         .expect("Failed to get API key from user")
-    //         .trim()
-    //         .to_string()
-    //         .into()
-    // }
-    "________________________________PITSU_API_KEY_PLACEHOLDER________________________________"
-        .trim_matches('_')
-        .to_string()
-        .into()
+    INVITE_API_KEY.get().cloned().unwrap_or_else(|| Arc::from(""))
+}
+
+// invite links carry a code rather than the key, the server this binary was built for trades one for the other
+fn redeem_invite(code: &str) -> Result<Arc<str>> {
+    let api_key = Client::new(PUBLIC_URL, "").call(RedeemInvite { code: code.into() })?;
+    log::info!("Redeemed invite link");
+    Ok(api_key.trim().into())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RepositoryAlias {
+    pub name: Arc<str>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<[u8; 3]>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -353,57 +869,47 @@ pub struct UserInfo {
     username: Arc<str>,
 }
 
-impl<'de> Deserialize<'de> for UserInfo {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let inner = ThisUser::deserialize(deserializer)?;
-        Ok(UserInfo {
+impl From<ThisUser> for UserInfo {
+    fn from(inner: ThisUser) -> Self {
+        UserInfo {
             uuid: inner.user.uuid,
             username: inner.user.username,
-        })
+        }
     }
 }
 
 impl UserInfo {
-    pub fn get(api_key: Arc<str>) -> Pending<Self> {
-        let mut request = Request::get(format!("{PUBLIC_URL}/api/user"));
-        request
-            .headers
-            .insert("Authorization", format!("Bearer {}", api_key.as_ref()));
-        Pending::<UserInfo>::new(request)
+    pub fn get(url: &str, api_key: Arc<str>) -> Pending<Self> {
+        Pending::new(&Client::new(url, &api_key), GetSelf)
     }
 }
 
+// the client for whichever profile is active right now
+pub fn client() -> Client {
+    Client::new(&CONFIG.public_url(), &CONFIG.api_key())
+}
+
 type TResult<T> = Result<Arc<T>, Arc<anyhow::Error>>;
 #[derive(Debug, Clone)]
-pub struct Pending<T: Debug + DeserializeOwned + Send + Sync + 'static> {
+pub struct Pending<T: Debug + Send + Sync + 'static> {
     channel: Arc<Mutex<std::sync::mpsc::Receiver<TResult<T>>>>,
     value: Arc<Mutex<Option<TResult<T>>>>,
 }
 
-impl<T: Debug + DeserializeOwned + Send + Sync + 'static> Pending<T> {
-    pub fn new(request: Request) -> Self {
+impl<T: Debug + Send + Sync + 'static> Pending<T> {
+    pub fn new<E: Endpoint>(client: &Client, endpoint: E) -> Self
+    where
+        T: From<E::Response>,
+    {
         let (sender, receiver) = std::sync::mpsc::channel::<TResult<T>>();
-        fetch(request, move |response| {
-            if let Err(err) = response {
-                if let Err(e) = sender.send(Err(Arc::new(anyhow::anyhow!(err)))) {
-                    log::error!("Failed to send error through channel: {e}");
+        client.fetch(endpoint, move |response| match response {
+            Ok(value) => {
+                if let Err(e) = sender.send(Ok(Arc::new(T::from(value)))) {
+                    log::error!("Failed to send value through channel: {e}");
                 }
-            } else if let Ok(response) = response {
-                if !(200..=299).contains(&response.status) {
-                    if let Err(e) = sender.send(Err(Arc::new(anyhow::anyhow!(
-                        "Request failed with status: {}",
-                        response.status
-                    )))) {
-                        log::error!("Failed to send error through channel: {e}");
-                    }
-                } else if let Ok(value) = response.json::<T>() {
-                    if let Err(e) = sender.send(Ok(Arc::new(value))) {
-                        log::error!("Failed to send value through channel: {e}");
-                    }
-                } else if let Err(e) = sender.send(Err(Arc::new(anyhow::anyhow!("Failed to parse response as JSON")))) {
+            }
+            Err(err) => {
+                if let Err(e) = sender.send(Err(Arc::new(err))) {
                     log::error!("Failed to send error through channel: {e}");
                 }
             }
@@ -499,37 +1005,16 @@ pub mod icons {
     }
 }
 
-pub fn get_request(url: &str) -> Request {
-    let mut request = Request::get(url);
-    request
-        .headers
-        .insert("Authorization", format!("Bearer {}", CONFIG.api_key()));
-    request
-}
-
-pub fn post_request(url: &str, body: Value) -> Request {
-    let mut request: Request = Request::json(url, &body).expect("Failed to create JSON request");
-    request
-        .headers
-        .insert("Authorization", format!("Bearer {}", CONFIG.api_key()));
-    request.method = "POST".to_string();
-    request
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-pub fn delete_request(url: &str) -> Request {
-    let mut request = Request::get(url);
-    request.method = "DELETE".to_string();
-    request
-        .headers
-        .insert("Authorization", format!("Bearer {}", CONFIG.api_key()));
-    request
-}
-
-pub fn delete_request_with_body(url: &str, body: Value) -> Request {
-    let mut request = Request::json(url, &body).expect("Failed to create JSON request");
-    request.method = "DELETE".to_string();
-    request
-        .headers
-        .insert("Authorization", format!("Bearer {}", CONFIG.api_key()));
-    request
+    #[test]
+    fn test_empty_profiles_fall_back_to_default() -> Result<()> {
+        let config = ConfigVersion::load("profiles = []\nactive_profile = 3\n")?;
+        assert_eq!(config.profiles.len(), 1);
+        assert_eq!(config.active_profile, 0);
+        assert_eq!(&*config.profile().url, PUBLIC_URL);
+        Ok(())
+    }
 }
@@ -3,11 +3,12 @@ use std::sync::Arc;
 use anyhow::Result;
 use clipboard_rs::Clipboard as _;
 use eframe::{
-    egui::{self, mutex::Mutex, ViewportBuilder},
     NativeOptions,
+    egui::{self, ViewportBuilder, mutex::Mutex},
 };
+use pitsu_lib::api::{Client, RedeemInvite};
 
-use crate::config::UserInfo;
+use crate::config::{Pending, UserInfo};
 
 pub fn rfd_confirm_response(query: &str, skip: bool) -> Result<bool> {
     if skip {
@@ -34,8 +35,7 @@ pub fn rfd_ok_dialogue(query: &str) -> Result<()> {
     Ok(())
 }
 
-#[allow(unused)]
-pub fn get_api_key() -> Result<Arc<str>> {
+pub fn get_api_key(url: &str) -> Result<Arc<str>> {
     let api_key = Arc::new(Mutex::new(String::new()));
     let user_info = Arc::new(Mutex::new(None));
     let api_query = Arc::new(Mutex::new(None));
@@ -43,9 +43,12 @@ pub fn get_api_key() -> Result<Arc<str>> {
         let api_key = Arc::clone(&api_key);
         let api_query = Arc::clone(&api_query);
         let user_info = Arc::clone(&user_info);
+        let url = url.to_string();
         let interval = std::time::Duration::from_millis(100);
         let mut last_check = std::time::Instant::now();
         let mut error = None;
+        // an invite link pasted instead of a key, traded for the key before that is checked
+        let mut redeem: Option<Pending<Arc<str>>> = None;
         eframe::run_simple_native(
             "Enter API Key",
             NativeOptions {
@@ -59,20 +62,42 @@ pub fn get_api_key() -> Result<Arc<str>> {
                 egui::CentralPanel::default().show(ctx, |ui| {
                     let query_is_some = {
                         let api_query = api_query.lock();
-                        api_query.is_some()
+                        api_query.is_some() || redeem.is_some()
                     };
                     let mut api_key = api_key.lock();
                     ui.horizontal(|ui| {
-                        ui.add_enabled(!query_is_some, egui::TextEdit::singleline(&mut *api_key));
+                        ui.add_enabled(
+                            !query_is_some,
+                            egui::TextEdit::singleline(&mut *api_key).hint_text("API key or invite link"),
+                        );
                         if ui.add_enabled(!query_is_some, egui::Button::new("Submit")).clicked() {
-                            let mut query = api_query.lock();
-                            *query = Some(UserInfo::get(api_key.clone().into()));
+                            match pitsu_lib::invite_code(&api_key) {
+                                Some(code) => {
+                                    let invite = RedeemInvite { code: code.into() };
+                                    redeem = Some(Pending::new(&Client::new(&url, ""), invite));
+                                }
+                                None => *api_query.lock() = Some(UserInfo::get(&url, api_key.clone().into())),
+                            }
                         }
                         if query_is_some {
                             ui.spinner();
                         }
                         if last_check.elapsed() >= interval {
                             last_check = std::time::Instant::now();
+                            if let Some(pending) = &mut redeem {
+                                match pending.try_ready() {
+                                    Ok(Some(key)) => {
+                                        *api_key = key.to_string();
+                                        *api_query.lock() = Some(UserInfo::get(&url, (*key).clone()));
+                                        redeem = None;
+                                    }
+                                    Err(e) => {
+                                        error = Some(format!("Failed to redeem invite: {e}"));
+                                        redeem = None;
+                                    }
+                                    Ok(None) => {}
+                                }
+                            }
                             let mut query = api_query.lock();
                             if let Some(pending) = &mut *query {
                                 match pending.try_ready() {
@@ -114,9 +139,9 @@ pub fn get_api_key() -> Result<Arc<str>> {
 }
 
 pub fn rfd_panic_dialogue(info: &std::panic::PanicHookInfo) {
+    // no CONFIG in here, the panic may have come from loading it. the logger is already tagged with the user's uuid
     log::error!(
-        "{} PANIC:\n{}",
-        crate::config::CONFIG.username(),
+        "PANIC:\n{}",
         // info.payload().downcast_ref::<&str>().unwrap_or(&"No payload"),
         info
     );
@@ -144,6 +169,45 @@ pub fn rfd_panic_dialogue(info: &std::panic::PanicHookInfo) {
             eprintln!("Failed to copy text to clipboard: {e}");
         }
     }
+
+    // an update that crashes before it got healthy goes back to the previous version now rather than on the next launch
+    match crate::update::rollback_after_crash() {
+        Ok(true) => {
+            rfd_ok_dialogue("Pitsu crashed right after updating and has been rolled back to the previous version.")
+                .ok();
+            match std::env::current_exe().and_then(|exe| std::process::Command::new(exe).spawn()) {
+                Ok(_) => std::process::exit(1),
+                Err(e) => eprintln!("Failed to restart after rolling back: {e}"),
+            }
+        }
+        Ok(false) => {}
+        Err(e) => log::error!("Failed to roll back update after panicking: {e}"),
+    }
+    // the fix may already be out, and if it isn't safe mode still gets the user to the update button
+    match crate::update::offer_after_crash() {
+        Ok(true) => {}
+        Ok(false) => offer_safe_mode(),
+        Err(e) => {
+            log::error!("Failed to offer an update after panicking: {e}");
+            offer_safe_mode();
+        }
+    }
+}
+
+fn offer_safe_mode() {
+    if crate::config::safe_mode() {
+        return;
+    }
+    let restart = rfd_confirm_response(
+        "Would you like to restart PITSU in safe mode?\n\nSafe mode doesn't load your downloaded repositories, so you can still reach settings and updates.",
+        false,
+    );
+    if let Ok(true) = restart {
+        match std::env::current_exe().and_then(|exe| std::process::Command::new(exe).arg("--safe-mode").spawn()) {
+            Ok(_) => std::process::exit(1),
+            Err(e) => eprintln!("Failed to restart in safe mode: {e}"),
+        }
+    }
 }
 
 // pub mod in_thread {
@@ -1,12 +1,14 @@
 #![warn(clippy::todo)]
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 use colors_transform::Color;
 use eframe::egui::{self, FontData, Id};
-use pitsu_lib::{AccessLevel, ChangeType, Diff, Pitignore, UserWithAccess};
-use self_update::self_replace;
+use pitsu_lib::{
+    AccessLevel, ArchiveFormat, ChangeType, Diff, FileOnDisk, LaunchTarget, Pitexecute, Pitignore, RemoteRepository,
+    SyncPlan, TransferEncoding, UpdateChannel, UpdateRemoteRepository, UserWithAccess,
+};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -15,17 +17,62 @@ use crate::{
     double_progress_bar::DoubleProgressBar,
 };
 mod cache;
+mod cli;
 mod config;
 mod dialogue;
 mod double_progress_bar;
 mod nerdfonts;
+mod throttle;
+mod trash;
+mod update;
+mod url_scheme;
 
 // list of safely openable file extensions, non executable
 const OPENABLE_FILE_TYPES: &[&str] = &["txt", "md", "toml", "yaml", "json", "cfg", "ini", "me3"];
 static mut DIM_FACTOR: f32 = 0.6;
 
 fn main() -> anyhow::Result<()> {
-    config::setup();
+    match update::check_pending() {
+        Ok(true) => {
+            dialogue::rfd_ok_dialogue(
+                "Pitsu failed to start after the last update and has been rolled back to the previous version.",
+            )
+            .ok();
+            std::process::Command::new(std::env::current_exe()?).spawn()?;
+            return Ok(());
+        }
+        Ok(false) => {}
+        Err(e) => eprintln!("Failed to check pending update: {e}"),
+    }
+    let cli = cli::parse();
+    config::setup(cli.safe_mode, cli.open.as_deref());
+    if let Some(command) = cli.command {
+        // the build got this far, a command failing says nothing about the update
+        update::mark_healthy();
+        command.run()?;
+        return Ok(());
+    }
+    let safe_mode = config::safe_mode();
+    let open = cli
+        .open
+        // invite links were already redeemed by setup
+        .filter(|open| !safe_mode && pitsu_lib::invite_code(open).is_none())
+        .and_then(|repo| match CONFIG.resolve_repository(&repo) {
+            Ok(uuid) => Some(uuid),
+            Err(e) => {
+                log::error!("Failed to open {repo}: {e}");
+                dialogue::rfd_ok_dialogue(&format!("Failed to open {repo}:\n{e}")).ok();
+                None
+            }
+        });
+    if !safe_mode {
+        std::thread::spawn(|| {
+            if let Err(e) = trash::purge_expired(CONFIG.trash_retention_days()) {
+                log::warn!("Failed to purge expired trash: {e}");
+            }
+        });
+    }
+    std::thread::spawn(url_scheme::register);
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder {
             icon: Some(Arc::clone(&config::icons::WINDOW_ICON)),
@@ -67,6 +114,13 @@ fn main() -> anyhow::Result<()> {
             Ok(Box::new(App {
                 ppp,
                 sort: sort_states,
+                state: match open {
+                    Some(uuid) => AppState::RepositoryDetails {
+                        uuid,
+                        hover_state: HoverType::None,
+                    },
+                    None => AppState::Main,
+                },
                 ..Default::default()
             }))
         }),
@@ -80,11 +134,71 @@ fn main() -> anyhow::Result<()> {
 #[derive(Debug, Clone)]
 pub struct Repository {
     local: Arc<LocalRepository>,
-    // remote: Arc<RemoteRepository>,
+    remote: Arc<RemoteRepository>,
     local_pitignore_diff: Arc<[Diff]>,
     remote_pitignore_diff: Arc<[Diff]>,
     local_pitignore: Arc<Pitignore>,
     remote_pitignore: Arc<Pitignore>,
+    launch_targets: Arc<[LaunchTarget]>,
+}
+
+impl Repository {
+    pub fn new(local: Arc<LocalRepository>, remote: Arc<RemoteRepository>) -> anyhow::Result<Self> {
+        // let mut diff = remote.files.diff(&local.folder);
+        let diff = Arc::from(local.folder.diff(&remote.files));
+        let pitignore = Pitignore::from_repository(local.path.clone())?;
+        // personal overrides only ever narrow what gets synced, on top of whichever .pitignore applies
+        let local_pitignore_diff = local.overrides.apply_patterns(&pitignore.apply_patterns(&diff));
+        let remote_pitignore_diff = local.overrides.apply_patterns(&remote.pitignore.apply_patterns(&diff));
+        let launch_targets = Pitexecute::from_repository(local.path.clone())?
+            .launch_targets(&local.folder)
+            .into();
+        Ok(Self {
+            launch_targets,
+            local,
+            remote_pitignore: Arc::from(remote.pitignore.clone()),
+            remote,
+            local_pitignore_diff,
+            remote_pitignore_diff,
+            local_pitignore: Arc::from(pitignore),
+        })
+    }
+    pub fn plan(&self, upload: bool) -> SyncPlan {
+        let diffs = if upload {
+            &self.local_pitignore_diff
+        } else {
+            &self.remote_pitignore_diff
+        };
+        SyncPlan::new(diffs, upload, &self.local.folder, &self.remote.files)
+    }
+    // None when the target can be launched, otherwise the reason it can't
+    pub fn launch_blocked(&self, target: &LaunchTarget) -> Option<&'static str> {
+        let unsynced = |path: &str| {
+            self.local_pitignore_diff
+                .iter()
+                .chain(self.remote_pitignore_diff.iter())
+                .any(|diff| diff.change_type.is_on_client() && diff.full_path.trim_start_matches('/') == path)
+        };
+        if unsynced(".pitexecute") {
+            Some(".pitexecute has local changes, sync before launching anything")
+        } else if unsynced(&target.full_path) {
+            Some("This file has local changes, sync before launching it")
+        } else {
+            None
+        }
+    }
+    pub fn launch(&self, target: &LaunchTarget) -> anyhow::Result<()> {
+        if let Some(reason) = self.launch_blocked(target) {
+            return Err(anyhow::anyhow!(reason));
+        }
+        let root = &self.local.path;
+        log::info!("Launching {} {:?}", target.full_path, target.arguments);
+        std::process::Command::new(root.join(&*target.full_path))
+            .args(target.arguments.iter())
+            .current_dir(root.join(&*target.working_directory))
+            .spawn()?;
+        Ok(())
+    }
 }
 
 pub struct App {
@@ -94,10 +208,21 @@ pub struct App {
     state_stack: Vec<AppState>,
     sort: SortStates,
     edit_pitignore: Option<(Pitignore, EditState, bool)>,
+    selective_sync: Option<SelectiveSync>,
+    recently_removed: Option<(Uuid, Vec<trash::TrashedSync>)>,
     add_user_text: String,
     add_user_modal: bool,
+    // (repository, name, template being edited)
+    edit_recommended_path: Option<(Uuid, Arc<str>, String)>,
+    // (repository, alias, colour) being edited
+    edit_alias: Option<(Uuid, String, Option<[u8; 3]>)>,
+    repository_filter: String,
+    // (name, url, api key) for the profile being added in settings
+    new_profile: (String, String, String),
     updating: bool,
     skip_confirmation: bool,
+    // started with --safe-mode, downloaded repositories are never loaded
+    safe_mode: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -106,6 +231,8 @@ pub enum AppState {
     Settings,
     RepositoryDetails { uuid: Uuid, hover_state: HoverType },
     EditPitignore { uuid: Uuid },
+    SelectiveSync { uuid: Uuid },
+    RecentlyRemoved { uuid: Uuid },
     CreateRepository,
 }
 
@@ -115,24 +242,72 @@ pub enum EditState {
     EditingPattern { index: usize },
 }
 
+// working copy for AppState::SelectiveSync, the tree is built once so toggling doesn't wait on a repository reload
+pub struct SelectiveSync {
+    uuid: Uuid,
+    tree: SelectionTree,
+    overrides: Pitignore,
+}
+
+#[derive(Default)]
+struct SelectionTree {
+    folders: BTreeMap<Arc<str>, SelectionTree>,
+    files: BTreeMap<Arc<str>, u64>,
+}
+
+impl SelectionTree {
+    fn new(files: impl IntoIterator<Item = FileOnDisk>) -> Self {
+        let mut tree = Self::default();
+        for file in files {
+            let mut node = &mut tree;
+            let mut parts = file.full_path.split('/').peekable();
+            while let Some(part) = parts.next() {
+                if parts.peek().is_none() {
+                    node.files.insert(part.into(), file.size);
+                } else {
+                    node = node.folders.entry(part.into()).or_default();
+                }
+            }
+        }
+        tree
+    }
+}
+
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         self.skip_confirmation = ctx.input(|i| i.modifiers.shift || CONFIG.skip_confirmation());
+        throttle::set_focused(ctx.input(|i| i.focused));
+        if ctx.input(|i| i.time) > update::HEALTHY_AFTER {
+            update::mark_healthy();
+        } else {
+            ctx.request_repaint_after(std::time::Duration::from_secs_f64(update::HEALTHY_AFTER));
+        }
         match self.long_running.remote_update_bytes(true) {
-            Ok(Some(bytes)) => {
-                self_update(bytes.to_vec()).expect(
-                    "Failed to update Pitsu, If missing permissions try running as administrator before updating.",
-                );
-                std::process::Command::new(std::env::current_exe().expect("Failed to get current executable path"))
-                    .spawn()
-                    .expect("Failed to spawn new Pitsu process");
-                std::process::exit(0);
-            }
+            Ok(Some((bytes, signature))) => match update::apply(&bytes, &signature) {
+                Ok(()) => {
+                    std::process::Command::new(std::env::current_exe().expect("Failed to get current executable path"))
+                        .spawn()
+                        .expect("Failed to spawn new Pitsu process");
+                    std::process::exit(0);
+                }
+                Err(e) => {
+                    log::error!("Failed to update Pitsu: {e}");
+                    dialogue::rfd_ok_dialogue(&format!(
+                        "Failed to update Pitsu: {e}\n\nIf missing permissions try running as administrator before updating."
+                    ))
+                    .ok();
+                    self.long_running.reset_update_bytes();
+                    self.updating = false;
+                }
+            },
             Ok(None) => {
                 // No update bytes available
             }
             Err(e) => {
                 log::error!("Failed to fetch update bytes: {e}");
+                dialogue::rfd_ok_dialogue(&format!("Failed to update Pitsu: {e}")).ok();
+                self.long_running.reset_update_bytes();
+                self.updating = false;
             }
         }
         if self.updating {
@@ -142,6 +317,21 @@ impl eframe::App for App {
             });
             return;
         }
+        match self.long_running.take_archive_transfer() {
+            Ok(Some((message, reload))) => {
+                if let Some(uuid) = reload {
+                    if let Err(e) = self.long_running.reload_repository(uuid) {
+                        log::error!("Failed to reload repository after archive import: {e}");
+                    }
+                }
+                dialogue::rfd_ok_dialogue(&message).ok();
+            }
+            Ok(None) => {}
+            Err(e) => {
+                log::error!("Archive transfer failed: {e}");
+                dialogue::rfd_ok_dialogue(&e).ok();
+            }
+        }
         match self.long_running.resolve_user_action() {
             Ok(Some(uuid)) => {
                 self.long_running
@@ -156,11 +346,27 @@ impl eframe::App for App {
                 log::error!("Failed to resolve user action: {e}");
             }
         };
+        self.recommended_path_modal(ctx);
+        self.alias_modal(ctx);
         egui::CentralPanel::default().show(ctx, |ui| {
             let mut new_state = self.header(ui, ctx, frame);
+            if self.safe_mode {
+                ui.label(
+                    egui::RichText::new(
+                        "Safe mode: downloaded repositories are not loaded. Update or fix your settings, then restart Pitsu normally.",
+                    )
+                    .color(egui::Color32::YELLOW),
+                );
+            }
             match self.state {
                 AppState::Main => {
                     if let Ok(Some(this)) = self.long_running.this_user() {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.repository_filter)
+                                .hint_text(format!("{} Search by name, alias or UUID", nerdfonts::MAGNIFY)),
+                        );
+                        let filter = self.repository_filter.trim().to_lowercase();
+                        let safe_mode = self.safe_mode;
                         let table = egui_extras::TableBuilder::new(ui)
                             .striped(false)
                             .resizable(false)
@@ -185,12 +391,31 @@ impl eframe::App for App {
                                 .iter()
                                 .chain(this.accessible_repositories.iter())
                             {
+                                let alias = CONFIG.alias(repo.uuid);
+                                if !filter.is_empty()
+                                    && !repo.name.to_lowercase().contains(&filter)
+                                    && !alias.as_ref().is_some_and(|a| a.name.to_lowercase().contains(&filter))
+                                    && !repo.uuid.to_string().starts_with(&filter)
+                                {
+                                    continue;
+                                }
                                 body.row(20.0, |mut row| {
                                     row.col(|ui| {
-                                        if ui
-                                            .add(egui::Button::new(&*repo.name).wrap_mode(egui::TextWrapMode::Extend))
-                                            .clicked()
-                                        {
+                                        let button = match &alias {
+                                            Some(alias) => {
+                                                let mut text = egui::RichText::new(&*alias.name);
+                                                if let Some([r, g, b]) = alias.color {
+                                                    text = text.color(egui::Color32::from_rgb(r, g, b));
+                                                }
+                                                egui::Button::new(text)
+                                            }
+                                            None => egui::Button::new(&*repo.name),
+                                        };
+                                        let response = ui
+                                            .add_enabled(!safe_mode, button.wrap_mode(egui::TextWrapMode::Extend))
+                                            .on_hover_text(format!("{}\n{}", repo.name, repo.uuid))
+                                            .on_disabled_hover_text("Repositories can't be opened in safe mode");
+                                        if response.clicked() {
                                             new_state = Some(AppState::RepositoryDetails {
                                                 uuid: repo.uuid,
                                                 hover_state: HoverType::None,
@@ -274,7 +499,7 @@ impl eframe::App for App {
                     }
                 }
                 AppState::Settings => {
-                    todo!("Settings page not implemented yet");
+                    self.settings(ui);
                 }
                 AppState::RepositoryDetails { uuid, hover_state } => {
                     if let Ok(Some(repo)) = self.long_running.get_repository(uuid) {
@@ -297,7 +522,7 @@ impl eframe::App for App {
                                     )
                                     .clicked()
                                 {
-                                    self.change_repository_path(uuid);
+                                    self.change_repository_path(uuid, repo.recommended_path.as_deref());
                                 }
                             }
                             Ok(None) => {
@@ -319,6 +544,16 @@ impl eframe::App for App {
                         },
                     );
                 }
+                AppState::SelectiveSync { uuid } => {
+                    egui::ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+                        self.selective_sync(ui, uuid, &mut new_state);
+                    });
+                }
+                AppState::RecentlyRemoved { uuid } => {
+                    egui::ScrollArea::vertical().auto_shrink(false).show(ui, |ui| {
+                        self.recently_removed(ui, uuid);
+                    });
+                }
             }
             if let Some(new_state) = new_state {
                 self.state = new_state;
@@ -332,6 +567,10 @@ impl eframe::App for App {
             serde_json::to_string(&self.sort).expect("Failed to serialize sort states"),
         );
     }
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        // closing quickly after an update isn't a crash
+        update::mark_healthy();
+    }
 }
 
 impl Default for App {
@@ -352,11 +591,18 @@ impl App {
             state: AppState::Main,
             state_stack: Vec::new(),
             edit_pitignore: None,
+            selective_sync: None,
+            recently_removed: None,
             sort: SortStates::default(),
             add_user_text: String::new(),
             add_user_modal: false,
+            edit_recommended_path: None,
+            edit_alias: None,
+            repository_filter: String::new(),
+            new_profile: Default::default(),
             updating: false,
             skip_confirmation: false,
+            safe_mode: config::safe_mode(),
         }
     }
     fn header(&mut self, ui: &mut egui::Ui, ctx: &egui::Context, _frame: &mut eframe::Frame) -> Option<AppState> {
@@ -371,7 +617,9 @@ impl App {
             AppState::CreateRepository => Some(AppState::Main),
             AppState::Settings => Some(AppState::Main),
             AppState::RepositoryDetails { .. } => Some(AppState::Main),
-            AppState::EditPitignore { uuid, .. } => Some(AppState::RepositoryDetails {
+            AppState::EditPitignore { uuid, .. }
+            | AppState::SelectiveSync { uuid }
+            | AppState::RecentlyRemoved { uuid } => Some(AppState::RepositoryDetails {
                 uuid,
                 hover_state: HoverType::None,
             }),
@@ -472,7 +720,27 @@ impl App {
                                 self.add_user_text.clear();
                             }
                         }
-                        ui.label(format!("{}", repo.name));
+                        let alias = CONFIG.alias(uuid);
+                        let title = match &alias {
+                            Some(alias) => {
+                                let text = egui::RichText::new(format!("{} ({})", alias.name, repo.name));
+                                match alias.color {
+                                    Some([r, g, b]) => text.color(egui::Color32::from_rgb(r, g, b)),
+                                    None => text,
+                                }
+                            }
+                            None => egui::RichText::new(&*repo.name),
+                        };
+                        if ui
+                            .add(egui::Label::new(title).sense(egui::Sense::click()))
+                            .on_hover_text("Click to set a local alias")
+                            .clicked()
+                        {
+                            self.edit_alias = Some(match alias {
+                                Some(alias) => (uuid, alias.name.to_string(), alias.color),
+                                None => (uuid, String::new(), None),
+                            });
+                        }
                         if ui
                             .menu_button(nerdfonts::ACCOUNT, |ui| {
                                 let is_admin = repo.access_level >= AccessLevel::Admin;
@@ -611,6 +879,37 @@ impl App {
                         {
                             self.add_user_text.clear();
                         };
+                        if self.long_running.archive_transfer_in_progress() {
+                            ui.spinner();
+                        } else {
+                            ui.menu_button(nerdfonts::ARCHIVE_ARROW_DOWN, |ui| {
+                                for format in [ArchiveFormat::Zip, ArchiveFormat::TarGz] {
+                                    if ui.button(format!("Export as .{format}")).clicked() {
+                                        self.export_archive(uuid, &repo.name, format);
+                                        ui.close();
+                                    }
+                                }
+                                if repo.access_level >= AccessLevel::Write {
+                                    ui.separator();
+                                    if ui.button("Import archive").clicked() {
+                                        self.import_archive(uuid, &repo.name, 0);
+                                        ui.close();
+                                    }
+                                    if ui
+                                        .button("Import archive without its top folder")
+                                        .on_hover_text(
+                                            "For release archives that wrap everything in one folder named after the release",
+                                        )
+                                        .clicked()
+                                    {
+                                        self.import_archive(uuid, &repo.name, 1);
+                                        ui.close();
+                                    }
+                                }
+                            })
+                            .response
+                            .on_hover_text("Export or import the repository as an archive");
+                        }
                         if let Some(stored) = self
                             .long_running
                             .get_stored_repository(uuid, &repo)
@@ -627,7 +926,9 @@ impl App {
                                     .desired_height(12.0)
                                     .desired_width((ui.available_width() - 240.0).max(96.0)),
                                 )
-                                .on_hover_text(if progress.batched != 0 {
+                                .on_hover_text(if throttle::is_paused() {
+                                    String::from("Paused while Pitsu is unfocused")
+                                } else if progress.batched != 0 {
                                     format!(
                                         "Syncing: {}/{} files ({:.2}%) ({} batched)",
                                         progress.completed,
@@ -651,7 +952,43 @@ impl App {
                                         .reload_repository(uuid)
                                         .expect("Failed to reload repository after changing path");
                                 }
-                                if !stored.local_pitignore_diff.is_empty() && repo.access_level >= AccessLevel::Write {
+                                if !stored.launch_targets.is_empty() {
+                                    ui.menu_button(nerdfonts::ROCKET_LAUNCH, |ui| {
+                                        for target in stored.launch_targets.iter() {
+                                            let blocked = stored.launch_blocked(target);
+                                            let button = ui
+                                                .add_enabled(blocked.is_none(), egui::Button::new(&*target.full_path));
+                                            ui.label(egui::RichText::new(launch_details(target)).small().weak());
+                                            if let Some(reason) = blocked {
+                                                button.on_disabled_hover_text(reason);
+                                            } else if button.clicked() {
+                                                launch_target(&stored, target, self.skip_confirmation);
+                                                ui.close();
+                                            }
+                                        }
+                                    })
+                                    .response
+                                    .on_hover_text("Launch targets from .pitexecute");
+                                }
+                                let can_upload =
+                                    !stored.local_pitignore_diff.is_empty() && repo.access_level >= AccessLevel::Write;
+                                let can_download =
+                                    !stored.remote_pitignore_diff.is_empty() && repo.access_level >= AccessLevel::Read;
+                                if can_upload || can_download {
+                                    ui.menu_button(nerdfonts::FILE_EXPORT, |ui| {
+                                        if can_upload && ui.button("Export upload plan").clicked() {
+                                            export_sync_plan(&stored.plan(true));
+                                            ui.close();
+                                        }
+                                        if can_download && ui.button("Export download plan").clicked() {
+                                            export_sync_plan(&stored.plan(false));
+                                            ui.close();
+                                        }
+                                    })
+                                    .response
+                                    .on_hover_text("Export a dry run of the sync as JSON or text");
+                                }
+                                if can_upload {
                                     let hover_text = {
                                         let mut text = String::from("Clicking this will:\n");
                                         let number_to_upload = stored
@@ -708,7 +1045,7 @@ impl App {
                                         new_hover_state = HoverType::None;
                                     }
                                 }
-                                if !stored.remote_pitignore_diff.is_empty() && repo.access_level >= AccessLevel::Read {
+                                if can_download {
                                     let hover_text = {
                                         let mut text = String::from("Clicking this will:\n");
                                         let number_to_download = stored
@@ -798,6 +1135,20 @@ impl App {
                         ui.spinner();
                     }
                 }
+                AppState::SelectiveSync { uuid } => {
+                    if let Ok(Some(repo)) = self.long_running.get_repository(uuid) {
+                        ui.label(format!("Selective sync for {}", repo.name));
+                    } else {
+                        ui.spinner();
+                    }
+                }
+                AppState::RecentlyRemoved { uuid } => {
+                    if let Ok(Some(repo)) = self.long_running.get_repository(uuid) {
+                        ui.label(format!("Recently removed from {}", repo.name));
+                    } else {
+                        ui.spinner();
+                    }
+                }
             }
             if let AppState::RepositoryDetails { hover_state, .. } = &mut self.state {
                 std::mem::swap(hover_state, &mut new_hover_state);
@@ -817,6 +1168,10 @@ impl App {
                     if res.clicked() {
                         CONFIG.set_skip_confirmation(skip_confirmation);
                     }
+                    if ui.button(format!("{} Settings", nerdfonts::COG)).clicked() {
+                        new_state = Some(AppState::Settings);
+                        ui.close();
+                    }
                     // ui.add(egui::Slider::new(unsafe { &mut DIM_FACTOR }, 0.0..=1.0).text("Dim Factor"));
                     ui.add(egui::Label::new(format!("Version: {}", *config::VERSION_NUMBER)).extend());
                     if let Ok(Some(hash)) = self.long_running.remote_version_number() {
@@ -832,17 +1187,48 @@ impl App {
 
     fn update_app_button(&mut self, ui: &mut egui::Ui) {
         if let Ok(Some(version_number)) = self.long_running.remote_version_number() {
-            if *version_number != *config::VERSION_NUMBER
-                && ui
-                    .button(
-                        egui::RichText::new(nerdfonts::UPDATE).color(if config::VERSION_NUMBER.is_dev() {
-                            egui::Color32::YELLOW
-                        } else {
-                            egui::Color32::GREEN
-                        }),
-                    )
-                    .on_hover_text("Update Pitsu to the latest version")
-                    .clicked()
+            if *version_number == *config::VERSION_NUMBER {
+                return;
+            }
+            let changelog = self.long_running.changelog(&version_number);
+            if ui
+                .button(
+                    egui::RichText::new(nerdfonts::UPDATE).color(if version_number.is_prerelease() {
+                        egui::Color32::YELLOW
+                    } else {
+                        egui::Color32::GREEN
+                    }),
+                )
+                .on_hover_ui(|ui| {
+                    if version_number.semver() < config::VERSION_NUMBER.semver() {
+                        ui.label(format!(
+                            "Switch Pitsu from {} back to {version_number}, the newest version on the {} channel",
+                            *config::VERSION_NUMBER,
+                            CONFIG.update_channel()
+                        ));
+                        return;
+                    }
+                    ui.label(format!(
+                        "Update Pitsu from {} to {version_number}",
+                        *config::VERSION_NUMBER
+                    ));
+                    match &changelog {
+                        Ok(Some(entries)) => {
+                            for entry in entries.iter() {
+                                ui.separator();
+                                ui.strong(&*entry.version);
+                                ui.label(&*entry.notes);
+                            }
+                        }
+                        Ok(None) => {
+                            ui.spinner();
+                        }
+                        Err(e) => {
+                            ui.label(egui::RichText::new(&**e).weak());
+                        }
+                    }
+                })
+                .clicked()
             {
                 // // Copy the executable to pitsu_old.exe
                 // let this_exe = std::env::current_exe().expect("Failed to get current executable path");
@@ -890,7 +1276,7 @@ impl App {
                     (HoverType::SyncDown, _) => (&stored_repo.remote_pitignore_diff, &stored_repo.remote_pitignore),
                 };
                 ui.vertical(|ui| {
-                    self.repository_info(ui, stored_repo);
+                    self.repository_info(ui, stored_repo, new_state);
                     // if !stored_repo.pitignore.patterns.is_empty() {
                     //     // ui.separator();
                     self.repository_pitignore(
@@ -922,7 +1308,7 @@ impl App {
             },
         );
     }
-    fn repository_info(&mut self, ui: &mut egui::Ui, stored_repo: &Repository) {
+    fn repository_info(&mut self, ui: &mut egui::Ui, stored_repo: &Repository, new_state: &mut Option<AppState>) {
         let display_path = stored_repo.local.path.to_string_lossy().replace("\\", "/");
         ui.menu_button(
             if display_path.len() > MAX_PATH_LENGTH {
@@ -952,7 +1338,57 @@ impl App {
                     .on_hover_text("Change the local repository path.")
                     .clicked()
                 {
-                    self.change_repository_path(stored_repo.local.uuid);
+                    self.change_repository_path(stored_repo.local.uuid, stored_repo.remote.recommended_path.as_deref());
+                    ui.close();
+                }
+                if let Some(template) = &stored_repo.remote.recommended_path {
+                    ui.add(egui::Label::new(format!("Recommended: {template}")).extend())
+                        .on_hover_text("Where the owner suggests storing this repository.");
+                }
+                if stored_repo.remote.access_level == AccessLevel::Owner
+                    && ui
+                        .button("Set recommended path")
+                        .on_hover_text("Suggest where members should store this repository.")
+                        .clicked()
+                {
+                    self.edit_recommended_path = Some((
+                        stored_repo.local.uuid,
+                        stored_repo.remote.name.clone(),
+                        stored_repo
+                            .remote
+                            .recommended_path
+                            .as_deref()
+                            .unwrap_or_default()
+                            .to_string(),
+                    ));
+                    ui.close();
+                }
+                if ui
+                    .button("Selective sync")
+                    .on_hover_text("Choose which files and folders are synced to this machine.")
+                    .clicked()
+                {
+                    let mut files = stored_repo.local.folder.files();
+                    files.extend(stored_repo.remote.files.files());
+                    self.selective_sync = Some(SelectiveSync {
+                        uuid: stored_repo.local.uuid,
+                        tree: SelectionTree::new(files),
+                        overrides: stored_repo.local.overrides.clone(),
+                    });
+                    *new_state = Some(AppState::SelectiveSync {
+                        uuid: stored_repo.local.uuid,
+                    });
+                    ui.close();
+                }
+                if ui
+                    .button("Recently removed")
+                    .on_hover_text("Restore files that a sync deleted or overwrote on this machine.")
+                    .clicked()
+                {
+                    self.recently_removed = None;
+                    *new_state = Some(AppState::RecentlyRemoved {
+                        uuid: stored_repo.local.uuid,
+                    });
                     ui.close();
                 }
             },
@@ -1312,11 +1748,92 @@ impl App {
         });
     }
 
-    fn change_repository_path(&mut self, uuid: Uuid) {
-        let path = rfd::FileDialog::new()
-            .set_title("Select Repository Storage Location")
-            .pick_folder();
+    fn export_archive(&mut self, uuid: Uuid, name: &str, format: ArchiveFormat) {
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Export Repository Archive")
+            .set_file_name(format!("{name}.{format}"))
+            .add_filter(format.extension(), &[format.extension()])
+            .save_file()
+        else {
+            return;
+        };
+        self.long_running.export_archive(uuid, format, path);
+    }
+    // strip_components drops that many leading folders from every entry in the archive
+    fn import_archive(&mut self, uuid: Uuid, name: &str, strip_components: usize) {
+        let Some(path) = rfd::FileDialog::new()
+            .set_title("Import Archive")
+            .add_filter("Archives", &["zip", "tar", "gz", "tgz"])
+            .pick_file()
+        else {
+            return;
+        };
+        match dialogue::rfd_confirm_response(
+            &format!(
+                "Import {} into {name}?\n\nFiles in the repository with the same paths will be replaced for everyone.",
+                path.display()
+            ),
+            self.skip_confirmation,
+        ) {
+            Ok(true) => self.long_running.import_archive(uuid, path, strip_components),
+            Ok(false) => {}
+            Err(e) => log::error!("Failed to show confirmation dialog: {e}"),
+        }
+    }
+    fn change_repository_path(&mut self, uuid: Uuid, recommended_path: Option<&str>) {
+        let pick_folder = |directory: Option<&std::path::Path>| {
+            let mut dialog = rfd::FileDialog::new().set_title("Select Repository Storage Location");
+            if let Some(directory) = directory {
+                dialog = dialog.set_directory(directory);
+            }
+            dialog.pick_folder()
+        };
+        let recommended = recommended_path.and_then(|template| match pitsu_lib::resolve_path_template(template) {
+            Ok(path) => Some(path),
+            Err(e) => {
+                log::warn!("Failed to resolve recommended path {template}: {e}");
+                None
+            }
+        });
+        let path = match recommended {
+            Some(recommended) => match dialogue::rfd_confirm_response(
+                &format!(
+                    "This repository recommends being stored at\n\n{}\n\nUse this folder?",
+                    recommended.display()
+                ),
+                false,
+            ) {
+                Ok(true) => Some(recommended),
+                // start the picker as close to the recommendation as exists
+                Ok(false) => pick_folder(recommended.ancestors().find(|path| path.is_dir())),
+                Err(e) => {
+                    log::error!("Failed to show confirmation dialog: {e}");
+                    return;
+                }
+            },
+            None => pick_folder(None),
+        };
         if let Some(path) = path {
+            if std::fs::read_dir(&path).is_ok_and(|mut entries| entries.next().is_some()) {
+                match dialogue::rfd_confirm_response(
+                    &format!(
+                        "{} already has content.\n\nFiles that differ from the repository will show up as changes, and downloading will replace them. Continue?",
+                        path.display()
+                    ),
+                    self.skip_confirmation,
+                ) {
+                    Ok(true) => {}
+                    Ok(false) => return,
+                    Err(e) => {
+                        log::error!("Failed to show confirmation dialog: {e}");
+                        return;
+                    }
+                }
+            }
+            if let Err(e) = std::fs::create_dir_all(&path) {
+                dialogue::rfd_ok_dialogue(&format!("Failed to create {}:\n{e}", path.display())).ok();
+                return;
+            }
             if let Err(e) = CONFIG.add_stored(uuid, path) {
                 dialogue::rfd_ok_dialogue(&format!("Failed to store repository:\n{e}")).ok();
             }
@@ -1326,6 +1843,388 @@ impl App {
         }
     }
 
+    fn alias_modal(&mut self, ctx: &egui::Context) {
+        let Some((uuid, name, color)) = &mut self.edit_alias else {
+            return;
+        };
+        let mut close = false;
+        let mut save = None;
+        let modal = egui::Modal::new(Id::new("alias_modal")).show(ctx, |ui| {
+            ui.label("Local alias");
+            ui.add(egui::TextEdit::singleline(name).hint_text("Only shown to you"));
+            ui.horizontal(|ui| {
+                let mut use_color = color.is_some();
+                if ui.checkbox(&mut use_color, "Colour").changed() {
+                    *color = use_color.then_some([255, 255, 255]);
+                }
+                if let Some(color) = color {
+                    ui.color_edit_button_srgb(color);
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!name.trim().is_empty(), egui::Button::new("Save"))
+                    .clicked()
+                {
+                    save = Some((
+                        *uuid,
+                        Some(config::RepositoryAlias {
+                            name: name.trim().into(),
+                            color: *color,
+                        }),
+                    ));
+                }
+                if ui.button("Remove").clicked() {
+                    save = Some((*uuid, None));
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+        if let Some((uuid, alias)) = save {
+            match CONFIG.set_alias(uuid, alias) {
+                Ok(()) => close = true,
+                Err(e) => {
+                    dialogue::rfd_ok_dialogue(&format!("Failed to set alias:\n{e}")).ok();
+                }
+            }
+        }
+        if close || modal.backdrop_response.clicked() {
+            self.edit_alias = None;
+        }
+    }
+    fn recommended_path_modal(&mut self, ctx: &egui::Context) {
+        let Some((uuid, name, template)) = &mut self.edit_recommended_path else {
+            return;
+        };
+        let mut close = false;
+        let mut save = None;
+        let modal = egui::Modal::new(Id::new("recommended_path_modal")).show(ctx, |ui| {
+            ui.label("Recommended path");
+            ui.add(
+                egui::TextEdit::singleline(template)
+                    .hint_text("%localappdata%\\mods")
+                    .desired_width(360.0),
+            )
+            .on_hover_text("Environment variables (%VAR%, $VAR, ${VAR}) and ~ are expanded on each machine.");
+            if !template.trim().is_empty() {
+                match pitsu_lib::resolve_path_template(template) {
+                    Ok(path) => ui.label(format!("Resolves to {} here", path.display())),
+                    Err(e) => ui.label(format!("Can't be resolved here: {e}")),
+                };
+            }
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    save = Some((
+                        *uuid,
+                        UpdateRemoteRepository {
+                            name: name.clone(),
+                            recommended_path: Some(template.trim().into()),
+                        },
+                    ));
+                }
+                if ui.button("Cancel").clicked() {
+                    close = true;
+                }
+            });
+        });
+        if let Some((uuid, update)) = save {
+            self.long_running.update_repository(uuid, update);
+            close = true;
+        }
+        if close || modal.backdrop_response.clicked() {
+            self.edit_recommended_path = None;
+        }
+    }
+
+    fn settings(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Profiles");
+        let active = CONFIG.active_profile();
+        let can_switch = self.long_running.sync_in_progress().is_none();
+        let mut switch_to = None;
+        let mut remove = None;
+        for (index, (name, url)) in CONFIG.profiles().into_iter().enumerate() {
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(can_switch, egui::RadioButton::new(index == active, &*name))
+                    .on_hover_text(&*url)
+                    .on_disabled_hover_text("Wait for the current sync to finish before switching")
+                    .clicked()
+                    && index != active
+                {
+                    switch_to = Some(index);
+                }
+                ui.label(egui::RichText::new(&*url).small().weak());
+                if index != active
+                    && ui
+                        .button(nerdfonts::TRASH)
+                        .on_hover_text("Remove this profile")
+                        .clicked()
+                {
+                    remove = Some((index, name));
+                }
+            });
+        }
+        if let Some(index) = switch_to {
+            match CONFIG.set_active_profile(index) {
+                Ok(()) => {
+                    // everything cached belongs to the old server
+                    self.long_running = cache::RequestCache::new();
+                    self.recently_removed = None;
+                    self.state_stack.clear();
+                }
+                Err(e) => {
+                    log::error!("Failed to switch profile: {e}");
+                    dialogue::rfd_ok_dialogue(&format!("Failed to switch profile: {e}")).ok();
+                }
+            }
+        }
+        if let Some((index, name)) = remove {
+            match dialogue::rfd_confirm_response(
+                &format!(
+                    "Remove the profile {name}?\n\nRepositories downloaded with it stay on disk but will no longer be tracked."
+                ),
+                self.skip_confirmation,
+            ) {
+                Ok(true) => {
+                    if let Err(e) = CONFIG.remove_profile(index) {
+                        log::error!("Failed to remove profile: {e}");
+                        dialogue::rfd_ok_dialogue(&format!("Failed to remove profile: {e}")).ok();
+                    }
+                }
+                Ok(false) => {}
+                Err(e) => log::error!("Failed to show confirmation dialogue: {e}"),
+            }
+        }
+        egui::CollapsingHeader::new("Add profile").show(ui, |ui| {
+            let (name, url, api_key) = &mut self.new_profile;
+            egui::Grid::new("new_profile").num_columns(2).show(ui, |ui| {
+                ui.label("Name");
+                ui.text_edit_singleline(name);
+                ui.end_row();
+                ui.label("Server URL");
+                ui.add(egui::TextEdit::singleline(url).hint_text("https://"));
+                ui.end_row();
+                ui.label("API key");
+                ui.add(egui::TextEdit::singleline(api_key).password(true));
+                ui.end_row();
+            });
+            if ui.button(format!("{} Add", nerdfonts::PLUS)).clicked() {
+                match CONFIG.add_profile(name, url, api_key) {
+                    Ok(()) => self.new_profile = Default::default(),
+                    Err(e) => {
+                        dialogue::rfd_ok_dialogue(&format!("Failed to add profile: {e}")).ok();
+                    }
+                }
+            }
+        });
+        ui.separator();
+        ui.heading("Updates");
+        let mut channel = CONFIG.update_channel();
+        ui.horizontal(|ui| {
+            ui.label("Channel");
+            egui::ComboBox::from_id_salt("update_channel")
+                .selected_text(channel.to_string())
+                .show_ui(ui, |ui| {
+                    for option in UpdateChannel::ALL {
+                        ui.selectable_value(&mut channel, option, option.to_string());
+                    }
+                });
+            ui.label(egui::RichText::new(format!("Installed: {}", *config::VERSION_NUMBER)).weak());
+        });
+        if channel != CONFIG.update_channel() {
+            CONFIG.set_update_channel(channel);
+            self.long_running.reset_version_check();
+        }
+        ui.separator();
+        ui.heading("Transfers");
+        let mut upload_limit = CONFIG.upload_limit();
+        ui.horizontal(|ui| {
+            ui.label("Upload limit");
+            if ui
+                .add(egui::DragValue::new(&mut upload_limit).speed(64).suffix(" KiB/s"))
+                .on_hover_text("0 is unlimited")
+                .changed()
+            {
+                CONFIG.set_upload_limit(upload_limit);
+            }
+        });
+        let mut download_limit = CONFIG.download_limit();
+        ui.horizontal(|ui| {
+            ui.label("Download limit");
+            if ui
+                .add(egui::DragValue::new(&mut download_limit).speed(64).suffix(" KiB/s"))
+                .on_hover_text("0 is unlimited")
+                .changed()
+            {
+                CONFIG.set_download_limit(download_limit);
+            }
+        });
+        let (mut compression, mut compression_level) = CONFIG.compression();
+        ui.horizontal(|ui| {
+            ui.label("Compression");
+            egui::ComboBox::from_id_salt("compression")
+                .selected_text(compression.to_string())
+                .show_ui(ui, |ui| {
+                    for option in TransferEncoding::ALL {
+                        ui.selectable_value(&mut compression, option, option.to_string());
+                    }
+                })
+                .response
+                .on_hover_text("Files that are already compressed are always sent as is.");
+            ui.add_enabled(
+                compression != TransferEncoding::None,
+                egui::Slider::new(&mut compression_level, compression.levels()).text("level"),
+            )
+            .on_hover_text("Higher levels are smaller but slower to upload.");
+        });
+        if compression != CONFIG.compression().0 {
+            CONFIG.set_compression(compression, compression.default_level());
+        } else if compression_level != CONFIG.compression().1 {
+            CONFIG.set_compression(compression, compression_level);
+        }
+        let mut pause_when_unfocused = CONFIG.pause_when_unfocused();
+        if ui
+            .checkbox(&mut pause_when_unfocused, "Pause transfers while Pitsu is unfocused")
+            .on_hover_text("The file currently being transferred will still finish.")
+            .clicked()
+        {
+            CONFIG.set_pause_when_unfocused(pause_when_unfocused);
+        }
+        ui.separator();
+        ui.heading("Trash");
+        let mut retention = CONFIG.trash_retention_days();
+        ui.horizontal(|ui| {
+            ui.label("Keep removed files for");
+            if ui
+                .add(egui::DragValue::new(&mut retention).range(1..=365).suffix(" days"))
+                .on_hover_text("Files deleted or overwritten by a sync are kept in the trash for this long.")
+                .changed()
+            {
+                CONFIG.set_trash_retention_days(retention);
+            }
+        });
+    }
+    fn recently_removed(&mut self, ui: &mut egui::Ui, uuid: Uuid) {
+        let Some(root) = self
+            .long_running
+            .get_repository(uuid)
+            .ok()
+            .flatten()
+            .and_then(|repo| {
+                self.long_running
+                    .get_stored_repository(uuid, &repo)
+                    .ok()
+                    .flatten()
+                    .flatten()
+            })
+            .map(|stored| stored.local.path.clone())
+        else {
+            ui.spinner();
+            return;
+        };
+        if self.recently_removed.as_ref().is_none_or(|(u, _)| *u != uuid) {
+            let syncs = trash::list(uuid).unwrap_or_else(|e| {
+                log::error!("Failed to list trash for repository {uuid}: {e}");
+                Vec::new()
+            });
+            self.recently_removed = Some((uuid, syncs));
+        }
+        let Some((_, syncs)) = &self.recently_removed else {
+            return;
+        };
+        if syncs.is_empty() {
+            ui.label(format!(
+                "Nothing has been removed by a sync in the last {} days.",
+                CONFIG.trash_retention_days()
+            ));
+            return;
+        }
+        let can_restore = self.long_running.sync_in_progress().is_none();
+        let mut to_restore = None;
+        for sync in syncs {
+            egui::CollapsingHeader::new(format!(
+                "{} ago - {} file(s), {}",
+                readable_age(sync.removed_at.elapsed().unwrap_or_default()),
+                sync.files.len(),
+                readable_size_and_color(sync.size()).0
+            ))
+            .id_salt(("recently_removed", &sync.id))
+            .show(ui, |ui| {
+                if ui
+                    .add_enabled(
+                        can_restore,
+                        egui::Button::new(format!("{} Restore all", nerdfonts::DELETE_RESTORE)),
+                    )
+                    .on_hover_text("Restore every file removed by this sync")
+                    .clicked()
+                {
+                    to_restore = Some(sync.files.clone());
+                }
+                for file in &sync.files {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(can_restore, egui::Button::new(nerdfonts::DELETE_RESTORE))
+                            .on_hover_text("Restore this file")
+                            .clicked()
+                        {
+                            to_restore = Some(vec![file.clone()]);
+                        }
+                        ui.label(&*file.full_path);
+                        let (size, color) = readable_size_and_color(file.size);
+                        ui.label(egui::RichText::new(&*size).color(dim(color, unsafe { DIM_FACTOR })));
+                    });
+                }
+            });
+        }
+        if let Some(files) = to_restore {
+            match dialogue::rfd_confirm_response(
+                &format!(
+                    "Restore {} file(s)?\n\nAnything currently at those paths will be moved to the trash.",
+                    files.len()
+                ),
+                self.skip_confirmation,
+            ) {
+                Ok(true) => {
+                    if let Err(e) = trash::restore(uuid, &root, &files) {
+                        log::error!("Failed to restore files: {e}");
+                        dialogue::rfd_ok_dialogue(&format!("Failed to restore files:\n{e}")).ok();
+                    }
+                    self.recently_removed = None;
+                    if let Err(e) = self.long_running.reload_repository(uuid) {
+                        log::error!("Failed to reload repository after restoring files: {e}");
+                    }
+                }
+                Ok(false) => {}
+                Err(e) => log::error!("Failed to show confirmation dialog: {e}"),
+            }
+        }
+    }
+    fn selective_sync(&mut self, ui: &mut egui::Ui, uuid: Uuid, new_state: &mut Option<AppState>) {
+        let Some(selective) = self.selective_sync.as_mut().filter(|s| s.uuid == uuid) else {
+            *new_state = Some(AppState::RepositoryDetails {
+                uuid,
+                hover_state: HoverType::None,
+            });
+            return;
+        };
+        ui.label(
+            egui::RichText::new(
+                "Unchecked files and folders are never downloaded, deleted or shown as pending on this machine. The repository's .pitignore is not changed.",
+            )
+            .weak(),
+        );
+        ui.separator();
+        if selection_tree_ui(ui, &selective.tree, "", &mut selective.overrides, false) {
+            if let Err(e) = CONFIG.set_overrides(uuid, selective.overrides.clone()) {
+                log::error!("Failed to save selective sync overrides: {e}");
+            }
+            if let Err(e) = self.long_running.reload_repository(uuid) {
+                log::error!("Failed to reload repository after changing selective sync: {e}");
+            }
+        }
+    }
     fn pitignore_editor(&mut self, ui: &mut egui::Ui, uuid: Uuid, new_state: &mut Option<AppState>) {
         match self.edit_pitignore {
             Some((ref mut pitignore, ref mut edit, ref mut dirty)) => {
@@ -1492,6 +2391,75 @@ impl App {
     }
 }
 
+// returns true if any of the overrides changed
+fn selection_tree_ui(
+    ui: &mut egui::Ui,
+    tree: &SelectionTree,
+    prefix: &str,
+    overrides: &mut Pitignore,
+    parent_excluded: bool,
+) -> bool {
+    let mut changed = false;
+    for (name, folder) in &tree.folders {
+        let path = format!("{prefix}{name}");
+        let probe = format!("{path}/");
+        let mut included = !parent_excluded && !overrides.is_ignored(&probe);
+        egui::collapsing_header::CollapsingState::load_with_default_open(
+            ui.ctx(),
+            Id::new(("selective_sync", &path)),
+            false,
+        )
+        .show_header(ui, |ui| {
+            if ui
+                .add_enabled(
+                    !parent_excluded,
+                    egui::Checkbox::new(&mut included, format!("{} {name}", nerdfonts::FOLDER)),
+                )
+                .changed()
+            {
+                changed |= set_excluded(overrides, &format!("={probe}"), &probe, !included);
+            }
+        })
+        .body(|ui| {
+            changed |= selection_tree_ui(ui, folder, &probe, overrides, !included);
+        });
+    }
+    for (name, size) in &tree.files {
+        let path = format!("{prefix}{name}");
+        let mut included = !parent_excluded && !overrides.is_ignored(&path);
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!parent_excluded, egui::Checkbox::new(&mut included, &**name))
+                .changed()
+            {
+                changed |= set_excluded(overrides, &format!("={path}"), &path, !included);
+            }
+            let (size, color) = readable_size_and_color(*size);
+            ui.label(egui::RichText::new(&*size).color(dim(color, unsafe { DIM_FACTOR })));
+        });
+    }
+    changed
+}
+
+// prefers removing an existing pattern, only falls back to adding a (negated) one if something else still matches.
+// the tree only writes literal patterns, so unticking `data` leaves `data2.bin` alone
+fn set_excluded(overrides: &mut Pitignore, pattern: &str, probe: &str, excluded: bool) -> bool {
+    // excluding drops a negation that kept it in, including drops the exclusion
+    let mut changed = overrides.remove_pattern(pattern, excluded);
+    if overrides.is_ignored(probe) != excluded {
+        let line = if excluded {
+            pattern.to_string()
+        } else {
+            format!("!{pattern}")
+        };
+        match overrides.add_pattern(&line) {
+            Ok(()) => changed = true,
+            Err(e) => log::error!("Failed to add selective sync pattern: {e}"),
+        }
+    }
+    changed
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum HoverType {
     None,
@@ -1499,24 +2467,13 @@ pub enum HoverType {
     SyncDown,
 }
 
-const SIZES: &[&str] = &["B", "KB", "MB", "GB", "TB", "PB"];
-
 fn readable_size_and_color(bytes: u64) -> (Arc<str>, egui::Color32) {
     // the color returned should be a rainbow gradient, with red being 1B, orange being 1KB, yellow being 1MB, green being 1GB, blue being 1TB, and purple being 1PB
-    let mut size = bytes as f32;
-    let mut index = 0;
-    while size >= 1024.0 && index < SIZES.len() - 1 {
-        size /= 1024.0;
-        index += 1;
-    }
-    // let hsl = colors_transform::Hsl::from(
-    //     360.0 - ((((index as f32 / (SIZES.len() - 1) as f32) * 360.0) + 240.0) % 360.0),
-    //     100.0,
-    //     50.0,
-    // );
-    // like above but with smooth transition based on size / 1024.0
+    let (size, index) = pitsu_lib::scale_size(bytes);
+    // smooth transition based on size / 1024.0
     let hsl = colors_transform::Hsl::from(
-        360.0 - ((((index as f32 + size / 1024.0) / (SIZES.len() - 1) as f32) * 360.0 + 240.0) % 360.0),
+        360.0
+            - ((((index as f32 + size as f32 / 1024.0) / (pitsu_lib::SIZES.len() - 1) as f32) * 360.0 + 240.0) % 360.0),
         100.0,
         50.0,
     );
@@ -1524,19 +2481,7 @@ fn readable_size_and_color(bytes: u64) -> (Arc<str>, egui::Color32) {
     let rgb = hsl.to_rgb();
 
     (
-        Arc::from(format!(
-            "{} {}",
-            if size >= 100.0 {
-                format!("{size:.0}")
-            } else if size >= 10.0 {
-                format!("{size:.1}")
-            } else {
-                format!("{size:.2}")
-            }
-            .trim_end_matches('0')
-            .trim_end_matches('.'),
-            SIZES[index]
-        )),
+        Arc::from(pitsu_lib::readable_size(bytes)),
         egui::Color32::from_rgb(
             rgb.get_red().round() as u8,
             rgb.get_green().round() as u8,
@@ -1577,17 +2522,19 @@ pub enum LocalSort {
     SizeReverse, // Sort by size in reverse
 }
 
-fn self_update(bytes: Vec<u8>) -> Result<(), anyhow::Error> {
-    let tmp_dir = dirs::cache_dir()
-        .ok_or_else(|| anyhow::anyhow!("Failed to get cache directory"))?
-        .join("pitsu_update");
-    std::fs::create_dir_all(&tmp_dir).map_err(|e| anyhow::anyhow!("Failed to create temporary directory: {e}"))?;
-    let new_exe = tmp_dir.join("pitsu.exe");
-    std::fs::write(&new_exe, bytes).map_err(|e| anyhow::anyhow!("Failed to write new executable: {e}"))?;
-    log::info!("New executable written to {}", new_exe.display());
-    self_replace::self_replace(new_exe).map_err(|e| anyhow::anyhow!("Failed to replace current executable: {e}"))?;
-    log::info!("Successfully replaced current executable. Restarting PITSU...");
-    Ok(())
+fn readable_age(age: std::time::Duration) -> String {
+    let secs = age.as_secs();
+    let (amount, unit) = match secs {
+        0..60 => (secs, "second"),
+        60..3600 => (secs / 60, "minute"),
+        3600..86400 => (secs / 3600, "hour"),
+        _ => (secs / 86400, "day"),
+    };
+    if amount == 1 {
+        format!("{amount} {unit}")
+    } else {
+        format!("{amount} {unit}s")
+    }
 }
 
 fn confirm_and_open(path: &str, skip: bool) -> Result<(), anyhow::Error> {
@@ -1597,6 +2544,68 @@ fn confirm_and_open(path: &str, skip: bool) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+fn launch_details(target: &LaunchTarget) -> String {
+    let working_directory = if target.working_directory.is_empty() {
+        "repository root"
+    } else {
+        &target.working_directory
+    };
+    if target.arguments.is_empty() {
+        format!("in {working_directory}")
+    } else {
+        format!("{} in {working_directory}", target.arguments.join(" "))
+    }
+}
+
+fn launch_target(repository: &Repository, target: &LaunchTarget, skip_confirmation: bool) {
+    match dialogue::rfd_confirm_response(
+        &format!("Run {}?\n\n{}", target.full_path, launch_details(target)),
+        skip_confirmation,
+    ) {
+        Ok(true) => {
+            if let Err(e) = repository.launch(target) {
+                log::error!("Failed to launch {}: {e}", target.full_path);
+                dialogue::rfd_ok_dialogue(&format!("Failed to launch {}:\n{e}", target.full_path)).ok();
+            }
+        }
+        Ok(false) => {}
+        Err(e) => log::error!("Failed to show confirmation dialog: {e}"),
+    }
+}
+
+fn export_sync_plan(plan: &SyncPlan) {
+    let Some(path) = rfd::FileDialog::new()
+        .set_title("Export Sync Plan")
+        .set_file_name(if plan.upload {
+            "upload-plan.json"
+        } else {
+            "download-plan.json"
+        })
+        .add_filter("JSON", &["json"])
+        .add_filter("Text", &["txt"])
+        .save_file()
+    else {
+        return;
+    };
+    if let Err(e) = write_sync_plan(plan, &path) {
+        log::error!("Failed to export sync plan: {e}");
+        if let Err(e) = dialogue::rfd_ok_dialogue(&format!("Failed to export sync plan: {e}")) {
+            log::error!("Failed to show export error dialog: {e}");
+        }
+    }
+}
+
+// the format is picked from the extension, anything that isn't .json gets the text report
+fn write_sync_plan(plan: &SyncPlan, path: &std::path::Path) -> anyhow::Result<()> {
+    let contents = if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json")) {
+        plan.to_json()?
+    } else {
+        plan.to_text()
+    };
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
 fn dim(color: egui::Color32, factor: f32) -> egui::Color32 {
     let rgb = (color.r() as f32, color.g() as f32, color.b() as f32);
     let new_rgb = (
@@ -1606,3 +2615,41 @@ fn dim(color: egui::Color32, factor: f32) -> egui::Color32 {
     );
     egui::Color32::from_rgb(new_rgb.0, new_rgb.1, new_rgb.2)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_excluded() {
+        let mut overrides = Pitignore::default();
+        assert!(set_excluded(&mut overrides, "=data", "data", true));
+        assert!(overrides.is_ignored("data"));
+        assert!(
+            !overrides.is_ignored("data2.bin"),
+            "A sibling sharing the prefix should still sync"
+        );
+        assert!(!overrides.is_ignored("data/inner.txt"));
+
+        // folders take everything in them, and only them
+        assert!(set_excluded(&mut overrides, "=mods/", "mods/", true));
+        assert!(overrides.is_ignored("mods/big/file.bin"));
+        assert!(!overrides.is_ignored("mods.txt"));
+
+        // a file inside an excluded folder gets a negated pattern, re-including the folder removes the pattern
+        assert!(set_excluded(&mut overrides, "=mods/keep.txt", "mods/keep.txt", false));
+        assert!(!overrides.is_ignored("mods/keep.txt"));
+        assert!(set_excluded(&mut overrides, "=mods/", "mods/", false));
+        assert!(!overrides.is_ignored("mods/big/file.bin"));
+
+        assert!(set_excluded(&mut overrides, "=data", "data", false));
+        assert!(!overrides.is_ignored("data"));
+        assert!(
+            !set_excluded(&mut overrides, "=data", "data", false),
+            "Nothing left to change"
+        );
+        // and it can be excluded again
+        assert!(set_excluded(&mut overrides, "=data", "data", true));
+        assert!(overrides.is_ignored("data"));
+    }
+}
@@ -0,0 +1,84 @@
+// ehttp sends and receives whole bodies in one go, so limits are enforced between requests:
+// files are split so no single request carries more than about a second at the limit,
+// and after each transfer we sleep until the average rate for the sync is back under the limit.
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::config::CONFIG;
+
+static FOCUSED: AtomicBool = AtomicBool::new(true);
+
+// how often a paused or throttled transfer re-checks the settings, so changes apply mid-sync
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+// a single request holds roughly this many seconds of transfer at the current limit
+const REQUEST_SECONDS: u64 = 1;
+
+pub fn set_focused(focused: bool) {
+    FOCUSED.store(focused, Ordering::Relaxed);
+}
+
+pub fn is_paused() -> bool {
+    CONFIG.pause_when_unfocused() && !FOCUSED.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+pub struct Throttle {
+    direction: Direction,
+    started: Instant,
+    transferred: u64,
+}
+
+impl Throttle {
+    pub fn new(direction: Direction) -> Self {
+        Self {
+            direction,
+            started: Instant::now(),
+            transferred: 0,
+        }
+    }
+    // bytes per second, None when unlimited
+    fn limit(&self) -> Option<u64> {
+        let kib = match self.direction {
+            Direction::Upload => CONFIG.upload_limit(),
+            Direction::Download => CONFIG.download_limit(),
+        };
+        (kib > 0).then_some(kib * 1024)
+    }
+    // blocks while the app is unfocused and pausing is enabled
+    pub fn wait_for_focus(&self) {
+        while is_paused() {
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+    // call after a transfer completes, blocks until the average rate is under the limit again
+    pub fn record(&mut self, bytes: u64) {
+        self.transferred += bytes;
+        while let Some(limit) = self.limit() {
+            let expected = Duration::from_secs_f64(self.transferred as f64 / limit as f64);
+            let elapsed = self.started.elapsed();
+            if elapsed >= expected {
+                break;
+            }
+            std::thread::sleep((expected - elapsed).min(POLL_INTERVAL));
+        }
+        self.wait_for_focus();
+    }
+    // smaller upload batches when limited, otherwise one batch would saturate the connection for its whole duration
+    pub fn batch_size(&self, default: usize) -> usize {
+        match self.limit() {
+            Some(limit) => default.min((limit * REQUEST_SECONDS) as usize),
+            None => default,
+        }
+    }
+    // None when unlimited, otherwise files larger than this go up or come down in pieces of this size
+    pub fn part_size(&self) -> Option<u64> {
+        self.limit().map(|limit| limit * REQUEST_SECONDS)
+    }
+}
@@ -0,0 +1,297 @@
+// Files that a sync would delete or overwrite are moved here instead.
+// Layout: <cache dir>/pitsu_trash/<repository uuid>/<sync id (unix millis-random suffix)>/<path inside the repository>
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use uuid::Uuid;
+
+fn trash_dir() -> Result<PathBuf> {
+    Ok(dirs::cache_dir()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get cache directory"))?
+        .join("pitsu_trash"))
+}
+
+// one of these per sync, so a whole sync can be restored at once
+pub struct TrashSession {
+    dir: PathBuf,
+}
+
+impl TrashSession {
+    pub fn new(repository: Uuid) -> Result<Self> {
+        Self::new_in(&trash_dir()?, repository)
+    }
+    // the suffix keeps two syncs started in the same millisecond out of each other's directory
+    fn new_in(root: &Path, repository: Uuid) -> Result<Self> {
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let suffix = Uuid::new_v4().simple().to_string();
+        Ok(Self {
+            dir: root
+                .join(repository.to_string())
+                .join(format!("{millis}-{}", &suffix[..8])),
+        })
+    }
+    pub fn move_file(&self, local_path: &Path, full_path: &str) -> Result<()> {
+        let target = self.dir.join(full_path.trim_start_matches('/'));
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        move_file(local_path, &target)?;
+        log::info!("Moved {} to trash at {}", local_path.display(), target.display());
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TrashedSync {
+    pub id: Arc<str>,
+    pub removed_at: SystemTime,
+    pub files: Vec<TrashedFile>,
+}
+
+impl TrashedSync {
+    pub fn size(&self) -> u64 {
+        self.files.iter().map(|f| f.size).sum()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TrashedFile {
+    pub full_path: Arc<str>,
+    pub size: u64,
+    trash_path: PathBuf,
+}
+
+// None for anything that isn't a `{millis}-{8 hex}` session directory
+fn session_millis(name: &std::ffi::OsStr) -> Option<u128> {
+    let (millis, suffix) = name.to_str()?.split_once('-')?;
+    if suffix.len() != 8 || !suffix.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    millis.parse().ok()
+}
+
+// newest first
+pub fn list(repository: Uuid) -> Result<Vec<TrashedSync>> {
+    list_in(&trash_dir()?, repository)
+}
+
+fn list_in(root: &Path, repository: Uuid) -> Result<Vec<TrashedSync>> {
+    let repository_dir = root.join(repository.to_string());
+    if !repository_dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut syncs = Vec::new();
+    for entry in std::fs::read_dir(&repository_dir)? {
+        let entry = entry?;
+        let Some(millis) = session_millis(&entry.file_name()) else {
+            continue;
+        };
+        let mut files = Vec::new();
+        collect_files(&entry.path(), "", &mut files)?;
+        if files.is_empty() {
+            continue;
+        }
+        files.sort_by(|a, b| a.full_path.cmp(&b.full_path));
+        syncs.push(TrashedSync {
+            id: entry.file_name().to_string_lossy().into(),
+            removed_at: UNIX_EPOCH + Duration::from_millis(millis as u64),
+            files,
+        });
+    }
+    syncs.sort_by(|a, b| b.removed_at.cmp(&a.removed_at).then_with(|| b.id.cmp(&a.id)));
+    Ok(syncs)
+}
+
+fn collect_files(dir: &Path, path_so_far: &str, files: &mut Vec<TrashedFile>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let full_path = format!("{path_so_far}/{}", entry.file_name().to_string_lossy());
+        let full_path = full_path.trim_start_matches('/');
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), full_path, files)?;
+        } else {
+            files.push(TrashedFile {
+                full_path: full_path.into(),
+                size: entry.metadata()?.len(),
+                trash_path: entry.path(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// whatever currently sits at the destination goes to the trash first, so restoring is never destructive either
+pub fn restore(repository: Uuid, root: &Path, files: &[TrashedFile]) -> Result<()> {
+    restore_in(&trash_dir()?, repository, root, files)
+}
+
+fn restore_in(trash_root: &Path, repository: Uuid, root: &Path, files: &[TrashedFile]) -> Result<()> {
+    let session = TrashSession::new_in(trash_root, repository)?;
+    for file in files {
+        let target = root.join(file.full_path.trim_start_matches('/'));
+        if target.exists() {
+            session.move_file(&target, &file.full_path)?;
+        }
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        move_file(&file.trash_path, &target)?;
+        log::info!("Restored {} from trash", target.display());
+    }
+    remove_empty_dirs(&trash_root.join(repository.to_string()))?;
+    Ok(())
+}
+
+pub fn purge_expired(retention_days: u64) -> Result<usize> {
+    purge_expired_in(&trash_dir()?, retention_days)
+}
+
+fn purge_expired_in(root: &Path, retention_days: u64) -> Result<usize> {
+    if !root.exists() {
+        return Ok(0);
+    }
+    let cutoff = SystemTime::now()
+        .checked_sub(Duration::from_secs(retention_days * 24 * 60 * 60))
+        .unwrap_or(UNIX_EPOCH)
+        .duration_since(UNIX_EPOCH)?
+        .as_millis();
+    let mut purged = 0;
+    for repository in std::fs::read_dir(root)? {
+        let repository = repository?;
+        if !repository.file_type()?.is_dir() {
+            continue;
+        }
+        for sync in std::fs::read_dir(repository.path())? {
+            let sync = sync?;
+            if session_millis(&sync.file_name()).is_some_and(|millis| millis < cutoff) {
+                std::fs::remove_dir_all(sync.path())?;
+                purged += 1;
+            }
+        }
+        remove_empty_dirs(&repository.path())?;
+    }
+    if purged > 0 {
+        log::info!("Purged {purged} expired sync(s) from trash");
+    }
+    Ok(purged)
+}
+
+// rename doesn't work across drives, and the cache dir is rarely on the same one as the repository on windows
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    if std::fs::rename(from, to).is_err() {
+        copy_and_remove(from, to)?;
+    }
+    Ok(())
+}
+
+fn copy_and_remove(from: &Path, to: &Path) -> Result<()> {
+    std::fs::copy(from, to)?;
+    std::fs::remove_file(from)?;
+    Ok(())
+}
+
+// returns true if `dir` was removed
+fn remove_empty_dirs(dir: &Path) -> Result<bool> {
+    if !dir.is_dir() {
+        return Ok(false);
+    }
+    let mut empty = true;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !(entry.file_type()?.is_dir() && remove_empty_dirs(&entry.path())?) {
+            empty = false;
+        }
+    }
+    if empty {
+        std::fs::remove_dir(dir)?;
+    }
+    Ok(empty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sessions_do_not_collide() -> Result<()> {
+        let trash = tempfile::tempdir()?;
+        let repo = tempfile::tempdir()?;
+        let repository = Uuid::new_v4();
+        std::fs::write(repo.path().join("a.txt"), "first")?;
+        std::fs::write(repo.path().join("b.txt"), "second")?;
+        let first = TrashSession::new_in(trash.path(), repository)?;
+        let second = TrashSession::new_in(trash.path(), repository)?;
+        assert_ne!(first.dir, second.dir);
+        first.move_file(&repo.path().join("a.txt"), "/same.txt")?;
+        second.move_file(&repo.path().join("b.txt"), "/same.txt")?;
+        let syncs = list_in(trash.path(), repository)?;
+        assert_eq!(syncs.len(), 2);
+        assert_eq!(
+            syncs.iter().map(|sync| sync.files.len()).collect::<Vec<_>>(),
+            vec![1, 1]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_moves_current_file_to_trash() -> Result<()> {
+        let trash = tempfile::tempdir()?;
+        let repo = tempfile::tempdir()?;
+        let repository = Uuid::new_v4();
+        std::fs::create_dir(repo.path().join("saves"))?;
+        let path = repo.path().join("saves/slot1.sav");
+        std::fs::write(&path, "old")?;
+        TrashSession::new_in(trash.path(), repository)?.move_file(&path, "/saves/slot1.sav")?;
+        assert!(!path.exists());
+        std::fs::write(&path, "new")?;
+
+        let syncs = list_in(trash.path(), repository)?;
+        assert_eq!(syncs.len(), 1);
+        restore_in(trash.path(), repository, repo.path(), &syncs[0].files)?;
+        assert_eq!(std::fs::read_to_string(&path)?, "old");
+
+        // the file that was in the way is now the only thing in the trash
+        let syncs = list_in(trash.path(), repository)?;
+        assert_eq!(syncs.len(), 1);
+        assert_eq!(&*syncs[0].files[0].full_path, "saves/slot1.sav");
+        assert_eq!(std::fs::read_to_string(&syncs[0].files[0].trash_path)?, "new");
+        Ok(())
+    }
+
+    #[test]
+    fn test_purge_expired() -> Result<()> {
+        let trash = tempfile::tempdir()?;
+        let repository = trash.path().join(Uuid::new_v4().to_string());
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let old = now - 10 * 24 * 60 * 60 * 1000;
+        let recent = now - 1;
+        for name in [format!("{old}-0123abcd"), format!("{recent}-0123abcd")] {
+            std::fs::create_dir_all(repository.join(&name))?;
+            std::fs::write(repository.join(&name).join("file"), "contents")?;
+        }
+        assert_eq!(purge_expired_in(trash.path(), 7)?, 1);
+        let remaining = std::fs::read_dir(&repository)?.collect::<std::io::Result<Vec<_>>>()?;
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].file_name().to_string_lossy(), format!("{recent}-0123abcd"));
+
+        assert_eq!(purge_expired_in(trash.path(), 0)?, 1);
+        assert!(!repository.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_fallback() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let from = dir.path().join("from");
+        let to = dir.path().join("to");
+        std::fs::write(&from, "contents")?;
+        copy_and_remove(&from, &to)?;
+        assert!(!from.exists());
+        assert_eq!(std::fs::read_to_string(&to)?, "contents");
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+// Self updates are signed by the server with the key whose public half is baked in at build time.
+// The exe being replaced is kept so a build that can't get through startup is rolled back, from the panic hook or on the next launch.
+// Layout: <cache dir>/pitsu_update/{new exe, previous exe, pending}
+use std::{path::PathBuf, sync::OnceLock};
+
+use anyhow::Result;
+use ed25519_dalek::{Signature, Verifier as _, VerifyingKey};
+use pitsu_lib::api::{Client, DownloadUpdate, LatestVersion};
+use self_update::self_replace;
+
+use crate::config::VERSION_NUMBER;
+
+const PUBLIC_KEY: &str = env!("PITSU_UPDATE_PUBLIC_KEY");
+// seconds the gui has to stay up before an update counts as working
+pub const HEALTHY_AFTER: f64 = 10.0;
+
+fn update_dir() -> Result<PathBuf> {
+    Ok(dirs::cache_dir()
+        .ok_or_else(|| anyhow::anyhow!("Failed to get cache directory"))?
+        .join("pitsu_update"))
+}
+
+fn previous_exe() -> Result<PathBuf> {
+    Ok(update_dir()?.join(format!("pitsu_previous{}", std::env::consts::EXE_SUFFIX)))
+}
+
+// "installed" right after replacing, "started" once the new exe has launched, removed when it's healthy
+fn pending_marker() -> Result<PathBuf> {
+    Ok(update_dir()?.join("pending"))
+}
+
+pub fn verify(bytes: &[u8], signature: &str) -> Result<()> {
+    let key: [u8; 32] = pitsu_lib::decode_base64(PUBLIC_KEY)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Built in update key is not 32 bytes"))?;
+    let key = VerifyingKey::from_bytes(&key).map_err(|e| anyhow::anyhow!("Built in update key is invalid: {e}"))?;
+    let signature = Signature::from_slice(&pitsu_lib::decode_base64(signature.trim())?)
+        .map_err(|e| anyhow::anyhow!("Update signature is malformed: {e}"))?;
+    key.verify(bytes, &signature)
+        .map_err(|_| anyhow::anyhow!("Update signature does not match, refusing to install it"))
+}
+
+pub fn apply(bytes: &[u8], signature: &str) -> Result<()> {
+    verify(bytes, signature)?;
+    let dir = update_dir()?;
+    std::fs::create_dir_all(&dir).map_err(|e| anyhow::anyhow!("Failed to create update directory: {e}"))?;
+    let this_exe =
+        std::env::current_exe().map_err(|e| anyhow::anyhow!("Failed to get current executable path: {e}"))?;
+    std::fs::copy(&this_exe, previous_exe()?)
+        .map_err(|e| anyhow::anyhow!("Failed to keep a copy of the current executable: {e}"))?;
+    let new_exe = dir.join(format!("pitsu{}", std::env::consts::EXE_SUFFIX));
+    std::fs::write(&new_exe, bytes).map_err(|e| anyhow::anyhow!("Failed to write new executable: {e}"))?;
+    log::info!("New executable written to {}", new_exe.display());
+    self_replace::self_replace(&new_exe).map_err(|e| anyhow::anyhow!("Failed to replace current executable: {e}"))?;
+    std::fs::write(pending_marker()?, "installed").map_err(|e| anyhow::anyhow!("Failed to mark update: {e}"))?;
+    log::info!("Successfully replaced current executable. Restarting PITSU...");
+    Ok(())
+}
+
+// called first thing on launch, returns true if we rolled back and the caller should restart
+pub fn check_pending() -> Result<bool> {
+    let marker = pending_marker()?;
+    match std::fs::read_to_string(&marker).ok().as_deref().map(str::trim) {
+        None => Ok(false),
+        Some("installed") => {
+            std::fs::write(&marker, "started")?;
+            Ok(false)
+        }
+        // the last launch of this build never got healthy
+        Some(_) => {
+            restore_previous()?;
+            Ok(true)
+        }
+    }
+}
+
+// for the panic hook, only a running update that hasn't got healthy yet is rolled back
+pub fn rollback_after_crash() -> Result<bool> {
+    if HEALTHY.get().is_some() {
+        return Ok(false);
+    }
+    match std::fs::read_to_string(pending_marker()?)
+        .ok()
+        .as_deref()
+        .map(str::trim)
+    {
+        Some("started") => {
+            restore_previous()?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+fn restore_previous() -> Result<()> {
+    std::fs::remove_file(pending_marker()?)?;
+    let previous = previous_exe()?;
+    if !previous.exists() {
+        return Err(anyhow::anyhow!(
+            "Update failed to start and there is no previous version to restore"
+        ));
+    }
+    self_replace::self_replace(&previous).map_err(|e| anyhow::anyhow!("Failed to restore previous executable: {e}"))?;
+    log::warn!("Update failed to start, restored the previous version");
+    Ok(())
+}
+
+static HEALTHY: OnceLock<()> = OnceLock::new();
+
+pub fn mark_healthy() {
+    HEALTHY.get_or_init(|| {
+        let Ok(marker) = pending_marker() else {
+            return;
+        };
+        if marker.exists() {
+            match std::fs::remove_file(&marker) {
+                Ok(()) => log::info!("Update started successfully"),
+                Err(e) => log::error!("Failed to clear pending update marker: {e}"),
+            }
+        }
+    });
+}
+
+// blocking version of the update button for the panic hook, the event loop is gone by then
+// returns false if there was nothing newer to offer or the user said no
+pub fn offer_after_crash() -> Result<bool> {
+    let connection = crate::config::crash_connection().ok_or(anyhow::anyhow!("Not connected to a server yet"))?;
+    let client = Client::new(&connection.url, &connection.api_key);
+    let offered = client
+        .call(LatestVersion {
+            channel: connection.channel,
+            target: env!("PITSU_TARGET").into(),
+        })
+        .map_err(|e| anyhow::anyhow!("Failed to check for updates: {e}"))?;
+    // a rebuild of the same version counts, it may well contain the fix
+    if offered == *VERSION_NUMBER || offered.semver() < VERSION_NUMBER.semver() {
+        return Ok(false);
+    }
+    if !crate::dialogue::rfd_confirm_response(
+        &format!(
+            "Pitsu {offered} is available, you are running {}.\n\nWould you like to download and install it now?",
+            *VERSION_NUMBER
+        ),
+        false,
+    )? {
+        return Ok(false);
+    }
+    let update = client
+        .call(DownloadUpdate {
+            channel: connection.channel,
+            target: env!("PITSU_TARGET").into(),
+        })
+        .map_err(|e| anyhow::anyhow!("Failed to download update: {e}"))?;
+    apply(&update.executable, &update.signature)?;
+    std::process::Command::new(std::env::current_exe()?).spawn()?;
+    std::process::exit(0);
+}
@@ -0,0 +1,104 @@
+// registers pitsu:// with the OS so links in a browser or chat open the app on that repository,
+// or hand it the api key from an invite (see config::setup).
+// done on every start, so the registration follows the executable when it moves or is updated
+use anyhow::Result;
+
+const SCHEME: &str = "pitsu";
+
+pub fn register() {
+    if let Err(e) = register_current_exe() {
+        log::warn!("Failed to register the {SCHEME}:// link handler: {e}");
+    }
+}
+
+fn register_current_exe() -> Result<()> {
+    let exe = std::env::current_exe()?;
+    platform::register(&exe.to_string_lossy())
+}
+
+#[cfg(windows)]
+mod platform {
+    use anyhow::Result;
+
+    use super::SCHEME;
+
+    // per user keys under HKCU\Software\Classes need no elevation
+    pub fn register(exe: &str) -> Result<()> {
+        let key = format!(r"HKCU\Software\Classes\{SCHEME}");
+        reg_add(&key, None, "URL:PITSU Repository")?;
+        reg_add(&key, Some("URL Protocol"), "")?;
+        reg_add(&format!(r"{key}\DefaultIcon"), None, &format!("\"{exe}\",0"))?;
+        reg_add(
+            &format!(r"{key}\shell\open\command"),
+            None,
+            &format!("\"{exe}\" --open \"%1\""),
+        )
+    }
+
+    fn reg_add(key: &str, value: Option<&str>, data: &str) -> Result<()> {
+        use std::os::windows::process::CommandExt as _;
+        // CREATE_NO_WINDOW, otherwise every start flashes a console
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        let mut command = std::process::Command::new("reg");
+        command.args(["add", key]);
+        match value {
+            Some(value) => command.args(["/v", value]),
+            None => command.arg("/ve"),
+        };
+        let status = command
+            .args(["/t", "REG_SZ", "/d", data, "/f"])
+            .creation_flags(CREATE_NO_WINDOW)
+            .status()?;
+        if !status.success() {
+            anyhow::bail!("reg add {key} exited with {status}");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use anyhow::Result;
+
+    use super::SCHEME;
+
+    const DESKTOP_FILE: &str = "pitsu-url-handler.desktop";
+
+    pub fn register(exe: &str) -> Result<()> {
+        let applications = dirs::data_dir()
+            .ok_or_else(|| anyhow::anyhow!("Failed to get data directory"))?
+            .join("applications");
+        std::fs::create_dir_all(&applications)?;
+        let entry = format!(
+            "[Desktop Entry]\nType=Application\nName=PITSU\nExec=\"{}\" --open %u\nMimeType=x-scheme-handler/{SCHEME};\nNoDisplay=true\nTerminal=false\n",
+            exe.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+        let path = applications.join(DESKTOP_FILE);
+        if std::fs::read_to_string(&path).is_ok_and(|existing| existing == entry) {
+            return Ok(());
+        }
+        std::fs::write(&path, entry)?;
+        let result = std::process::Command::new("xdg-mime")
+            .args(["default", DESKTOP_FILE, &format!("x-scheme-handler/{SCHEME}")])
+            .status()
+            .map_err(anyhow::Error::from)
+            .and_then(|status| match status.success() {
+                true => Ok(()),
+                false => Err(anyhow::anyhow!("xdg-mime exited with {status}")),
+            });
+        if result.is_err() {
+            // so the next start tries again instead of seeing an up to date entry
+            std::fs::remove_file(&path).ok();
+        }
+        result
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+mod platform {
+    use anyhow::Result;
+
+    pub fn register(_exe: &str) -> Result<()> {
+        anyhow::bail!("Link handlers can only be registered on Windows and Linux")
+    }
+}
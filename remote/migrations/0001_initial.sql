@@ -1,4 +1,6 @@
-CREATE TABLE Users (
+-- The schema as it was before migrations were tracked. Everything is IF NOT EXISTS so databases
+-- created from the old schema.sql are adopted as they are.
+CREATE TABLE IF NOT EXISTS Users (
     uuid UUID PRIMARY KEY DEFAULT gen_random_uuid(),
     username TEXT NOT NULL UNIQUE,
     api_key TEXT NOT NULL UNIQUE DEFAULT gen_random_uuid(),
@@ -6,7 +8,7 @@ CREATE TABLE Users (
     updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
 );
 
-CREATE TABLE Repositories (
+CREATE TABLE IF NOT EXISTS Repositories (
     uuid UUID PRIMARY KEY DEFAULT gen_random_uuid(),
     name TEXT NOT NULL,
     owner_uuid UUID NOT NULL REFERENCES Users(uuid) ON DELETE CASCADE,
@@ -15,15 +17,18 @@ CREATE TABLE Repositories (
     updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
 );
 
-CREATE TYPE access_level AS ENUM ('NONE', 'READ', 'WRITE', 'ADMIN', 'OWNER');
+DO $$ BEGIN
+    CREATE TYPE access_level AS ENUM ('NONE', 'READ', 'WRITE', 'ADMIN', 'OWNER');
+EXCEPTION
+    WHEN duplicate_object THEN NULL;
+END $$;
 
-CREATE TABLE Access (
+CREATE TABLE IF NOT EXISTS Access (
     uuid UUID PRIMARY KEY DEFAULT gen_random_uuid(),
     repository_uuid UUID NOT NULL REFERENCES Repositories(uuid) ON DELETE CASCADE,
     user_uuid UUID NOT NULL REFERENCES Users(uuid) ON DELETE CASCADE,
-    -- access_level TEXT NOT NULL CHECK (access_level IN ('READ', 'WRITE', 'ADMIN')),
     access_level access_level NOT NULL CHECK (access_level IN ('READ', 'WRITE', 'ADMIN')), -- 'NONE' and 'OWNER' are not included here as they are generated on-the-fly during queries
     created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
     updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
     UNIQUE (repository_uuid, user_uuid)
-);
\ No newline at end of file
+);
@@ -0,0 +1,2 @@
+-- Path template suggested to clients, empty for none
+ALTER TABLE Repositories ADD COLUMN IF NOT EXISTS recommended_path TEXT NOT NULL DEFAULT '';
@@ -4,7 +4,8 @@
 --     owner_uuid UUID NOT NULL REFERENCES Users(uuid) ON DELETE CASCADE,
 --     file_hashes JSONB NOT NULL DEFAULT '{}', -- Updated when someone with write access pushes changes
 --     created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
---     updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+--     updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+--     recommended_path TEXT NOT NULL DEFAULT ''
 -- );
 
 --! create
@@ -42,6 +43,6 @@ UPDATE Repositories
 
 --! update_metadata_by_uuid
 UPDATE Repositories
-    SET name = :name, updated_at = CURRENT_TIMESTAMP
+    SET name = :name, recommended_path = :recommended_path, updated_at = CURRENT_TIMESTAMP
     WHERE uuid = :uuid
     RETURNING *;
\ No newline at end of file
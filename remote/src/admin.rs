@@ -0,0 +1,463 @@
+// `remote repo ...` subcommands, everything that used to need psql by hand
+// text is for people, json and tsv are for scripts (tsv has no header row, columns are listed per command)
+use std::path::Path;
+
+use aws_sdk_s3::Client as S3Client;
+use cornucopia_async::GenericClient;
+use deadpool_postgres::Pool;
+use pitsu_lib::{
+    anyhow::{self, Result},
+    readable_size, AccessLevel, RootFolder, SimpleRemoteRepository, User, UserWithAccess,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::cornucopia::queries::{access, repository, user};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Tsv,
+}
+
+// owner can't be granted, it belongs to whoever created the repository
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GrantLevel {
+    Read,
+    Write,
+    Admin,
+}
+
+impl From<GrantLevel> for AccessLevel {
+    fn from(level: GrantLevel) -> Self {
+        match level {
+            GrantLevel::Read => AccessLevel::Read,
+            GrantLevel::Write => AccessLevel::Write,
+            GrantLevel::Admin => AccessLevel::Admin,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RepositoryDetails {
+    uuid: Uuid,
+    name: String,
+    owner: User,
+    recommended_path: String,
+    size: u64,
+    file_count: usize,
+    created_at: String,
+    updated_at: String,
+    users: Vec<UserWithAccess>,
+}
+
+#[derive(Serialize)]
+struct Renamed {
+    uuid: Uuid,
+    previous_name: String,
+    name: String,
+}
+
+// access_level is none after a revoke
+#[derive(Serialize)]
+struct AccessChange {
+    repository: Uuid,
+    user: User,
+    access_level: AccessLevel,
+}
+
+#[derive(Serialize)]
+struct DiskUsage {
+    uuid: Uuid,
+    name: String,
+    // what the file index says, this is what clients see
+    indexed_size: u64,
+    file_count: usize,
+    // what is actually in ROOT_FOLDER, includes .pitignore and anything not yet hashed
+    disk_size: u64,
+}
+
+fn repository_folder(uuid: &Uuid) -> String {
+    format!("{}/{}", crate::config::get().root_folder(), uuid)
+}
+
+fn parse_files(file_hashes: serde_json::Value) -> Result<RootFolder> {
+    serde_json::from_value(file_hashes).map_err(|err| anyhow::anyhow!("Failed to parse file hashes: {err}"))
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<()> {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).map_err(|err| anyhow::anyhow!("Failed to serialize output: {err}"))?
+    );
+    Ok(())
+}
+
+async fn user_by_name<C: GenericClient>(client: &C, username: &str) -> Result<user::GetByUsername> {
+    user::get_by_username()
+        .bind(client, &username)
+        .opt()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to fetch user {username}: {err}"))?
+        .ok_or_else(|| anyhow::anyhow!("No user named {username}"))
+}
+
+async fn repository_by_uuid<C: GenericClient>(client: &C, uuid: &Uuid) -> Result<repository::GetByUuid> {
+    repository::get_by_uuid()
+        .bind(client, uuid)
+        .opt()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to fetch repository {uuid}: {err}"))?
+        .ok_or_else(|| anyhow::anyhow!("No repository with UUID {uuid}"))
+}
+
+async fn get_connection(pool: &Pool) -> Result<deadpool_postgres::Client> {
+    pool.get()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to get database connection: {err}"))
+}
+
+// tsv: uuid, name, size, file count
+pub async fn list(pool: &Pool, format: OutputFormat) -> Result<()> {
+    let connection = get_connection(pool).await?;
+    let repos = repository::get_all()
+        .bind(&connection)
+        .all()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to list repositories: {err}"))?;
+    let mut simple = Vec::with_capacity(repos.len());
+    for repo in repos {
+        let files = parse_files(repo.file_hashes)?;
+        simple.push(SimpleRemoteRepository {
+            uuid: repo.uuid,
+            name: repo.name.into(),
+            access_level: AccessLevel::Owner,
+            size: files.size(),
+            file_count: files.file_count(),
+        });
+    }
+    match format {
+        OutputFormat::Text => {
+            println!("Repositories:");
+            for repo in simple.iter() {
+                println!("- {} <{}>", repo.name, repo.uuid);
+            }
+            if simple.is_empty() {
+                println!("No repositories found.");
+            }
+        }
+        OutputFormat::Json => print_json(&simple)?,
+        OutputFormat::Tsv => {
+            for repo in simple.iter() {
+                println!("{}\t{}\t{}\t{}", repo.uuid, repo.name, repo.size, repo.file_count);
+            }
+        }
+    }
+    Ok(())
+}
+
+// tsv: uuid
+pub async fn create(pool: &Pool, name: &str, owner: &str, format: OutputFormat) -> Result<()> {
+    let mut connection = get_connection(pool).await?;
+    let transaction = connection
+        .transaction()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to start transaction: {err}"))?;
+    let owner = user_by_name(&transaction, owner).await?;
+    let repo = repository::create()
+        .bind(&transaction, &name, &owner.uuid)
+        .one()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to create repository: {err}"))?;
+    transaction
+        .commit()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to commit transaction: {err}"))?;
+    match format {
+        OutputFormat::Text => println!(
+            "Created repository {} <{}> owned by {}",
+            repo.name, repo.uuid, owner.username
+        ),
+        OutputFormat::Json => print_json(&SimpleRemoteRepository {
+            uuid: repo.uuid,
+            name: repo.name.into(),
+            access_level: AccessLevel::Owner,
+            size: 0,
+            file_count: 0,
+        })?,
+        OutputFormat::Tsv => println!("{}", repo.uuid),
+    }
+    Ok(())
+}
+
+// tsv: uuid, new name
+pub async fn rename(pool: &Pool, uuid: &Uuid, name: &str, format: OutputFormat) -> Result<()> {
+    let connection = get_connection(pool).await?;
+    let repo = repository_by_uuid(&connection, uuid).await?;
+    repository::update_metadata_by_uuid()
+        .bind(&connection, &name, &repo.recommended_path, uuid)
+        .one()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to rename repository: {err}"))?;
+    let renamed = Renamed {
+        uuid: *uuid,
+        previous_name: repo.name,
+        name: name.to_string(),
+    };
+    match format {
+        OutputFormat::Text => println!("Renamed repository {} to {}", renamed.previous_name, renamed.name),
+        OutputFormat::Json => print_json(&renamed)?,
+        OutputFormat::Tsv => println!("{}\t{}", renamed.uuid, renamed.name),
+    }
+    Ok(())
+}
+
+// drops the row (access goes with it), the files on disk and their copies in s3
+// tsv: uuid
+pub async fn delete(pool: &Pool, s3_client: &S3Client, uuid: &Uuid, yes: bool, format: OutputFormat) -> Result<()> {
+    let connection = get_connection(pool).await?;
+    let repo = repository_by_uuid(&connection, uuid).await?;
+    if !yes {
+        return Err(anyhow::anyhow!(
+            "This permanently deletes {} <{uuid}> and all of its files, pass --yes to confirm",
+            repo.name
+        ));
+    }
+    repository::delete_by_uuid()
+        .bind(&connection, uuid)
+        .all()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to delete repository: {err}"))?;
+    let folder = repository_folder(uuid);
+    if Path::new(&folder).exists() {
+        tokio::fs::remove_dir_all(&folder)
+            .await
+            .map_err(|err| anyhow::anyhow!("Repository row deleted but failed to remove {folder}: {err}"))?;
+    }
+    let (keys, _) = crate::get_all_from_s3(s3_client).await?;
+    for key in keys.iter().filter(|key| key.repo_uuid == *uuid) {
+        if let Err(err) = crate::remove_from_s3(s3_client, key).await {
+            log::error!("Failed to remove {key} from S3: {err}");
+        }
+    }
+    match format {
+        OutputFormat::Text => println!("Deleted repository {} <{uuid}>", repo.name),
+        OutputFormat::Json => print_json(&SimpleRemoteRepository {
+            uuid: *uuid,
+            name: repo.name.into(),
+            access_level: AccessLevel::Owner,
+            size: 0,
+            file_count: 0,
+        })?,
+        OutputFormat::Tsv => println!("{uuid}"),
+    }
+    Ok(())
+}
+
+// tsv: one row per user, access level, username, user uuid
+pub async fn show(pool: &Pool, uuid: &Uuid, format: OutputFormat) -> Result<()> {
+    let connection = get_connection(pool).await?;
+    let repo = repository_by_uuid(&connection, uuid).await?;
+    let owner = user::get_by_uuid()
+        .bind(&connection, &repo.owner_uuid)
+        .one()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to fetch owner: {err}"))?;
+    let users = access::get_all_users_with_access()
+        .bind(&connection, uuid)
+        .all()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to fetch users with access: {err}"))?;
+    let files = parse_files(repo.file_hashes)?;
+    let details = RepositoryDetails {
+        uuid: repo.uuid,
+        name: repo.name,
+        owner: User {
+            uuid: owner.uuid,
+            username: owner.username.into(),
+        },
+        recommended_path: repo.recommended_path,
+        size: files.size(),
+        file_count: files.file_count(),
+        created_at: repo.created_at.to_string(),
+        updated_at: repo.updated_at.to_string(),
+        users: users
+            .into_iter()
+            .map(|user| UserWithAccess {
+                user: User {
+                    uuid: user.user_uuid,
+                    username: user.username.into(),
+                },
+                access_level: user.access_level.into(),
+            })
+            .collect(),
+    };
+    match format {
+        OutputFormat::Text => {
+            println!("{} <{}>", details.name, details.uuid);
+            println!("  owner: {} <{}>", details.owner.username, details.owner.uuid);
+            if !details.recommended_path.is_empty() {
+                println!("  recommended path: {}", details.recommended_path);
+            }
+            println!("  files: {} ({})", details.file_count, readable_size(details.size));
+            println!("  created: {}", details.created_at);
+            println!("  updated: {}", details.updated_at);
+            println!("  access:");
+            for user in details.users.iter() {
+                println!(
+                    "    {:<6} {} <{}>",
+                    user.access_level, user.user.username, user.user.uuid
+                );
+            }
+        }
+        OutputFormat::Json => print_json(&details)?,
+        OutputFormat::Tsv => {
+            for user in details.users.iter() {
+                println!("{}\t{}\t{}", user.access_level, user.user.username, user.user.uuid);
+            }
+        }
+    }
+    Ok(())
+}
+
+// tsv: repository uuid, username, user uuid, access level
+pub async fn grant(pool: &Pool, uuid: &Uuid, username: &str, level: GrantLevel, format: OutputFormat) -> Result<()> {
+    let connection = get_connection(pool).await?;
+    let repo = repository_by_uuid(&connection, uuid).await?;
+    let user = user_by_name(&connection, username).await?;
+    if user.uuid == repo.owner_uuid {
+        return Err(anyhow::anyhow!(
+            "{username} owns {}, their access can't be changed",
+            repo.name
+        ));
+    }
+    access::create_or_update()
+        .bind(&connection, uuid, &user.uuid, &AccessLevel::from(level).into())
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to update access level: {err}"))?;
+    match format {
+        OutputFormat::Text => println!(
+            "Granted {username} {} access to {}",
+            AccessLevel::from(level),
+            repo.name
+        ),
+        _ => print_access_change(uuid, user, AccessLevel::from(level), format)?,
+    }
+    Ok(())
+}
+
+// tsv: repository uuid, username, user uuid, none
+pub async fn revoke(pool: &Pool, uuid: &Uuid, username: &str, format: OutputFormat) -> Result<()> {
+    let connection = get_connection(pool).await?;
+    let repo = repository_by_uuid(&connection, uuid).await?;
+    let user = user_by_name(&connection, username).await?;
+    let removed = access::delete_by_user_uuid_and_repository_uuid()
+        .bind(&connection, &user.uuid, uuid)
+        .all()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to revoke access: {err}"))?;
+    if removed.is_empty() {
+        return Err(anyhow::anyhow!("{username} had no access to {}", repo.name));
+    }
+    match format {
+        OutputFormat::Text => println!("Revoked {username}'s access to {}", repo.name),
+        _ => print_access_change(uuid, user, AccessLevel::None, format)?,
+    }
+    Ok(())
+}
+
+fn print_access_change(
+    repository: &Uuid,
+    user: user::GetByUsername,
+    access_level: AccessLevel,
+    format: OutputFormat,
+) -> Result<()> {
+    let change = AccessChange {
+        repository: *repository,
+        user: User {
+            uuid: user.uuid,
+            username: user.username.into(),
+        },
+        access_level,
+    };
+    match format {
+        OutputFormat::Json => print_json(&change)?,
+        _ => println!(
+            "{}\t{}\t{}\t{}",
+            change.repository, change.user.username, change.user.uuid, change.access_level
+        ),
+    }
+    Ok(())
+}
+
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => disk_usage(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+// tsv: uuid, name, indexed size, file count, disk size
+pub async fn du(pool: &Pool, only: Option<Uuid>, format: OutputFormat) -> Result<()> {
+    let connection = get_connection(pool).await?;
+    let mut repos = repository::get_all()
+        .bind(&connection)
+        .all()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to list repositories: {err}"))?;
+    if let Some(uuid) = only {
+        repos.retain(|repo| repo.uuid == uuid);
+        if repos.is_empty() {
+            return Err(anyhow::anyhow!("No repository with UUID {uuid}"));
+        }
+    }
+    let mut usage = Vec::with_capacity(repos.len());
+    for repo in repos {
+        let files = parse_files(repo.file_hashes)?;
+        let folder = repository_folder(&repo.uuid);
+        usage.push(DiskUsage {
+            uuid: repo.uuid,
+            name: repo.name,
+            indexed_size: files.size(),
+            file_count: files.file_count(),
+            disk_size: tokio::task::spawn_blocking(move || disk_usage(Path::new(&folder))).await?,
+        });
+    }
+    usage.sort_by(|a, b| b.disk_size.cmp(&a.disk_size));
+    match format {
+        OutputFormat::Text => {
+            for repo in usage.iter() {
+                println!(
+                    "{:>10} {:>8} files  {} <{}>",
+                    readable_size(repo.disk_size),
+                    repo.file_count,
+                    repo.name,
+                    repo.uuid
+                );
+            }
+            println!(
+                "{:>10} total",
+                readable_size(usage.iter().map(|repo| repo.disk_size).sum())
+            );
+        }
+        OutputFormat::Json => print_json(&usage)?,
+        OutputFormat::Tsv => {
+            for repo in usage.iter() {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    repo.uuid, repo.name, repo.indexed_size, repo.file_count, repo.disk_size
+                );
+            }
+        }
+    }
+    Ok(())
+}
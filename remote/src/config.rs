@@ -0,0 +1,361 @@
+// server settings, loaded once at startup instead of being baked in with env!
+// read from a toml file of KEY = "value" pairs (PITSU_CONFIG, default pitsu-server.toml next to the working directory),
+// then any environment variable with the same name wins. `remote config check` reports what's missing
+use std::{collections::BTreeMap, path::PathBuf, sync::OnceLock};
+
+use pitsu_lib::anyhow::{self, Result};
+
+struct Key {
+    name: &'static str,
+    required: bool,
+    // never printed, only whether it's set
+    secret: bool,
+    default: Option<&'static str>,
+}
+
+const fn required(name: &'static str) -> Key {
+    Key {
+        name,
+        required: true,
+        secret: false,
+        default: None,
+    }
+}
+
+const fn secret(name: &'static str) -> Key {
+    Key {
+        name,
+        required: true,
+        secret: true,
+        default: None,
+    }
+}
+
+const fn optional(name: &'static str, default: Option<&'static str>) -> Key {
+    Key {
+        name,
+        required: false,
+        secret: false,
+        default,
+    }
+}
+
+const fn optional_secret(name: &'static str) -> Key {
+    Key {
+        name,
+        required: false,
+        secret: true,
+        default: None,
+    }
+}
+
+const KEYS: &[Key] = &[
+    // `run --host` and `--port` win over these
+    optional("HOST", Some("0.0.0.0")),
+    optional("PORT", Some("8080")),
+    optional("ROOT_FOLDER", Some("repositories")),
+    optional("ARTIFACT_FOLDER", Some("artifacts")),
+    required("PITSU_PUBLIC_URL"),
+    required("POSTGRES_USER"),
+    secret("POSTGRES_PASSWORD"),
+    optional("POSTGRES_HOST", Some("localhost")),
+    optional("POSTGRES_PORT", Some("5432")),
+    required("POSTGRES_DB"),
+    required("AWS_BUCKET_NAME"),
+    required("AWS_REGION"),
+    secret("AWS_ACCESS_KEY_ID"),
+    secret("AWS_SECRET_ACCESS_KEY"),
+    secret("INVITE_CODE_PREFIX"),
+    secret("INVITE_CODE_SUFFIX"),
+    secret("INVITE_CODE_ENCRYPTION_KEY"),
+    // only `release` needs it, the running server just serves what was already signed
+    optional_secret("CLIENT_SIGNING_KEY"),
+    optional_secret("REMOTE_SEQ_API_KEY"),
+    // /metrics is only served with this as the bearer token
+    optional_secret("METRICS_TOKEN"),
+    // passed through to client builds, the client keeps compiling these in
+    optional_secret("LOCAL_SEQ_API_KEY"),
+    optional("SEQ_API_URL", None),
+    optional("SEQ_LOG_LEVEL", None),
+    // comma separated addresses of reverse proxies, X-Forwarded-For is only believed from these
+    optional("TRUSTED_PROXIES", None),
+    // per user, requests a minute and transfers in flight at once. 0 turns a limit off
+    optional("RATE_LIMIT_METADATA", Some("600")),
+    optional("RATE_LIMIT_DOWNLOADS", Some("3000")),
+    optional("RATE_LIMIT_UPLOADS", Some("600")),
+    optional("MAX_CONCURRENT_DOWNLOADS", Some("16")),
+    optional("MAX_CONCURRENT_UPLOADS", Some("4")),
+];
+
+// only compiled into the client, allowed so a copy of .cargo/config.toml loads without complaints
+const BUILD_KEYS: &[&str] = &["PITSU_UPDATE_PUBLIC_KEY", "VERSION_HASH"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    File,
+    Environment,
+    Default,
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::File => write!(f, "file"),
+            Source::Environment => write!(f, "env"),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ServerConfig {
+    file: Option<PathBuf>,
+    values: BTreeMap<&'static str, (String, Source)>,
+}
+
+static CONFIG: OnceLock<ServerConfig> = OnceLock::new();
+
+// panics if called before init, main loads the config before anything else runs
+pub fn get() -> &'static ServerConfig {
+    CONFIG.get().expect("Server configuration used before it was loaded")
+}
+
+pub fn init() -> Result<&'static ServerConfig> {
+    let config = ServerConfig::load()?;
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+fn config_path() -> PathBuf {
+    PathBuf::from(std::env::var("PITSU_CONFIG").unwrap_or_else(|_| "pitsu-server.toml".to_string()))
+}
+
+impl ServerConfig {
+    fn load() -> Result<Self> {
+        let path = config_path();
+        let mut table = toml::Table::new();
+        let file = if path.exists() {
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|err| anyhow::anyhow!("Failed to read {}: {err}", path.display()))?;
+            table = contents
+                .parse::<toml::Table>()
+                .map_err(|err| anyhow::anyhow!("Failed to parse {}: {err}", path.display()))?;
+            Some(path)
+        } else if std::env::var("PITSU_CONFIG").is_ok() {
+            return Err(anyhow::anyhow!(
+                "PITSU_CONFIG points at {} which does not exist",
+                path.display()
+            ));
+        } else {
+            None
+        };
+        // accept the [env] table of a cargo config too, that's where these used to live
+        if let Some(toml::Value::Table(env)) = table.remove("env") {
+            table.extend(env);
+        }
+        // printed, the logger is set up from this config so it isn't running yet
+        for name in table.keys() {
+            if !KEYS.iter().any(|key| key.name == name) && !BUILD_KEYS.contains(&name.as_str()) {
+                eprintln!("Ignoring unknown configuration key {name}");
+            }
+        }
+        let mut values = BTreeMap::new();
+        for key in KEYS {
+            let value = if let Ok(value) = std::env::var(key.name) {
+                Some((value, Source::Environment))
+            } else if let Some(value) = table.get(key.name) {
+                let value = match value {
+                    toml::Value::String(value) => value.clone(),
+                    toml::Value::Integer(_) | toml::Value::Boolean(_) | toml::Value::Float(_) => value.to_string(),
+                    _ => return Err(anyhow::anyhow!("{} must be a string", key.name)),
+                };
+                Some((value, Source::File))
+            } else {
+                key.default.map(|value| (value.to_string(), Source::Default))
+            };
+            if let Some(value) = value {
+                values.insert(key.name, value);
+            }
+        }
+        Ok(Self { file, values })
+    }
+
+    fn value(&self, name: &str) -> &str {
+        self.values.get(name).map_or("", |(value, _)| value.as_str())
+    }
+
+    fn optional_value(&self, name: &str) -> Option<&str> {
+        Some(self.value(name)).filter(|value| !value.is_empty())
+    }
+
+    pub fn host(&self) -> &str {
+        self.value("HOST")
+    }
+    pub fn port(&self) -> Result<u16> {
+        self.value("PORT")
+            .parse()
+            .map_err(|err| anyhow::anyhow!("PORT is not a port number: {err}"))
+    }
+    pub fn root_folder(&self) -> &str {
+        self.value("ROOT_FOLDER")
+    }
+    pub fn artifact_folder(&self) -> &str {
+        self.value("ARTIFACT_FOLDER")
+    }
+    pub fn public_url(&self) -> &str {
+        self.value("PITSU_PUBLIC_URL").trim_end_matches('/')
+    }
+    pub fn postgres(&self) -> Result<deadpool_postgres::Config> {
+        let mut cfg = deadpool_postgres::Config::new();
+        cfg.user = Some(self.value("POSTGRES_USER").to_string());
+        cfg.password = Some(self.value("POSTGRES_PASSWORD").to_string());
+        cfg.host = Some(self.value("POSTGRES_HOST").to_string());
+        cfg.port = Some(
+            self.value("POSTGRES_PORT")
+                .parse()
+                .map_err(|err| anyhow::anyhow!("POSTGRES_PORT is not a port number: {err}"))?,
+        );
+        cfg.dbname = Some(self.value("POSTGRES_DB").to_string());
+        Ok(cfg)
+    }
+    pub fn aws_bucket_name(&self) -> &str {
+        self.value("AWS_BUCKET_NAME")
+    }
+    pub fn aws_region(&self) -> &str {
+        self.value("AWS_REGION")
+    }
+    pub fn aws_credentials(&self) -> aws_sdk_s3::config::Credentials {
+        aws_sdk_s3::config::Credentials::new(
+            self.value("AWS_ACCESS_KEY_ID"),
+            self.value("AWS_SECRET_ACCESS_KEY"),
+            None,
+            None,
+            "pitsu-server",
+        )
+    }
+    pub fn invite_code_prefix(&self) -> &str {
+        self.value("INVITE_CODE_PREFIX")
+    }
+    pub fn invite_code_suffix(&self) -> &str {
+        self.value("INVITE_CODE_SUFFIX")
+    }
+    pub fn invite_code_encryption_key(&self) -> &str {
+        self.value("INVITE_CODE_ENCRYPTION_KEY")
+    }
+    pub fn client_signing_key(&self) -> Option<&str> {
+        self.optional_value("CLIENT_SIGNING_KEY")
+    }
+    pub fn metrics_token(&self) -> Option<&str> {
+        self.optional_value("METRICS_TOKEN")
+    }
+    pub fn trusted_proxies(&self) -> Result<Vec<std::net::IpAddr>> {
+        self.value("TRUSTED_PROXIES")
+            .split(',')
+            .map(str::trim)
+            .filter(|proxy| !proxy.is_empty())
+            .map(|proxy| {
+                proxy
+                    .parse()
+                    .map_err(|err| anyhow::anyhow!("TRUSTED_PROXIES entry {proxy} is not an IP address: {err}"))
+            })
+            .collect()
+    }
+    pub fn rate_limits(&self) -> Result<crate::ratelimit::Limits> {
+        let number = |name: &str| {
+            self.value(name)
+                .parse::<u32>()
+                .map_err(|err| anyhow::anyhow!("{name} is not a number: {err}"))
+        };
+        Ok(crate::ratelimit::Limits {
+            metadata_per_minute: number("RATE_LIMIT_METADATA")?,
+            downloads_per_minute: number("RATE_LIMIT_DOWNLOADS")?,
+            uploads_per_minute: number("RATE_LIMIT_UPLOADS")?,
+            concurrent_downloads: number("MAX_CONCURRENT_DOWNLOADS")?,
+            concurrent_uploads: number("MAX_CONCURRENT_UPLOADS")?,
+        })
+    }
+    // settings the client build reads with env!, forwarded to cargo
+    pub fn client_build_env(&self) -> Vec<(&'static str, &str)> {
+        ["PITSU_PUBLIC_URL", "LOCAL_SEQ_API_KEY", "SEQ_API_URL", "SEQ_LOG_LEVEL"]
+            .into_iter()
+            .filter_map(|name| self.optional_value(name).map(|value| (name, value)))
+            .collect()
+    }
+    // datalust_logger reads its settings from the environment
+    pub fn logger_env(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("SEQ_API_KEY", "REMOTE_SEQ_API_KEY"),
+            ("SEQ_API_URL", "SEQ_API_URL"),
+            ("SEQ_LOG_LEVEL", "SEQ_LOG_LEVEL"),
+        ]
+        .into_iter()
+        .filter_map(|(env, name)| self.optional_value(name).map(|value| (env, value)))
+        .collect()
+    }
+
+    // everything that stops the server from starting, `run` refuses to go on with any of these
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        for key in KEYS.iter().filter(|key| key.required) {
+            if self.value(key.name).is_empty() {
+                problems.push(format!("{} is not set", key.name));
+            }
+        }
+        if let Err(err) = self.port() {
+            problems.push(err.to_string());
+        }
+        if let Err(err) = self.postgres() {
+            problems.push(err.to_string());
+        }
+        if let Err(err) = self.rate_limits() {
+            problems.push(err.to_string());
+        }
+        if let Err(err) = self.trusted_proxies() {
+            problems.push(err.to_string());
+        }
+        if !self.public_url().is_empty()
+            && !(self.public_url().starts_with("http://") || self.public_url().starts_with("https://"))
+        {
+            problems.push("PITSU_PUBLIC_URL must start with http:// or https://".to_string());
+        }
+        if !std::path::Path::new(self.root_folder()).is_dir() {
+            problems.push(format!("ROOT_FOLDER {} is not a directory", self.root_folder()));
+        }
+        if self.client_signing_key().is_some() {
+            if let Err(err) = crate::client_signing_key() {
+                problems.push(format!("CLIENT_SIGNING_KEY is invalid: {err}"));
+            }
+        }
+        problems
+    }
+
+    // the server still starts, but a feature won't work
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = vec![];
+        if self.client_signing_key().is_some() {
+            warnings.push(
+                "CLIENT_SIGNING_KEY is set, only `release` needs it so it can be left out of the server's environment"
+                    .to_string(),
+            );
+        }
+        warnings
+    }
+
+    // for `remote config check`
+    pub fn print(&self) {
+        match &self.file {
+            Some(path) => println!("Configuration file: {}", path.display()),
+            None => println!(
+                "Configuration file: none ({} not found), environment only",
+                config_path().display()
+            ),
+        }
+        for key in KEYS {
+            match self.values.get(key.name) {
+                Some((_, source)) if key.secret => println!("  {:<28} set ({source})", key.name),
+                Some((value, source)) => println!("  {:<28} {value} ({source})", key.name),
+                None if key.required => println!("  {:<28} MISSING", key.name),
+                None => println!("  {:<28} -", key.name),
+            }
+        }
+    }
+}
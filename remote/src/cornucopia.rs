@@ -337,12 +337,12 @@ repository_uuid: &'a uuid::Uuid,) -> GetAllUsersWithAccessQuery<'a,C, GetAllUser
         |row| { GetAllUsersWithAccessBorrowed { user_uuid: row.get(0),access_level: row.get(1),username: row.get(2),} }, mapper: |it| { <GetAllUsersWithAccess>::from(it) },
     }
 } }}pub mod repository
-{ use futures::{{StreamExt, TryStreamExt}};use futures; use cornucopia_async::GenericClient;#[derive( Debug)] pub struct CreateParams<T1: cornucopia_async::StringSql,> { pub name: T1,pub owner_uuid: uuid::Uuid,}#[derive( Debug)] pub struct GetByNameAndOwnerParams<T1: cornucopia_async::StringSql,> { pub name: T1,pub owner_uuid: uuid::Uuid,}#[derive( Debug)] pub struct UpdateFileHashesByUuidParams<T1: cornucopia_async::JsonSql,> { pub file_hashes: T1,pub uuid: uuid::Uuid,}#[derive( Debug)] pub struct UpdateMetadataByUuidParams<T1: cornucopia_async::StringSql,> { pub name: T1,pub uuid: uuid::Uuid,}#[derive( Debug, Clone, PartialEq,)] pub struct Create
-{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}pub struct CreateBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}
+{ use futures::{{StreamExt, TryStreamExt}};use futures; use cornucopia_async::GenericClient;#[derive( Debug)] pub struct CreateParams<T1: cornucopia_async::StringSql,> { pub name: T1,pub owner_uuid: uuid::Uuid,}#[derive( Debug)] pub struct GetByNameAndOwnerParams<T1: cornucopia_async::StringSql,> { pub name: T1,pub owner_uuid: uuid::Uuid,}#[derive( Debug)] pub struct UpdateFileHashesByUuidParams<T1: cornucopia_async::JsonSql,> { pub file_hashes: T1,pub uuid: uuid::Uuid,}#[derive( Debug)] pub struct UpdateMetadataByUuidParams<T1: cornucopia_async::StringSql,T2: cornucopia_async::StringSql,> { pub name: T1,pub recommended_path: T2,pub uuid: uuid::Uuid,}#[derive( Debug, Clone, PartialEq,)] pub struct Create
+{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : String,}pub struct CreateBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : &'a str,}
 impl<'a> From<CreateBorrowed<'a>> for Create
 {
-    fn from(CreateBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,}: CreateBorrowed<'a>) -> Self
-    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,} }
+    fn from(CreateBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,recommended_path,}: CreateBorrowed<'a>) -> Self
+    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,recommended_path: recommended_path.into(),} }
 }pub struct CreateQuery<'a, C: GenericClient, T, const N: usize>
 {
     client: &'a  C, params:
@@ -383,11 +383,11 @@ GenericClient
         Ok(it)
     }
 }#[derive( Debug, Clone, PartialEq,)] pub struct DeleteByUuid
-{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}pub struct DeleteByUuidBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}
+{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : String,}pub struct DeleteByUuidBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : &'a str,}
 impl<'a> From<DeleteByUuidBorrowed<'a>> for DeleteByUuid
 {
-    fn from(DeleteByUuidBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,}: DeleteByUuidBorrowed<'a>) -> Self
-    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,} }
+    fn from(DeleteByUuidBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,recommended_path,}: DeleteByUuidBorrowed<'a>) -> Self
+    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,recommended_path: recommended_path.into(),} }
 }pub struct DeleteByUuidQuery<'a, C: GenericClient, T, const N: usize>
 {
     client: &'a  C, params:
@@ -428,11 +428,11 @@ GenericClient
         Ok(it)
     }
 }#[derive( Debug, Clone, PartialEq,)] pub struct GetByUuid
-{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}pub struct GetByUuidBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}
+{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : String,}pub struct GetByUuidBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : &'a str,}
 impl<'a> From<GetByUuidBorrowed<'a>> for GetByUuid
 {
-    fn from(GetByUuidBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,}: GetByUuidBorrowed<'a>) -> Self
-    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,} }
+    fn from(GetByUuidBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,recommended_path,}: GetByUuidBorrowed<'a>) -> Self
+    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,recommended_path: recommended_path.into(),} }
 }pub struct GetByUuidQuery<'a, C: GenericClient, T, const N: usize>
 {
     client: &'a  C, params:
@@ -473,11 +473,11 @@ GenericClient
         Ok(it)
     }
 }#[derive( Debug, Clone, PartialEq,)] pub struct GetByNameAndOwner
-{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}pub struct GetByNameAndOwnerBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}
+{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : String,}pub struct GetByNameAndOwnerBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : &'a str,}
 impl<'a> From<GetByNameAndOwnerBorrowed<'a>> for GetByNameAndOwner
 {
-    fn from(GetByNameAndOwnerBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,}: GetByNameAndOwnerBorrowed<'a>) -> Self
-    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,} }
+    fn from(GetByNameAndOwnerBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,recommended_path,}: GetByNameAndOwnerBorrowed<'a>) -> Self
+    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,recommended_path: recommended_path.into(),} }
 }pub struct GetByNameAndOwnerQuery<'a, C: GenericClient, T, const N: usize>
 {
     client: &'a  C, params:
@@ -518,11 +518,11 @@ GenericClient
         Ok(it)
     }
 }#[derive( Debug, Clone, PartialEq,)] pub struct GetByOwner
-{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}pub struct GetByOwnerBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}
+{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : String,}pub struct GetByOwnerBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : &'a str,}
 impl<'a> From<GetByOwnerBorrowed<'a>> for GetByOwner
 {
-    fn from(GetByOwnerBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,}: GetByOwnerBorrowed<'a>) -> Self
-    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,} }
+    fn from(GetByOwnerBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,recommended_path,}: GetByOwnerBorrowed<'a>) -> Self
+    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,recommended_path: recommended_path.into(),} }
 }pub struct GetByOwnerQuery<'a, C: GenericClient, T, const N: usize>
 {
     client: &'a  C, params:
@@ -563,11 +563,11 @@ GenericClient
         Ok(it)
     }
 }#[derive( Debug, Clone, PartialEq,)] pub struct GetAll
-{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}pub struct GetAllBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}
+{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : String,}pub struct GetAllBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : &'a str,}
 impl<'a> From<GetAllBorrowed<'a>> for GetAll
 {
-    fn from(GetAllBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,}: GetAllBorrowed<'a>) -> Self
-    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,} }
+    fn from(GetAllBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,recommended_path,}: GetAllBorrowed<'a>) -> Self
+    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,recommended_path: recommended_path.into(),} }
 }pub struct GetAllQuery<'a, C: GenericClient, T, const N: usize>
 {
     client: &'a  C, params:
@@ -608,11 +608,11 @@ GenericClient
         Ok(it)
     }
 }#[derive( Debug, Clone, PartialEq,)] pub struct UpdateFileHashesByUuid
-{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}pub struct UpdateFileHashesByUuidBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}
+{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : String,}pub struct UpdateFileHashesByUuidBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : &'a str,}
 impl<'a> From<UpdateFileHashesByUuidBorrowed<'a>> for UpdateFileHashesByUuid
 {
-    fn from(UpdateFileHashesByUuidBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,}: UpdateFileHashesByUuidBorrowed<'a>) -> Self
-    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,} }
+    fn from(UpdateFileHashesByUuidBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,recommended_path,}: UpdateFileHashesByUuidBorrowed<'a>) -> Self
+    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,recommended_path: recommended_path.into(),} }
 }pub struct UpdateFileHashesByUuidQuery<'a, C: GenericClient, T, const N: usize>
 {
     client: &'a  C, params:
@@ -653,11 +653,11 @@ GenericClient
         Ok(it)
     }
 }#[derive( Debug, Clone, PartialEq,)] pub struct UpdateMetadataByUuid
-{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}pub struct UpdateMetadataByUuidBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}
+{ pub uuid : uuid::Uuid,pub name : String,pub owner_uuid : uuid::Uuid,pub file_hashes : serde_json::Value,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : String,}pub struct UpdateMetadataByUuidBorrowed<'a> { pub uuid : uuid::Uuid,pub name : &'a str,pub owner_uuid : uuid::Uuid,pub file_hashes : postgres_types::Json<&'a serde_json::value::RawValue>,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,pub recommended_path : &'a str,}
 impl<'a> From<UpdateMetadataByUuidBorrowed<'a>> for UpdateMetadataByUuid
 {
-    fn from(UpdateMetadataByUuidBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,}: UpdateMetadataByUuidBorrowed<'a>) -> Self
-    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,} }
+    fn from(UpdateMetadataByUuidBorrowed { uuid,name,owner_uuid,file_hashes,created_at,updated_at,recommended_path,}: UpdateMetadataByUuidBorrowed<'a>) -> Self
+    { Self { uuid,name: name.into(),owner_uuid,file_hashes: serde_json::from_str(file_hashes.0.get()).unwrap(),created_at,updated_at,recommended_path: recommended_path.into(),} }
 }pub struct UpdateMetadataByUuidQuery<'a, C: GenericClient, T, const N: usize>
 {
     client: &'a  C, params:
@@ -711,7 +711,7 @@ name: &'a T1,owner_uuid: &'a uuid::Uuid,) -> CreateQuery<'a,C, Create,
     CreateQuery
     {
         client, params: [name,owner_uuid,], stmt: &mut self.0, extractor:
-        |row| { CreateBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),} }, mapper: |it| { <Create>::from(it) },
+        |row| { CreateBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),recommended_path: row.get(6),} }, mapper: |it| { <Create>::from(it) },
     }
 } }impl <'a, C: GenericClient,T1: cornucopia_async::StringSql,> cornucopia_async::Params<'a,
 CreateParams<T1,>, CreateQuery<'a, C, Create,
@@ -735,7 +735,7 @@ uuid: &'a uuid::Uuid,) -> DeleteByUuidQuery<'a,C, DeleteByUuid,
     DeleteByUuidQuery
     {
         client, params: [uuid,], stmt: &mut self.0, extractor:
-        |row| { DeleteByUuidBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),} }, mapper: |it| { <DeleteByUuid>::from(it) },
+        |row| { DeleteByUuidBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),recommended_path: row.get(6),} }, mapper: |it| { <DeleteByUuid>::from(it) },
     }
 } }pub fn get_by_uuid() -> GetByUuidStmt
 { GetByUuidStmt(cornucopia_async::private::Stmt::new("SELECT * FROM Repositories
@@ -749,7 +749,7 @@ uuid: &'a uuid::Uuid,) -> GetByUuidQuery<'a,C, GetByUuid,
     GetByUuidQuery
     {
         client, params: [uuid,], stmt: &mut self.0, extractor:
-        |row| { GetByUuidBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),} }, mapper: |it| { <GetByUuid>::from(it) },
+        |row| { GetByUuidBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),recommended_path: row.get(6),} }, mapper: |it| { <GetByUuid>::from(it) },
     }
 } }pub fn get_by_name_and_owner() -> GetByNameAndOwnerStmt
 { GetByNameAndOwnerStmt(cornucopia_async::private::Stmt::new("SELECT * FROM Repositories
@@ -764,7 +764,7 @@ name: &'a T1,owner_uuid: &'a uuid::Uuid,) -> GetByNameAndOwnerQuery<'a,C, GetByN
     GetByNameAndOwnerQuery
     {
         client, params: [name,owner_uuid,], stmt: &mut self.0, extractor:
-        |row| { GetByNameAndOwnerBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),} }, mapper: |it| { <GetByNameAndOwner>::from(it) },
+        |row| { GetByNameAndOwnerBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),recommended_path: row.get(6),} }, mapper: |it| { <GetByNameAndOwner>::from(it) },
     }
 } }impl <'a, C: GenericClient,T1: cornucopia_async::StringSql,> cornucopia_async::Params<'a,
 GetByNameAndOwnerParams<T1,>, GetByNameAndOwnerQuery<'a, C, GetByNameAndOwner,
@@ -788,7 +788,7 @@ owner_uuid: &'a uuid::Uuid,) -> GetByOwnerQuery<'a,C, GetByOwner,
     GetByOwnerQuery
     {
         client, params: [owner_uuid,], stmt: &mut self.0, extractor:
-        |row| { GetByOwnerBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),} }, mapper: |it| { <GetByOwner>::from(it) },
+        |row| { GetByOwnerBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),recommended_path: row.get(6),} }, mapper: |it| { <GetByOwner>::from(it) },
     }
 } }pub fn get_all() -> GetAllStmt
 { GetAllStmt(cornucopia_async::private::Stmt::new("SELECT * FROM Repositories
@@ -802,7 +802,7 @@ GenericClient,>(&'a mut self, client: &'a  C,
     GetAllQuery
     {
         client, params: [], stmt: &mut self.0, extractor:
-        |row| { GetAllBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),} }, mapper: |it| { <GetAll>::from(it) },
+        |row| { GetAllBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),recommended_path: row.get(6),} }, mapper: |it| { <GetAll>::from(it) },
     }
 } }pub fn update_file_hashes_by_uuid() -> UpdateFileHashesByUuidStmt
 { UpdateFileHashesByUuidStmt(cornucopia_async::private::Stmt::new("UPDATE Repositories
@@ -819,7 +819,7 @@ file_hashes: &'a T1,uuid: &'a uuid::Uuid,) -> UpdateFileHashesByUuidQuery<'a,C,
     UpdateFileHashesByUuidQuery
     {
         client, params: [file_hashes,uuid,], stmt: &mut self.0, extractor:
-        |row| { UpdateFileHashesByUuidBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),} }, mapper: |it| { <UpdateFileHashesByUuid>::from(it) },
+        |row| { UpdateFileHashesByUuidBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),recommended_path: row.get(6),} }, mapper: |it| { <UpdateFileHashesByUuid>::from(it) },
     }
 } }impl <'a, C: GenericClient,T1: cornucopia_async::JsonSql,> cornucopia_async::Params<'a,
 UpdateFileHashesByUuidParams<T1,>, UpdateFileHashesByUuidQuery<'a, C, UpdateFileHashesByUuid,
@@ -832,30 +832,31 @@ UpdateFileHashesByUuidParams<T1,>, UpdateFileHashesByUuidQuery<'a, C, UpdateFile
     { self.bind(client, &params.file_hashes,&params.uuid,) }
 }pub fn update_metadata_by_uuid() -> UpdateMetadataByUuidStmt
 { UpdateMetadataByUuidStmt(cornucopia_async::private::Stmt::new("UPDATE Repositories
-    SET name = $1, updated_at = CURRENT_TIMESTAMP
-    WHERE uuid = $2
+    SET name = $1, recommended_path = $2, updated_at = CURRENT_TIMESTAMP
+    WHERE uuid = $3
     RETURNING *")) } pub struct
 UpdateMetadataByUuidStmt(cornucopia_async::private::Stmt); impl UpdateMetadataByUuidStmt
 { pub fn bind<'a, C:
 GenericClient,T1:
+cornucopia_async::StringSql,T2:
 cornucopia_async::StringSql,>(&'a mut self, client: &'a  C,
-name: &'a T1,uuid: &'a uuid::Uuid,) -> UpdateMetadataByUuidQuery<'a,C, UpdateMetadataByUuid,
-2>
+name: &'a T1,recommended_path: &'a T2,uuid: &'a uuid::Uuid,) -> UpdateMetadataByUuidQuery<'a,C, UpdateMetadataByUuid,
+3>
 {
     UpdateMetadataByUuidQuery
     {
-        client, params: [name,uuid,], stmt: &mut self.0, extractor:
-        |row| { UpdateMetadataByUuidBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),} }, mapper: |it| { <UpdateMetadataByUuid>::from(it) },
+        client, params: [name,recommended_path,uuid,], stmt: &mut self.0, extractor:
+        |row| { UpdateMetadataByUuidBorrowed { uuid: row.get(0),name: row.get(1),owner_uuid: row.get(2),file_hashes: row.get(3),created_at: row.get(4),updated_at: row.get(5),recommended_path: row.get(6),} }, mapper: |it| { <UpdateMetadataByUuid>::from(it) },
     }
-} }impl <'a, C: GenericClient,T1: cornucopia_async::StringSql,> cornucopia_async::Params<'a,
-UpdateMetadataByUuidParams<T1,>, UpdateMetadataByUuidQuery<'a, C, UpdateMetadataByUuid,
-2>, C> for UpdateMetadataByUuidStmt
+} }impl <'a, C: GenericClient,T1: cornucopia_async::StringSql,T2: cornucopia_async::StringSql,> cornucopia_async::Params<'a,
+UpdateMetadataByUuidParams<T1,T2,>, UpdateMetadataByUuidQuery<'a, C, UpdateMetadataByUuid,
+3>, C> for UpdateMetadataByUuidStmt
 {
     fn
     params(&'a mut self, client: &'a  C, params: &'a
-    UpdateMetadataByUuidParams<T1,>) -> UpdateMetadataByUuidQuery<'a, C,
-    UpdateMetadataByUuid, 2>
-    { self.bind(client, &params.name,&params.uuid,) }
+    UpdateMetadataByUuidParams<T1,T2,>) -> UpdateMetadataByUuidQuery<'a, C,
+    UpdateMetadataByUuid, 3>
+    { self.bind(client, &params.name,&params.recommended_path,&params.uuid,) }
 }}pub mod user
 { use futures::{{StreamExt, TryStreamExt}};use futures; use cornucopia_async::GenericClient;#[derive( Debug, Clone, PartialEq,)] pub struct Create
 { pub uuid : uuid::Uuid,pub username : String,pub api_key : String,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}pub struct CreateBorrowed<'a> { pub uuid : uuid::Uuid,pub username : &'a str,pub api_key : &'a str,pub created_at : time::PrimitiveDateTime,pub updated_at : time::PrimitiveDateTime,}
@@ -0,0 +1,418 @@
+// `remote repo fsck`, cross checks ROOT_FOLDER, the file_hashes manifests and the S3 bucket
+// the disk is the source of truth: manifests get rebuilt from it and S3 gets topped up from it,
+// except for files the disk lost, those are restored from S3 and stay in the manifest until they are
+// anything that would delete data (orphaned objects and folders) also needs --delete
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use aws_sdk_s3::Client as S3Client;
+use deadpool_postgres::Pool;
+use pitsu_lib::{
+    anyhow::{self, Result},
+    ChangeType, RootFolder,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{admin::OutputFormat, cornucopia::queries::repository, S3Key};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum ProblemKind {
+    // in the manifest but not on disk
+    MissingBlob,
+    // on disk with different contents than the manifest says
+    HashMismatch,
+    // on disk but not in the manifest
+    Unindexed,
+    // on disk but never made it to S3
+    MissingFromS3,
+    // in S3 with nothing on disk behind it
+    OrphanedObject,
+    // in the bucket but not a key we would ever write
+    InvalidObject,
+    // a uuid folder in ROOT_FOLDER with no repository row
+    OrphanedFolder,
+}
+
+impl ProblemKind {
+    fn repair_deletes(&self) -> bool {
+        matches!(
+            self,
+            ProblemKind::OrphanedObject | ProblemKind::InvalidObject | ProblemKind::OrphanedFolder
+        )
+    }
+}
+
+impl std::fmt::Display for ProblemKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            ProblemKind::MissingBlob => "missing_blob",
+            ProblemKind::HashMismatch => "hash_mismatch",
+            ProblemKind::Unindexed => "unindexed",
+            ProblemKind::MissingFromS3 => "missing_from_s3",
+            ProblemKind::OrphanedObject => "orphaned_object",
+            ProblemKind::InvalidObject => "invalid_object",
+            ProblemKind::OrphanedFolder => "orphaned_folder",
+        };
+        write!(f, "{kind}")
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct Problem {
+    kind: ProblemKind,
+    repository: Option<Uuid>,
+    // file path inside the repository, S3 key for objects, folder for orphaned folders
+    path: String,
+    repaired: bool,
+}
+
+#[derive(Serialize, Debug, Default)]
+struct Report {
+    repositories: usize,
+    files: usize,
+    objects: usize,
+    problems: Vec<Problem>,
+}
+
+impl Report {
+    fn push(&mut self, kind: ProblemKind, repository: Option<Uuid>, path: impl Into<String>) -> usize {
+        self.problems.push(Problem {
+            kind,
+            repository,
+            path: path.into(),
+            repaired: false,
+        });
+        self.problems.len() - 1
+    }
+}
+
+fn root_folder() -> &'static str {
+    crate::config::get().root_folder()
+}
+
+pub async fn fsck(
+    pool: &Pool,
+    s3_client: &S3Client,
+    only: Option<Uuid>,
+    repair: bool,
+    delete: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    let connection = pool
+        .get()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to get database connection: {err}"))?;
+    let mut repos = repository::get_all()
+        .bind(&connection)
+        .all()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to list repositories: {err}"))?;
+    if let Some(uuid) = only {
+        repos.retain(|repo| repo.uuid == uuid);
+        if repos.is_empty() {
+            return Err(anyhow::anyhow!("No repository with UUID {uuid}"));
+        }
+    }
+    let mut report = Report {
+        repositories: repos.len(),
+        ..Default::default()
+    };
+
+    // listed first, files the disk lost are restored from here
+    let (mut objects, mut invalid) = crate::get_all_from_s3(s3_client).await?;
+    if let Some(uuid) = only {
+        objects.retain(|key| key.repo_uuid == uuid);
+        // can't tell which repository these would belong to
+        invalid.clear();
+    }
+    report.objects = objects.len();
+    let in_s3 = objects.iter().cloned().collect::<HashSet<_>>();
+
+    // disk against manifest
+    // anything on disk or in a manifest has a reason to be in S3
+    let mut expected_keys: HashSet<S3Key> = HashSet::new();
+    // S3 only stores a hash of the path, keep the path around to re-upload from
+    let mut on_disk: Vec<(Uuid, Arc<str>, S3Key)> = vec![];
+    // in S3 with the contents from before the file changed on disk, (problem, repository, path)
+    let mut outdated: Vec<(usize, Uuid, Arc<str>)> = vec![];
+    for repo in repos.iter() {
+        let manifest: RootFolder = match serde_json::from_value(repo.file_hashes.clone()) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                log::warn!("Manifest for {} does not parse, treating it as empty: {err}", repo.name);
+                RootFolder::default()
+            }
+        };
+        for file in manifest.files() {
+            expected_keys.insert(crate::generate_aws_key_from_path(&repo.uuid, &file.full_path));
+        }
+        let path = PathBuf::from(format!("{}/{}", root_folder(), repo.uuid));
+        let mut ingested = ingest(&path).await?;
+        let mut reindex = vec![];
+        let mut mismatched = vec![];
+        let mut restored = false;
+        let mut unrestored = 0;
+        for diff in ingested.diff(&manifest) {
+            let kind = match diff.change_type {
+                ChangeType::OnServer => ProblemKind::MissingBlob,
+                ChangeType::Modified => ProblemKind::HashMismatch,
+                ChangeType::OnClient => ProblemKind::Unindexed,
+            };
+            let index = report.push(kind, Some(repo.uuid), &*diff.full_path);
+            match kind {
+                ProblemKind::MissingBlob if repair => {
+                    let key = crate::generate_aws_key_from_path(&repo.uuid, &diff.full_path);
+                    let hash = manifest.hash_of(&diff.full_path);
+                    let restore = if in_s3.contains(&key) {
+                        restore_from_s3(s3_client, &key, &path.join(&*diff.full_path), hash.as_deref()).await
+                    } else {
+                        Err(anyhow::anyhow!("there is no copy in S3"))
+                    };
+                    match restore {
+                        Ok(()) => {
+                            report.problems[index].repaired = true;
+                            restored = true;
+                        }
+                        Err(err) => {
+                            log::error!("Failed to restore {} in {}: {err}", diff.full_path, repo.name);
+                            unrestored += 1;
+                        }
+                    }
+                }
+                ProblemKind::MissingBlob => unrestored += 1,
+                ProblemKind::HashMismatch => {
+                    reindex.push(index);
+                    mismatched.push((index, diff.full_path.clone()));
+                }
+                _ => reindex.push(index),
+            }
+        }
+        if restored {
+            ingested = ingest(&path).await?;
+        }
+        // rebuilding from disk now would drop the files that couldn't be restored
+        if repair && unrestored > 0 && !reindex.is_empty() {
+            log::warn!(
+                "Keeping the manifest for {}, {unrestored} files in it are missing from disk and S3",
+                repo.name
+            );
+        } else if repair && !reindex.is_empty() {
+            let file_hashes = serde_json::to_value(&ingested)?;
+            match repository::update_file_hashes_by_uuid()
+                .bind(&connection, &file_hashes, &repo.uuid)
+                .one()
+                .await
+            {
+                Ok(_) => {
+                    for index in reindex {
+                        report.problems[index].repaired = true;
+                    }
+                    // ones S3 doesn't have at all are uploaded with the rest of missing_from_s3
+                    outdated.extend(mismatched.into_iter().filter_map(|(index, path)| {
+                        in_s3
+                            .contains(&crate::generate_aws_key_from_path(&repo.uuid, &path))
+                            .then_some((index, repo.uuid, path))
+                    }));
+                }
+                Err(err) => log::error!("Failed to rebuild manifest for {}: {err}", repo.name),
+            }
+        }
+        let files = ingested.files();
+        report.files += files.len();
+        for file in files {
+            let key = crate::generate_aws_key_from_path(&repo.uuid, &file.full_path);
+            expected_keys.insert(key.clone());
+            on_disk.push((repo.uuid, file.full_path, key));
+        }
+    }
+
+    // folders nobody owns, only looked for when checking everything
+    let mut orphaned_folders = vec![];
+    if only.is_none() {
+        let known = repos.iter().map(|repo| repo.uuid).collect::<HashSet<_>>();
+        let mut read_dir = tokio::fs::read_dir(root_folder())
+            .await
+            .map_err(|err| anyhow::anyhow!("Failed to read root folder: {err}"))?;
+        while let Ok(Some(entry)) = read_dir.next_entry().await {
+            let Ok(uuid) = Uuid::parse_str(&entry.file_name().to_string_lossy()) else {
+                continue;
+            };
+            if !known.contains(&uuid) {
+                let index = report.push(
+                    ProblemKind::OrphanedFolder,
+                    Some(uuid),
+                    entry.path().display().to_string(),
+                );
+                orphaned_folders.push((index, entry.path()));
+            }
+        }
+    }
+
+    // disk against S3
+    let missing = on_disk
+        .iter()
+        .filter(|(_, _, key)| !in_s3.contains(key))
+        .map(|(uuid, path, key)| {
+            (
+                report.push(ProblemKind::MissingFromS3, Some(*uuid), &**path),
+                uuid,
+                path,
+                key,
+            )
+        })
+        .collect::<Vec<_>>();
+    let mut orphaned = vec![];
+    for key in objects.into_iter().filter(|key| !expected_keys.contains(key)) {
+        orphaned.push((
+            report.push(ProblemKind::OrphanedObject, Some(key.repo_uuid), key.to_string()),
+            key,
+        ));
+    }
+    let invalid = invalid
+        .into_iter()
+        .map(|key| (report.push(ProblemKind::InvalidObject, None, key.clone()), key))
+        .collect::<Vec<_>>();
+
+    if repair {
+        for (index, uuid, path, key) in missing.iter() {
+            match upload(s3_client, uuid, path, key).await {
+                Ok(()) => report.problems[*index].repaired = true,
+                Err(err) => log::error!("Failed to upload {path} to S3: {err}"),
+            }
+        }
+        // the manifest already follows the disk, S3 still has the old contents
+        for (index, uuid, path) in outdated.iter() {
+            let key = crate::generate_aws_key_from_path(uuid, path);
+            if let Err(err) = upload(s3_client, uuid, path, &key).await {
+                log::error!("Failed to upload {path} to S3: {err}");
+                report.problems[*index].repaired = false;
+            }
+        }
+    }
+
+    if repair && delete {
+        for (index, key) in orphaned.iter() {
+            match crate::remove_from_s3(s3_client, key).await {
+                Ok(()) => report.problems[*index].repaired = true,
+                Err(err) => log::error!("Failed to delete {key} from S3: {err}"),
+            }
+        }
+        for (index, key) in invalid.iter() {
+            match crate::remove_raw_key_from_s3(s3_client, key).await {
+                Ok(()) => report.problems[*index].repaired = true,
+                Err(err) => log::error!("Failed to delete {key} from S3: {err}"),
+            }
+        }
+        for (index, path) in orphaned_folders.iter() {
+            match tokio::fs::remove_dir_all(path).await {
+                Ok(()) => report.problems[*index].repaired = true,
+                Err(err) => log::error!("Failed to remove {}: {err}", path.display()),
+            }
+        }
+    }
+
+    print_report(&report, &repos, repair, delete, format)?;
+    let unrepaired = report.problems.iter().filter(|problem| !problem.repaired).count();
+    if unrepaired > 0 {
+        return Err(anyhow::anyhow!("{unrepaired} problems left unrepaired"));
+    }
+    Ok(())
+}
+
+async fn ingest(path: &Path) -> Result<RootFolder> {
+    if !path.exists() {
+        return Ok(RootFolder::default());
+    }
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || RootFolder::ingest_folder(&path)).await?
+}
+
+async fn upload(s3_client: &S3Client, uuid: &Uuid, path: &str, key: &S3Key) -> Result<()> {
+    let body = crate::get_byte_stream(uuid, path).await?;
+    crate::put_in_s3(s3_client, key, body).await
+}
+
+// only written if it still matches the manifest, an older copy would pass for the file the manifest promises
+async fn restore_from_s3(s3_client: &S3Client, key: &S3Key, destination: &Path, hash: Option<&str>) -> Result<()> {
+    let object = crate::get_from_s3(s3_client, key).await?;
+    let bytes = object
+        .body
+        .collect()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to read {key} from S3: {err}"))?
+        .to_vec();
+    if let Some(hash) = hash {
+        let actual = format!("{:x}", Sha256::digest(&bytes));
+        if actual != hash {
+            return Err(anyhow::anyhow!("the copy in S3 doesn't match the manifest"));
+        }
+    }
+    if let Some(parent) = destination.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(destination, bytes).await?;
+    Ok(())
+}
+
+fn print_report(
+    report: &Report,
+    repos: &[repository::GetAll],
+    repair: bool,
+    delete: bool,
+    format: OutputFormat,
+) -> Result<()> {
+    match format {
+        OutputFormat::Text => {
+            println!(
+                "Checked {} repositories, {} files on disk, {} S3 objects",
+                report.repositories, report.files, report.objects
+            );
+            for problem in report.problems.iter() {
+                let repository = problem.repository.map_or_else(
+                    || "-".to_string(),
+                    |uuid| {
+                        repos
+                            .iter()
+                            .find(|repo| repo.uuid == uuid)
+                            .map_or_else(|| uuid.to_string(), |repo| repo.name.clone())
+                    },
+                );
+                println!(
+                    "{:<16} {repository}: {}{}",
+                    problem.kind,
+                    problem.path,
+                    if problem.repaired { " (repaired)" } else { "" }
+                );
+            }
+            let repaired = report.problems.iter().filter(|problem| problem.repaired).count();
+            println!("{} problems, {repaired} repaired", report.problems.len());
+            if !repair && !report.problems.is_empty() {
+                println!("Run again with --repair to fix them, deleting orphans also needs --delete");
+            } else if !delete && report.problems.iter().any(|problem| problem.kind.repair_deletes()) {
+                println!("Orphans were left alone, pass --delete to remove them");
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(report)?),
+        // kind, repository uuid (or -), path, repaired
+        OutputFormat::Tsv => {
+            for problem in report.problems.iter() {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    problem.kind,
+                    problem
+                        .repository
+                        .map_or_else(|| "-".to_string(), |uuid| uuid.to_string()),
+                    problem.path,
+                    problem.repaired
+                );
+            }
+        }
+    }
+    Ok(())
+}
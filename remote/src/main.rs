@@ -1,33 +1,51 @@
-use std::{
-    ops::{Deref, DerefMut},
-    path::PathBuf,
-    sync::Arc,
-};
+use std::{path::PathBuf, sync::Arc};
 
 use aws_sdk_s3::{error::DisplayErrorContext, primitives::ByteStream, Client as S3Client};
 
 use actix_web::{
-    delete, get, patch, post,
-    web::{Data, Json, JsonConfig},
+    delete,
+    dev::Service as _,
+    get,
+    http::header::{
+        Charset, ContentDisposition, DispositionParam, DispositionType, ExtendedValue, HeaderName, HeaderValue,
+    },
+    patch, post,
+    web::{Data, Json, JsonConfig, PathConfig, PayloadConfig, QueryConfig},
     App, HttpResponse, HttpServer, Responder,
 };
 use clap::Parser as _;
+mod admin;
+mod config;
 mod cornucopia;
-use crate::cornucopia::queries::access::get_all_users_with_access;
+mod fsck;
+mod metrics;
+mod migrations;
+mod objects;
+mod openapi;
+mod ratelimit;
+mod store;
+use crate::{
+    objects::{ObjectStore, S3Store},
+    store::{DataStore, PostgresStore},
+};
 use deadpool_postgres::Pool;
+use ed25519_dalek::{Signer as _, SigningKey};
 use futures::StreamExt;
 use pitsu_lib::{
     anyhow::{self, Result},
-    decode_string_base64, encode_string_base64, AccessLevel, CreateRemoteRepository, FileUpload, Pitignore,
-    RemoteRepository, RootFolder, SimpleRemoteRepository, ThisUser, UpdateRemoteRepository, User, UserWithAccess,
+    decode_string_base64, encode_string_base64, AccessLevel, ApiError, ArchiveFormat, ChangelogEntry,
+    CreateRemoteRepository, ErrorCode, FilePart, FileUpload, InviteCode, Pitignore, RemoteRepository, RootFolder,
+    SimpleRemoteRepository, ThisUser, TransferEncoding, UpdateChannel, UpdateRemoteRepository, User, UserWithAccess,
     VersionNumber,
 };
-use tokio::{
-    io::{AsyncReadExt as _, AsyncWriteExt as _},
-    sync::Mutex,
-};
 use uuid::Uuid;
 
+#[utoipa::path(
+    tag = "server",
+    responses(
+        (status = 200, description = "Server name and version", body = String),
+    )
+)]
 #[get("/")]
 async fn root() -> impl Responder {
     HttpResponse::Ok().body(format!(
@@ -36,6 +54,78 @@ async fn root() -> impl Responder {
     ))
 }
 
+// for load balancers and uptime checks, 503 with the failing parts if anything is down.
+// unauthenticated, so the reasons only go to the log
+#[utoipa::path(
+    tag = "server",
+    responses(
+        (status = 200, description = "Database, disk and s3 are all reachable", body = serde_json::Value),
+        (status = 503, description = "At least one of them is down, each part says whether it is ok", body = serde_json::Value),
+    )
+)]
+#[get("/healthz")]
+async fn healthz(store: Data<dyn DataStore>, objects: Data<dyn ObjectStore>) -> impl Responder {
+    let database = store.ping().await.map_err(|err| err.to_string());
+    let disk = if std::path::Path::new(config::get().root_folder()).is_dir() {
+        Ok(())
+    } else {
+        Err(format!("{} is not a directory", config::get().root_folder()))
+    };
+    let s3 = objects.ping().await.map_err(|err| err.to_string());
+    let healthy = database.is_ok() && disk.is_ok() && s3.is_ok();
+    let check = |part: &str, result: Result<(), String>| match result {
+        Ok(()) => serde_json::json!({ "ok": true }),
+        Err(err) => {
+            log::warn!("Health check failed for {part}: {err}");
+            serde_json::json!({ "ok": false })
+        }
+    };
+    let body = serde_json::json!({
+        "status": if healthy { "ok" } else { "unavailable" },
+        "database": check("database", database),
+        "disk": check("disk", disk),
+        "s3": check("s3", s3),
+    });
+    if healthy {
+        HttpResponse::Ok().json(body)
+    } else {
+        HttpResponse::ServiceUnavailable().json(body)
+    }
+}
+
+// off unless METRICS_TOKEN is set, scrapers send it as a bearer token
+#[utoipa::path(
+    tag = "server",
+    responses(
+        (status = 200, description = "Prometheus text format", body = String, content_type = "text/plain"),
+        (status = 401, description = "Missing or wrong METRICS_TOKEN", body = ApiError),
+        (status = 404, description = "METRICS_TOKEN is not set", body = ApiError),
+    )
+)]
+#[get("/metrics")]
+async fn metrics_endpoint(req: actix_web::HttpRequest, pool: Data<Pool>) -> impl Responder {
+    let Some(token) = config::get().metrics_token() else {
+        return error_response(ErrorCode::NotFound, "Metrics are disabled");
+    };
+    let bearer = req
+        .headers()
+        .get(actix_web::http::header::AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "));
+    if bearer != Some(token) {
+        return error_response(ErrorCode::Unauthorized, "Missing or wrong metrics token");
+    }
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render(&pool))
+}
+
+#[utoipa::path(
+    tag = "server",
+    responses(
+        (status = 200, description = "API name and version", body = String),
+    )
+)]
 #[get("/api")]
 async fn api() -> impl Responder {
     HttpResponse::Ok().body(format!(
@@ -44,44 +134,51 @@ async fn api() -> impl Responder {
     ))
 }
 
+#[utoipa::path(
+    tag = "server",
+    responses(
+        (status = 200, description = "This document", body = serde_json::Value),
+    )
+)]
+#[get("/api/openapi.json")]
+async fn openapi_json() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(openapi::DOCUMENT.as_str())
+}
+
+#[utoipa::path(
+    tag = "server",
+    responses(
+        (status = 404, description = "Anything under /api that isn't a route", body = ApiError),
+    )
+)]
 #[get("/api/{path:.*}")]
 async fn api_catch_all(path: actix_web::web::Path<String>) -> impl Responder {
     let path = path.into_inner();
     log::debug!("API catch-all route hit with path: {path}");
-    HttpResponse::NotFound().body(format!(
-        "API endpoint not found: {path}. Please check the documentation for available endpoints."
-    ))
+    error_response(
+        ErrorCode::NotFound,
+        format!("API endpoint not found: {path}. Please check the documentation for available endpoints."),
+    )
 }
 
+#[utoipa::path(
+    tag = "users",
+    security(("api_key" = [])),
+    responses(
+        (status = 200, description = "The owner of the api key and every repository they can access", body = ThisUser),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
 #[get("/api/user")]
-async fn get_self(req: actix_web::HttpRequest, pool: Data<Pool>) -> impl Responder {
-    let pool = pool.into_inner();
-    let user = match get_user(&req, pool.clone()).await {
+async fn get_self(req: actix_web::HttpRequest, store: Data<dyn DataStore>) -> impl Responder {
+    let user = match get_user(&req, &store).await {
         Ok(user) => user,
-        Err(err) => {
-            log::error!("Failed to get bearer token: {err}");
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
-    };
-    let mut connection = match pool.get().await {
-        Ok(conn) => conn,
-        Err(err) => {
-            log::error!("Failed to get database connection: {err}");
-            return HttpResponse::InternalServerError().body("Database connection error");
-        }
-    };
-    let transaction = match connection.transaction().await {
-        Ok(tx) => tx,
-        Err(err) => {
-            log::error!("Failed to start transaction: {err}");
-            return HttpResponse::InternalServerError().body("Transaction error");
-        }
+        Err(response) => return response,
     };
-    let owned_repositories: Vec<SimpleRemoteRepository> = match cornucopia::queries::repository::get_by_owner()
-        .bind(&transaction, &user.uuid)
-        .all()
-        .await
-    {
+    let owned_repositories: Vec<SimpleRemoteRepository> = match store.repositories_owned_by(user.uuid).await {
         Ok(repos) => {
             let mut new_repos: Vec<SimpleRemoteRepository> = Vec::with_capacity(repos.len());
             for repo in repos {
@@ -89,7 +186,7 @@ async fn get_self(req: actix_web::HttpRequest, pool: Data<Pool>) -> impl Respond
                     Ok(files) => files,
                     Err(err) => {
                         log::error!("Failed to parse file hashes: {err}");
-                        return HttpResponse::InternalServerError().body("Failed to parse file hashes");
+                        return error_response(ErrorCode::Internal, "Failed to parse file hashes");
                     }
                 };
                 new_repos.push(SimpleRemoteRepository {
@@ -104,39 +201,31 @@ async fn get_self(req: actix_web::HttpRequest, pool: Data<Pool>) -> impl Respond
         }
         Err(err) => {
             log::error!("Failed to fetch owned repositories: {err}");
-            return HttpResponse::InternalServerError().body("Failed to fetch owned repositories");
+            return error_response(ErrorCode::Database, "Failed to fetch owned repositories");
         }
     };
-    let accessible_repositories: Vec<SimpleRemoteRepository> = match cornucopia::queries::access::get_by_user()
-        .bind(&transaction, &user.uuid)
-        .all()
-        .await
-    {
+    let accessible_repositories: Vec<SimpleRemoteRepository> = match store.granted_to(user.uuid).await {
         Ok(access) => {
             let mut new_accessible_repos: Vec<SimpleRemoteRepository> = Vec::with_capacity(access.len());
-            for access_entry in access {
-                let repo = match cornucopia::queries::repository::get_by_uuid()
-                    .bind(&transaction, &access_entry.repository_uuid)
-                    .one()
-                    .await
-                {
+            for (repository_uuid, access_level) in access {
+                let repo = match store.repository(repository_uuid).await {
                     Ok(repo) => repo,
                     Err(err) => {
                         log::error!("Failed to fetch repository: {err}");
-                        return HttpResponse::InternalServerError().body("Failed to fetch repository");
+                        return error_response(ErrorCode::Database, "Failed to fetch repository");
                     }
                 };
                 let files: RootFolder = match serde_json::from_value(repo.file_hashes) {
                     Ok(files) => files,
                     Err(err) => {
                         log::error!("Failed to parse file hashes: {err}");
-                        return HttpResponse::InternalServerError().body("Failed to parse file hashes");
+                        return error_response(ErrorCode::Internal, "Failed to parse file hashes");
                     }
                 };
                 new_accessible_repos.push(SimpleRemoteRepository {
                     uuid: repo.uuid,
                     name: repo.name.into(),
-                    access_level: Into::<AccessLevel>::into(access_entry.access_level),
+                    access_level,
                     size: files.size(),
                     file_count: files.file_count(),
                 });
@@ -145,7 +234,7 @@ async fn get_self(req: actix_web::HttpRequest, pool: Data<Pool>) -> impl Respond
         }
         Err(err) => {
             log::error!("Failed to fetch accessible repositories: {err}");
-            return HttpResponse::InternalServerError().body("Failed to fetch accessible repositories");
+            return error_response(ErrorCode::Database, "Failed to fetch accessible repositories");
         }
     };
     HttpResponse::Ok().json(ThisUser {
@@ -155,213 +244,161 @@ async fn get_self(req: actix_web::HttpRequest, pool: Data<Pool>) -> impl Respond
     })
 }
 
+#[utoipa::path(
+    tag = "users",
+    security(("api_key" = [])),
+    responses(
+        (status = 200, body = User),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 404, description = "No such user", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
 #[get("/api/user/{uuid}")]
 async fn get_other(
     req: actix_web::HttpRequest,
     uuid: actix_web::web::Path<uuid::Uuid>,
-    pool: Data<Pool>,
+    store: Data<dyn DataStore>,
 ) -> impl Responder {
-    let pool = pool.into_inner();
-    let _user = match get_user(&req, pool.clone()).await {
+    let _user = match get_user(&req, &store).await {
         Ok(user) => user,
-        Err(err) => {
-            log::error!("Failed to get bearer token: {err}");
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
+        Err(response) => return response,
     };
     let uuid = uuid.into_inner();
-    let mut connection = match pool.get().await {
-        Ok(conn) => conn,
-        Err(err) => {
-            log::error!("Failed to get database connection: {err}");
-            return HttpResponse::InternalServerError().body("Database connection error");
-        }
-    };
-    let transaction = match connection.transaction().await {
-        Ok(tx) => tx,
-        Err(err) => {
-            log::error!("Failed to start transaction: {err}");
-            return HttpResponse::InternalServerError().body("Transaction error");
-        }
-    };
 
-    match cornucopia::queries::user::get_by_uuid()
-        .bind(&transaction, &uuid)
-        .one()
-        .await
-    {
-        Ok(user) => HttpResponse::Ok().json(User {
-            uuid: user.uuid,
-            username: user.username.into(),
-        }),
+    match store.user(uuid).await {
+        Ok(Some(user)) => HttpResponse::Ok().json(User::from(user)),
+        Ok(None) => error_response(ErrorCode::NotFound, "User not found"),
         Err(err) => {
             log::error!("Failed to fetch user: {err}");
-            HttpResponse::InternalServerError().body("Failed to fetch user")
+            error_response(ErrorCode::Database, "Failed to fetch user")
         }
     }
 }
 
+#[utoipa::path(
+    tag = "users",
+    responses(
+        (status = 200, body = Vec<User>),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
 #[get("/api/users")]
-async fn get_all_users(pool: Data<Pool>) -> impl Responder {
-    let pool = pool.into_inner();
-    let mut connection = match pool.get().await {
-        Ok(conn) => conn,
-        Err(err) => {
-            log::error!("Failed to get database connection: {err}");
-            return HttpResponse::InternalServerError().body("Database connection error");
-        }
-    };
-    let transaction = match connection.transaction().await {
-        Ok(tx) => tx,
-        Err(err) => {
-            log::error!("Failed to start transaction: {err}");
-            return HttpResponse::InternalServerError().body("Transaction error");
-        }
-    };
-
-    match cornucopia::queries::user::get_all().bind(&transaction).all().await {
-        Ok(users) => {
-            let mut user_list: Vec<User> = Vec::with_capacity(users.len());
-            for user in users {
-                user_list.push(User {
-                    uuid: user.uuid,
-                    username: user.username.into(),
-                });
-            }
-            HttpResponse::Ok().json(user_list)
-        }
+async fn get_all_users(store: Data<dyn DataStore>) -> impl Responder {
+    match store.users().await {
+        Ok(users) => HttpResponse::Ok().json(users.into_iter().map(User::from).collect::<Vec<User>>()),
         Err(err) => {
             log::error!("Failed to fetch users: {err}");
-            HttpResponse::InternalServerError().body("Failed to fetch users")
+            error_response(ErrorCode::Database, "Failed to fetch users")
         }
     }
 }
 
+#[utoipa::path(
+    tag = "repositories",
+    security(("api_key" = [])),
+    description = "Needs Read access to the repository.",
+    responses(
+        (status = 200, body = RemoteRepository),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 403, description = "Not enough access to the repository", body = ApiError),
+        (status = 404, description = "Repository not found", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
 #[get("/{uuid}")]
 async fn repository(
     req: actix_web::HttpRequest,
     uuid: actix_web::web::Path<uuid::Uuid>,
-    pool: Data<Pool>,
+    store: Data<dyn DataStore>,
 ) -> impl Responder {
-    let pool = pool.into_inner();
-    let user = match get_user(&req, pool.clone()).await {
+    let user = match get_user(&req, &store).await {
         Ok(user) => user,
-        Err(err) => {
-            log::error!("Failed to get bearer token: {err}");
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
+        Err(response) => return response,
     };
     let uuid = uuid.into_inner();
 
-    let access_level = match check_user_access(pool.clone(), &user.uuid, &uuid).await {
+    let access_level = match check_user_access(&store, &user.uuid, &uuid).await {
         Ok(level) => level,
-        Err(err) => {
-            log::error!("Failed to check user access: {err}");
-            return HttpResponse::Forbidden().body("Access denied");
-        }
+        Err(response) => return response,
     };
 
     if access_level == AccessLevel::None {
         log::warn!("User {} does not have access to repository {}", user.username, uuid);
-        return HttpResponse::Forbidden().body("Access denied");
+        return error_response(ErrorCode::Forbidden, "Access denied");
     }
 
-    let mut connection = match pool.get().await {
-        Ok(conn) => conn,
-        Err(err) => {
-            log::error!("Failed to get database connection: {err}");
-            return HttpResponse::InternalServerError().body("Database connection error");
-        }
-    };
-    let transaction = match connection.transaction().await {
-        Ok(tx) => tx,
-        Err(err) => {
-            log::error!("Failed to start transaction: {err}");
-            return HttpResponse::InternalServerError().body("Transaction error");
-        }
-    };
-
-    match cornucopia::queries::repository::get_by_uuid()
-        .bind(&transaction, &uuid)
-        .one()
-        .await
-    {
+    match store.repository(uuid).await {
         Ok(repo) => {
             let files: RootFolder = match serde_json::from_value(repo.file_hashes) {
                 Ok(files) => files,
                 Err(err) => {
                     log::error!("Failed to parse file hashes: {err}");
-                    return HttpResponse::InternalServerError().body("Failed to parse file hashes");
+                    return error_response(ErrorCode::Internal, "Failed to parse file hashes");
                 }
             };
 
-            match get_all_users_with_access().bind(&transaction, &uuid).all().await {
+            match store.users_with_access(uuid).await {
                 Ok(users) => {
-                    let pitignore = Pitignore::from_repository(
-                        format!(
-                            "{}/{}/",
-                            std::env::var("ROOT_FOLDER").unwrap_or_else(|_| "repositories".to_string()),
-                            repo.uuid
-                        )
-                        .into(),
-                    )
-                    .unwrap_or_default();
+                    let pitignore =
+                        Pitignore::from_repository(format!("{}/{}/", config::get().root_folder(), repo.uuid).into())
+                            .unwrap_or_default();
                     HttpResponse::Ok().json(RemoteRepository {
                         pitignore,
+                        recommended_path: Some(repo.recommended_path)
+                            .filter(|path| !path.is_empty())
+                            .map(Into::into),
                         uuid: repo.uuid,
                         name: repo.name.into(),
                         access_level,
                         size: files.size(),
                         file_count: files.file_count(),
                         files,
-                        users: users
-                            .into_iter()
-                            .map(|user| UserWithAccess {
-                                user: User {
-                                    uuid: user.user_uuid,
-                                    username: user.username.into(),
-                                },
-                                access_level: Into::<AccessLevel>::into(user.access_level),
-                            })
-                            .collect(),
+                        users,
                     })
                 }
                 Err(err) => {
                     log::error!("Failed to fetch users with access: {err}");
-                    HttpResponse::InternalServerError().body("Failed to fetch users with access")
+                    error_response(ErrorCode::Database, "Failed to fetch users with access")
                 }
             }
         }
         Err(err) => {
             log::debug!("Failed to fetch repository: {err}");
-            HttpResponse::InternalServerError().body("Failed to fetch repository")
+            error_response(ErrorCode::Database, "Failed to fetch repository")
         }
     }
 }
 
+#[utoipa::path(
+    tag = "repositories",
+    security(("api_key" = [])),
+    description = "Needs Owner access to the repository.",
+    request_body = UpdateRemoteRepository,
+    responses(
+        (status = 200, body = String),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 403, description = "Not enough access to the repository", body = ApiError),
+        (status = 404, description = "Repository not found", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
 #[patch("/{uuid}")]
 async fn repository_update(
     req: actix_web::HttpRequest,
     uuid: actix_web::web::Path<uuid::Uuid>,
-    pool: Data<Pool>,
+    store: Data<dyn DataStore>,
     body: actix_web::web::Json<UpdateRemoteRepository>,
 ) -> impl Responder {
-    let pool = pool.into_inner();
-    let user = match get_user(&req, pool.clone()).await {
+    let user = match get_user(&req, &store).await {
         Ok(user) => user,
-        Err(err) => {
-            log::error!("Failed to get bearer token: {err}");
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
+        Err(response) => return response,
     };
     let uuid = uuid.into_inner();
 
-    let access_level = match check_user_access(pool.clone(), &user.uuid, &uuid).await {
+    let access_level = match check_user_access(&store, &user.uuid, &uuid).await {
         Ok(level) => level,
-        Err(err) => {
-            log::error!("Failed to check user access: {err}");
-            return HttpResponse::Forbidden().body("Access denied");
-        }
+        Err(response) => return response,
     };
 
     if access_level < AccessLevel::Owner {
@@ -370,66 +407,59 @@ async fn repository_update(
             user.username,
             uuid
         );
-        return HttpResponse::Forbidden().body("Access denied");
+        return error_response(ErrorCode::Forbidden, "Access denied");
     }
 
-    let mut connection = match pool.get().await {
-        Ok(conn) => conn,
-        Err(err) => {
-            log::error!("Failed to get database connection: {err}");
-            return HttpResponse::InternalServerError().body("Database connection error");
-        }
-    };
-    let transaction = match connection.transaction().await {
-        Ok(tx) => tx,
-        Err(err) => {
-            log::error!("Failed to start transaction: {err}");
-            return HttpResponse::InternalServerError().body("Transaction error");
-        }
+    // leaving the template out keeps the current one, an empty template clears it
+    let recommended_path = match body.recommended_path.as_deref() {
+        Some(template) => template.trim().to_string(),
+        None => match store.repository(uuid).await {
+            Ok(repo) => repo.recommended_path,
+            Err(err) => {
+                log::error!("Failed to get repository: {err}");
+                return error_response(ErrorCode::NotFound, "Repository not found");
+            }
+        },
     };
-
-    match cornucopia::queries::repository::update_metadata_by_uuid()
-        .bind(&transaction, &&*body.name, &uuid)
-        .one()
+    match store
+        .update_repository_metadata(uuid, &body.name, &recommended_path)
         .await
     {
-        Ok(_) => {
-            if let Err(err) = transaction.commit().await {
-                log::error!("Failed to commit transaction: {err}");
-                return HttpResponse::InternalServerError().body("Failed to commit changes");
-            }
-            HttpResponse::Ok().body("Repository updated successfully")
-        }
+        Ok(()) => HttpResponse::Ok().body("Repository updated successfully"),
         Err(err) => {
             log::error!("Failed to update repository: {err}");
-            transaction.rollback().await.ok();
-            HttpResponse::InternalServerError().body("Failed to update repository")
+            error_response(ErrorCode::Database, "Failed to update repository")
         }
     }
 }
 
+#[utoipa::path(
+    tag = "repositories",
+    security(("api_key" = [])),
+    description = "Needs Owner access to the repository.",
+    responses(
+        (status = 200, body = Vec<UserWithAccess>),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 403, description = "Not enough access to the repository", body = ApiError),
+        (status = 404, description = "Repository not found", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
 #[get("/{uuid}/.pit/access")]
 async fn get_users_with_access(
     req: actix_web::HttpRequest,
     uuid: actix_web::web::Path<uuid::Uuid>,
-    pool: Data<Pool>,
+    store: Data<dyn DataStore>,
 ) -> impl Responder {
-    let pool = pool.into_inner();
-    let user = match get_user(&req, pool.clone()).await {
+    let user = match get_user(&req, &store).await {
         Ok(user) => user,
-        Err(err) => {
-            log::error!("Failed to get bearer token: {err}");
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
+        Err(response) => return response,
     };
     let uuid = uuid.into_inner();
 
-    let access_level = match check_user_access(pool.clone(), &user.uuid, &uuid).await {
+    let access_level = match check_user_access(&store, &user.uuid, &uuid).await {
         Ok(level) => level,
-        Err(err) => {
-            log::error!("Failed to check user access: {err}");
-            return HttpResponse::Forbidden().body("Access denied");
-        }
+        Err(response) => return response,
     };
 
     if access_level < AccessLevel::Owner {
@@ -438,81 +468,47 @@ async fn get_users_with_access(
             user.username,
             uuid
         );
-        return HttpResponse::Forbidden().body("Access denied");
+        return error_response(ErrorCode::Forbidden, "Access denied");
     }
 
-    let mut connection = match pool.get().await {
-        Ok(conn) => conn,
-        Err(err) => {
-            log::error!("Failed to get database connection: {err}");
-            return HttpResponse::InternalServerError().body("Database connection error");
-        }
-    };
-    let transaction = match connection.transaction().await {
-        Ok(tx) => tx,
-        Err(err) => {
-            log::error!("Failed to start transaction: {err}");
-            return HttpResponse::InternalServerError().body("Transaction error");
-        }
-    };
-
-    match cornucopia::queries::access::get_all_users_with_access()
-        .bind(&transaction, &uuid)
-        .all()
-        .await
-    {
-        Ok(access_list) => {
-            let mut users_with_access: Vec<UserWithAccess> = Vec::with_capacity(access_list.len());
-            for access in access_list {
-                // let Ok(access_level) = AccessLevel::try_from(access.access_level.as_str()) else {
-                //     log::error!(
-                //         "Invalid access level in database: {} for user {}",
-                //         access.access_level,
-                //         access.user_uuid
-                //     );
-                //     continue;
-                // };
-                let access_level = Into::<AccessLevel>::into(access.access_level);
-                users_with_access.push(UserWithAccess {
-                    user: User {
-                        uuid: access.user_uuid,
-                        username: access.username.into(),
-                    },
-                    access_level,
-                });
-            }
-            HttpResponse::Ok().json(users_with_access)
-        }
+    match store.users_with_access(uuid).await {
+        Ok(users_with_access) => HttpResponse::Ok().json(users_with_access),
         Err(err) => {
             log::error!("Failed to fetch users with access: {err}");
-            HttpResponse::InternalServerError().body("Failed to fetch users with access")
+            error_response(ErrorCode::Database, "Failed to fetch users with access")
         }
     }
 }
 
+#[utoipa::path(
+    tag = "repositories",
+    security(("api_key" = [])),
+    description = "Needs Admin access to the repository.",
+    request_body = UserWithAccess,
+    responses(
+        (status = 200, body = String),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 403, description = "Not enough access to the repository", body = ApiError),
+        (status = 404, description = "Repository not found", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
 #[post("/{uuid}/.pit/user/access")]
 async fn set_access_level(
     req: actix_web::HttpRequest,
     uuid: actix_web::web::Path<uuid::Uuid>,
-    pool: Data<Pool>,
+    store: Data<dyn DataStore>,
     body: actix_web::web::Json<UserWithAccess>,
 ) -> impl Responder {
-    let pool = pool.into_inner();
-    let user = match get_user(&req, pool.clone()).await {
+    let user = match get_user(&req, &store).await {
         Ok(user) => user,
-        Err(err) => {
-            log::error!("Failed to get bearer token: {err}");
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
+        Err(response) => return response,
     };
     let uuid = uuid.into_inner();
 
-    let access_level = match check_user_access(pool.clone(), &user.uuid, &uuid).await {
+    let access_level = match check_user_access(&store, &user.uuid, &uuid).await {
         Ok(level) => level,
-        Err(err) => {
-            log::error!("Failed to check user access: {err}");
-            return HttpResponse::Forbidden().body("Access denied");
-        }
+        Err(response) => return response,
     };
 
     if access_level < AccessLevel::Admin {
@@ -521,64 +517,47 @@ async fn set_access_level(
             user.username,
             uuid
         );
-        return HttpResponse::Forbidden().body("Access denied");
-    }
-
-    let mut connection = match pool.get().await {
-        Ok(conn) => conn,
-        Err(err) => {
-            log::error!("Failed to get database connection: {err}");
-            return HttpResponse::InternalServerError().body("Database connection error");
-        }
-    };
-    let transaction = match connection.transaction().await {
-        Ok(tx) => tx,
-        Err(err) => {
-            log::error!("Failed to start transaction: {err}");
-            return HttpResponse::InternalServerError().body("Transaction error");
-        }
-    };
-
-    if let Err(err) = cornucopia::queries::access::create_or_update()
-        .bind(&transaction, &uuid, &body.user.uuid, &body.access_level.into())
-        .await
-    {
-        log::error!("Failed to update access level: {err}");
-        return HttpResponse::InternalServerError().body("Failed to update access level");
+        return error_response(ErrorCode::Forbidden, "Access denied");
     }
 
-    match transaction.commit().await {
-        Ok(_) => HttpResponse::Ok().body("Access level updated successfully"),
+    match store.set_access_level(uuid, body.user.uuid, body.access_level).await {
+        Ok(()) => HttpResponse::Ok().body("Access level updated successfully"),
         Err(err) => {
-            log::error!("Failed to commit transaction: {err}");
-            HttpResponse::InternalServerError().body("Failed to commit changes")
+            log::error!("Failed to update access level: {err}");
+            error_response(ErrorCode::Database, "Failed to update access level")
         }
     }
 }
 
+#[utoipa::path(
+    tag = "repositories",
+    security(("api_key" = [])),
+    description = "Needs Admin access to the repository.",
+    request_body(content = Uuid, description = "The user to remove"),
+    responses(
+        (status = 200, body = String),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 403, description = "Not enough access to the repository", body = ApiError),
+        (status = 404, description = "Repository not found", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
 #[delete("/{uuid}/.pit/user/access")]
 async fn remove_user_access(
     req: actix_web::HttpRequest,
     uuid: actix_web::web::Path<uuid::Uuid>,
-    pool: Data<Pool>,
+    store: Data<dyn DataStore>,
     body: actix_web::web::Json<Uuid>,
 ) -> impl Responder {
-    let pool = pool.into_inner();
-    let user = match get_user(&req, pool.clone()).await {
+    let user = match get_user(&req, &store).await {
         Ok(user) => user,
-        Err(err) => {
-            log::error!("Failed to get bearer token: {err}");
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
+        Err(response) => return response,
     };
     let uuid = uuid.into_inner();
 
-    let access_level = match check_user_access(pool.clone(), &user.uuid, &uuid).await {
+    let access_level = match check_user_access(&store, &user.uuid, &uuid).await {
         Ok(level) => level,
-        Err(err) => {
-            log::error!("Failed to check user access: {err}");
-            return HttpResponse::Forbidden().body("Access denied");
-        }
+        Err(response) => return response,
     };
 
     if access_level < AccessLevel::Admin {
@@ -587,103 +566,71 @@ async fn remove_user_access(
             user.username,
             uuid
         );
-        return HttpResponse::Forbidden().body("Access denied");
+        return error_response(ErrorCode::Forbidden, "Access denied");
     }
 
-    let mut connection = match pool.get().await {
-        Ok(conn) => conn,
-        Err(err) => {
-            log::error!("Failed to get database connection: {err}");
-            return HttpResponse::InternalServerError().body("Database connection error");
-        }
-    };
-    let transaction = match connection.transaction().await {
-        Ok(tx) => tx,
-        Err(err) => {
-            log::error!("Failed to start transaction: {err}");
-            return HttpResponse::InternalServerError().body("Transaction error");
-        }
-    };
-
-    if let Err(err) = cornucopia::queries::access::delete_by_user_uuid_and_repository_uuid()
-        .bind(&transaction, &body.0, &uuid)
-        .one()
-        .await
-    {
-        log::error!("Failed to remove access level: {err}");
-        return HttpResponse::InternalServerError().body("Failed to remove access level");
-    }
-    match transaction.commit().await {
-        Ok(_) => HttpResponse::Ok().body("Access level removed successfully"),
+    match store.remove_access(uuid, body.0).await {
+        Ok(()) => HttpResponse::Ok().body("Access level removed successfully"),
         Err(err) => {
-            log::error!("Failed to commit transaction: {err}");
-            HttpResponse::InternalServerError().body("Failed to commit changes")
+            log::error!("Failed to remove access level: {err}");
+            error_response(ErrorCode::Database, "Failed to remove access level")
         }
     }
 }
 
-#[get("{uuid}/{path:.*}")]
+#[utoipa::path(
+    tag = "files",
+    security(("api_key" = [])),
+    description = "Needs Read access to the repository. A folder is answered with its index, a file with its \
+        contents, encoded with one of the encodings in x-pitsu-accept-encoding if the client sent it.",
+    params(
+        ("x-pitsu-accept-encoding" = Option<String>, Header, description = "Encodings the client can decode, best first"),
+        ("Range" = Option<String>, Header, description = "Only these bytes of a file, served unencoded"),
+    ),
+    responses(
+        (
+            status = 200,
+            headers(("x-pitsu-encoding" = String, description = "Set when the contents are encoded")),
+            content((Vec<u8> = "application/octet-stream"), (RootFolder = "application/json")),
+        ),
+        (status = 206, description = "The requested range of a file", body = Vec<u8>, content_type = "application/octet-stream"),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 403, description = "Not enough access to the repository", body = ApiError),
+        (status = 404, description = "No such repository, file or folder", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
+#[get("/{uuid}/{path:.*}")]
 async fn repository_path(
     req: actix_web::HttpRequest,
     path_stuff: actix_web::web::Path<(uuid::Uuid, String)>,
-    pool: Data<Pool>,
-    client: Data<S3Client>,
+    store: Data<dyn DataStore>,
+    objects: Data<dyn ObjectStore>,
 ) -> impl Responder {
-    let pool = pool.into_inner();
-    let user = match get_user(&req, pool.clone()).await {
+    let user = match get_user(&req, &store).await {
         Ok(user) => user,
-        Err(err) => {
-            log::error!("Failed to get bearer token: {err}");
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
+        Err(response) => return response,
     };
     let (uuid, path) = path_stuff.into_inner();
 
-    let access_level = match check_user_access(pool.clone(), &user.uuid, &uuid).await {
+    let access_level = match check_user_access(&store, &user.uuid, &uuid).await {
         Ok(level) => level,
-        Err(err) => {
-            log::error!("Failed to check user access: {err}");
-            return HttpResponse::Forbidden().body("Access denied");
-        }
+        Err(response) => return response,
     };
 
     if access_level == AccessLevel::None {
         log::warn!("User {} does not have access to repository {}", user.username, uuid);
-        return HttpResponse::Forbidden().body("Access denied");
+        return error_response(ErrorCode::Forbidden, "Access denied");
     }
 
-    let mut connection = match pool.get().await {
-        Ok(conn) => conn,
-        Err(err) => {
-            log::error!("Failed to get database connection: {err}");
-            return HttpResponse::InternalServerError().body("Database connection error");
-        }
-    };
-    let transaction = match connection.transaction().await {
-        Ok(tx) => tx,
-        Err(err) => {
-            log::error!("Failed to start transaction: {err}");
-            return HttpResponse::InternalServerError().body("Transaction error");
-        }
-    };
-
-    let repo = match cornucopia::queries::repository::get_by_uuid()
-        .bind(&transaction, &uuid)
-        .one()
-        .await
-    {
+    let repo = match store.repository(uuid).await {
         Ok(repo) => repo,
         Err(err) => {
             log::debug!("Failed to fetch repository: {err}");
-            return HttpResponse::InternalServerError().body("Failed to fetch repository");
+            return error_response(ErrorCode::Database, "Failed to fetch repository");
         }
     };
-    let full_path = format!(
-        "{}/{}/{}",
-        std::env::var("ROOT_FOLDER").unwrap_or_else(|_| "repositories".to_string()),
-        repo.uuid,
-        path
-    );
+    let full_path = format!("{}/{}/{}", config::get().root_folder(), repo.uuid, path);
     log::debug!("Full path to file: {full_path}");
 
     if std::path::Path::new(&full_path).exists() {
@@ -693,215 +640,921 @@ async fn repository_path(
                 Ok(folder) => folder,
                 Err(err) => {
                     log::error!("Failed to parse file hashes: {err}");
-                    return HttpResponse::InternalServerError().body("Failed to parse file hashes");
+                    return error_response(ErrorCode::Internal, "Failed to parse file hashes");
                 }
             };
             match root_folder.index_through(&path) {
                 Ok(index) => HttpResponse::Ok().json(index),
                 Err(err) => {
                     log::error!("Failed to index folder: {err}");
-                    HttpResponse::InternalServerError().body("Failed to index folder")
+                    error_response(ErrorCode::Storage, "Failed to index folder")
                 }
             }
         } else {
-            let potential_key = generate_aws_key_from_path(&repo.uuid, &path);
-            match get_from_s3(&client, &potential_key).await {
-                Ok(_) => {
-                    let location = format!(
-                        "https://{}.s3.{}.amazonaws.com/{}",
-                        std::env!("AWS_BUCKET_NAME"),
-                        std::env!("AWS_REGION"),
-                        potential_key
-                    );
-                    match HttpResponse::TemporaryRedirect()
-                        .append_header(("Location", location.as_str()))
-                        .await
-                    {
-                        Ok(response) => {
-                            log::debug!("Redirecting to {location}");
-                            response
-                        }
-                        Err(e) => {
-                            log::error!("Failed to create redirect response: {e}");
-                            // Fallback to serving the file from disk
-                            match actix_files::NamedFile::open(full_path) {
-                                Ok(file) => file.into_response(&req),
-                                Err(err) => {
-                                    log::error!("Failed to open file: {err}");
-                                    HttpResponse::InternalServerError().body("File not found")
-                                }
-                            }
-                        }
-                    }
+            // throttled clients fetch large files a piece at a time, NamedFile answers ranges
+            if req.headers().contains_key(actix_web::http::header::RANGE) {
+                return file_from_disk(&req, full_path);
+            }
+            // clients that can decode get compressible files straight from disk, s3 only serves them raw
+            let accepted = req
+                .headers()
+                .get(pitsu_lib::ACCEPT_ENCODING_HEADER)
+                .and_then(|header| header.to_str().ok())
+                .and_then(TransferEncoding::from_accept_header);
+            let hash = serde_json::from_value::<RootFolder>(repo.file_hashes.clone())
+                .ok()
+                .and_then(|root_folder| root_folder.hash_of(&path));
+            if let Some(encoding) = accepted {
+                if let Some(response) = encoded_file_response(&full_path, &path, hash, encoding).await {
+                    return response;
                 }
-                Err(_) => {
-                    // Fallback to serving the file from disk
-                    log::warn!("File not found on S3, serving from disk: {full_path}");
-                    {
-                        let path = path.clone();
-                        let repository_uuid = repo.uuid;
-                        let s3_client = (*client).clone();
-                        tokio::spawn(async move {
-                            // if let Err(e) = delayed_upload_and_insert(pool, file_path, repository_uuid, s3_client).await
-                            // {
-                            //     log::error!("Failed to upload file to S3: {e}");
-                            // }
-                            match get_byte_stream(&repository_uuid, &path).await {
-                                Ok(byte_stream) => {
-                                    if let Err(e) = put_in_s3(
-                                        &s3_client,
-                                        &generate_aws_key_from_path(&repository_uuid, &path),
-                                        byte_stream,
-                                    )
-                                    .await
-                                    {
-                                        log::error!("Failed to upload file to S3: {e}");
-                                    }
-                                }
-                                Err(e) => {
-                                    log::error!("Failed to get byte stream: {e}");
-                                }
-                            }
-                        });
+            }
+            let in_s3 = objects.contains(repo.uuid, &path).await.unwrap_or_else(|err| {
+                log::warn!("{err}");
+                false
+            });
+            if in_s3 {
+                let location = format!(
+                    "https://{}.s3.{}.amazonaws.com/{}",
+                    config::get().aws_bucket_name(),
+                    config::get().aws_region(),
+                    generate_aws_key_from_path(&repo.uuid, &path)
+                );
+                match HttpResponse::TemporaryRedirect()
+                    .append_header(("Location", location.as_str()))
+                    .await
+                {
+                    Ok(response) => {
+                        log::debug!("Redirecting to {location}");
+                        metrics::s3_redirect();
+                        response
                     }
-                    match actix_files::NamedFile::open(full_path) {
-                        Ok(file) => file.into_response(&req),
-                        Err(err) => {
-                            log::error!("Failed to open file: {err}");
-                            HttpResponse::InternalServerError().body("File not found")
-                        }
+                    Err(e) => {
+                        log::error!("Failed to create redirect response: {e}");
+                        // Fallback to serving the file from disk
+                        file_from_disk(&req, full_path)
                     }
                 }
+            } else {
+                // Fallback to serving the file from disk
+                log::warn!("File not found on S3, serving from disk: {full_path}");
+                metrics::s3_fallback();
+                {
+                    let objects = objects.clone();
+                    let repository_uuid = repo.uuid;
+                    tokio::spawn(async move {
+                        if let Err(e) = objects.upload(repository_uuid, &path).await {
+                            log::error!("Failed to upload file to S3: {e}");
+                        }
+                    });
+                }
+                file_from_disk(&req, full_path)
             }
         }
     } else {
-        HttpResponse::NotFound().body("File not found")
+        error_response(ErrorCode::NotFound, "File not found")
     }
 }
 
-#[post("{uuid}/.pit/upload")]
-async fn upload_file(
+fn file_from_disk(req: &actix_web::HttpRequest, full_path: String) -> HttpResponse {
+    match actix_files::NamedFile::open(full_path) {
+        Ok(file) => {
+            metrics::add_download_bytes(file.metadata().len());
+            file.into_response(req)
+        }
+        Err(err) => {
+            log::error!("Failed to open file: {err}");
+            error_response(ErrorCode::Storage, "File not found")
+        }
+    }
+}
+
+// None if the file won't get smaller, the caller should serve it as is
+async fn encoded_file_response(
+    full_path: &str,
+    path: &str,
+    hash: Option<Arc<str>>,
+    encoding: TransferEncoding,
+) -> Option<HttpResponse> {
+    if encoding.for_path(path) == TransferEncoding::None {
+        return None;
+    }
+    // encoded once per content and encoding, an empty entry means it didn't get any smaller
+    // hashes are only ever hex, anything else in a manifest could point outside the cache
+    let cached = hash
+        .filter(|hash| !hash.is_empty() && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(|hash| {
+            PathBuf::from(format!(
+                "{}/.pit-encoded/{hash}.{encoding}",
+                config::get().root_folder()
+            ))
+        });
+    if let Some(cached) = &cached {
+        match tokio::fs::read(cached).await {
+            Ok(encoded) if encoded.is_empty() => return None,
+            Ok(encoded) => return Some(encoded_response(encoding, encoded)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => log::warn!("Failed to read cached encoding {}: {err}", cached.display()),
+        }
+    }
+    let bytes = match tokio::fs::read(full_path).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::error!("Failed to read file {full_path}: {err}");
+            return None;
+        }
+    };
+    let path = path.to_string();
+    let encoded = tokio::task::spawn_blocking(move || {
+        let encoding = encoding.for_file(&path, &bytes);
+        match encoding.encode(&bytes, encoding.default_level()) {
+            Ok(Some(encoded)) if encoded.len() < bytes.len() => Ok(Some((encoding, encoded))),
+            Ok(_) => Ok(None),
+            Err(err) => Err(anyhow::anyhow!("Failed to encode {path} as {encoding}: {err}")),
+        }
+    })
+    .await;
+    let encoded = match encoded {
+        Ok(Ok(encoded)) => encoded,
+        Ok(Err(err)) => {
+            log::error!("{err}");
+            return None;
+        }
+        Err(err) => {
+            log::error!("Failed to join encoding task: {err}");
+            return None;
+        }
+    };
+    if let Some(cached) = &cached {
+        let contents = encoded
+            .as_ref()
+            .map(|(_, encoded)| encoded.as_slice())
+            .unwrap_or_default();
+        if let Err(err) = write_cached_encoding(cached, contents).await {
+            log::warn!("Failed to cache encoding {}: {err}", cached.display());
+        }
+    }
+    encoded.map(|(encoding, encoded)| encoded_response(encoding, encoded))
+}
+
+fn encoded_response(encoding: TransferEncoding, encoded: Vec<u8>) -> HttpResponse {
+    metrics::add_download_bytes(encoded.len() as u64);
+    HttpResponse::Ok()
+        .insert_header((pitsu_lib::ENCODING_HEADER, encoding.to_string()))
+        .body(encoded)
+}
+
+// written next to the final name and renamed so a concurrent request never reads half an entry
+async fn write_cached_encoding(cached: &std::path::Path, contents: &[u8]) -> Result<()> {
+    if let Some(parent) = cached.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let partial = cached.with_extension(format!("{}.partial", Uuid::new_v4()));
+    tokio::fs::write(&partial, contents).await?;
+    tokio::fs::rename(&partial, cached).await?;
+    Ok(())
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ArchiveQuery {
+    #[serde(default)]
+    format: ArchiveFormat,
+    #[serde(default)]
+    path: String,
+}
+
+#[utoipa::path(
+    tag = "files",
+    security(("api_key" = [])),
+    description = "Needs Read access to the repository.",
+    params(ArchiveQuery),
+    responses(
+        (status = 200, content((Vec<u8> = "application/zip"), (Vec<u8> = "application/gzip"))),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 403, description = "Not enough access to the repository", body = ApiError),
+        (status = 404, description = "No such repository or folder", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
+#[get("/{uuid}/.pit/archive")]
+async fn repository_archive(
     req: actix_web::HttpRequest,
     uuid: actix_web::web::Path<uuid::Uuid>,
-    pool: Data<Pool>,
-    client: Data<S3Client>,
-    mut body: Json<FileUpload>,
+    store: Data<dyn DataStore>,
+    objects: Data<dyn ObjectStore>,
+    query: actix_web::web::Query<ArchiveQuery>,
 ) -> impl Responder {
-    let pool = pool.into_inner();
-    let user = match get_user(&req, pool.clone()).await {
+    let user = match get_user(&req, &store).await {
         Ok(user) => user,
-        Err(err) => {
-            log::error!("Failed to get bearer token: {err}");
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
+        Err(response) => return response,
     };
+    let uuid = uuid.into_inner();
 
-    let access_level = match check_user_access(pool.clone(), &user.uuid, &uuid).await {
+    let access_level = match check_user_access(&store, &user.uuid, &uuid).await {
         Ok(level) => level,
-        Err(err) => {
-            log::error!("Failed to check user access: {err}");
-            return HttpResponse::Forbidden().body("Access denied");
-        }
+        Err(response) => return response,
     };
-    if access_level < AccessLevel::Write {
-        log::warn!(
-            "User {} does not have write access to repository {}",
-            user.username,
-            uuid
-        );
-        return HttpResponse::Forbidden().body("Access denied");
+
+    if access_level < AccessLevel::Read {
+        log::warn!("User {} does not have access to repository {}", user.username, uuid);
+        return error_response(ErrorCode::Forbidden, "Access denied");
     }
-    let mut connection = match pool.get().await {
-        Ok(conn) => conn,
+
+    let repo = match store.repository(uuid).await {
+        Ok(repo) => repo,
         Err(err) => {
-            log::error!("Failed to get database connection: {err}");
-            return HttpResponse::InternalServerError().body("Database connection error");
+            log::debug!("Failed to fetch repository: {err}");
+            return error_response(ErrorCode::Database, "Failed to fetch repository");
         }
     };
-    let transaction = match connection.transaction().await {
-        Ok(tx) => tx,
+    let files: RootFolder = match serde_json::from_value(repo.file_hashes) {
+        Ok(files) => files,
         Err(err) => {
-            log::error!("Failed to start transaction: {err}");
-            return HttpResponse::InternalServerError().body("Transaction error");
+            log::error!("Failed to parse file hashes: {err}");
+            return error_response(ErrorCode::Internal, "Failed to parse file hashes");
         }
     };
-
-    let repo = match cornucopia::queries::repository::get_by_uuid()
-        .bind(&transaction, &uuid)
-        .one()
-        .await
-    {
-        Ok(repo) => repo,
+    let folder = match files.index_through(&query.path) {
+        Ok(folder) => folder,
         Err(err) => {
-            log::debug!("Failed to fetch repository: {err}");
-            return HttpResponse::InternalServerError().body("Failed to fetch repository");
+            log::debug!("Failed to index folder: {err}");
+            return error_response(ErrorCode::NotFound, "Folder not found");
         }
     };
-    let root_path = std::env::var("ROOT_FOLDER").unwrap_or_else(|_| "repositories".to_string());
-    let repo_path = format!("{}/{}", root_path, repo.uuid);
-    let mut cleanup_paths = Vec::new();
-    {
-        let _ = transaction.commit().await;
-    }
-    for file in &mut body.files {
-        let raw_path = file.path.clone();
-        let path = raw_path.trim_start_matches("/");
-        let full_path = format!("{repo_path}/{path}");
-        log::debug!("Full path to file: {full_path}");
 
-        if let Some(parent) = std::path::Path::new(&full_path).parent() {
-            if let Err(err) = tokio::fs::create_dir_all(parent).await {
-                log::error!("Failed to create directory: {err}");
-                return HttpResponse::InternalServerError().body("Failed to create directory");
+    let root = PathBuf::from(format!("{}/{}", config::get().root_folder(), repo.uuid));
+    let pitignore = Pitignore::from_repository(root.clone()).unwrap_or_default();
+    let prefix = query.path.trim_matches('/');
+    // (path on disk, path inside the archive)
+    let entries = folder
+        .files()
+        .into_iter()
+        .filter_map(|file| {
+            let full_path = if prefix.is_empty() {
+                file.full_path.to_string()
+            } else {
+                format!("{prefix}/{}", file.full_path)
+            };
+            if pitignore.is_ignored(&full_path) {
+                None
+            } else {
+                Some((root.join(&full_path), file.full_path.to_string()))
             }
+        })
+        .collect::<Vec<_>>();
+    let file_name = match prefix.rsplit('/').next().filter(|s| !s.is_empty()) {
+        Some(folder_name) => format!("{}-{folder_name}.{}", repo.name, query.format.extension()),
+        None => format!("{}.{}", repo.name, query.format.extension()),
+    };
+    log::info!(
+        "User {} is downloading {} file(s) from repository {} as {}",
+        user.username,
+        entries.len(),
+        uuid,
+        query.format
+    );
+
+    // the archive is written on a blocking thread and handed to the response a chunk at a time, so it's never held in memory
+    let (sender, receiver) = tokio::sync::mpsc::channel::<std::io::Result<actix_web::web::Bytes>>(16);
+    let format = query.format;
+    let runtime = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || {
+        // files the disk lost are still in s3, those are fetched one at a time while writing
+        let fetch_from_s3 = |disk_path: &std::path::Path| -> Result<Vec<u8>> {
+            let path = disk_path.strip_prefix(&root)?.to_string_lossy();
+            runtime.block_on(objects.get(uuid, &path))
+        };
+        let mut writer = std::io::BufWriter::with_capacity(64 * 1024, ChannelWriter(sender.clone()));
+        if let Err(e) = write_archive(format, &mut writer, &entries, fetch_from_s3) {
+            log::error!("Failed to write archive for repository {uuid}: {e}");
+            // the status is long gone, the error ends the stream so the client sees a broken download rather than a short archive
+            sender.blocking_send(Err(std::io::Error::other(e.to_string()))).ok();
         }
+    });
+    HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(attachment(&file_name))
+        .streaming(futures::stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|chunk| (chunk, receiver))
+        }))
+}
 
-        let bytes = match file.get_bytes() {
-            Ok(bytes) => bytes,
-            Err(err) => {
-                log::error!("Failed to get file bytes: {err}");
-                return HttpResponse::BadRequest().body("Invalid file data");
+struct ChannelWriter(tokio::sync::mpsc::Sender<std::io::Result<actix_web::web::Bytes>>);
+
+impl std::io::Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .blocking_send(Ok(actix_web::web::Bytes::copy_from_slice(buf)))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Client disconnected"))?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// stops passing bytes on once the archive failed. zip writes its central directory when dropped,
+// and that must not turn a half written archive into one that looks complete
+struct Abortable<'a, W> {
+    inner: W,
+    aborted: &'a std::cell::Cell<bool>,
+}
+
+impl<W: std::io::Write> std::io::Write for Abortable<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.aborted.get() {
+            return Err(std::io::Error::other("Archive aborted"));
+        }
+        self.inner.write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.aborted.get() {
+            return Err(std::io::Error::other("Archive aborted"));
+        }
+        self.inner.flush()
+    }
+}
+
+// entries are (path on disk, path inside the archive), anything missing from disk comes from `fetch_missing`
+fn write_archive<W: std::io::Write>(
+    format: ArchiveFormat,
+    writer: &mut W,
+    entries: &[(PathBuf, String)],
+    mut fetch_missing: impl FnMut(&std::path::Path) -> Result<Vec<u8>>,
+) -> Result<()> {
+    let aborted = std::cell::Cell::new(false);
+    let mut writer = Abortable {
+        inner: writer,
+        aborted: &aborted,
+    };
+    let result = (|| -> Result<()> {
+        match format {
+            ArchiveFormat::Zip => {
+                let mut zip = zip::ZipWriter::new_stream(&mut writer);
+                let options = zip::write::SimpleFileOptions::default()
+                    .compression_method(zip::CompressionMethod::Deflated)
+                    .large_file(true);
+                for (disk_path, name) in entries {
+                    let result = (|| -> Result<()> {
+                        zip.start_file(name.as_str(), options)?;
+                        if disk_path.exists() {
+                            let mut file = std::fs::File::open(disk_path)
+                                .map_err(|e| anyhow::anyhow!("Failed to open {}: {e}", disk_path.display()))?;
+                            std::io::copy(&mut file, &mut zip)
+                                .map_err(|e| anyhow::anyhow!("Failed to read {}: {e}", disk_path.display()))?;
+                        } else {
+                            std::io::Write::write_all(&mut zip, &fetch_missing(disk_path)?)?;
+                        }
+                        Ok(())
+                    })();
+                    if let Err(e) = result {
+                        aborted.set(true);
+                        return Err(e);
+                    }
+                }
+                zip.finish()?;
             }
-        };
+            ArchiveFormat::TarGz => {
+                let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+                    &mut writer,
+                    flate2::Compression::default(),
+                ));
+                for (disk_path, name) in entries {
+                    let result = if disk_path.exists() {
+                        tar.append_path_with_name(disk_path, name)
+                            .map_err(|e| anyhow::anyhow!("Failed to add {}: {e}", disk_path.display()))
+                    } else {
+                        fetch_missing(disk_path).and_then(|bytes| {
+                            let mut header = tar::Header::new_gnu();
+                            header.set_size(bytes.len() as u64);
+                            header.set_mode(0o644);
+                            header.set_mtime(
+                                std::time::SystemTime::now()
+                                    .duration_since(std::time::UNIX_EPOCH)?
+                                    .as_secs(),
+                            );
+                            tar.append_data(&mut header, name, bytes.as_slice())
+                                .map_err(|e| anyhow::anyhow!("Failed to add {name}: {e}"))
+                        })
+                    };
+                    if let Err(e) = result {
+                        aborted.set(true);
+                        return Err(e);
+                    }
+                }
+                tar.into_inner()?.finish()?;
+            }
+        }
+        Ok(())
+    })();
+    result?;
+    writer.flush()?;
+    Ok(())
+}
 
-        if let Err(err) = tokio::fs::write(&full_path, &bytes).await {
-            log::error!("Failed to write file: {err}");
-            return HttpResponse::InternalServerError().body("Failed to write file");
+// a quoted ascii fallback with anything awkward replaced, and the real name rfc 5987 encoded for clients that read it
+fn attachment(file_name: &str) -> ContentDisposition {
+    let file_name = file_name.chars().filter(|c| !c.is_control()).collect::<String>();
+    let fallback = file_name
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii() => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![
+            DispositionParam::Filename(fallback),
+            DispositionParam::FilenameExt(ExtendedValue {
+                charset: Charset::Ext("UTF-8".to_string()),
+                language_tag: None,
+                value: file_name.into_bytes(),
+            }),
+        ],
+    }
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ImportQuery {
+    // drops this many leading folders from every entry, release archives usually wrap everything in one
+    #[serde(default)]
+    strip_components: usize,
+}
+
+#[utoipa::path(
+    tag = "files",
+    security(("api_key" = [])),
+    description = "Needs Write access to the repository.",
+    params(ImportQuery),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream", description = "A zip or tar.gz archive"),
+    responses(
+        (status = 200, description = "What was imported", body = String),
+        (status = 400, description = "Not an archive, or an entry points outside the repository", body = ApiError),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 403, description = "Not enough access to the repository", body = ApiError),
+        (status = 404, description = "Repository not found", body = ApiError),
+        (status = 413, body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
+#[post("/{uuid}/.pit/import")]
+async fn import_archive(
+    req: actix_web::HttpRequest,
+    uuid: actix_web::web::Path<uuid::Uuid>,
+    store: Data<dyn DataStore>,
+    objects: Data<dyn ObjectStore>,
+    query: actix_web::web::Query<ImportQuery>,
+    // PayloadConfig has no error handler, so the limit error is turned into json here
+    body: Result<actix_web::web::Bytes, actix_web::Error>,
+) -> impl Responder {
+    let user = match get_user(&req, &store).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(err) if err.as_response_error().status_code() == actix_web::http::StatusCode::PAYLOAD_TOO_LARGE => {
+            return error_response(ErrorCode::PayloadTooLarge, err.to_string());
         }
+        Err(err) => return error_response(ErrorCode::InvalidRequest, err.to_string()),
+    };
 
-        let byte_stream = match get_byte_stream(&repo.uuid, &file.path).await {
-            Ok(stream) => stream,
-            Err(err) => {
-                log::error!("Failed to get byte stream: {err}");
-                return HttpResponse::InternalServerError().body("Failed to get byte stream");
+    let access_level = match check_user_access(&store, &user.uuid, &uuid).await {
+        Ok(level) => level,
+        Err(response) => return response,
+    };
+    if access_level < AccessLevel::Write {
+        log::warn!(
+            "User {} does not have write access to repository {}",
+            user.username,
+            uuid
+        );
+        return error_response(ErrorCode::Forbidden, "Access denied");
+    }
+    let repo = match store.repository(uuid).await {
+        Ok(repo) => repo,
+        Err(err) => {
+            log::debug!("Failed to fetch repository: {err}");
+            return error_response(ErrorCode::Database, "Failed to fetch repository");
+        }
+    };
+    let repo_path = PathBuf::from(format!("{}/{}", config::get().root_folder(), repo.uuid));
+
+    let strip_components = query.strip_components;
+    let body_len = body.len() as u64;
+    // unpacked next to the repositories rather than into one, nothing in the repository changes until it all unpacked
+    let staging = PathBuf::from(format!(
+        "{}/.pit-import/{}",
+        config::get().root_folder(),
+        Uuid::new_v4()
+    ));
+    let unpack_staging = staging.clone();
+    let unpack_repo_path = repo_path.clone();
+    let unpacked = match tokio::task::spawn_blocking(move || {
+        unpack_archive(&body, &unpack_repo_path, &unpack_staging, strip_components)
+    })
+    .await
+    {
+        Ok(Ok(unpacked)) => unpacked,
+        Ok(Err(err)) => {
+            log::warn!(
+                "User {} uploaded an archive that could not be imported: {err}",
+                user.username
+            );
+            let _ = tokio::fs::remove_dir_all(&staging).await;
+            return error_response(ErrorCode::InvalidArchive, format!("Failed to import archive: {err}"));
+        }
+        Err(err) => {
+            log::error!("Failed to join archive unpacking task: {err}");
+            let _ = tokio::fs::remove_dir_all(&staging).await;
+            return error_response(ErrorCode::Storage, "Failed to import archive");
+        }
+    };
+    metrics::add_upload_bytes(body_len);
+
+    let mut import = ImportedFiles::new(staging, repo_path.clone());
+    if let Err(err) = import.apply(&unpacked.written) {
+        log::error!("Failed to move imported files into repository {uuid}: {err}");
+        import.rollback(repo.uuid, &objects).await;
+        return error_response(ErrorCode::Storage, "Failed to import archive");
+    }
+
+    for path in import.written.clone() {
+        if let Err(err) = objects.upload(repo.uuid, &path).await {
+            log::error!("Failed to upload file to S3: {err}");
+            import.rollback(repo.uuid, &objects).await;
+            return error_response(ErrorCode::Storage, "Failed to upload file to S3");
+        }
+    }
+
+    // one manifest update for the whole archive, so clients see the import as a single change
+    let root_folder = match metrics::ingest_folder(&repo_path) {
+        Ok(folder) => folder,
+        Err(err) => {
+            log::error!("Failed to ingest folder: {err}");
+            import.rollback(repo.uuid, &objects).await;
+            return error_response(ErrorCode::Storage, "Failed to ingest folder");
+        }
+    };
+    let file_hashes = match serde_json::to_value(&root_folder) {
+        Ok(value) => value,
+        Err(err) => {
+            log::error!("Failed to serialize file hashes: {err}");
+            import.rollback(repo.uuid, &objects).await;
+            return error_response(ErrorCode::Internal, "Failed to serialize file hashes");
+        }
+    };
+    match store.update_file_hashes(repo.uuid, &file_hashes).await {
+        Ok(()) => {
+            let written = import.written.len();
+            import.commit().await;
+            log::info!(
+                "User {} imported {} file(s) into repository {} ({} ignored by .pitignore)",
+                user.username,
+                written,
+                uuid,
+                unpacked.ignored
+            );
+            HttpResponse::Ok().body(format!(
+                "Imported {written} file(s), skipped {} ignored by .pitignore",
+                unpacked.ignored
+            ))
+        }
+        Err(err) => {
+            log::error!("Failed to update file hashes: {err}");
+            import.rollback(repo.uuid, &objects).await;
+            error_response(ErrorCode::Database, "Failed to update file hashes")
+        }
+    }
+}
+
+// what an archive may expand to, MAX_UPLOAD_SIZE only limits what is sent
+const MAX_IMPORT_SIZE: u64 = 8 * 1024 * 1024 * 1024;
+const MAX_IMPORT_ENTRIES: usize = 100_000;
+// a .pitignore is read into memory to decide what gets unpacked
+const MAX_PITIGNORE_SIZE: u64 = 1024 * 1024;
+
+struct UnpackedArchive {
+    // paths relative to the repository root, each once
+    written: Vec<String>,
+    ignored: usize,
+}
+
+// every path is validated and the archive is unpacked into `staging`/files, the repository itself isn't touched
+fn unpack_archive(
+    bytes: &[u8],
+    repo_path: &std::path::Path,
+    staging: &std::path::Path,
+    strip_components: usize,
+) -> Result<UnpackedArchive> {
+    let mut pitignore_contents = None;
+    let mut count = 0;
+    let mut entries = 0;
+    for_each_archive_file(bytes, |name, reader| {
+        entries += 1;
+        if entries > MAX_IMPORT_ENTRIES {
+            return Err(anyhow::anyhow!("Archive has more than {MAX_IMPORT_ENTRIES} files"));
+        }
+        if let Some(path) = sanitize_archive_path(name, strip_components)? {
+            if path == ".pitignore" {
+                let mut contents = String::new();
+                std::io::Read::read_to_string(&mut std::io::Read::take(reader, MAX_PITIGNORE_SIZE), &mut contents)?;
+                pitignore_contents = Some(contents);
             }
+            count += 1;
+        }
+        Ok(())
+    })?;
+    if count == 0 {
+        return Err(anyhow::anyhow!("Archive contains no files"));
+    }
+    // a .pitignore shipped in the archive replaces the repository's one once imported, so it's the one that applies
+    let pitignore = match pitignore_contents {
+        Some(contents) => Pitignore::parse(&contents),
+        None => Pitignore::from_repository(repo_path.to_path_buf()).unwrap_or_default(),
+    };
+
+    let mut unpacked = UnpackedArchive {
+        written: Vec::new(),
+        ignored: 0,
+    };
+    let mut seen = std::collections::HashSet::new();
+    let mut size = 0;
+    for_each_archive_file(bytes, |name, reader| {
+        let Some(path) = sanitize_archive_path(name, strip_components)? else {
+            return Ok(());
         };
+        if pitignore.is_ignored(&path) {
+            unpacked.ignored += 1;
+            return Ok(());
+        }
+        let full_path = staging.join("files").join(&path);
+        if let Some(parent) = full_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = std::fs::File::create(&full_path)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {e}", full_path.display()))?;
+        // entry sizes in the archive's headers can lie, so the limit is enforced on what actually comes out
+        let remaining = MAX_IMPORT_SIZE - size;
+        let copied = std::io::copy(&mut std::io::Read::take(reader, remaining + 1), &mut file)?;
+        if copied > remaining {
+            return Err(anyhow::anyhow!(
+                "Archive expands to more than {}",
+                pitsu_lib::readable_size(MAX_IMPORT_SIZE)
+            ));
+        }
+        size += copied;
+        // a later entry with the same path replaces the earlier one, as it would when extracting
+        if seen.insert(path.clone()) {
+            unpacked.written.push(path);
+        }
+        Ok(())
+    })?;
+    Ok(unpacked)
+}
 
-        // Add the file to the files table in the database and upload to S3
-        match put_in_s3(
-            &client,
-            &generate_aws_key_from_path(&repo.uuid, &file.path),
-            byte_stream,
-        )
-        .await
-        {
-            Ok(_) => {}
-            Err(err) => {
-                log::error!("Failed to upload file to S3: {err}");
-                return HttpResponse::InternalServerError().body("Failed to upload file to S3");
+// moves an unpacked archive into the repository, keeping whatever it replaces until the import is committed.
+// layout: <staging>/files/<path> as unpacked, <staging>/replaced/<path> for the originals
+struct ImportedFiles {
+    staging: PathBuf,
+    repo_path: PathBuf,
+    // relative to the repository root
+    written: Vec<String>,
+    // the ones that existed before the import
+    replaced: Vec<String>,
+}
+
+impl ImportedFiles {
+    fn new(staging: PathBuf, repo_path: PathBuf) -> Self {
+        Self {
+            staging,
+            repo_path,
+            written: Vec::new(),
+            replaced: Vec::new(),
+        }
+    }
+    fn apply(&mut self, paths: &[String]) -> Result<()> {
+        for path in paths {
+            let target = self.repo_path.join(path);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
             }
+            if target.exists() {
+                let original = self.staging.join("replaced").join(path);
+                if let Some(parent) = original.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                std::fs::rename(&target, &original)
+                    .map_err(|e| anyhow::anyhow!("Failed to set aside {}: {e}", target.display()))?;
+                self.replaced.push(path.clone());
+            }
+            std::fs::rename(self.staging.join("files").join(path), &target)
+                .map_err(|e| anyhow::anyhow!("Failed to move {path} into the repository: {e}"))?;
+            self.written.push(path.clone());
         }
-        cleanup_paths.push(full_path.clone());
+        Ok(())
+    }
+    // only files the import created are deleted, everything it replaced is put back on disk and in s3
+    async fn rollback(self, repo_uuid: Uuid, objects: &dyn ObjectStore) {
+        for path in &self.written {
+            if let Err(e) = tokio::fs::remove_file(self.repo_path.join(path)).await {
+                log::error!("Failed to remove imported file {path}: {e}");
+            }
+        }
+        let mut restored = true;
+        for path in &self.replaced {
+            if let Err(e) = tokio::fs::rename(self.staging.join("replaced").join(path), self.repo_path.join(path)).await
+            {
+                log::error!(
+                    "Failed to restore {path} after a failed import, the original is kept in {}: {e}",
+                    self.staging.display()
+                );
+                restored = false;
+            }
+        }
+        for path in &self.written {
+            let result = if self.replaced.contains(path) {
+                objects.upload(repo_uuid, path).await
+            } else {
+                objects.remove(repo_uuid, path).await
+            };
+            if let Err(e) = result {
+                log::error!("Failed to roll back S3 object for {path}: {e}");
+            }
+        }
+        if restored {
+            self.commit().await;
+        }
+    }
+    // drops the originals, the import stays
+    async fn commit(self) {
+        if let Err(e) = tokio::fs::remove_dir_all(&self.staging).await {
+            log::warn!("Failed to remove import staging folder {}: {e}", self.staging.display());
+        }
+    }
+}
+
+// calls `f` with the name and contents of every regular file, directories and links are skipped
+fn for_each_archive_file(bytes: &[u8], mut f: impl FnMut(&str, &mut dyn std::io::Read) -> Result<()>) -> Result<()> {
+    if bytes.starts_with(b"PK\x03\x04") {
+        let mut zip = zip::ZipArchive::new(std::io::Cursor::new(bytes))?;
+        for i in 0..zip.len() {
+            let mut file = zip.by_index(i)?;
+            if !file.is_file() || file.is_symlink() {
+                continue;
+            }
+            let name = file.name().to_string();
+            f(&name, &mut file)?;
+        }
+    } else {
+        // plain tar is accepted too, gzip is detected by its magic bytes
+        let reader: Box<dyn std::io::Read + '_> = if bytes.starts_with(&[0x1f, 0x8b]) {
+            Box::new(flate2::read::GzDecoder::new(bytes))
+        } else {
+            Box::new(bytes)
+        };
+        let mut tar = tar::Archive::new(reader);
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let name = entry.path()?.to_string_lossy().to_string();
+            f(&name, &mut entry)?;
+        }
+    }
+    Ok(())
+}
+
+// Ok(None) for entries that are entirely stripped away, Err for anything that could escape the repository
+fn sanitize_archive_path(name: &str, strip_components: usize) -> Result<Option<String>> {
+    let name = name.replace('\\', "/");
+    if name.starts_with('/') {
+        return Err(anyhow::anyhow!("Archive entry {name} has an absolute path"));
+    }
+    let mut parts = Vec::new();
+    for part in name.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => return Err(anyhow::anyhow!("Archive entry {name} points outside the repository")),
+            part if part.contains(':') => return Err(anyhow::anyhow!("Archive entry {name} has an invalid path")),
+            part => parts.push(part),
+        }
+    }
+    if parts.len() <= strip_components {
+        return Ok(None);
+    }
+    Ok(Some(parts[strip_components..].join("/")))
+}
+
+#[utoipa::path(
+    tag = "files",
+    security(("api_key" = [])),
+    description = "Needs Write access to the repository. Files with a part are held back until their last part \
+        arrives, parts have to be sent in order.",
+    request_body = FileUpload,
+    responses(
+        (status = 200, body = String),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 403, description = "Not enough access to the repository", body = ApiError),
+        (status = 404, description = "Repository not found", body = ApiError),
+        (status = 413, body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
+#[post("/{uuid}/.pit/upload")]
+async fn upload_file(
+    req: actix_web::HttpRequest,
+    uuid: actix_web::web::Path<uuid::Uuid>,
+    store: Data<dyn DataStore>,
+    objects: Data<dyn ObjectStore>,
+    mut body: Json<FileUpload>,
+) -> impl Responder {
+    let user = match get_user(&req, &store).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let access_level = match check_user_access(&store, &user.uuid, &uuid).await {
+        Ok(level) => level,
+        Err(response) => return response,
+    };
+    if access_level < AccessLevel::Write {
+        log::warn!(
+            "User {} does not have write access to repository {}",
+            user.username,
+            uuid
+        );
+        return error_response(ErrorCode::Forbidden, "Access denied");
     }
-    let transaction = match connection.transaction().await {
-        Ok(tx) => tx,
+    let repo = match store.repository(uuid).await {
+        Ok(repo) => repo,
         Err(err) => {
-            log::error!("Failed to start transaction: {err}");
-            return HttpResponse::InternalServerError().body("Transaction error");
+            log::debug!("Failed to fetch repository: {err}");
+            return error_response(ErrorCode::Database, "Failed to fetch repository");
         }
     };
+    let root_path = config::get().root_folder();
+    let repo_path = format!("{}/{}", root_path, repo.uuid);
+    let mut cleanup_paths = Vec::new();
+    for file in &mut body.files {
+        let raw_path = file.path.clone();
+        let path = raw_path.trim_start_matches("/");
+        let full_path = format!("{repo_path}/{path}");
+        log::debug!("Full path to file: {full_path}");
+
+        if let Some(parent) = std::path::Path::new(&full_path).parent() {
+            if let Err(err) = tokio::fs::create_dir_all(parent).await {
+                log::error!("Failed to create directory: {err}");
+                return error_response(ErrorCode::Storage, "Failed to create directory");
+            }
+        }
 
-    let root_folder = match RootFolder::ingest_folder(&repo_path.clone().into()) {
+        let bytes = match file.get_bytes() {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                log::error!("Failed to get file bytes: {err}");
+                return error_response(ErrorCode::InvalidRequest, "Invalid file data");
+            }
+        };
+
+        if let Some(part) = file.part {
+            match write_part(&repo.uuid, path, &full_path, part, &bytes).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    metrics::add_upload_bytes(bytes.len() as u64);
+                    continue;
+                }
+                Err(err) => {
+                    log::error!("Failed to write part of {path}: {err}");
+                    return error_response(
+                        ErrorCode::InvalidRequest,
+                        format!("Failed to write part of {path}: {err}"),
+                    );
+                }
+            }
+        } else if let Err(err) = tokio::fs::write(&full_path, &bytes).await {
+            log::error!("Failed to write file: {err}");
+            return error_response(ErrorCode::Storage, "Failed to write file");
+        }
+        metrics::add_upload_bytes(bytes.len() as u64);
+
+        // keyed without the leading slash, like downloads, `sync` and fsck look it up
+        if let Err(err) = objects.upload(repo.uuid, path).await {
+            log::error!("Failed to upload file to S3: {err}");
+            return error_response(ErrorCode::Storage, "Failed to upload file to S3");
+        }
+        cleanup_paths.push(full_path.clone());
+    }
+    if cleanup_paths.is_empty() && !body.files.is_empty() {
+        // only pieces of files that aren't complete yet, nothing in the repository changed
+        return HttpResponse::Ok().body("Part received");
+    }
+    let root_folder = match metrics::ingest_folder(&repo_path.clone().into()) {
         Ok(folder) => folder,
         Err(err) => {
             log::error!("Failed to ingest folder: {err}");
@@ -909,20 +1562,13 @@ async fn upload_file(
             for path in cleanup_paths {
                 let _ = tokio::fs::remove_file(&path).await;
             }
-            return HttpResponse::InternalServerError().body("Failed to ingest folder");
+            return error_response(ErrorCode::Storage, "Failed to ingest folder");
         }
     };
 
     // .pitignore handling, delete any files that are in the .pitignore if it's just been uploaded
-    let pitignore = Pitignore::from_repository(
-        format!(
-            "{}/{}/",
-            std::env::var("ROOT_FOLDER").unwrap_or_else(|_| "repositories".to_string()),
-            uuid
-        )
-        .into(),
-    )
-    .unwrap_or_default();
+    let pitignore =
+        Pitignore::from_repository(format!("{}/{}/", config::get().root_folder(), uuid).into()).unwrap_or_default();
 
     for file in root_folder.files() {
         if pitignore.is_ignored(&file.full_path) {
@@ -935,7 +1581,7 @@ async fn upload_file(
         }
     }
 
-    let root_folder = match RootFolder::ingest_folder(&repo_path.into()) {
+    let root_folder = match metrics::ingest_folder(&repo_path.into()) {
         Ok(folder) => folder,
         Err(err) => {
             log::error!("Failed to ingest folder: {err}");
@@ -943,7 +1589,7 @@ async fn upload_file(
             for path in cleanup_paths {
                 let _ = tokio::fs::remove_file(&path).await;
             }
-            return HttpResponse::InternalServerError().body("Failed to ingest folder");
+            return error_response(ErrorCode::Storage, "Failed to ingest folder");
         }
     };
 
@@ -955,60 +1601,142 @@ async fn upload_file(
             for path in cleanup_paths {
                 let _ = tokio::fs::remove_file(&path).await;
             }
-            return HttpResponse::InternalServerError().body("Failed to serialize file hashes");
+            return error_response(ErrorCode::Internal, "Failed to serialize file hashes");
         }
     };
-    match cornucopia::queries::repository::update_file_hashes_by_uuid()
-        .bind(&transaction, &file_hashes, &repo.uuid)
-        .one()
-        .await
-    {
-        Ok(_) => {
-            if let Err(err) = transaction.commit().await {
-                log::error!("Failed to commit transaction: {err}");
-
-                for path in cleanup_paths {
-                    let _ = tokio::fs::remove_file(&path).await;
-                }
-                return HttpResponse::InternalServerError().body("Failed to commit changes");
-            }
-            HttpResponse::Ok().body("File uploaded successfully")
-        }
+    match store.update_file_hashes(repo.uuid, &file_hashes).await {
+        Ok(()) => HttpResponse::Ok().body("File uploaded successfully"),
         Err(err) => {
             log::error!("Failed to update file hashes: {err}");
 
             for path in cleanup_paths {
                 let _ = tokio::fs::remove_file(&path).await;
             }
-            transaction.rollback().await.ok();
-            HttpResponse::InternalServerError().body("Failed to update file hashes")
+            error_response(ErrorCode::Database, "Failed to update file hashes")
+        }
+    }
+}
+
+// pieces are appended to a copy outside the repository, which replaces the real file once the last one is in.
+// returns true when that happened
+async fn write_part(repo_uuid: &Uuid, path: &str, full_path: &str, part: FilePart, bytes: &[u8]) -> Result<bool> {
+    let staging = parts_folder().join(repo_uuid.to_string()).join(path);
+    let result = append_part(&staging, full_path, part, bytes).await;
+    match result {
+        Ok(false) => {}
+        // a failed part makes the client start over at offset 0, so the copy is of no use anymore
+        Ok(true) | Err(_) => {
+            let _ = tokio::fs::remove_file(&staging).await;
+            remove_empty_parts_folders(&staging);
+        }
+    }
+    result
+}
+
+async fn append_part(staging: &std::path::Path, full_path: &str, part: FilePart, bytes: &[u8]) -> Result<bool> {
+    use tokio::io::AsyncWriteExt as _;
+    if let Some(parent) = staging.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    let received = match part.offset {
+        0 => 0,
+        _ => tokio::fs::metadata(staging).await.map(|m| m.len()).unwrap_or(0),
+    };
+    if received != part.offset {
+        anyhow::bail!("expected the part at offset {received}, got {}", part.offset);
+    }
+    let received = received + bytes.len() as u64;
+    if received > part.total {
+        anyhow::bail!("parts add up to more than {} bytes", part.total);
+    }
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(part.offset != 0)
+        .truncate(part.offset == 0)
+        .open(staging)
+        .await?;
+    file.write_all(bytes).await?;
+    file.flush().await?;
+    if received < part.total {
+        return Ok(false);
+    }
+    tokio::fs::rename(staging, full_path).await?;
+    Ok(true)
+}
+
+// uploads the client gave up on never send the part that would clean them up, the server sweeps them on startup
+const PARTS_MAX_AGE: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+fn parts_folder() -> PathBuf {
+    PathBuf::from(config::get().root_folder()).join(".pit-parts")
+}
+
+// walks up from a staging file, stops at the first folder that still has something in it
+fn remove_empty_parts_folders(staging: &std::path::Path) {
+    let root = parts_folder();
+    for folder in staging.ancestors().skip(1) {
+        if folder == root || !folder.starts_with(&root) || std::fs::remove_dir(folder).is_err() {
+            break;
+        }
+    }
+}
+
+// removes staged parts that were not written to for max_age, returns how many
+fn sweep_parts(max_age: std::time::Duration) -> usize {
+    fn sweep(folder: &std::path::Path, max_age: std::time::Duration) -> usize {
+        let Ok(read_dir) = std::fs::read_dir(folder) else {
+            return 0;
+        };
+        let mut removed = 0;
+        for entry in read_dir.flatten() {
+            let path = entry.path();
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                removed += sweep(&path, max_age);
+                let _ = std::fs::remove_dir(&path);
+                continue;
+            }
+            let age = metadata.modified().ok().and_then(|modified| modified.elapsed().ok());
+            if age.is_some_and(|age| age >= max_age) && std::fs::remove_file(&path).is_ok() {
+                removed += 1;
+            }
         }
+        removed
     }
+    sweep(&parts_folder(), max_age)
 }
 
+#[utoipa::path(
+    tag = "files",
+    security(("api_key" = [])),
+    description = "Needs Write access to the repository.",
+    responses(
+        (status = 200, body = String),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 403, description = "Not enough access to the repository", body = ApiError),
+        (status = 404, description = "No such repository, file or folder", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
 #[delete("/{uuid}/{path:.*}")]
 async fn delete_file(
     req: actix_web::HttpRequest,
     path_stuff: actix_web::web::Path<(uuid::Uuid, String)>,
-    pool: Data<Pool>,
-    client: Data<S3Client>,
+    store: Data<dyn DataStore>,
+    objects: Data<dyn ObjectStore>,
 ) -> impl Responder {
-    let pool = pool.into_inner();
-    let user = match get_user(&req, pool.clone()).await {
+    let user = match get_user(&req, &store).await {
         Ok(user) => user,
-        Err(err) => {
-            log::error!("Failed to get bearer token: {err}");
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
+        Err(response) => return response,
     };
     let (uuid, path) = path_stuff.into_inner();
 
-    let access_level = match check_user_access(pool.clone(), &user.uuid, &uuid).await {
+    let access_level = match check_user_access(&store, &user.uuid, &uuid).await {
         Ok(level) => level,
-        Err(err) => {
-            log::error!("Failed to check user access: {err}");
-            return HttpResponse::Forbidden().body("Access denied");
-        }
+        Err(response) => return response,
     };
     if access_level < AccessLevel::Write {
         log::warn!(
@@ -1016,35 +1744,16 @@ async fn delete_file(
             user.username,
             uuid
         );
-        return HttpResponse::Forbidden().body("Access denied");
+        return error_response(ErrorCode::Forbidden, "Access denied");
     }
-    let mut connection = match pool.get().await {
-        Ok(conn) => conn,
-        Err(err) => {
-            log::error!("Failed to get database connection: {err}");
-            return HttpResponse::InternalServerError().body("Database connection error");
-        }
-    };
-    let transaction = match connection.transaction().await {
-        Ok(tx) => tx,
-        Err(err) => {
-            log::error!("Failed to start transaction: {err}");
-            return HttpResponse::InternalServerError().body("Transaction error");
-        }
-    };
-
-    let repo = match cornucopia::queries::repository::get_by_uuid()
-        .bind(&transaction, &uuid)
-        .one()
-        .await
-    {
+    let repo = match store.repository(uuid).await {
         Ok(repo) => repo,
         Err(err) => {
             log::debug!("Failed to fetch repository: {err}");
-            return HttpResponse::InternalServerError().body("Failed to fetch repository");
+            return error_response(ErrorCode::Database, "Failed to fetch repository");
         }
     };
-    let root_path = std::env::var("ROOT_FOLDER").unwrap_or_else(|_| "repositories".to_string());
+    let root_path = config::get().root_folder();
     let full_path = format!("{}/{}/{}", root_path, repo.uuid, path);
     log::debug!("Full path to file: {full_path}");
 
@@ -1052,201 +1761,206 @@ async fn delete_file(
         Ok(meta) => meta,
         Err(err) => {
             log::debug!("Failed to get metadata for path: {err}");
-            return HttpResponse::NotFound().body("File or directory not found");
+            return error_response(ErrorCode::NotFound, "File or directory not found");
         }
     };
 
     if metadata.is_dir() {
         if let Err(err) = tokio::fs::remove_dir_all(&full_path).await {
             log::error!("Failed to delete file or directory: {err}");
-            return HttpResponse::InternalServerError().body("Failed to delete file or directory");
+            return error_response(ErrorCode::Storage, "Failed to delete file or directory");
         }
     } else if metadata.is_file() {
         if let Err(err) = tokio::fs::remove_file(&full_path).await {
             log::error!("Failed to delete file: {err}");
-            return HttpResponse::InternalServerError().body("Failed to delete file");
+            return error_response(ErrorCode::Storage, "Failed to delete file");
         }
     } else {
         log::debug!("Path is neither a file nor a directory");
-        return HttpResponse::NotFound().body("File or directory not found");
+        return error_response(ErrorCode::NotFound, "File or directory not found");
     }
 
     // Delete file from S3
-    if let Err(err) = remove_from_s3(&client, &generate_aws_key_from_path(&repo.uuid, &path)).await {
+    if let Err(err) = objects.remove(repo.uuid, &path).await {
         log::warn!("Failed to delete S3 object: {err}");
     }
 
-    let root_folder = match RootFolder::ingest_folder(&format!("{}/{}", root_path, repo.uuid).into()) {
+    let root_folder = match metrics::ingest_folder(&format!("{}/{}", root_path, repo.uuid).into()) {
         Ok(folder) => folder,
         Err(err) => {
             log::error!("Failed to ingest folder: {err}");
-            return HttpResponse::InternalServerError().body("Failed to ingest folder");
+            return error_response(ErrorCode::Storage, "Failed to ingest folder");
         }
     };
     let file_hashes = match serde_json::to_value(&root_folder) {
         Ok(value) => value,
         Err(err) => {
             log::error!("Failed to serialize file hashes: {err}");
-            return HttpResponse::InternalServerError().body("Failed to serialize file hashes");
+            return error_response(ErrorCode::Internal, "Failed to serialize file hashes");
         }
     };
-    match cornucopia::queries::repository::update_file_hashes_by_uuid()
-        .bind(&transaction, &file_hashes, &repo.uuid)
-        .one()
-        .await
-    {
-        Ok(_) => {
-            if let Err(err) = transaction.commit().await {
-                log::error!("Failed to commit transaction: {err}");
-                return HttpResponse::InternalServerError().body("Failed to commit changes");
-            }
-            HttpResponse::Ok().body("File deleted successfully")
-        }
+    match store.update_file_hashes(repo.uuid, &file_hashes).await {
+        Ok(()) => HttpResponse::Ok().body("File deleted successfully"),
         Err(err) => {
             log::error!("Failed to update file hashes: {err}");
-            transaction.rollback().await.ok();
-            HttpResponse::InternalServerError().body("Failed to update file hashes")
+            error_response(ErrorCode::Database, "Failed to update file hashes")
         }
     }
 }
 
+#[utoipa::path(
+    tag = "repositories",
+    security(("api_key" = [])),
+    description = "The new repository is owned by whoever created it.",
+    request_body = CreateRemoteRepository,
+    responses(
+        (status = 201, body = RemoteRepository),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
 #[post("/api/repository")]
 async fn create_repository(
     req: actix_web::HttpRequest,
-    pool: Data<Pool>,
+    store: Data<dyn DataStore>,
     body: actix_web::web::Json<CreateRemoteRepository>,
 ) -> HttpResponse {
-    let pool = pool.into_inner();
-    let user = match get_user(&req, pool.clone()).await {
+    let user = match get_user(&req, &store).await {
         Ok(user) => user,
-        Err(err) => {
-            log::error!("Failed to get bearer token: {err}");
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
-    };
-    let mut connection = match pool.get().await {
-        Ok(conn) => conn,
-        Err(err) => {
-            log::error!("Failed to get database connection: {err}");
-            return HttpResponse::InternalServerError().body("Failed to get database connection");
-        }
-    };
-    let transaction = match connection.transaction().await {
-        Ok(tx) => tx,
-        Err(err) => {
-            log::error!("Failed to start transaction: {err}");
-            return HttpResponse::InternalServerError().body("Failed to start transaction");
-        }
-    };
-    let res = crate::cornucopia::queries::repository::create()
-        .bind(&transaction, &&*body.name, &user.uuid)
-        .one()
-        .await;
-    match res {
-        Ok(repo) => {
-            transaction.commit().await.unwrap_or_else(|err| {
-                log::error!("Failed to commit transaction: {err}");
-            });
-            HttpResponse::Created().json(RemoteRepository {
-                pitignore: Pitignore::default(),
-                uuid: repo.uuid,
-                name: repo.name.into(),
+        Err(response) => return response,
+    };
+    match store.create_repository(&body.name, user.uuid).await {
+        Ok(repo) => HttpResponse::Created().json(RemoteRepository {
+            pitignore: Pitignore::default(),
+            recommended_path: None,
+            uuid: repo.uuid,
+            name: repo.name.into(),
+            access_level: AccessLevel::Owner,
+            size: 0,
+            file_count: 0,
+            files: RootFolder::default(),
+            users: vec![UserWithAccess {
+                user: User {
+                    uuid: user.uuid,
+                    username: user.username,
+                },
                 access_level: AccessLevel::Owner,
-                size: 0,
-                file_count: 0,
-                files: RootFolder::default(),
-                users: vec![UserWithAccess {
-                    user: User {
-                        uuid: user.uuid,
-                        username: user.username,
-                    },
-                    access_level: AccessLevel::Owner,
-                }],
-            })
-        }
+            }],
+        }),
         Err(err) => {
             log::error!("Failed to create repository: {err}");
-            transaction.rollback().await.unwrap_or_else(|err| {
-                log::error!("Failed to rollback transaction: {err}");
-            });
-            HttpResponse::InternalServerError().body("Failed to create repository")
+            error_response(ErrorCode::Database, "Failed to create repository")
         }
     }
 }
 
+#[utoipa::path(
+    tag = "client",
+    description = "Downloads the client, the same binary for every user. Authenticated by the invite code rather \
+        than an api key, the key itself is handed over by POST /api/invite.",
+    params(InviteQuery),
+    responses(
+        (status = 200, body = Vec<u8>, content_type = "application/octet-stream"),
+        (status = 400, description = "Malformed or unknown invite code", body = ApiError),
+        (status = 404, description = "No client is built for the target", body = ApiError),
+        (status = 503, description = "The client hasn't been built yet", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
 #[get("/api/invite")]
 async fn invite_user(
-    req: actix_web::HttpRequest,
-    pool: Data<Pool>,
-    lock: Data<InviteLock>,
+    store: Data<dyn DataStore>,
+    artifacts: Data<ArtifactStore>,
     query: actix_web::web::Query<InviteQuery>,
 ) -> impl Responder {
-    let path = {
-        // Acquire an invite lock to prevent concurrent invites (this builds the app with set env vars so we can't do multiple invites at once)
-        #[allow(unused_variables, unused_mut)]
-        let mut lock = lock.lock().await;
-        let pool = pool.into_inner();
-        let user_uuid = match query.extract() {
-            Ok(code) => code,
-            Err(err) => {
-                log::error!("Failed to extract invite code: {err}");
-                return HttpResponse::BadRequest().body("Invalid invite code format");
-            }
-        };
-        let mut connection = match pool.get().await {
-            Ok(conn) => conn,
-            Err(err) => {
-                log::error!("Failed to get database connection: {err}");
-                return HttpResponse::InternalServerError().body("Database connection error");
-            }
-        };
-        let transaction = match connection.transaction().await {
-            Ok(tx) => tx,
-            Err(err) => {
-                log::error!("Failed to start transaction: {err}");
-                return HttpResponse::InternalServerError().body("Transaction error");
-            }
-        };
-        let user = match cornucopia::queries::user::get_by_uuid()
-            .bind(&transaction, &user_uuid)
-            .one()
-            .await
-        {
-            Ok(user) => user,
-            Err(err) => {
-                log::error!("Failed to fetch user: {err}");
-                return HttpResponse::InternalServerError().body("Failed to fetch user");
-            }
-        };
-        // eventually expand this to build for the users OS, but for now just windows
-        match build_executable(Some(Arc::from(user.api_key))).await {
-            Ok(path) => path,
-            Err(err) => {
-                log::error!("Failed to build executable: {err}");
-                return HttpResponse::InternalServerError().body("Failed to build executable");
-            }
+    if let Err(response) = invited_user(&store, &query.code).await {
+        return response;
+    }
+    let path = match artifacts.release(query.channel, &query.target).await {
+        Ok((_, path)) => path,
+        Err(response) => return response,
+    };
+    let bytes = match tokio::fs::read(&path).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::error!("Failed to read executable file {}: {err}", path.display());
+            return error_response(ErrorCode::Storage, "Failed to open invite file");
         }
     };
-    // serve the executable file as a download
-    let file = match actix_files::NamedFile::open(path) {
-        Ok(file) => file,
+    HttpResponse::Ok()
+        .content_type("application/octet-stream")
+        .insert_header(attachment(client_file_name(&query.target)))
+        .body(bytes)
+}
+
+// the client opens pitsu://invite/{code} on launch and trades the code for the api key here
+#[utoipa::path(
+    tag = "client",
+    description = "Answers with the invited user's api key. Authenticated by the invite code rather than an api key.",
+    request_body = InviteCode,
+    responses(
+        (status = 200, description = "The api key", body = String),
+        (status = 400, description = "Malformed or unknown invite code", body = ApiError),
+        (status = 500, description = "Database failure", body = ApiError),
+    )
+)]
+#[post("/api/invite")]
+async fn redeem_invite(store: Data<dyn DataStore>, body: Json<InviteCode>) -> impl Responder {
+    match invited_user(&store, &body.code).await {
+        Ok(user) => {
+            log::info!("Redeemed invite for {}", user.username);
+            HttpResponse::Ok().body(user.api_key)
+        }
+        Err(response) => response,
+    }
+}
+
+async fn invited_user(store: &Data<dyn DataStore>, code: &str) -> Result<store::StoredUser, HttpResponse> {
+    let user_uuid = match InviteQuery::extract(code) {
+        Ok(uuid) => uuid,
         Err(err) => {
-            log::error!("Failed to open executable file: {err}");
-            return HttpResponse::InternalServerError().body("Failed to open invite file");
+            log::error!("Failed to extract invite code: {err}");
+            return Err(error_response(
+                ErrorCode::InvalidInviteCode,
+                "Invalid invite code format",
+            ));
         }
     };
-    file.into_response(&req)
+    match store.user(user_uuid).await {
+        Ok(Some(user)) => Ok(user),
+        Ok(None) => {
+            log::warn!("Invite code for unknown user {user_uuid}");
+            Err(error_response(ErrorCode::InvalidInviteCode, "Unknown invite code"))
+        }
+        Err(err) => {
+            log::error!("Failed to fetch user: {err}");
+            Err(error_response(ErrorCode::Database, "Failed to fetch user"))
+        }
+    }
 }
 
+#[utoipa::path(
+    tag = "client",
+    security(("api_key" = [])),
+    params(ReleaseQuery),
+    responses(
+        (status = 200, body = VersionNumber),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 404, description = "No client is built for the target", body = ApiError),
+        (status = 503, description = "The client hasn't been built yet", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
 #[get("/api/local/version")]
-async fn get_local_version(req: actix_web::HttpRequest, pool: Data<Pool>) -> impl Responder {
-    let pool = pool.into_inner();
-    let _user = match get_user(&req, pool.clone()).await {
+async fn get_local_version(
+    req: actix_web::HttpRequest,
+    store: Data<dyn DataStore>,
+    artifacts: Data<ArtifactStore>,
+    query: actix_web::web::Query<ReleaseQuery>,
+) -> impl Responder {
+    let _user = match get_user(&req, &store).await {
         Ok(user) => user,
-        Err(err) => {
-            log::error!("Failed to get bearer token: {err}");
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
+        Err(response) => return response,
     };
     // git pull in the Pitsu repository
     // let output = match std::process::Command::new("git")
@@ -1281,52 +1995,102 @@ async fn get_local_version(req: actix_web::HttpRequest, pool: Data<Pool>) -> imp
     //     }
     // };
     // let commit_hash = String::from_utf8_lossy(&output.stdout).to_string();
-    let version_number = match get_client_version() {
-        Ok(version) => version,
+    let version_number = match artifacts.release(query.channel, &query.target).await {
+        Ok((version, _)) => version,
+        Err(response) => return response,
+    };
+    HttpResponse::Ok().json(version_number)
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ChangelogQuery {
+    from: String,
+    to: String,
+}
+
+// notes for every version after `from` up to and including `to`, newest first
+#[utoipa::path(
+    tag = "client",
+    security(("api_key" = [])),
+    params(ChangelogQuery),
+    responses(
+        (status = 200, description = "Newest first", body = Vec<ChangelogEntry>),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
+#[get("/api/local/changelog")]
+async fn get_changelog(
+    req: actix_web::HttpRequest,
+    store: Data<dyn DataStore>,
+    query: actix_web::web::Query<ChangelogQuery>,
+) -> impl Responder {
+    let _user = match get_user(&req, &store).await {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+    let (from, to) = match (
+        pitsu_lib::semver::Version::parse(&query.from),
+        pitsu_lib::semver::Version::parse(&query.to),
+    ) {
+        (Ok(from), Ok(to)) => (from, to),
+        _ => return error_response(ErrorCode::InvalidRequest, "Invalid version"),
+    };
+    let changelog_path = PathBuf::from(CHECKOUT).join("local/CHANGELOG.md");
+    let contents = match tokio::fs::read_to_string(&changelog_path).await {
+        Ok(contents) => contents,
         Err(err) => {
-            log::error!("Failed to get client version: {err}");
-            return HttpResponse::InternalServerError().body("Failed to get local version");
+            log::warn!("Failed to read {}: {err}", changelog_path.display());
+            String::new()
         }
     };
-    HttpResponse::Ok().json(version_number)
+    let entries = pitsu_lib::parse_changelog(&contents)
+        .into_iter()
+        .filter(|(version, _)| *version > from && *version <= to)
+        .map(|(_, entry)| entry)
+        .collect::<Vec<ChangelogEntry>>();
+    HttpResponse::Ok().json(entries)
 }
 
 // like invite except checks via the user's bearer token rather than the invite code
+#[utoipa::path(
+    tag = "client",
+    security(("api_key" = [])),
+    params(ReleaseQuery),
+    responses(
+        (
+            status = 200,
+            body = Vec<u8>,
+            content_type = "application/octet-stream",
+            headers(("x-pitsu-signature" = String, description = "Base64 ed25519 signature of the body")),
+        ),
+        (status = 401, description = "Missing or unknown api key", body = ApiError),
+        (status = 404, description = "No client is built for the target", body = ApiError),
+        (status = 503, description = "The client hasn't been built yet", body = ApiError),
+        (status = 500, description = "Database or storage failure", body = ApiError),
+    )
+)]
 #[get("/api/local/update")]
-async fn get_latest_version(req: actix_web::HttpRequest, pool: Data<Pool>) -> impl Responder {
-    let pool = pool.into_inner();
-    let _user = match get_user(&req, pool.clone()).await {
+async fn get_latest_version(
+    req: actix_web::HttpRequest,
+    store: Data<dyn DataStore>,
+    artifacts: Data<ArtifactStore>,
+    query: actix_web::web::Query<ReleaseQuery>,
+) -> impl Responder {
+    let _user = match get_user(&req, &store).await {
         Ok(user) => user,
-        Err(err) => {
-            log::error!("Failed to get bearer token: {err}");
-            return HttpResponse::Unauthorized().body("Unauthorized");
-        }
+        Err(response) => return response,
     };
-    // git pull in the Pitsu repository
-    // let output = match std::process::Command::new("git")
-    //     .arg("pull")
-    //     .current_dir(env!("CARGO_MANIFEST_DIR"))
-    //     .output()
-    // {
-    //     Ok(output) => output,
-    //     Err(err) => {
-    //         log::error!("Failed to run git pull: {err}");
-    //         return HttpResponse::InternalServerError().body("Failed to update local version");
-    //     }
-    // };
-    // if !output.status.success() {
-    //     log::error!(
-    //         "Git pull failed: {}",
-    //         String::from_utf8_lossy(&output.stderr)
-    //     );
-    //     return HttpResponse::InternalServerError().body("Failed to update local version");
-    // }
-    // build and serve the executable file
-    let path = match build_executable(None).await {
-        Ok(path) => path,
+    let path = match artifacts.release(query.channel, &query.target).await {
+        Ok((_, path)) => path,
+        Err(response) => return response,
+    };
+    let signature = match tokio::fs::read_to_string(signature_path(&path)).await {
+        Ok(signature) => signature,
         Err(err) => {
-            log::error!("Failed to build executable: {err}");
-            return HttpResponse::InternalServerError().body("Failed to build executable");
+            log::error!("Failed to read signature for {}: {err}", path.display());
+            return error_response(ErrorCode::Storage, "Failed to read update signature");
         }
     };
     // serve the executable file as a download
@@ -1334,62 +2098,223 @@ async fn get_latest_version(req: actix_web::HttpRequest, pool: Data<Pool>) -> im
         Ok(file) => file,
         Err(err) => {
             log::error!("Failed to open executable file: {err}");
-            return HttpResponse::InternalServerError().body("Failed to open update file");
+            return error_response(ErrorCode::Storage, "Failed to open update file");
         }
     };
-    // serve the executable file as a download
-    file.into_response(&req)
+    let mut response = file.into_response(&req);
+    match HeaderValue::from_str(signature.trim()) {
+        Ok(value) => {
+            response
+                .headers_mut()
+                .insert(HeaderName::from_static(pitsu_lib::SIGNATURE_HEADER), value);
+        }
+        Err(err) => {
+            log::error!("Stored signature is not a valid header value: {err}");
+            return error_response(ErrorCode::Storage, "Failed to read update signature");
+        }
+    }
+    response
+}
+
+fn signature_path(artifact: &std::path::Path) -> PathBuf {
+    let mut path = artifact.as_os_str().to_owned();
+    path.push(".sig");
+    PathBuf::from(path)
+}
+
+// CLIENT_SIGNING_KEY is the base64 seed printed by `keygen`, read at runtime so it never ends up in a binary
+fn client_signing_key() -> Result<SigningKey> {
+    let encoded = config::get()
+        .client_signing_key()
+        .ok_or_else(|| anyhow::anyhow!("CLIENT_SIGNING_KEY is not set"))?;
+    let seed: [u8; 32] = pitsu_lib::decode_base64(encoded.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("CLIENT_SIGNING_KEY must be 32 bytes"))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ReleaseQuery {
+    #[serde(default)]
+    channel: UpdateChannel,
+    #[serde(default = "default_client_target")]
+    target: String,
+}
+
+// targets the client is built for, the first one is served when a request doesn't say
+const CLIENT_TARGETS: &[&str] = &["x86_64-pc-windows-gnu", "x86_64-unknown-linux-gnu"];
+
+fn default_client_target() -> String {
+    CLIENT_TARGETS[0].to_string()
 }
-struct InviteLock(Mutex<()>);
 
-impl Deref for InviteLock {
-    type Target = Mutex<()>;
+// the workspace this server was built from, client builds, git pull and the changelog all read it
+const CHECKOUT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/..");
+
+fn client_file_name(target: &str) -> &'static str {
+    if target.contains("windows") {
+        "pitsu.exe"
+    } else {
+        "pitsu"
+    }
+}
 
-    fn deref(&self) -> &Self::Target {
-        &self.0
-    }
+// client binaries built once per version and target, served to every user from disk.
+// requests only ever read the store, `refresh` fills it from the `release` command
+struct ArtifactStore {
+    root: PathBuf,
 }
 
-impl DerefMut for InviteLock {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+impl ArtifactStore {
+    fn new() -> Self {
+        Self {
+            root: PathBuf::from(config::get().artifact_folder()),
+        }
+    }
+    fn path(&self, version: &VersionNumber, target: &str) -> PathBuf {
+        self.root
+            .join(version.artifact_name())
+            .join(target)
+            .join(client_file_name(target))
+    }
+    // newest signed artifact on the channel, older versions stay in the store so stable keeps being served while
+    // the checkout is on a pre-release. the error is the response to send, 404 for unknown targets and 503 while nothing is built
+    async fn release(&self, channel: UpdateChannel, target: &str) -> Result<(VersionNumber, PathBuf), HttpResponse> {
+        if !CLIENT_TARGETS.contains(&target) {
+            return Err(error_response(
+                ErrorCode::NotFound,
+                format!("No client is built for {target}"),
+            ));
+        }
+        match self.newest(channel, target).await {
+            Ok(Some(release)) => Ok(release),
+            Ok(None) => Err(error_response(
+                ErrorCode::Unavailable,
+                format!("Nothing has been released on the {channel} channel for {target} yet"),
+            )),
+            Err(err) => {
+                log::error!("Failed to read artifact store {}: {err}", self.root.display());
+                Err(error_response(ErrorCode::Storage, "Failed to read client artifacts"))
+            }
+        }
+    }
+    async fn newest(&self, channel: UpdateChannel, target: &str) -> Result<Option<(VersionNumber, PathBuf)>> {
+        let mut entries = match tokio::fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let mut newest: Option<(VersionNumber, PathBuf, std::time::SystemTime)> = None;
+        while let Some(entry) = entries.next_entry().await? {
+            let Some(version) = entry.file_name().to_str().and_then(VersionNumber::from_artifact_name) else {
+                continue;
+            };
+            let path = self.path(&version, target);
+            if !channel.includes(&version) || !signature_path(&path).exists() {
+                continue;
+            }
+            let Ok(built) = tokio::fs::metadata(&path)
+                .await
+                .and_then(|metadata| metadata.modified())
+            else {
+                continue;
+            };
+            // builds of the same version from different checkouts, the latest one wins
+            if newest
+                .as_ref()
+                .is_none_or(|(newest, _, newest_built)| (version.semver(), built) > (newest.semver(), *newest_built))
+            {
+                newest = Some((version, path, built));
+            }
+        }
+        Ok(newest.map(|(version, path, _)| (version, path)))
+    }
+    // pulls the client source and builds and signs whatever target is missing for its version
+    async fn refresh(&self) -> Result<VersionNumber> {
+        let signing_key = client_signing_key()?;
+        let version = get_client_version().await?;
+        let mut failed = Vec::new();
+        for target in CLIENT_TARGETS {
+            let path = self.path(&version, target);
+            if path.exists() && signature_path(&path).exists() {
+                log::info!("Client artifact for {target} ready at {}", path.display());
+                continue;
+            }
+            if let Err(err) = build_client(&version, target, &path, &signing_key).await {
+                log::error!("Failed to build client {version} for {target}: {err}");
+                failed.push(format!("{target}: {err}"));
+            }
+        }
+        if !failed.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Failed to build client {version}\n{}",
+                failed.join("\n")
+            ));
+        }
+        Ok(version)
     }
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 struct InviteQuery {
     code: String,
+    #[serde(default)]
+    channel: UpdateChannel,
+    #[serde(default = "default_client_target")]
+    target: String,
 }
 
 impl InviteQuery {
     fn new(user_uuid: Uuid) -> Self {
         let encrypted_code = xor_cypher(format!(
             "{}|{}|{}",
-            env!("INVITE_CODE_PREFIX"),
+            config::get().invite_code_prefix(),
             user_uuid,
-            env!("INVITE_CODE_SUFFIX")
+            config::get().invite_code_suffix()
         ));
         Self {
             code: encode_string_base64(&encrypted_code),
+            channel: UpdateChannel::default(),
+            target: default_client_target(),
         }
     }
-    fn extract(&self) -> Result<Uuid> {
-        let decoded_code =
-            decode_string_base64(&self.code).map_err(|_| anyhow::anyhow!("Failed to decode invite code"))?;
+    fn extract(code: &str) -> Result<Uuid> {
+        let decoded_code = decode_string_base64(code).map_err(|_| anyhow::anyhow!("Failed to decode invite code"))?;
         let decrypted_code = xor_cypher(decoded_code);
         let parts: Vec<&str> = decrypted_code.split('|').collect();
         if parts.len() != 3 {
             return Err(anyhow::anyhow!("Invalid invite code format"));
         }
-        if parts[0] != env!("INVITE_CODE_PREFIX") || parts[2] != env!("INVITE_CODE_SUFFIX") {
+        if parts[0] != config::get().invite_code_prefix() || parts[2] != config::get().invite_code_suffix() {
             return Err(anyhow::anyhow!("Invalid invite code prefix or suffix"));
         }
         Uuid::parse_str(parts[1]).map_err(|_| anyhow::anyhow!("Invalid UUID in invite code"))
     }
 }
 
+// the download links are the same binary for everyone, the link after them is what carries the api key
+fn print_invite(name: &str, user_uuid: Uuid) {
+    let code = InviteQuery::new(user_uuid).code;
+    println!(
+        "Invite for user {name}: {}/api/invite?code={code}",
+        config::get().public_url()
+    );
+    for target in CLIENT_TARGETS.iter().skip(1) {
+        println!(
+            "  {target}: {}/api/invite?code={code}&target={target}",
+            config::get().public_url()
+        );
+    }
+    println!(
+        "Once it's running, open {} or paste it into the API key prompt",
+        pitsu_lib::invite_link(&code)
+    );
+}
+
 fn xor_cypher(input: String) -> String {
-    let key = env!("INVITE_CODE_ENCRYPTION_KEY");
+    let key = config::get().invite_code_encryption_key();
     input
         .chars()
         .zip(key.chars().cycle())
@@ -1398,36 +2323,45 @@ fn xor_cypher(input: String) -> String {
 }
 
 async fn exec(host: String, port: u16, pool: Pool, client: S3Client) -> Result<()> {
-    std::env::set_var("SEQ_API_KEY", env!("REMOTE_SEQ_API_KEY"));
+    for (name, value) in config::get().logger_env() {
+        std::env::set_var(name, value);
+    }
     if let Err(e) = datalust_logger::init("pitsu") {
         eprintln!("Failed to initialize logger: {e}");
         std::process::exit(1);
     };
-    let json_cfg = JsonConfig::default().limit(pitsu_lib::MAX_UPLOAD_SIZE);
+    match sweep_parts(PARTS_MAX_AGE) {
+        0 => {}
+        removed => log::info!("Removed {removed} unfinished uploads older than a day"),
+    }
+    // only read here, clients are built and signed by the `release` command so nothing pulled in is signed unattended
+    let artifacts = Data::new(ArtifactStore::new());
+    // shared by every worker, limits are per client not per thread
+    let limiter = Data::new(ratelimit::RateLimiter::new(config::get().rate_limits()?));
+    let store: Data<dyn DataStore> = Data::from(Arc::new(PostgresStore::new(pool.clone())) as Arc<dyn DataStore>);
+    let objects: Data<dyn ObjectStore> = Data::from(Arc::new(S3Store::new(client)) as Arc<dyn ObjectStore>);
     HttpServer::new(move || {
         App::new()
-            .app_data(json_cfg.clone())
+            .configure(extractors)
             .app_data(Data::new(pool.clone()))
-            .app_data(Data::new(InviteLock(Mutex::new(()))))
-            .app_data(Data::new(client.clone()))
-            .service(root)
-            .service(set_access_level)
-            .service(remove_user_access)
-            .service(api)
-            .service(invite_user)
-            .service(get_local_version)
-            .service(get_latest_version)
-            .service(get_self)
-            .service(get_other)
-            .service(get_all_users)
-            .service(get_users_with_access)
-            .service(create_repository)
-            .service(api_catch_all)
-            .service(repository)
-            .service(upload_file)
-            .service(delete_file)
-            .service(repository_path)
-            .service(repository_update)
+            .app_data(store.clone())
+            .app_data(artifacts.clone())
+            .app_data(objects.clone())
+            .app_data(limiter.clone())
+            .wrap_fn(|req, srv| {
+                let start = std::time::Instant::now();
+                let method = req.method().to_string();
+                let fut = srv.call(req);
+                async move {
+                    let res = fut.await?;
+                    // only known once routing ran, so read it off the response side
+                    let route = res.request().match_pattern().unwrap_or_else(|| "unmatched".to_string());
+                    metrics::record_request(&method, &route, res.status().as_u16(), start.elapsed());
+                    Ok(res)
+                }
+            })
+            .wrap(actix_web::middleware::from_fn(ratelimit::limit))
+            .configure(routes)
     })
     .bind((host, port))?
     .run()
@@ -1435,6 +2369,62 @@ async fn exec(host: String, port: u16, pool: Pool, client: S3Client) -> Result<(
     Ok(())
 }
 
+// the order matters, the catch all routes have to come after the specific ones
+// rejected bodies and paths get the same json errors as the handlers instead of actix' plaintext
+fn extractors(cfg: &mut actix_web::web::ServiceConfig) {
+    let json = JsonConfig::default()
+        .limit(pitsu_lib::MAX_UPLOAD_SIZE)
+        .error_handler(|err, _| {
+            let response = match &err {
+                actix_web::error::JsonPayloadError::OverflowKnownLength { .. }
+                | actix_web::error::JsonPayloadError::Overflow { .. } => {
+                    error_response(ErrorCode::PayloadTooLarge, err.to_string())
+                }
+                _ => error_response(ErrorCode::InvalidRequest, err.to_string()),
+            };
+            actix_web::error::InternalError::from_response(err, response).into()
+        });
+    let payload = PayloadConfig::default().limit(pitsu_lib::MAX_UPLOAD_SIZE);
+    let query = QueryConfig::default().error_handler(|err, _| {
+        let response = error_response(ErrorCode::InvalidRequest, err.to_string());
+        actix_web::error::InternalError::from_response(err, response).into()
+    });
+    let path = PathConfig::default().error_handler(|err, _| {
+        // a malformed id can never name anything, so it reads as not found
+        let response = error_response(ErrorCode::NotFound, err.to_string());
+        actix_web::error::InternalError::from_response(err, response).into()
+    });
+    cfg.app_data(json).app_data(payload).app_data(query).app_data(path);
+}
+
+fn routes(cfg: &mut actix_web::web::ServiceConfig) {
+    cfg.service(root)
+        .service(healthz)
+        .service(metrics_endpoint)
+        .service(set_access_level)
+        .service(remove_user_access)
+        .service(api)
+        .service(openapi_json)
+        .service(invite_user)
+        .service(redeem_invite)
+        .service(get_local_version)
+        .service(get_latest_version)
+        .service(get_changelog)
+        .service(get_self)
+        .service(get_other)
+        .service(get_all_users)
+        .service(get_users_with_access)
+        .service(create_repository)
+        .service(api_catch_all)
+        .service(repository)
+        .service(upload_file)
+        .service(import_archive)
+        .service(delete_file)
+        .service(repository_archive)
+        .service(repository_path)
+        .service(repository_update);
+}
+
 #[derive(clap::Parser)]
 #[clap(name = "remote", version = env!("CARGO_PKG_VERSION"), author = "Ethan Conaway <you@willsh.art>", about = "Planet51 Internet Transfer and Synchronization Utility")]
 struct Cli {
@@ -1444,19 +2434,48 @@ struct Cli {
 #[derive(clap::Subcommand)]
 enum Command {
     Run {
-        #[clap(long, default_value = "8080", env = "PORT")]
-        port: u16,
-        #[clap(long, default_value = "0.0.0.0", env = "HOST")]
-        host: String,
+        /// Overrides PORT from the configuration
+        #[clap(long)]
+        port: Option<u16>,
+        /// Overrides HOST from the configuration
+        #[clap(long)]
+        host: Option<String>,
     },
     User {
         #[clap(subcommand)]
         user_command: UserCommand,
     },
     Repo {
+        /// Text is for people, json and tsv are for scripts
+        #[clap(long, value_enum, default_value_t, global = true)]
+        format: admin::OutputFormat,
         #[clap(subcommand)]
         repository_command: RepositoryCommand,
     },
+    /// Print a new CLIENT_SIGNING_KEY, clients built with the old key will refuse updates signed with it
+    Keygen,
+    /// Pull the client source and build and sign it for every target, a running server picks the new version up on its own
+    Release,
+    Config {
+        #[clap(subcommand)]
+        config_command: ConfigCommand,
+    },
+    Db {
+        #[clap(subcommand)]
+        db_command: DbCommand,
+    },
+}
+#[derive(clap::Subcommand)]
+enum DbCommand {
+    /// Apply pending migrations, `run` does this on its own
+    Migrate,
+    Status,
+}
+#[derive(clap::Subcommand)]
+enum ConfigCommand {
+    /// Print every setting and where it came from, secrets are only shown as set. Exits non zero if anything is
+    /// missing, warnings are listed but don't fail the check
+    Check,
 }
 #[derive(clap::Subcommand)]
 enum UserCommand {
@@ -1469,6 +2488,55 @@ enum UserCommand {
 #[derive(clap::Subcommand)]
 enum RepositoryCommand {
     List,
+    /// Create an empty repository
+    Create {
+        name: String,
+        /// Username of the owner
+        #[clap(long)]
+        owner: String,
+    },
+    /// Rename a repository, its files and access are kept
+    Rename {
+        repo: Uuid,
+        name: String,
+    },
+    /// Delete a repository, also removes its files from disk and S3
+    Delete {
+        repo: Uuid,
+        /// Confirm the deletion, nothing is deleted without it
+        #[clap(long)]
+        yes: bool,
+    },
+    /// Show the owner, size and who has access
+    Show {
+        repo: Uuid,
+    },
+    /// Give a user access, or change the access they have
+    Grant {
+        repo: Uuid,
+        username: String,
+        #[clap(value_enum)]
+        level: admin::GrantLevel,
+    },
+    /// Take away a user's access
+    Revoke {
+        repo: Uuid,
+        username: String,
+    },
+    /// Compare the size in the file index with the size on disk, for one repository or all of them
+    Du {
+        repo: Option<Uuid>,
+    },
+    /// Check disk, manifests and S3 against each other, exits non zero if anything is left broken
+    Fsck {
+        repo: Option<Uuid>,
+        /// Rebuild manifests from disk and upload what S3 is missing
+        #[clap(long)]
+        repair: bool,
+        /// Also delete orphaned S3 objects and repository folders
+        #[clap(long, requires = "repair")]
+        delete: bool,
+    },
     Sync {
         #[clap(subcommand)]
         stage: RepositorySyncStage,
@@ -1503,22 +2571,116 @@ impl RepositorySyncStage {
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     let cli = Cli::parse();
+    let config = config::init().unwrap_or_else(|err| {
+        eprintln!("Failed to load configuration: {err}");
+        std::process::exit(1);
+    });
+    // before the pool, a broken configuration is exactly what this is for
+    if let Command::Config {
+        config_command: ConfigCommand::Check,
+    } = cli.command
+    {
+        config.print();
+        let problems = config.problems();
+        for problem in problems.iter() {
+            println!("- {problem}");
+        }
+        for warning in config.warnings() {
+            println!("- warning: {warning}");
+        }
+        if !problems.is_empty() {
+            std::process::exit(1);
+        }
+        println!("Configuration OK");
+        return Ok(());
+    }
+    // needs neither the database nor the rest of the configuration
+    if let Command::Keygen = cli.command {
+        let signing_key = SigningKey::generate(&mut rand::rngs::OsRng);
+        println!(
+            "CLIENT_SIGNING_KEY = \"{}\"",
+            pitsu_lib::encode_base64(&signing_key.to_bytes())
+        );
+        println!(
+            "Public key: {}",
+            pitsu_lib::encode_base64(signing_key.verifying_key().as_bytes())
+        );
+        return Ok(());
+    }
+    // needs the artifact folder and the signing key, not the database
+    if let Command::Release = cli.command {
+        match ArtifactStore::new().refresh().await {
+            Ok(version) => println!("Client {version} is built and signed for {}", CLIENT_TARGETS.join(", ")),
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+    if let Command::Run { .. } = cli.command {
+        let problems = config.problems();
+        for problem in problems.iter() {
+            eprintln!("Configuration error: {problem}");
+        }
+        if !problems.is_empty() {
+            eprintln!("Run `config check` for the full configuration");
+            std::process::exit(1);
+        }
+        for warning in config.warnings() {
+            eprintln!("Warning: {warning}");
+        }
+    }
     let pool = create_pool().await.unwrap_or_else(|err| {
-        log::error!("Failed to create database pool: {err}");
+        eprintln!("Failed to create database pool: {err}");
         std::process::exit(1);
     });
-    unsafe {
-        std::env::set_var("AWS_BUCKET_NAME", std::env!("AWS_BUCKET_NAME"));
-        std::env::set_var("AWS_REGION", std::env!("AWS_REGION"));
-        std::env::set_var("AWS_ACCESS_KEY_ID", std::env!("AWS_ACCESS_KEY_ID"));
-        std::env::set_var("AWS_SECRET_ACCESS_KEY", std::env!("AWS_SECRET_ACCESS_KEY"));
+    // `run` brings the schema up to date itself, everything else only refuses a database newer than this build
+    match &cli.command {
+        Command::Run { .. }
+        | Command::Db {
+            db_command: DbCommand::Migrate,
+        } => match migrations::migrate(&pool).await {
+            Ok(applied) => {
+                for migration in applied {
+                    println!("Applied migration {:04} {}", migration.version, migration.name);
+                }
+            }
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        },
+        Command::Db {
+            db_command: DbCommand::Status,
+        } => {}
+        _ => match migrations::check(&pool).await {
+            Ok(version) if version < migrations::latest() => {
+                eprintln!(
+                    "Database schema is at version {version} of {}, run `db migrate`",
+                    migrations::latest()
+                );
+            }
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        },
     }
-    let aws_config = aws_config::load_defaults(aws_config::BehaviorVersion::v2025_01_17()).await;
+    let aws_config = aws_config::defaults(aws_config::BehaviorVersion::v2025_01_17())
+        .region(aws_config::Region::new(config.aws_region().to_string()))
+        .credentials_provider(config.aws_credentials())
+        .load()
+        .await;
     println!("Using AWS region: {:?}", aws_config.region());
     let s3_client = aws_sdk_s3::Client::new(&aws_config);
 
     match cli.command {
         Command::Run { port, host } => {
+            let host = host.unwrap_or_else(|| config::get().host().to_string());
+            // checked with the other problems before connecting
+            let port = port.unwrap_or_else(|| config::get().port().unwrap_or(8080));
             exec(host, port, pool, s3_client).await.unwrap_or_else(|err| {
                 log::error!("Failed to start server: {err}");
                 std::process::exit(1);
@@ -1556,12 +2718,7 @@ async fn main() -> std::io::Result<()> {
                 std::process::exit(1);
             });
             println!("User {name} added successfully");
-            let invite_code = InviteQuery::new(user.uuid);
-            let invite_code_str = invite_code.code;
-            println!(
-                "Invite for user {name}: {}/api/invite?code={invite_code_str}",
-                env!("PITSU_PUBLIC_URL")
-            );
+            print_invite(&name, user.uuid);
         }
         Command::User {
             user_command: UserCommand::List,
@@ -1667,42 +2824,15 @@ async fn main() -> std::io::Result<()> {
                     std::process::exit(1);
                 }
             };
-            let invite_code = InviteQuery::new(user.uuid);
-            let invite_code_str = invite_code.code;
             transaction.commit().await.unwrap_or_else(|err| {
                 log::error!("Failed to commit transaction: {err}");
                 std::process::exit(1);
             });
-            println!(
-                "Invite for user {name}: {}/api/invite?code={invite_code_str}",
-                env!("PITSU_PUBLIC_URL")
-            );
-        }
-        Command::Repo {
-            repository_command: RepositoryCommand::List,
-        } => {
-            println!("Repositories:");
-            let connection = pool.get().await.unwrap_or_else(|err| {
-                log::error!("Failed to get database connection: {err}");
-                std::process::exit(1);
-            });
-            let repos = crate::cornucopia::queries::repository::get_all()
-                .bind(&connection)
-                .all()
-                .await
-                .unwrap_or_else(|err| {
-                    log::error!("Failed to list repositories: {err}");
-                    std::process::exit(1);
-                });
-            for repo in repos.iter() {
-                println!("- {} <{}>", repo.name, repo.uuid);
-            }
-            if repos.is_empty() {
-                println!("No repositories found.");
-            }
+            print_invite(&name, user.uuid);
         }
         Command::Repo {
             repository_command: RepositoryCommand::Sync { stage },
+            ..
         } => {
             let repo = stage.repo();
             if stage.sync_hashes() {
@@ -1737,7 +2867,7 @@ async fn main() -> std::io::Result<()> {
                         std::process::exit(1);
                     });
 
-                    let root_path = std::env::var("ROOT_FOLDER").unwrap_or_else(|_| "repositories".to_string());
+                    let root_path = config::get().root_folder();
                     let full_path = format!("{}/{}", root_path, repo.uuid);
 
                     if !std::path::Path::new(&full_path).exists() {
@@ -1797,73 +2927,107 @@ async fn main() -> std::io::Result<()> {
                 }
             }
         }
+        Command::Repo {
+            format,
+            repository_command,
+        } => {
+            let res = match repository_command {
+                RepositoryCommand::List => admin::list(&pool, format).await,
+                RepositoryCommand::Create { name, owner } => admin::create(&pool, &name, &owner, format).await,
+                RepositoryCommand::Rename { repo, name } => admin::rename(&pool, &repo, &name, format).await,
+                RepositoryCommand::Delete { repo, yes } => admin::delete(&pool, &s3_client, &repo, yes, format).await,
+                RepositoryCommand::Show { repo } => admin::show(&pool, &repo, format).await,
+                RepositoryCommand::Grant { repo, username, level } => {
+                    admin::grant(&pool, &repo, &username, level, format).await
+                }
+                RepositoryCommand::Revoke { repo, username } => admin::revoke(&pool, &repo, &username, format).await,
+                RepositoryCommand::Du { repo } => admin::du(&pool, repo, format).await,
+                RepositoryCommand::Fsck { repo, repair, delete } => {
+                    fsck::fsck(&pool, &s3_client, repo, repair, delete, format).await
+                }
+                RepositoryCommand::Sync { .. } => unreachable!("handled above"),
+            };
+            // printed rather than logged so scripts can tell failures apart from output
+            if let Err(err) = res {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
+        Command::Config { .. } | Command::Keygen | Command::Release => unreachable!("handled before connecting"),
+        Command::Db {
+            db_command: DbCommand::Migrate,
+        } => {
+            println!("Database schema is at version {}", migrations::latest());
+        }
+        Command::Db {
+            db_command: DbCommand::Status,
+        } => {
+            if let Err(err) = migrations::status(&pool).await {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+        }
     }
     Ok(())
 }
 
-async fn create_pool() -> Result<Pool, deadpool_postgres::CreatePoolError> {
-    let mut cfg = deadpool_postgres::Config::new();
-    cfg.user = Some(env!("POSTGRES_USER").to_string());
-    cfg.password = Some(env!("POSTGRES_PASSWORD").to_string());
-    cfg.host = Some(env!("POSTGRES_HOST").to_string());
-    cfg.port = Some(env!("POSTGRES_PORT").parse().unwrap());
-    cfg.dbname = Some(env!("POSTGRES_DB").to_string());
-    cfg.create_pool(Some(deadpool_postgres::Runtime::Tokio1), postgres::NoTls)
+async fn create_pool() -> Result<Pool> {
+    config::get()
+        .postgres()?
+        .create_pool(Some(deadpool_postgres::Runtime::Tokio1), postgres::NoTls)
+        .map_err(|err| anyhow::anyhow!("{err}"))
 }
 
-pub async fn get_user(req: &actix_web::HttpRequest, pool: Arc<Pool>) -> Result<User, actix_web::Error> {
-    if let Some(auth_header) = req.headers().get("Authorization") {
-        if let Ok(auth_str) = auth_header.to_str() {
-            if let Some(token) = auth_str.strip_prefix("Bearer ") {
-                let mut connection = pool
-                    .get()
-                    .await
-                    .map_err(|_| actix_web::error::ErrorInternalServerError("Database connection error"))?;
-                let transaction = connection
-                    .transaction()
-                    .await
-                    .map_err(|_| actix_web::error::ErrorInternalServerError("Transaction error"))?;
-                match cornucopia::queries::user::get_by_api_key()
-                    .bind(&transaction, &token)
-                    .one()
-                    .await
-                {
-                    Ok(user) => {
-                        return Ok(User {
-                            uuid: user.uuid,
-                            username: user.username.into(),
-                        });
-                    }
-                    Err(_) => return Err(actix_web::error::ErrorUnauthorized("Invalid token")),
-                }
-            }
+// every error leaves the server as a pitsu_lib::ApiError, the client turns the code into something actionable
+pub fn error_response(code: ErrorCode, message: impl Into<Arc<str>>) -> HttpResponse {
+    api_error_response(ApiError::new(code, message))
+}
+
+pub fn api_error_response(error: ApiError) -> HttpResponse {
+    HttpResponse::build(
+        actix_web::http::StatusCode::from_u16(error.status())
+            .unwrap_or(actix_web::http::StatusCode::INTERNAL_SERVER_ERROR),
+    )
+    .json(error)
+}
+
+// the error is the response to send, 401 for a missing or unknown api key and 500 when the lookup itself failed
+pub async fn get_user(req: &actix_web::HttpRequest, store: &dyn DataStore) -> Result<User, HttpResponse> {
+    let Some(token) = req
+        .headers()
+        .get("Authorization")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "))
+    else {
+        return Err(error_response(
+            ErrorCode::Unauthorized,
+            "Missing or invalid Authorization header",
+        ));
+    };
+    match store.user_by_api_key(token).await {
+        Ok(Some(user)) => Ok(user.into()),
+        Ok(None) => Err(error_response(ErrorCode::Unauthorized, "Unknown API key")),
+        Err(err) => {
+            log::error!("Failed to look up API key: {err}");
+            Err(error_response(ErrorCode::Database, "Failed to look up API key"))
         }
     }
-    Err(actix_web::error::ErrorUnauthorized(
-        "Missing or invalid Authorization header",
-    ))
 }
 
+// the error is the response to send, 404 for a repository that doesn't exist and 500 when the lookup failed.
+// a level too low for the handler is the handler's 403 to give
 pub async fn check_user_access(
-    pool: Arc<Pool>,
+    store: &dyn DataStore,
     user_uuid: &uuid::Uuid,
     repo_uuid: &uuid::Uuid,
-) -> Result<AccessLevel, actix_web::Error> {
-    let mut connection = pool
-        .get()
-        .await
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Database connection error"))?;
-    let transaction = connection
-        .transaction()
-        .await
-        .map_err(|_| actix_web::error::ErrorInternalServerError("Transaction error"))?;
-    match cornucopia::queries::access::user_has_access()
-        .bind(&transaction, user_uuid, repo_uuid)
-        .one()
-        .await
-    {
-        Ok(access) => Ok(Into::<AccessLevel>::into(access)),
-        Err(_) => Err(actix_web::error::ErrorForbidden("Access denied")),
+) -> Result<AccessLevel, HttpResponse> {
+    match store.access_level(*user_uuid, *repo_uuid).await {
+        Ok(Some(level)) => Ok(level),
+        Ok(None) => Err(error_response(ErrorCode::NotFound, "Repository not found")),
+        Err(err) => {
+            log::error!("Failed to check user access: {err}");
+            Err(error_response(ErrorCode::Database, "Failed to check access"))
+        }
     }
 }
 
@@ -1891,117 +3055,73 @@ impl From<AccessLevel> for cornucopia::types::public::AccessLevel {
     }
 }
 
-async fn build_executable(api_key: Option<Arc<str>>) -> Result<PathBuf> {
-    tokio::spawn(async {
-        // Set the environment variables for the build
-        // std::env::set_var("PITSU_API_KEY", api_key);
-        // std::env::set_var("PITSU_API_USERNAME", api_username);
-        // let version_number = {
-        //     let output = std::process::Command::new("git")
-        //         .arg("rev-parse")
-        //         .arg("HEAD")
-        //         .current_dir(env!("CARGO_MANIFEST_DIR"))
-        //         .output()
-        //         .map_err(|e| anyhow::anyhow!("Failed to get git commit hash: {}", e))?;
-        //     if !output.status.success() {
-        //         return Err(anyhow::anyhow!(
-        //             "Git command failed: {}",
-        //             String::from_utf8_lossy(&output.stderr)
-        //         ));
-        //     }
-        //     String::from_utf8(output.stdout)
-        //         .map_err(|e| anyhow::anyhow!("Failed to parse git commit hash: {}", e))?
-        //         .trim()
-        //         .to_string()
-        // };
-        let version_number = get_client_version()?;
-        // std::env::set_var("VERSION_NUMBER", serde_json::to_string(&version_number)?);
-        std::env::set_var("VERSION_MAJOR", version_number.major.to_string());
-        std::env::set_var("VERSION_MINOR", version_number.minor.to_string());
-        std::env::set_var("VERSION_PATCH", version_number.patch.to_string());
-        std::env::set_var("VERSION_HASH", version_number.folder_hash);
-        // Move to {crate_root}/local
-        let crate_root = format!(
-            "{}/../",
-            std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string())
-        );
-        let local_path = format!("{crate_root}/local");
-        std::env::set_current_dir(local_path)
-            .map_err(|e| anyhow::anyhow!("Failed to change directory to local: {}", e))?;
-        // Run the build command
-        let output = tokio::process::Command::new("cargo")
-            .arg("build")
-            .arg("--release")
-            .arg("--target")
-            .arg("x86_64-pc-windows-gnu")
-            .output()
+async fn build_client(
+    version: &VersionNumber,
+    target: &str,
+    destination: &std::path::Path,
+    signing_key: &SigningKey,
+) -> Result<()> {
+    let crate_root = PathBuf::from(CHECKOUT);
+    log::info!("Building client {version} for {target}");
+    let output = tokio::process::Command::new("cargo")
+        .arg("build")
+        .arg("--release")
+        .arg("--target")
+        .arg(target)
+        .current_dir(crate_root.join("local"))
+        .env("VERSION_HASH", &version.folder_hash)
+        .envs(config::get().client_build_env())
+        .env(
+            "PITSU_UPDATE_PUBLIC_KEY",
+            pitsu_lib::encode_base64(signing_key.verifying_key().as_bytes()),
+        )
+        .output()
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to run cargo build: {}", e))?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "Cargo build failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    let built = crate_root
+        .join("target")
+        .join(target)
+        .join("release")
+        .join(client_file_name(target));
+    if let Some(parent) = destination.parent() {
+        tokio::fs::create_dir_all(parent)
             .await
-            .map_err(|e| anyhow::anyhow!("Failed to run cargo build: {}", e))?;
-        if !output.status.success() {
-            return Err(anyhow::anyhow!(
-                "Cargo build failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
-        }
-        let mut executable_path = format!("{crate_root}/target/x86_64-pc-windows-gnu/release/pitsu.exe");
-        if let Some(api_key) = api_key {
-            // Read in the bytes, and replace the sequence env!("PITSU_PPITSU_API_KEY_PLACEHOLDER") with the users API key
-            if !std::path::Path::new(&executable_path).exists() {
-                return Err(anyhow::anyhow!("Executable not found at {executable_path}"));
-            }
-            let mut executable_file = tokio::fs::File::open(&executable_path)
-                .await
-                .map_err(|e| anyhow::anyhow!("Failed to open executable file: {}", e))?;
-            let mut executable_bytes = Vec::new();
-            executable_file
-                .read_to_end(&mut executable_bytes)
-                .await
-                .map_err(|e| anyhow::anyhow!("Failed to read executable file: {}", e))?;
-            let api_key_placeholder =
-                "________________________________PITSU_API_KEY_PLACEHOLDER________________________________";
-            // let api_key_bytes = format!("{api_key}{}", "_".repeat(api_key.len() - api_key_placeholder.len()));
-            let api_key_bytes = format!("__________________________{api_key}___________________________");
-            let placeholder_bytes = api_key_placeholder.as_bytes();
-            let mut modified_bytes = Vec::new();
-            let mut start = 0;
-            while start < executable_bytes.len() {
-                if executable_bytes[start..].starts_with(placeholder_bytes) {
-                    modified_bytes.extend_from_slice(api_key_bytes.as_bytes());
-                    start += placeholder_bytes.len();
-                } else {
-                    modified_bytes.push(executable_bytes[start]);
-                    start += 1;
-                }
-            }
-            // Write the modified bytes to a new file in /tmp/pitsu/pitsu.{api_key}.exe
-            let binaries_path = "/tmp/pitsu/".to_string();
-            tokio::fs::create_dir_all(&binaries_path)
-                .await
-                .map_err(|e| anyhow::anyhow!("Failed to create binaries directory: {}", e))?;
-            // let og_executable_path = executable_path.clone();
-            // executable_path = format!("{binaries_path}/pitsu.{api_key}.exe");
-            executable_path = format!("{binaries_path}/pitsu.exe");
-            tokio::fs::remove_file(&executable_path).await.ok(); // Ignore error if file doesn't exist
-                                                                 // tokio::fs::copy(&og_executable_path, &executable_path).await?;
-            let mut modified_file = tokio::fs::File::create(&executable_path)
-                .await
-                .map_err(|e| anyhow::anyhow!("Failed to create modified executable file: {}", e))?;
-            modified_file
-                .write_all(&modified_bytes)
-                .await
-                .map_err(|e| anyhow::anyhow!("Failed to write modified executable file: {}", e))?;
-            log::info!("Successfully created modified executable file at {executable_path}");
-        }
-        Ok(PathBuf::from(executable_path))
-    })
-    .await?
+            .map_err(|e| anyhow::anyhow!("Failed to create artifact directory: {}", e))?;
+    }
+    let bytes = tokio::fs::read(&built)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", built.display(), e))?;
+    // the signature goes in first, an artifact is only ever served alongside it
+    tokio::fs::write(
+        signature_path(destination),
+        pitsu_lib::encode_base64(&signing_key.sign(&bytes).to_bytes()),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("Failed to write artifact signature: {}", e))?;
+    // write next to the destination first so a half written binary is never served
+    let partial = destination.with_extension("partial");
+    tokio::fs::write(&partial, &bytes)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", partial.display(), e))?;
+    tokio::fs::rename(&partial, destination)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to move artifact into place: {}", e))?;
+    log::info!("Stored client {version} for {target} at {}", destination.display());
+    Ok(())
 }
 
-fn get_client_version() -> Result<VersionNumber> {
-    let output = match std::process::Command::new("git")
+async fn get_client_version() -> Result<VersionNumber> {
+    let output = match tokio::process::Command::new("git")
         .arg("pull")
-        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .current_dir(CHECKOUT)
         .output()
+        .await
     {
         Ok(output) => output,
         Err(err) => {
@@ -2014,11 +3134,9 @@ fn get_client_version() -> Result<VersionNumber> {
         return Err(anyhow::anyhow!("Failed to update local version"));
     }
     // We need to read the line from the Cargo.toml file in ../local that has the version number
-    let client_path = PathBuf::from(format!(
-        "{}/../local",
-        std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string())
-    ));
-    VersionNumber::new(&client_path)
+    let client_path = PathBuf::from(CHECKOUT).join("local");
+    // hashes the whole client folder
+    tokio::task::spawn_blocking(move || VersionNumber::new(&client_path)).await?
 }
 
 #[derive(Debug, Clone)]
@@ -2033,7 +3151,7 @@ async fn sync_aws_files(s3_client: &aws_sdk_s3::Client, only_this_repo: Option<U
     {
         // get all repository directories out of the root folder by iterating over every folder in ROOT_FOLDER and attempting to parse the folders name as a uuid
         let mut raw_repository_folders = vec![]; // will be walked later
-        let mut read_dir = tokio::fs::read_dir(env!("ROOT_FOLDER"))
+        let mut read_dir = tokio::fs::read_dir(config::get().root_folder())
             .await
             .map_err(|err| anyhow::anyhow!("Failed to read root folder: {err}"))?;
         while let Ok(Some(file)) = read_dir.next_entry().await {
@@ -2077,8 +3195,14 @@ async fn sync_aws_files(s3_client: &aws_sdk_s3::Client, only_this_repo: Option<U
         );
     }
 
-    let mut all_s3_keys = get_all_from_s3(s3_client).await?;
+    let (mut all_s3_keys, invalid_keys) = get_all_from_s3(s3_client).await?;
     println!("Found {} S3 keys", all_s3_keys.len());
+    if !invalid_keys.is_empty() {
+        println!(
+            "Skipping {} S3 keys that don't belong to a repository, see `repo fsck`",
+            invalid_keys.len()
+        );
+    }
 
     if let Some(only_this_repo) = only_this_repo {
         all_s3_keys.retain(|key| key.repo_uuid == only_this_repo);
@@ -2214,12 +3338,7 @@ fn generate_aws_key_from_path(repo_uuid: &uuid::Uuid, path: &str) -> S3Key {
 }
 
 async fn get_byte_stream(repository_uuid: &Uuid, path: &str) -> Result<ByteStream> {
-    let full_path = format!(
-        "{}/{}/{}",
-        std::env::var("ROOT_FOLDER").expect("ROOT_FOLDER not set"),
-        repository_uuid,
-        path
-    );
+    let full_path = format!("{}/{}/{}", config::get().root_folder(), repository_uuid, path);
     ByteStream::from_path(&full_path).await.map_err(|err| {
         anyhow::anyhow!(
             "Failed to create ByteStream from path {}: {}",
@@ -2232,7 +3351,7 @@ async fn get_byte_stream(repository_uuid: &Uuid, path: &str) -> Result<ByteStrea
 async fn put_in_s3(client: &S3Client, s3_key: &S3Key, body: ByteStream) -> Result<()> {
     client
         .put_object()
-        .bucket(env!("AWS_BUCKET_NAME"))
+        .bucket(config::get().aws_bucket_name())
         .key(format!("{s3_key}"))
         .acl(aws_sdk_s3::types::ObjectCannedAcl::PublicRead)
         .body(body)
@@ -2245,7 +3364,7 @@ async fn put_in_s3(client: &S3Client, s3_key: &S3Key, body: ByteStream) -> Resul
 async fn get_from_s3(client: &S3Client, s3_key: &S3Key) -> Result<aws_sdk_s3::operation::get_object::GetObjectOutput> {
     match client
         .get_object()
-        .bucket(env!("AWS_BUCKET_NAME"))
+        .bucket(config::get().aws_bucket_name())
         .key(format!("{s3_key}"))
         .send()
         .await
@@ -2259,25 +3378,32 @@ async fn get_from_s3(client: &S3Client, s3_key: &S3Key) -> Result<aws_sdk_s3::op
 }
 
 async fn remove_from_s3(client: &S3Client, s3_key: &S3Key) -> Result<()> {
+    remove_raw_key_from_s3(client, &s3_key.to_string()).await
+}
+
+// for keys that don't parse as an S3Key
+async fn remove_raw_key_from_s3(client: &S3Client, key: &str) -> Result<()> {
     client
         .delete_object()
-        .bucket(env!("AWS_BUCKET_NAME"))
-        .key(format!("{s3_key}"))
+        .bucket(config::get().aws_bucket_name())
+        .key(key)
         .send()
         .await
         .map_err(|err| anyhow::anyhow!("Failed to delete file from S3: {}", DisplayErrorContext(err)))?;
     Ok(())
 }
 
-async fn get_all_from_s3(client: &S3Client) -> Result<Vec<S3Key>> {
+// the second list is keys that aren't ours, fsck decides what happens to them
+async fn get_all_from_s3(client: &S3Client) -> Result<(Vec<S3Key>, Vec<String>)> {
     let mut outputs = Vec::new();
+    let mut invalid = Vec::new();
     let mut continuation_token = None;
 
     loop {
         let list_objects = if let Some(token) = continuation_token {
             client
                 .list_objects_v2()
-                .bucket(env!("AWS_BUCKET_NAME"))
+                .bucket(config::get().aws_bucket_name())
                 .continuation_token(token)
                 .send()
                 .await
@@ -2285,7 +3411,7 @@ async fn get_all_from_s3(client: &S3Client) -> Result<Vec<S3Key>> {
         } else {
             client
                 .list_objects_v2()
-                .bucket(env!("AWS_BUCKET_NAME"))
+                .bucket(config::get().aws_bucket_name())
                 .send()
                 .await
                 .map_err(|err| anyhow::anyhow!("Failed to list objects from S3: {}", DisplayErrorContext(err)))?
@@ -2297,18 +3423,8 @@ async fn get_all_from_s3(client: &S3Client) -> Result<Vec<S3Key>> {
                     match key.parse::<S3Key>() {
                         Ok(key) => outputs.push(key),
                         Err(e) => {
-                            log::error!("Failed to parse S3Key from object key: {e} DELETING FROM S3");
-                            if let Err(e) = client
-                                .delete_object()
-                                .bucket(env!("AWS_BUCKET_NAME"))
-                                .key(key)
-                                .send()
-                                .await
-                            {
-                                log::error!("Failed to delete invalid S3 key: {}", DisplayErrorContext(e));
-                            } else {
-                                log::info!("Deleted invalid S3 key: {key}");
-                            }
+                            log::warn!("Failed to parse S3Key from object key {key}: {e}");
+                            invalid.push(key.to_string());
                         }
                     }
                 }
@@ -2320,5 +3436,611 @@ async fn get_all_from_s3(client: &S3Client) -> Result<Vec<S3Key>> {
         }
         continuation_token = list_objects.next_continuation_token().map(|s| s.to_string());
     }
-    Ok(outputs)
+    Ok((outputs, invalid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{objects::MemoryObjects, store::MemoryStore};
+    use actix_web::{http::StatusCode, test};
+    use pitsu_lib::{api, UploadFile};
+    use utoipa::OpenApi as _;
+
+    // every test shares one root folder, repository uuids are random so they never step on each other.
+    // the configuration is a file of its own so nothing from the developer's pitsu-server.toml leaks in
+    fn init_config() {
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(|| {
+            let temp = std::env::temp_dir().join(format!("pitsu-server-test-{}", std::process::id()));
+            let root = temp.join("repositories");
+            std::fs::create_dir_all(&root).expect("Failed to create test root folder");
+            let config = temp.join("pitsu-server.toml");
+            let mut table = toml::Table::new();
+            table.insert("ROOT_FOLDER".into(), root.display().to_string().into());
+            table.insert("AWS_BUCKET_NAME".into(), "pitsu-test".into());
+            table.insert("AWS_REGION".into(), "test-region".into());
+            table.insert("INVITE_CODE_PREFIX".into(), "pitsu".into());
+            table.insert("INVITE_CODE_SUFFIX".into(), "test".into());
+            table.insert("INVITE_CODE_ENCRYPTION_KEY".into(), "key".into());
+            let contents = toml::to_string(&table).expect("Failed to serialize test configuration");
+            std::fs::write(&config, contents).expect("Failed to write test configuration");
+            std::env::set_var("PITSU_CONFIG", &config);
+            config::init().expect("Failed to load test configuration");
+        });
+    }
+
+    // s3 is a MemoryObjects, pass one in to look at what the handlers put there
+    macro_rules! app {
+        ($store:expr) => {
+            app!($store, Arc::new(MemoryObjects::default()))
+        };
+        ($store:expr, $objects:expr) => {{
+            init_config();
+            test::init_service(
+                App::new()
+                    .app_data(Data::from($store.clone() as Arc<dyn DataStore>))
+                    .app_data(Data::from($objects.clone() as Arc<dyn ObjectStore>))
+                    .configure(extractors)
+                    .configure(routes),
+            )
+            .await
+        }};
+    }
+
+    // the route actix matched for a request, None when no handler is registered for it.
+    // nothing is authenticated so handlers turn the request away, only where it was routed matters
+    macro_rules! routed {
+        ($app:expr, $method:expr, $route:expr) => {{
+            let uri = $route
+                .replace("{uuid}", &Uuid::nil().to_string())
+                .replace("{path:.*}", "probe")
+                .replace("{path}", "probe");
+            let req = test::TestRequest::default()
+                .method(actix_web::http::Method::from_bytes($method.as_bytes()).expect("Invalid method"))
+                .uri(&uri)
+                .to_request();
+            test::call_service(&$app, req).await.request().match_pattern()
+        }};
+    }
+
+    // (method, path) of every operation in the openapi document, utoipa leaves the regex out of the path
+    fn documented() -> Vec<(&'static str, String)> {
+        let mut operations = vec![];
+        for (path, item) in openapi::ApiDoc::openapi().paths.paths {
+            for (method, operation) in [
+                ("GET", &item.get),
+                ("POST", &item.post),
+                ("PATCH", &item.patch),
+                ("PUT", &item.put),
+                ("DELETE", &item.delete),
+            ] {
+                if operation.is_some() {
+                    operations.push((method, path.clone()));
+                }
+            }
+        }
+        operations
+    }
+
+    fn without_regex(route: &str) -> String {
+        route.replace(":.*}", "}")
+    }
+
+    fn bearer(api_key: &str) -> (&'static str, String) {
+        ("Authorization", format!("Bearer {api_key}"))
+    }
+
+    async fn file_count(store: &MemoryStore, repository: Uuid) -> Result<usize> {
+        let files: RootFolder = serde_json::from_value(store.repository(repository).await?.file_hashes)?;
+        Ok(files.file_count())
+    }
+
+    #[actix_web::test]
+    async fn test_access_levels() -> Result<()> {
+        let store = Arc::new(MemoryStore::default());
+        let owner = store.add_user("owner", "owner-key");
+        let reader = store.add_user("reader", "reader-key");
+        let repository = store.create_repository("shared", owner).await?.uuid;
+        let app = app!(store);
+
+        let req = test::TestRequest::get().uri(&format!("/{repository}")).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::UNAUTHORIZED);
+        let req = test::TestRequest::get()
+            .uri(&format!("/{repository}"))
+            .insert_header(bearer("reader-key"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+        // only an admin can hand out access
+        let grant = UserWithAccess {
+            user: User {
+                uuid: reader,
+                username: "reader".into(),
+            },
+            access_level: AccessLevel::Read,
+        };
+        let req = test::TestRequest::post()
+            .uri(&format!("/{repository}/.pit/user/access"))
+            .insert_header(bearer("reader-key"))
+            .set_json(&grant)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+        let req = test::TestRequest::post()
+            .uri(&format!("/{repository}/.pit/user/access"))
+            .insert_header(bearer("owner-key"))
+            .set_json(&grant)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/{repository}"))
+            .insert_header(bearer("reader-key"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let remote: RemoteRepository = test::read_body_json(res).await;
+        assert_eq!(remote.access_level, AccessLevel::Read);
+        assert_eq!(remote.users.len(), 2);
+
+        // read access isn't enough to write
+        let req = test::TestRequest::post()
+            .uri(&format!("/{repository}/.pit/upload"))
+            .insert_header(bearer("reader-key"))
+            .set_json(FileUpload { files: vec![] })
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::get()
+            .uri("/api/user")
+            .insert_header(bearer("reader-key"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let this: ThisUser = test::read_body_json(res).await;
+        assert!(this.owned_repositories.is_empty());
+        assert_eq!(this.accessible_repositories.len(), 1);
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn test_api_routes() -> Result<()> {
+        let store = Arc::new(MemoryStore::default());
+        let app = app!(store);
+        // every endpoint pitsu-lib calls reaches a handler on the same route and method
+        for (method, route) in api::ENDPOINTS {
+            assert_eq!(
+                routed!(app, method.as_str(), route).as_deref(),
+                Some(*route),
+                "{method} {route} is not a route on the server"
+            );
+        }
+        for route in api::SERVER_ONLY_ROUTES {
+            assert_eq!(
+                routed!(app, "GET", route).as_deref(),
+                Some(*route),
+                "GET {route} is not a route on the server"
+            );
+        }
+        // and the other way round, every handler is an endpoint or knowingly server only
+        for (method, path) in documented() {
+            assert!(
+                api::SERVER_ONLY_ROUTES.iter().any(|route| without_regex(route) == path)
+                    || api::ENDPOINTS
+                        .iter()
+                        .any(|(m, route)| m.as_str() == method && without_regex(route) == path),
+                "{method} {path} has no endpoint in pitsu_lib::api"
+            );
+        }
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn test_redeem_invite() -> Result<()> {
+        let store = Arc::new(MemoryStore::default());
+        let invited = store.add_user("invited", "invited-key");
+        let app = app!(store);
+
+        let redeem = |code: String| {
+            test::TestRequest::post()
+                .uri("/api/invite")
+                .set_json(InviteCode { code: code.into() })
+                .to_request()
+        };
+        let res = test::call_service(&app, redeem(InviteQuery::new(invited).code)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test::read_body(res).await, "invited-key");
+
+        // garbage, and a well formed code for a user that was deleted since
+        for code in ["not base64 !".to_string(), InviteQuery::new(Uuid::new_v4()).code] {
+            let res = test::call_service(&app, redeem(code)).await;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            let error: ApiError = test::read_body_json(res).await;
+            assert_eq!(error.code, ErrorCode::InvalidInviteCode);
+        }
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn test_openapi_paths() -> Result<()> {
+        let store = Arc::new(MemoryStore::default());
+        let app = app!(store);
+        let documented = documented();
+        // every documented path is served by the handler it documents
+        for (method, path) in documented.iter() {
+            assert_eq!(
+                routed!(app, method, path).map(|route| without_regex(&route)).as_deref(),
+                Some(path.as_str()),
+                "{method} {path} is documented but not a route on the server"
+            );
+        }
+        // and every route is documented, or it silently goes missing from the document
+        let routes = api::ENDPOINTS
+            .iter()
+            .map(|(method, route)| (method.as_str(), *route))
+            .chain(api::SERVER_ONLY_ROUTES.iter().map(|route| ("GET", *route)));
+        for (method, route) in routes {
+            assert!(
+                documented.contains(&(method, without_regex(route))),
+                "{method} {route} is not in the paths of remote/src/openapi.rs"
+            );
+        }
+        assert_eq!(documented.len(), api::ENDPOINTS.len() + api::SERVER_ONLY_ROUTES.len());
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn test_access_errors() -> Result<()> {
+        let store = Arc::new(MemoryStore::default());
+        let owner = store.add_user("owner", "owner-key");
+        store.add_user("stranger", "stranger-key");
+        let repository = store.create_repository("private", owner).await?.uuid;
+        let app = app!(store);
+
+        let get = |uuid: Uuid, api_key: &str| {
+            test::TestRequest::get()
+                .uri(&format!("/{uuid}"))
+                .insert_header(bearer(api_key))
+                .to_request()
+        };
+        // a repository that isn't there is not found for everyone
+        let res = test::call_service(&app, get(Uuid::new_v4(), "owner-key")).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let error: ApiError = test::read_body_json(res).await;
+        assert_eq!(error.code, ErrorCode::NotFound);
+
+        // one that is but isn't shared with the caller is forbidden
+        let res = test::call_service(&app, get(repository, "stranger-key")).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        let error: ApiError = test::read_body_json(res).await;
+        assert_eq!(error.code, ErrorCode::Forbidden);
+
+        // and a failed lookup is the server's problem, not the caller's
+        store.break_access_lookups();
+        let res = test::call_service(&app, get(repository, "owner-key")).await;
+        assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        let error: ApiError = test::read_body_json(res).await;
+        assert_eq!(error.code, ErrorCode::Database);
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn test_errors_are_json() -> Result<()> {
+        let store = Arc::new(MemoryStore::default());
+        store.add_user("owner", "owner-key");
+        let app = app!(store);
+
+        let req = test::TestRequest::get()
+            .uri("/not-a-uuid")
+            .insert_header(bearer("owner-key"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let error: ApiError = test::read_body_json(res).await;
+        assert_eq!(error.code, ErrorCode::NotFound);
+
+        let req = test::TestRequest::get()
+            .uri("/api/user")
+            .insert_header(bearer("unknown-key"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let error: ApiError = test::read_body_json(res).await;
+        assert_eq!(error.code, ErrorCode::Unauthorized);
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn test_upload_and_delete() -> Result<()> {
+        let store = Arc::new(MemoryStore::default());
+        let objects = Arc::new(MemoryObjects::default());
+        let owner = store.add_user("owner", "owner-key");
+        let repository = store.create_repository("files", owner).await?.uuid;
+        let app = app!(store, objects);
+
+        let upload = FileUpload {
+            files: vec![UploadFile::new(
+                "/notes/hello.txt".into(),
+                b"hello".to_vec(),
+                TransferEncoding::None,
+                0,
+            )?],
+        };
+        let req = test::TestRequest::post()
+            .uri(&format!("/{repository}/.pit/upload"))
+            .insert_header(bearer("owner-key"))
+            .set_json(&upload)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        assert_eq!(file_count(&store, repository).await?, 1);
+        assert_eq!(
+            objects.object(repository, "notes/hello.txt").as_deref(),
+            Some(&b"hello"[..])
+        );
+
+        // what's in s3 is served from there
+        let req = test::TestRequest::get()
+            .uri(&format!("/{repository}/notes/hello.txt"))
+            .insert_header(bearer("owner-key"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+        let location = res
+            .headers()
+            .get("Location")
+            .and_then(|location| location.to_str().ok());
+        assert_eq!(
+            location,
+            Some(
+                format!(
+                    "https://pitsu-test.s3.test-region.amazonaws.com/{}",
+                    generate_aws_key_from_path(&repository, "notes/hello.txt")
+                )
+                .as_str()
+            )
+        );
+
+        // and what s3 lost comes from disk and is put back
+        objects.remove(repository, "notes/hello.txt").await?;
+        let req = test::TestRequest::get()
+            .uri(&format!("/{repository}/notes/hello.txt"))
+            .insert_header(bearer("owner-key"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test::read_body(res).await.as_ref(), b"hello");
+        for _ in 0..100 {
+            if objects.object(repository, "notes/hello.txt").is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            objects.object(repository, "notes/hello.txt").as_deref(),
+            Some(&b"hello"[..])
+        );
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/{repository}/notes/hello.txt"))
+            .insert_header(bearer("owner-key"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        assert_eq!(file_count(&store, repository).await?, 0);
+        assert!(objects.object(repository, "notes/hello.txt").is_none());
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/{repository}/notes/hello.txt"))
+            .insert_header(bearer("owner-key"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn test_archive() -> Result<()> {
+        let store = Arc::new(MemoryStore::default());
+        let objects = Arc::new(MemoryObjects::default());
+        let owner = store.add_user("owner", "owner-key");
+        store.add_user("stranger", "stranger-key");
+        let repository = store.create_repository("archived", owner).await?.uuid;
+        let app = app!(store, objects);
+
+        let upload = FileUpload {
+            files: vec![
+                UploadFile::new("/saves/slot1.sav".into(), b"saved".to_vec(), TransferEncoding::None, 0)?,
+                UploadFile::new("/readme.txt".into(), b"read me".to_vec(), TransferEncoding::None, 0)?,
+            ],
+        };
+        let req = test::TestRequest::post()
+            .uri(&format!("/{repository}/.pit/upload"))
+            .insert_header(bearer("owner-key"))
+            .set_json(&upload)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        // the disk lost it, s3 still has it
+        std::fs::remove_file(format!("{}/{repository}/saves/slot1.sav", config::get().root_folder()))?;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/{repository}/.pit/archive?path=saves"))
+            .insert_header(bearer("stranger-key"))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::FORBIDDEN);
+
+        // only the subfolder, paths relative to it
+        let req = test::TestRequest::get()
+            .uri(&format!("/{repository}/.pit/archive?path=saves"))
+            .insert_header(bearer("owner-key"))
+            .to_request();
+        let res = test::call_service(&app, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = test::read_body(res).await;
+        let mut files = vec![];
+        for_each_archive_file(&body, |name, reader| {
+            let mut contents = vec![];
+            std::io::Read::read_to_end(reader, &mut contents)?;
+            files.push((name.to_string(), contents));
+            Ok(())
+        })?;
+        assert_eq!(files, vec![("slot1.sav".to_string(), b"saved".to_vec())]);
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn test_update_keeps_recommended_path() -> Result<()> {
+        let store = Arc::new(MemoryStore::default());
+        let owner = store.add_user("owner", "owner-key");
+        let repository = store.create_repository("games", owner).await?.uuid;
+        let app = app!(store);
+
+        let update = |name: &str, recommended_path: Option<&str>| {
+            test::TestRequest::patch()
+                .uri(&format!("/{repository}"))
+                .insert_header(bearer("owner-key"))
+                .set_json(UpdateRemoteRepository {
+                    name: name.into(),
+                    recommended_path: recommended_path.map(Into::into),
+                })
+                .to_request()
+        };
+
+        let req = update("games", Some(" ~/games "));
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        assert_eq!(store.repository(repository).await?.recommended_path, "~/games");
+
+        // a rename alone leaves the template alone
+        let req = update("more games", None);
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        let repo = store.repository(repository).await?;
+        assert_eq!(repo.name, "more games");
+        assert_eq!(repo.recommended_path, "~/games");
+
+        let req = update("more games", Some(""));
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+        assert!(store.repository(repository).await?.recommended_path.is_empty());
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn test_import_archive() -> Result<()> {
+        let store = Arc::new(MemoryStore::default());
+        let objects = Arc::new(MemoryObjects::default());
+        let owner = store.add_user("owner", "owner-key");
+        let repository = store.create_repository("imported", owner).await?.uuid;
+        let app = app!(store, objects);
+
+        // a release archive with everything in one folder, like the ones this is meant for
+        let source = std::env::temp_dir().join(format!("pitsu-import-source-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(source.join("mods"))?;
+        std::fs::write(source.join("mods/a.dll"), "mod")?;
+        std::fs::write(source.join("install.log"), "log")?;
+        std::fs::write(source.join(".pitignore"), "*.log\n")?;
+        let entries = ["mods/a.dll", "install.log", ".pitignore"]
+            .iter()
+            .map(|path| (source.join(path), format!("modpack/{path}")))
+            .collect::<Vec<_>>();
+        let mut archive = vec![];
+        write_archive(ArchiveFormat::TarGz, &mut archive, &entries, |path| {
+            Err(anyhow::anyhow!("{} is not on disk", path.display()))
+        })?;
+        std::fs::remove_dir_all(&source)?;
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/{repository}/.pit/import?strip_components=1"))
+            .insert_header(bearer("owner-key"))
+            .set_payload(archive)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+
+        let repo_path = PathBuf::from(format!("{}/{repository}", config::get().root_folder()));
+        assert_eq!(std::fs::read_to_string(repo_path.join("mods/a.dll"))?, "mod");
+        assert!(!repo_path.join("install.log").exists());
+        assert_eq!(objects.object(repository, "mods/a.dll").as_deref(), Some(&b"mod"[..]));
+        assert!(objects.object(repository, "install.log").is_none());
+        let files: RootFolder = serde_json::from_value(store.repository(repository).await?.file_hashes)?;
+        assert!(files.hash_of("mods/a.dll").is_some());
+        assert!(files.hash_of("install.log").is_none());
+
+        // entries that climb out of the repository are refused outright
+        // tar refuses to write such a name, zip doesn't care
+        let entries = vec![(repo_path.join("mods/a.dll"), "../escaped.dll".to_string())];
+        let mut archive = vec![];
+        write_archive(ArchiveFormat::Zip, &mut archive, &entries, |path| {
+            Err(anyhow::anyhow!("{} is not on disk", path.display()))
+        })?;
+        let req = test::TestRequest::post()
+            .uri(&format!("/{repository}/.pit/import"))
+            .insert_header(bearer("owner-key"))
+            .set_payload(archive)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), StatusCode::BAD_REQUEST);
+        assert!(!repo_path.parent().unwrap_or(&repo_path).join("escaped.dll").exists());
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn test_failed_import_keeps_originals() -> Result<()> {
+        init_config();
+        let root = std::env::temp_dir().join(format!("pitsu-import-test-{}", Uuid::new_v4()));
+        let repo_path = root.join("repository");
+        let staging = root.join("staging");
+        std::fs::create_dir_all(repo_path.join("saves"))?;
+        std::fs::write(repo_path.join("saves/slot1.sav"), "original")?;
+        std::fs::create_dir_all(staging.join("files/saves"))?;
+        std::fs::write(staging.join("files/saves/slot1.sav"), "imported")?;
+        std::fs::write(staging.join("files/new.txt"), "imported")?;
+
+        let mut import = ImportedFiles::new(staging.clone(), repo_path.clone());
+        import.apply(&["saves/slot1.sav".to_string(), "new.txt".to_string()])?;
+        assert_eq!(std::fs::read_to_string(repo_path.join("saves/slot1.sav"))?, "imported");
+        import.rollback(Uuid::nil(), &MemoryObjects::default()).await;
+
+        assert_eq!(std::fs::read_to_string(repo_path.join("saves/slot1.sav"))?, "original");
+        assert!(!repo_path.join("new.txt").exists());
+        assert!(!staging.exists());
+        std::fs::remove_dir_all(&root)?;
+        Ok(())
+    }
+
+    #[actix_web::test]
+    async fn test_parts_are_cleaned_up() -> Result<()> {
+        init_config();
+        let repo_uuid = Uuid::new_v4();
+        let repo_path = PathBuf::from(config::get().root_folder()).join(repo_uuid.to_string());
+        std::fs::create_dir_all(repo_path.join("saves"))?;
+        let full_path = repo_path.join("saves/slot1.sav");
+        let full_path = full_path.to_string_lossy();
+        let staging = parts_folder().join(repo_uuid.to_string());
+        let part = |offset| FilePart { offset, total: 8 };
+
+        // a part at the wrong offset drops what was staged so far
+        assert!(!write_part(&repo_uuid, "saves/slot1.sav", &full_path, part(0), b"1234").await?);
+        assert!(staging.join("saves/slot1.sav").is_file());
+        assert!(write_part(&repo_uuid, "saves/slot1.sav", &full_path, part(2), b"5678")
+            .await
+            .is_err());
+        assert!(!staging.exists());
+
+        // so does one that overshoots the total
+        assert!(!write_part(&repo_uuid, "saves/slot1.sav", &full_path, part(0), b"1234").await?);
+        assert!(write_part(&repo_uuid, "saves/slot1.sav", &full_path, part(4), b"56789")
+            .await
+            .is_err());
+        assert!(!staging.exists());
+
+        // the last part moves the file into the repository and leaves nothing behind
+        assert!(!write_part(&repo_uuid, "saves/slot1.sav", &full_path, part(0), b"1234").await?);
+        assert!(write_part(&repo_uuid, "saves/slot1.sav", &full_path, part(4), b"5678").await?);
+        assert_eq!(std::fs::read_to_string(&*full_path)?, "12345678");
+        assert!(!staging.exists());
+
+        // abandoned uploads are left alone until they are old enough
+        assert!(!write_part(&repo_uuid, "saves/slot2.sav", "unused", part(0), b"1234").await?);
+        assert_eq!(sweep_parts(PARTS_MAX_AGE), 0);
+        assert!(staging.join("saves/slot2.sav").is_file());
+        assert!(sweep_parts(std::time::Duration::ZERO) >= 1);
+        assert!(!staging.exists());
+
+        std::fs::remove_dir_all(&repo_path)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,232 @@
+// in process counters for /metrics, rendered in the prometheus text format
+// everything resets on restart, prometheus deals with that on its own
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use pitsu_lib::{anyhow::Result, RootFolder};
+
+// seconds
+const BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    // per bucket, not cumulative, render adds them up
+    counts: [u64; BUCKETS.len()],
+    overflow: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        match BUCKETS.iter().position(|bound| seconds <= *bound) {
+            Some(index) => self.counts[index] += 1,
+            None => self.overflow += 1,
+        }
+        self.sum += seconds;
+    }
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(self.counts.iter()) {
+            cumulative += count;
+            let _ = writeln!(out, "{name}_bucket{{{labels}{separator}le=\"{bound}\"}} {cumulative}");
+        }
+        cumulative += self.overflow;
+        let _ = writeln!(out, "{name}_bucket{{{labels}{separator}le=\"+Inf\"}} {cumulative}");
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{labels}}}")
+        };
+        let _ = writeln!(out, "{name}_sum{labels} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{labels} {cumulative}");
+    }
+}
+
+lazy_static::lazy_static! {
+    // (method, route pattern, status)
+    static ref REQUESTS: Mutex<BTreeMap<(String, String, u16), u64>> = Mutex::new(BTreeMap::new());
+    // (method, route pattern)
+    static ref LATENCY: Mutex<BTreeMap<(String, String), Histogram>> = Mutex::new(BTreeMap::new());
+    static ref INGEST: Mutex<Histogram> = Mutex::new(Histogram::default());
+    static ref THROTTLED: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+}
+static UPLOAD_BYTES: AtomicU64 = AtomicU64::new(0);
+static DOWNLOAD_BYTES: AtomicU64 = AtomicU64::new(0);
+static S3_REDIRECTS: AtomicU64 = AtomicU64::new(0);
+static S3_FALLBACKS: AtomicU64 = AtomicU64::new(0);
+
+// route is the matched pattern ("/{uuid}/.pit/upload"), never the raw path, or every repository gets its own series
+pub fn record_request(method: &str, route: &str, status: u16, elapsed: Duration) {
+    if let Ok(mut requests) = REQUESTS.lock() {
+        *requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default() += 1;
+    }
+    if let Ok(mut latency) = LATENCY.lock() {
+        latency
+            .entry((method.to_string(), route.to_string()))
+            .or_default()
+            .observe(elapsed);
+    }
+}
+
+pub fn add_upload_bytes(bytes: u64) {
+    UPLOAD_BYTES.fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub fn add_download_bytes(bytes: u64) {
+    DOWNLOAD_BYTES.fetch_add(bytes, Ordering::Relaxed);
+}
+
+pub fn s3_redirect() {
+    S3_REDIRECTS.fetch_add(1, Ordering::Relaxed);
+}
+
+// a file S3 should have had and didn't, so it was served from disk instead
+pub fn s3_fallback() {
+    S3_FALLBACKS.fetch_add(1, Ordering::Relaxed);
+}
+
+// turned away by the rate limiter, these never reach routing so they aren't in the request counts
+pub fn throttled(class: crate::ratelimit::Class) {
+    if let Ok(mut throttled) = THROTTLED.lock() {
+        *throttled.entry(class.to_string()).or_default() += 1;
+    }
+}
+
+pub fn ingest_folder(root: &PathBuf) -> Result<RootFolder> {
+    let start = Instant::now();
+    let result = RootFolder::ingest_folder(root);
+    if let Ok(mut ingest) = INGEST.lock() {
+        ingest.observe(start.elapsed());
+    }
+    result
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+pub fn render(pool: &deadpool_postgres::Pool) -> String {
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "pitsu_http_requests_total",
+        "counter",
+        "HTTP requests by route and status",
+    );
+    if let Ok(requests) = REQUESTS.lock() {
+        for ((method, route, status), count) in requests.iter() {
+            let _ = writeln!(
+                out,
+                "pitsu_http_requests_total{{method=\"{method}\",route=\"{}\",status=\"{status}\"}} {count}",
+                escape(route)
+            );
+        }
+    }
+    header(
+        &mut out,
+        "pitsu_http_request_duration_seconds",
+        "histogram",
+        "Time spent handling HTTP requests",
+    );
+    if let Ok(latency) = LATENCY.lock() {
+        for ((method, route), histogram) in latency.iter() {
+            histogram.render(
+                &mut out,
+                "pitsu_http_request_duration_seconds",
+                &format!("method=\"{method}\",route=\"{}\"", escape(route)),
+            );
+        }
+    }
+
+    for (name, help, value) in [
+        (
+            "pitsu_upload_bytes_total",
+            "Decoded bytes written by uploads and archive imports",
+            &UPLOAD_BYTES,
+        ),
+        (
+            "pitsu_download_bytes_total",
+            "Bytes served from disk, not counting S3 redirects",
+            &DOWNLOAD_BYTES,
+        ),
+        (
+            "pitsu_s3_redirects_total",
+            "File downloads redirected to S3",
+            &S3_REDIRECTS,
+        ),
+        (
+            "pitsu_s3_fallbacks_total",
+            "File downloads served from disk because S3 did not have them",
+            &S3_FALLBACKS,
+        ),
+    ] {
+        header(&mut out, name, "counter", help);
+        let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+    }
+
+    header(
+        &mut out,
+        "pitsu_throttled_requests_total",
+        "counter",
+        "Requests rejected with 429 by the rate limiter",
+    );
+    if let Ok(throttled) = THROTTLED.lock() {
+        for (class, count) in throttled.iter() {
+            let _ = writeln!(out, "pitsu_throttled_requests_total{{class=\"{class}\"}} {count}");
+        }
+    }
+
+    let status = pool.status();
+    for (name, help, value) in [
+        (
+            "pitsu_db_pool_max",
+            "Maximum database connections",
+            status.max_size as i64,
+        ),
+        ("pitsu_db_pool_size", "Open database connections", status.size as i64),
+        (
+            "pitsu_db_pool_available",
+            "Idle database connections",
+            status.available as i64,
+        ),
+        (
+            "pitsu_db_pool_waiting",
+            "Requests waiting for a database connection",
+            status.waiting as i64,
+        ),
+    ] {
+        header(&mut out, name, "gauge", help);
+        let _ = writeln!(out, "{name} {value}");
+    }
+
+    header(
+        &mut out,
+        "pitsu_ingest_folder_duration_seconds",
+        "histogram",
+        "Time spent hashing repository folders",
+    );
+    if let Ok(ingest) = INGEST.lock() {
+        ingest.render(&mut out, "pitsu_ingest_folder_duration_seconds", "");
+    }
+    out
+}
@@ -0,0 +1,145 @@
+// schema changes live in remote/migrations as numbered sql files and are compiled into the binary.
+// the files are also the schema cornucopia generates from: `cornucopia schema migrations/*.sql`
+// applied versions are recorded in schema_migrations, `run` migrates on startup and refuses a newer database
+use deadpool_postgres::Pool;
+use pitsu_lib::anyhow::{self, Result};
+
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    sql: &'static str,
+}
+
+// append only, never edit one that has shipped
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        sql: include_str!("../migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "recommended_path",
+        sql: include_str!("../migrations/0002_recommended_path.sql"),
+    },
+];
+
+// arbitrary, just has to be the same for every server sharing the database
+const MIGRATION_LOCK: i64 = 0x7069_7473_75;
+
+pub fn latest() -> i32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+async fn ensure_table(client: &deadpool_postgres::Client) -> Result<()> {
+    client
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INTEGER PRIMARY KEY,
+                name TEXT NOT NULL,
+                applied_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+            )",
+        )
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to create schema_migrations: {err}"))
+}
+
+async fn current_version(client: &deadpool_postgres::Client) -> Result<i32> {
+    let row = client
+        .query_one("SELECT COALESCE(MAX(version), 0) FROM schema_migrations", &[])
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to read schema version: {err}"))?;
+    Ok(row.get(0))
+}
+
+fn refuse_newer(current: i32) -> Result<()> {
+    if current > latest() {
+        return Err(anyhow::anyhow!(
+            "Database schema is at version {current} but this build only knows up to {}, refusing to run against it. Update the server",
+            latest()
+        ));
+    }
+    Ok(())
+}
+
+// applied version, or an error if the database is newer than this build
+pub async fn check(pool: &Pool) -> Result<i32> {
+    let client = pool
+        .get()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to get database connection: {err}"))?;
+    ensure_table(&client).await?;
+    let current = current_version(&client).await?;
+    refuse_newer(current)?;
+    Ok(current)
+}
+
+// returns the migrations that were applied, each one runs in its own transaction
+pub async fn migrate(pool: &Pool) -> Result<Vec<&'static Migration>> {
+    let mut client = pool
+        .get()
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to get database connection: {err}"))?;
+    ensure_table(&client).await?;
+    // two servers starting at once would otherwise both try to apply the same migration
+    client
+        .execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK])
+        .await
+        .map_err(|err| anyhow::anyhow!("Failed to take the migration lock: {err}"))?;
+    let result = apply_pending(&mut client).await;
+    if let Err(err) = client
+        .execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK])
+        .await
+    {
+        log::error!("Failed to release the migration lock: {err}");
+    }
+    result
+}
+
+async fn apply_pending(client: &mut deadpool_postgres::Client) -> Result<Vec<&'static Migration>> {
+    let current = current_version(client).await?;
+    refuse_newer(current)?;
+    let mut applied = vec![];
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > current) {
+        let transaction = client
+            .transaction()
+            .await
+            .map_err(|err| anyhow::anyhow!("Failed to start transaction: {err}"))?;
+        transaction
+            .batch_execute(migration.sql)
+            .await
+            .map_err(|err| anyhow::anyhow!("Migration {} ({}) failed: {err}", migration.version, migration.name))?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version, name) VALUES ($1, $2)",
+                &[&migration.version, &migration.name],
+            )
+            .await
+            .map_err(|err| anyhow::anyhow!("Failed to record migration {}: {err}", migration.version))?;
+        transaction
+            .commit()
+            .await
+            .map_err(|err| anyhow::anyhow!("Failed to commit migration {}: {err}", migration.version))?;
+        log::info!("Applied migration {} ({})", migration.version, migration.name);
+        applied.push(migration);
+    }
+    Ok(applied)
+}
+
+pub async fn status(pool: &Pool) -> Result<()> {
+    let current = check(pool).await?;
+    println!("Database schema version {current}, this build knows up to {}", latest());
+    for migration in MIGRATIONS {
+        println!(
+            "  {:04} {:<24} {}",
+            migration.version,
+            migration.name,
+            if migration.version <= current {
+                "applied"
+            } else {
+                "pending"
+            }
+        );
+    }
+    Ok(())
+}
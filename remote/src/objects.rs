@@ -0,0 +1,158 @@
+// the s3 copy of every repository file, addressed by repository and path and keyed by generate_aws_key_from_path.
+// the disk stays the source of truth, handlers upload after writing and remove after deleting.
+// the cli commands (sync, fsck, repository delete) still go through the client directly
+use aws_sdk_s3::{error::DisplayErrorContext, Client as S3Client};
+use futures::future::FutureExt as _;
+use pitsu_lib::anyhow::{self, Result};
+use uuid::Uuid;
+
+use crate::{config, generate_aws_key_from_path, store::StoreFuture};
+
+pub trait ObjectStore: Send + Sync {
+    // for /healthz
+    fn ping(&self) -> StoreFuture<'_, ()>;
+
+    // false only when there's no such object, anything else going wrong is an error
+    fn contains<'a>(&'a self, repository: Uuid, path: &'a str) -> StoreFuture<'a, bool>;
+    fn get<'a>(&'a self, repository: Uuid, path: &'a str) -> StoreFuture<'a, Vec<u8>>;
+    // copies the file as it is on disk right now
+    fn upload<'a>(&'a self, repository: Uuid, path: &'a str) -> StoreFuture<'a, ()>;
+    // removing an object that isn't there is not an error, same as s3
+    fn remove<'a>(&'a self, repository: Uuid, path: &'a str) -> StoreFuture<'a, ()>;
+}
+
+pub struct S3Store {
+    client: S3Client,
+}
+
+impl S3Store {
+    pub fn new(client: S3Client) -> Self {
+        Self { client }
+    }
+}
+
+impl ObjectStore for S3Store {
+    fn ping(&self) -> StoreFuture<'_, ()> {
+        async move {
+            self.client
+                .head_bucket()
+                .bucket(config::get().aws_bucket_name())
+                .send()
+                .await
+                .map_err(|err| anyhow::anyhow!("Failed to reach the S3 bucket: {}", DisplayErrorContext(err)))?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn contains<'a>(&'a self, repository: Uuid, path: &'a str) -> StoreFuture<'a, bool> {
+        async move {
+            match self
+                .client
+                .head_object()
+                .bucket(config::get().aws_bucket_name())
+                .key(generate_aws_key_from_path(&repository, path).to_string())
+                .send()
+                .await
+            {
+                Ok(_) => Ok(true),
+                Err(err) if err.as_service_error().is_some_and(|err| err.is_not_found()) => Ok(false),
+                Err(err) => Err(anyhow::anyhow!(
+                    "Failed to look up {path} in S3: {}",
+                    DisplayErrorContext(err)
+                )),
+            }
+        }
+        .boxed()
+    }
+
+    fn get<'a>(&'a self, repository: Uuid, path: &'a str) -> StoreFuture<'a, Vec<u8>> {
+        async move {
+            let object = crate::get_from_s3(&self.client, &generate_aws_key_from_path(&repository, path)).await?;
+            let bytes = object
+                .body
+                .collect()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to read {path} from S3: {e}"))?;
+            Ok(bytes.to_vec())
+        }
+        .boxed()
+    }
+
+    fn upload<'a>(&'a self, repository: Uuid, path: &'a str) -> StoreFuture<'a, ()> {
+        async move {
+            let body = crate::get_byte_stream(&repository, path).await?;
+            crate::put_in_s3(&self.client, &generate_aws_key_from_path(&repository, path), body).await
+        }
+        .boxed()
+    }
+
+    fn remove<'a>(&'a self, repository: Uuid, path: &'a str) -> StoreFuture<'a, ()> {
+        async move { crate::remove_from_s3(&self.client, &generate_aws_key_from_path(&repository, path)).await }.boxed()
+    }
+}
+
+// a bucket in a mutex, keyed like the real one. only the tests use it
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryObjects {
+    objects: std::sync::Mutex<std::collections::HashMap<String, Vec<u8>>>,
+}
+
+#[cfg(test)]
+impl MemoryObjects {
+    pub fn object(&self, repository: Uuid, path: &str) -> Option<Vec<u8>> {
+        self.lock().get(&Self::key(repository, path)).cloned()
+    }
+
+    pub fn insert(&self, repository: Uuid, path: &str, contents: &[u8]) {
+        self.lock().insert(Self::key(repository, path), contents.to_vec());
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, std::collections::HashMap<String, Vec<u8>>> {
+        self.objects.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn key(repository: Uuid, path: &str) -> String {
+        generate_aws_key_from_path(&repository, path).to_string()
+    }
+}
+
+#[cfg(test)]
+impl ObjectStore for MemoryObjects {
+    fn ping(&self) -> StoreFuture<'_, ()> {
+        async move { Ok(()) }.boxed()
+    }
+
+    fn contains<'a>(&'a self, repository: Uuid, path: &'a str) -> StoreFuture<'a, bool> {
+        async move { Ok(self.lock().contains_key(&Self::key(repository, path))) }.boxed()
+    }
+
+    fn get<'a>(&'a self, repository: Uuid, path: &'a str) -> StoreFuture<'a, Vec<u8>> {
+        async move {
+            self.object(repository, path)
+                .ok_or_else(|| anyhow::anyhow!("Object for {path} not found"))
+        }
+        .boxed()
+    }
+
+    fn upload<'a>(&'a self, repository: Uuid, path: &'a str) -> StoreFuture<'a, ()> {
+        async move {
+            let full_path = format!("{}/{}/{}", config::get().root_folder(), repository, path);
+            let contents = tokio::fs::read(&full_path)
+                .await
+                .map_err(|err| anyhow::anyhow!("Failed to read {full_path}: {err}"))?;
+            self.insert(repository, path, &contents);
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn remove<'a>(&'a self, repository: Uuid, path: &'a str) -> StoreFuture<'a, ()> {
+        async move {
+            self.lock().remove(&Self::key(repository, path));
+            Ok(())
+        }
+        .boxed()
+    }
+}
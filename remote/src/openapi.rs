@@ -0,0 +1,105 @@
+// the openapi document served at /api/openapi.json, for tools that talk to the server without pitsu-lib
+// paths come from the #[utoipa::path] on each handler in main, a test in main fails if one is missing from here
+use lazy_static::lazy_static;
+use pitsu_lib::{
+    AccessLevel, ApiError, ArchiveFormat, ChangelogEntry, CreateRemoteRepository, ErrorCode, File, FilePart,
+    FileUpload, InviteCode, Pitignore, PitignorePattern, RemoteRepository, RootFolder, SetAccess,
+    SimpleRemoteRepository, ThisUser, TransferEncoding, UpdateChannel, UpdateRemoteRepository, UploadFile, User,
+    UserWithAccess, VersionNumber,
+};
+use utoipa::{
+    openapi::security::{Http, HttpAuthScheme, SecurityScheme},
+    Modify, OpenApi,
+};
+
+// name of the security scheme handlers refer to in security(...)
+pub const API_KEY: &str = "api_key";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Pitsu",
+        description = "Planet51 Internet Transfer and Synchronization Utility API.\n\n\
+            Authenticated routes take the user's api key as a bearer token. Repository routes also need an access \
+            level on the repository (Read < Write < Admin < Owner), the minimum is given on each route.\n\n\
+            Every error is an ApiError. Clients over their rate limit get 429 with Retry-After."
+    ),
+    paths(
+        crate::root,
+        crate::healthz,
+        crate::metrics_endpoint,
+        crate::api,
+        crate::openapi_json,
+        crate::api_catch_all,
+        crate::get_self,
+        crate::get_other,
+        crate::get_all_users,
+        crate::repository,
+        crate::repository_update,
+        crate::get_users_with_access,
+        crate::set_access_level,
+        crate::remove_user_access,
+        crate::repository_path,
+        crate::repository_archive,
+        crate::import_archive,
+        crate::upload_file,
+        crate::delete_file,
+        crate::create_repository,
+        crate::invite_user,
+        crate::redeem_invite,
+        crate::get_local_version,
+        crate::get_changelog,
+        crate::get_latest_version,
+    ),
+    components(schemas(
+        AccessLevel,
+        ApiError,
+        ArchiveFormat,
+        ChangelogEntry,
+        CreateRemoteRepository,
+        ErrorCode,
+        File,
+        FilePart,
+        FileUpload,
+        InviteCode,
+        Pitignore,
+        PitignorePattern,
+        RemoteRepository,
+        RootFolder,
+        SetAccess,
+        SimpleRemoteRepository,
+        ThisUser,
+        TransferEncoding,
+        UpdateChannel,
+        UpdateRemoteRepository,
+        UploadFile,
+        User,
+        UserWithAccess,
+        VersionNumber,
+    )),
+    modifiers(&BearerAuth),
+    tags(
+        (name = "server", description = "Status of the server itself"),
+        (name = "users", description = "Users and their api keys"),
+        (name = "repositories", description = "Repositories and who can access them"),
+        (name = "files", description = "Reading and writing the files in a repository"),
+        (name = "client", description = "Downloads and updates for the desktop client"),
+    )
+)]
+pub struct ApiDoc;
+
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(API_KEY, SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+    }
+}
+
+lazy_static! {
+    // the document never changes while the server is up
+    pub static ref DOCUMENT: String = ApiDoc::openapi()
+        .to_pretty_json()
+        .expect("Failed to serialize openapi document");
+}
@@ -0,0 +1,259 @@
+// per user request limits, applied in front of every route in exec
+// each class gets a token bucket refilled at its per minute rate (so bursts of up to a minute's worth are fine)
+// and transfers get a cap on how many can be in flight at once. anything over gets 429 with Retry-After
+// requests without a known api key share the budget of the address they come from
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::{BodySize, BoxBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderValue, RETRY_AFTER},
+    middleware::Next,
+    web::{Bytes, Data},
+    HttpResponse,
+};
+use pitsu_lib::{ApiError, ErrorCode};
+
+use crate::store::DataStore;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// past this many tracked clients idle ones get dropped
+const PRUNE_THRESHOLD: usize = 4096;
+// what a client is told to wait when all its transfer slots are taken, there's no telling when one frees up
+const TRANSFER_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Class {
+    Metadata,
+    Download,
+    Upload,
+}
+
+impl std::fmt::Display for Class {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Class::Metadata => write!(f, "metadata"),
+            Class::Download => write!(f, "download"),
+            Class::Upload => write!(f, "upload"),
+        }
+    }
+}
+
+// 0 turns a limit off
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub metadata_per_minute: u32,
+    pub downloads_per_minute: u32,
+    pub uploads_per_minute: u32,
+    pub concurrent_downloads: u32,
+    pub concurrent_uploads: u32,
+}
+
+impl Limits {
+    fn per_minute(&self, class: Class) -> u32 {
+        match class {
+            Class::Metadata => self.metadata_per_minute,
+            Class::Download => self.downloads_per_minute,
+            Class::Upload => self.uploads_per_minute,
+        }
+    }
+    fn concurrent(&self, class: Class) -> u32 {
+        match class {
+            Class::Metadata => 0,
+            Class::Download => self.concurrent_downloads,
+            Class::Upload => self.concurrent_uploads,
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct RateLimiter {
+    limits: Limits,
+    buckets: Mutex<HashMap<(String, Class), Bucket>>,
+    transfers: Mutex<HashMap<(String, Class), Arc<Semaphore>>>,
+}
+
+// None for routes that are never limited
+fn classify(req: &ServiceRequest) -> Option<Class> {
+    let path = req.path().trim_start_matches('/');
+    if path.is_empty() || path == "healthz" || path == "metrics" {
+        return None;
+    }
+    let method = req.method();
+    if method == actix_web::http::Method::POST && (path.ends_with("/.pit/upload") || path.ends_with("/.pit/import")) {
+        return Some(Class::Upload);
+    }
+    if method != actix_web::http::Method::GET {
+        return Some(Class::Metadata);
+    }
+    if path == "api/local/update" {
+        return Some(Class::Download);
+    }
+    // {uuid}/{path} is a file, {uuid}/.pit/archive a whole repository, the rest is json
+    match path.split_once('/') {
+        Some((first, rest)) if first != "api" && !rest.is_empty() && rest != ".pit/access" => Some(Class::Download),
+        _ => Some(Class::Metadata),
+    }
+}
+
+// the user behind the api key, otherwise whoever is connecting. keying on the raw token would give every made up
+// token a fresh budget
+async fn client_key(req: &ServiceRequest) -> String {
+    let token = req
+        .headers()
+        .get("Authorization")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "));
+    if let (Some(token), Some(store)) = (token, req.app_data::<Data<dyn DataStore>>()) {
+        if let Ok(Some(user)) = store.user_by_api_key(token).await {
+            return format!("user:{}", user.uuid);
+        }
+    }
+    format!("addr:{}", client_addr(req))
+}
+
+// forwarded headers are only believed from a proxy in TRUSTED_PROXIES, anyone else can send them
+fn client_addr(req: &ServiceRequest) -> String {
+    let peer = req.peer_addr().map(|addr| addr.ip());
+    let trusted = crate::config::get().trusted_proxies().unwrap_or_default();
+    if peer.is_some_and(|ip| trusted.contains(&ip)) {
+        if let Some(addr) = req.connection_info().realip_remote_addr() {
+            return addr.to_string();
+        }
+    }
+    peer.map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+}
+
+// the middleware, the limiter comes from app data so tests without one aren't limited
+pub async fn limit(req: ServiceRequest, next: Next<BoxBody>) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let (Some(limiter), Some(class)) = (req.app_data::<Data<RateLimiter>>().cloned(), classify(&req)) else {
+        return next.call(req).await;
+    };
+    let key = client_key(&req).await;
+    match limiter.admit(&key, class) {
+        Ok(permit) => Ok(next
+            .call(req)
+            .await?
+            .map_body(|_, body| hold(body, permit))
+            .map_into_boxed_body()),
+        Err(retry_after) => Ok(req.into_response(too_many_requests(retry_after))),
+    }
+}
+
+impl RateLimiter {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            buckets: Mutex::new(HashMap::new()),
+            transfers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Ok with the transfer slot to hold for as long as the response is being sent, or how long to wait
+    fn admit(&self, key: &str, class: Class) -> Result<Option<OwnedSemaphorePermit>, Duration> {
+        let permit = self.take_slot(key, class)?;
+        self.take_token(key, class)?;
+        Ok(permit)
+    }
+
+    fn take_slot(&self, key: &str, class: Class) -> Result<Option<OwnedSemaphorePermit>, Duration> {
+        let max = self.limits.concurrent(class);
+        if max == 0 {
+            return Ok(None);
+        }
+        let semaphore = {
+            let mut transfers = self.transfers.lock().map_err(|_| TRANSFER_RETRY_AFTER)?;
+            if transfers.len() > PRUNE_THRESHOLD {
+                // nobody else holds a clone, so nothing is in flight for it
+                transfers.retain(|_, semaphore| Arc::strong_count(semaphore) > 1);
+            }
+            transfers
+                .entry((key.to_string(), class))
+                .or_insert_with(|| Arc::new(Semaphore::new(max as usize)))
+                .clone()
+        };
+        match semaphore.try_acquire_owned() {
+            Ok(permit) => Ok(Some(permit)),
+            Err(_) => {
+                log::debug!("Too many concurrent {class} requests for one client");
+                crate::metrics::throttled(class);
+                Err(TRANSFER_RETRY_AFTER)
+            }
+        }
+    }
+
+    fn take_token(&self, key: &str, class: Class) -> Result<(), Duration> {
+        let per_minute = self.limits.per_minute(class);
+        if per_minute == 0 {
+            return Ok(());
+        }
+        let capacity = per_minute as f64;
+        let per_second = capacity / 60.0;
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().map_err(|_| TRANSFER_RETRY_AFTER)?;
+        if buckets.len() > PRUNE_THRESHOLD {
+            // a full bucket is the same as no bucket
+            buckets.retain(|(_, class), bucket| {
+                let capacity = self.limits.per_minute(*class) as f64;
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * capacity / 60.0 < capacity
+            });
+        }
+        let bucket = buckets.entry((key.to_string(), class)).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * per_second).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            log::debug!("Rate limited {class} request for one client");
+            crate::metrics::throttled(class);
+            return Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+pub fn too_many_requests(retry_after: Duration) -> HttpResponse {
+    // whole seconds, rounded up so the client never comes back too early
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let seconds = seconds.max(1);
+    let mut response = crate::api_error_response(
+        ApiError::new(ErrorCode::RateLimited, "Too many requests")
+            .with_details(serde_json::json!({ "retry_after": seconds })),
+    );
+    response.headers_mut().insert(RETRY_AFTER, HeaderValue::from(seconds));
+    response
+}
+
+// keeps the transfer slot taken until the body has been sent, not just until the handler returns
+pub struct Guarded {
+    body: BoxBody,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+pub fn hold(body: BoxBody, permit: Option<OwnedSemaphorePermit>) -> Guarded {
+    Guarded { body, _permit: permit }
+}
+
+impl MessageBody for Guarded {
+    type Error = Box<dyn std::error::Error>;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        Pin::new(&mut self.body).poll_next(cx)
+    }
+}
@@ -0,0 +1,518 @@
+// users, repositories and access grants: the DataStore the handlers query, PostgresStore on top of the cornucopia
+// queries and MemoryStore for tests. every method stands on its own, there's no transaction spanning calls.
+// lookups the handlers need to answer 404 for return None, other misses are errors like cornucopia's .one()
+use deadpool_postgres::Pool;
+use futures::future::{BoxFuture, FutureExt as _};
+use pitsu_lib::{
+    anyhow::{self, Result},
+    AccessLevel, User, UserWithAccess,
+};
+use uuid::Uuid;
+
+use crate::cornucopia::queries;
+
+pub type StoreFuture<'a, T> = BoxFuture<'a, Result<T>>;
+
+#[derive(Debug, Clone)]
+pub struct StoredUser {
+    pub uuid: Uuid,
+    pub username: String,
+    pub api_key: String,
+}
+
+impl From<StoredUser> for User {
+    fn from(user: StoredUser) -> Self {
+        User {
+            uuid: user.uuid,
+            username: user.username.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredRepository {
+    pub uuid: Uuid,
+    pub name: String,
+    // a serialized RootFolder
+    pub file_hashes: serde_json::Value,
+    // empty when there's no recommendation
+    pub recommended_path: String,
+}
+
+pub trait DataStore: Send + Sync {
+    // for /healthz
+    fn ping(&self) -> StoreFuture<'_, ()>;
+
+    // None for a key nobody has, so callers can tell it apart from the lookup failing
+    fn user_by_api_key<'a>(&'a self, api_key: &'a str) -> StoreFuture<'a, Option<StoredUser>>;
+    // None for a user that doesn't exist, an invite can outlive the user it was for
+    fn user(&self, uuid: Uuid) -> StoreFuture<'_, Option<StoredUser>>;
+    fn users(&self) -> StoreFuture<'_, Vec<StoredUser>>;
+
+    fn repository(&self, uuid: Uuid) -> StoreFuture<'_, StoredRepository>;
+    // newest first
+    fn repositories_owned_by(&self, owner: Uuid) -> StoreFuture<'_, Vec<StoredRepository>>;
+    fn create_repository<'a>(&'a self, name: &'a str, owner: Uuid) -> StoreFuture<'a, StoredRepository>;
+    fn update_repository_metadata<'a>(
+        &'a self,
+        uuid: Uuid,
+        name: &'a str,
+        recommended_path: &'a str,
+    ) -> StoreFuture<'a, ()>;
+    fn update_file_hashes<'a>(&'a self, uuid: Uuid, file_hashes: &'a serde_json::Value) -> StoreFuture<'a, ()>;
+
+    // Owner for the owner, AccessLevel::None for anyone without a grant, None if the repository doesn't exist
+    fn access_level(&self, user: Uuid, repository: Uuid) -> StoreFuture<'_, Option<AccessLevel>>;
+    // (repository, level) for every repository the user was granted access to, owned ones aren't included
+    fn granted_to(&self, user: Uuid) -> StoreFuture<'_, Vec<(Uuid, AccessLevel)>>;
+    // the owner first, then everyone with a grant
+    fn users_with_access(&self, repository: Uuid) -> StoreFuture<'_, Vec<UserWithAccess>>;
+    fn set_access_level(&self, repository: Uuid, user: Uuid, level: AccessLevel) -> StoreFuture<'_, ()>;
+    fn remove_access(&self, repository: Uuid, user: Uuid) -> StoreFuture<'_, ()>;
+}
+
+pub struct PostgresStore {
+    pool: Pool,
+}
+
+impl PostgresStore {
+    pub fn new(pool: Pool) -> Self {
+        Self { pool }
+    }
+
+    async fn connection(&self) -> Result<deadpool_postgres::Client> {
+        self.pool
+            .get()
+            .await
+            .map_err(|err| anyhow::anyhow!("Failed to get database connection: {err}"))
+    }
+}
+
+// cornucopia generates a row type per query, they all carry the same columns
+macro_rules! stored_user {
+    ($row:expr) => {{
+        let row = $row;
+        StoredUser {
+            uuid: row.uuid,
+            username: row.username,
+            api_key: row.api_key,
+        }
+    }};
+}
+
+macro_rules! stored_repository {
+    ($row:expr) => {{
+        let row = $row;
+        StoredRepository {
+            uuid: row.uuid,
+            name: row.name,
+            file_hashes: row.file_hashes,
+            recommended_path: row.recommended_path,
+        }
+    }};
+}
+
+impl DataStore for PostgresStore {
+    fn ping(&self) -> StoreFuture<'_, ()> {
+        async move {
+            self.connection()
+                .await?
+                .query_one("SELECT 1", &[])
+                .await
+                .map_err(|err| anyhow::anyhow!("Query failed: {err}"))?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn user_by_api_key<'a>(&'a self, api_key: &'a str) -> StoreFuture<'a, Option<StoredUser>> {
+        async move {
+            let connection = self.connection().await?;
+            let user = queries::user::get_by_api_key()
+                .bind(&connection, &api_key)
+                .opt()
+                .await?;
+            Ok(user.map(|user| stored_user!(user)))
+        }
+        .boxed()
+    }
+
+    fn user(&self, uuid: Uuid) -> StoreFuture<'_, Option<StoredUser>> {
+        async move {
+            let connection = self.connection().await?;
+            let user = queries::user::get_by_uuid().bind(&connection, &uuid).opt().await?;
+            Ok(user.map(|user| stored_user!(user)))
+        }
+        .boxed()
+    }
+
+    fn users(&self) -> StoreFuture<'_, Vec<StoredUser>> {
+        async move {
+            let connection = self.connection().await?;
+            let users = queries::user::get_all().bind(&connection).all().await?;
+            Ok(users.into_iter().map(|user| stored_user!(user)).collect())
+        }
+        .boxed()
+    }
+
+    fn repository(&self, uuid: Uuid) -> StoreFuture<'_, StoredRepository> {
+        async move {
+            let connection = self.connection().await?;
+            let repo = queries::repository::get_by_uuid()
+                .bind(&connection, &uuid)
+                .one()
+                .await?;
+            Ok(stored_repository!(repo))
+        }
+        .boxed()
+    }
+
+    fn repositories_owned_by(&self, owner: Uuid) -> StoreFuture<'_, Vec<StoredRepository>> {
+        async move {
+            let connection = self.connection().await?;
+            let repos = queries::repository::get_by_owner()
+                .bind(&connection, &owner)
+                .all()
+                .await?;
+            Ok(repos.into_iter().map(|repo| stored_repository!(repo)).collect())
+        }
+        .boxed()
+    }
+
+    fn create_repository<'a>(&'a self, name: &'a str, owner: Uuid) -> StoreFuture<'a, StoredRepository> {
+        async move {
+            let connection = self.connection().await?;
+            let repo = queries::repository::create()
+                .bind(&connection, &name, &owner)
+                .one()
+                .await?;
+            Ok(stored_repository!(repo))
+        }
+        .boxed()
+    }
+
+    fn update_repository_metadata<'a>(
+        &'a self,
+        uuid: Uuid,
+        name: &'a str,
+        recommended_path: &'a str,
+    ) -> StoreFuture<'a, ()> {
+        async move {
+            let connection = self.connection().await?;
+            queries::repository::update_metadata_by_uuid()
+                .bind(&connection, &name, &recommended_path, &uuid)
+                .one()
+                .await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn update_file_hashes<'a>(&'a self, uuid: Uuid, file_hashes: &'a serde_json::Value) -> StoreFuture<'a, ()> {
+        async move {
+            let connection = self.connection().await?;
+            queries::repository::update_file_hashes_by_uuid()
+                .bind(&connection, file_hashes, &uuid)
+                .one()
+                .await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn access_level(&self, user: Uuid, repository: Uuid) -> StoreFuture<'_, Option<AccessLevel>> {
+        async move {
+            let connection = self.connection().await?;
+            let level = queries::access::user_has_access()
+                .bind(&connection, &user, &repository)
+                .opt()
+                .await?;
+            Ok(level.map(Into::into))
+        }
+        .boxed()
+    }
+
+    fn granted_to(&self, user: Uuid) -> StoreFuture<'_, Vec<(Uuid, AccessLevel)>> {
+        async move {
+            let connection = self.connection().await?;
+            let access = queries::access::get_by_user().bind(&connection, &user).all().await?;
+            Ok(access
+                .into_iter()
+                .map(|access| (access.repository_uuid, access.access_level.into()))
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn users_with_access(&self, repository: Uuid) -> StoreFuture<'_, Vec<UserWithAccess>> {
+        async move {
+            let connection = self.connection().await?;
+            let users = queries::access::get_all_users_with_access()
+                .bind(&connection, &repository)
+                .all()
+                .await?;
+            Ok(users
+                .into_iter()
+                .map(|user| UserWithAccess {
+                    user: User {
+                        uuid: user.user_uuid,
+                        username: user.username.into(),
+                    },
+                    access_level: user.access_level.into(),
+                })
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn set_access_level(&self, repository: Uuid, user: Uuid, level: AccessLevel) -> StoreFuture<'_, ()> {
+        async move {
+            let connection = self.connection().await?;
+            queries::access::create_or_update()
+                .bind(&connection, &repository, &user, &level.into())
+                .await?;
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn remove_access(&self, repository: Uuid, user: Uuid) -> StoreFuture<'_, ()> {
+        async move {
+            let connection = self.connection().await?;
+            queries::access::delete_by_user_uuid_and_repository_uuid()
+                .bind(&connection, &user, &repository)
+                .one()
+                .await?;
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+// everything in a mutex, behaves like the postgres queries as far as the handlers can tell. only the tests use it
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStore {
+    state: std::sync::Mutex<MemoryState>,
+}
+
+#[cfg(test)]
+#[derive(Default)]
+struct MemoryState {
+    users: Vec<StoredUser>,
+    // (owner, repository), oldest first
+    repositories: Vec<(Uuid, StoredRepository)>,
+    // (repository, user, level)
+    access: Vec<(Uuid, Uuid, AccessLevel)>,
+    // access_level fails like a dropped connection would
+    access_broken: bool,
+}
+
+#[cfg(test)]
+impl MemoryStore {
+    pub fn add_user(&self, username: &str, api_key: &str) -> Uuid {
+        let uuid = Uuid::new_v4();
+        self.lock().users.push(StoredUser {
+            uuid,
+            username: username.to_string(),
+            api_key: api_key.to_string(),
+        });
+        uuid
+    }
+
+    pub fn break_access_lookups(&self) {
+        self.lock().access_broken = true;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MemoryState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // errors the same way .one() does when there's no row
+    fn find<T: Clone>(items: &[T], found: impl Fn(&T) -> bool, what: &str) -> Result<T> {
+        items
+            .iter()
+            .find(|item| found(item))
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("{what} not found"))
+    }
+}
+
+#[cfg(test)]
+impl DataStore for MemoryStore {
+    fn ping(&self) -> StoreFuture<'_, ()> {
+        async move { Ok(()) }.boxed()
+    }
+
+    fn user_by_api_key<'a>(&'a self, api_key: &'a str) -> StoreFuture<'a, Option<StoredUser>> {
+        async move { Ok(self.lock().users.iter().find(|user| user.api_key == api_key).cloned()) }.boxed()
+    }
+
+    fn user(&self, uuid: Uuid) -> StoreFuture<'_, Option<StoredUser>> {
+        async move { Ok(self.lock().users.iter().find(|user| user.uuid == uuid).cloned()) }.boxed()
+    }
+
+    fn users(&self) -> StoreFuture<'_, Vec<StoredUser>> {
+        async move { Ok(self.lock().users.clone()) }.boxed()
+    }
+
+    fn repository(&self, uuid: Uuid) -> StoreFuture<'_, StoredRepository> {
+        async move {
+            Self::find(&self.lock().repositories, |(_, repo)| repo.uuid == uuid, "Repository").map(|(_, repo)| repo)
+        }
+        .boxed()
+    }
+
+    fn repositories_owned_by(&self, owner: Uuid) -> StoreFuture<'_, Vec<StoredRepository>> {
+        async move {
+            Ok(self
+                .lock()
+                .repositories
+                .iter()
+                .rev()
+                .filter(|(owner_uuid, _)| *owner_uuid == owner)
+                .map(|(_, repo)| repo.clone())
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn create_repository<'a>(&'a self, name: &'a str, owner: Uuid) -> StoreFuture<'a, StoredRepository> {
+        async move {
+            let repo = StoredRepository {
+                uuid: Uuid::new_v4(),
+                name: name.to_string(),
+                file_hashes: serde_json::json!({}),
+                recommended_path: String::new(),
+            };
+            self.lock().repositories.push((owner, repo.clone()));
+            Ok(repo)
+        }
+        .boxed()
+    }
+
+    fn update_repository_metadata<'a>(
+        &'a self,
+        uuid: Uuid,
+        name: &'a str,
+        recommended_path: &'a str,
+    ) -> StoreFuture<'a, ()> {
+        async move {
+            let mut state = self.lock();
+            let (_, repo) = state
+                .repositories
+                .iter_mut()
+                .find(|(_, repo)| repo.uuid == uuid)
+                .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+            repo.name = name.to_string();
+            repo.recommended_path = recommended_path.to_string();
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn update_file_hashes<'a>(&'a self, uuid: Uuid, file_hashes: &'a serde_json::Value) -> StoreFuture<'a, ()> {
+        async move {
+            let mut state = self.lock();
+            let (_, repo) = state
+                .repositories
+                .iter_mut()
+                .find(|(_, repo)| repo.uuid == uuid)
+                .ok_or_else(|| anyhow::anyhow!("Repository not found"))?;
+            repo.file_hashes = file_hashes.clone();
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn access_level(&self, user: Uuid, repository: Uuid) -> StoreFuture<'_, Option<AccessLevel>> {
+        async move {
+            let state = self.lock();
+            if state.access_broken {
+                return Err(anyhow::anyhow!("Access lookup failed"));
+            }
+            let Some((owner, _)) = state.repositories.iter().find(|(_, repo)| repo.uuid == repository) else {
+                return Ok(None);
+            };
+            if *owner == user {
+                return Ok(Some(AccessLevel::Owner));
+            }
+            Ok(Some(
+                state
+                    .access
+                    .iter()
+                    .find(|(repo, who, _)| *repo == repository && *who == user)
+                    .map_or(AccessLevel::None, |(_, _, level)| *level),
+            ))
+        }
+        .boxed()
+    }
+
+    fn granted_to(&self, user: Uuid) -> StoreFuture<'_, Vec<(Uuid, AccessLevel)>> {
+        async move {
+            Ok(self
+                .lock()
+                .access
+                .iter()
+                .filter(|(_, who, _)| *who == user)
+                .map(|(repo, _, level)| (*repo, *level))
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn users_with_access(&self, repository: Uuid) -> StoreFuture<'_, Vec<UserWithAccess>> {
+        async move {
+            let state = self.lock();
+            let (owner, _) = Self::find(&state.repositories, |(_, repo)| repo.uuid == repository, "Repository")?;
+            let owner = std::iter::once((owner, AccessLevel::Owner));
+            let granted = state
+                .access
+                .iter()
+                .filter(|(repo, _, _)| *repo == repository)
+                .map(|(_, user, level)| (*user, *level));
+            owner
+                .chain(granted)
+                .map(|(uuid, access_level)| {
+                    let user = Self::find(&state.users, |user| user.uuid == uuid, "User")?;
+                    Ok(UserWithAccess {
+                        user: user.into(),
+                        access_level,
+                    })
+                })
+                .collect()
+        }
+        .boxed()
+    }
+
+    fn set_access_level(&self, repository: Uuid, user: Uuid, level: AccessLevel) -> StoreFuture<'_, ()> {
+        async move {
+            let mut state = self.lock();
+            match state
+                .access
+                .iter_mut()
+                .find(|(repo, who, _)| *repo == repository && *who == user)
+            {
+                Some(grant) => grant.2 = level,
+                None => state.access.push((repository, user, level)),
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+
+    fn remove_access(&self, repository: Uuid, user: Uuid) -> StoreFuture<'_, ()> {
+        async move {
+            let mut state = self.lock();
+            let before = state.access.len();
+            state
+                .access
+                .retain(|(repo, who, _)| !(*repo == repository && *who == user));
+            if state.access.len() == before {
+                return Err(anyhow::anyhow!("Access not found"));
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
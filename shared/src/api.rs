@@ -0,0 +1,612 @@
+// every endpoint the client talks to, one struct each with its path, method, body and response type.
+// the server's tests send a request to every ROUTE here and check which handler it reaches, so renaming a
+// handler's path without updating the endpoint here fails `cargo test` instead of the client at runtime.
+// with the `client` feature this also has a blocking and a callback based Client on top of ehttp
+use std::sync::Arc;
+
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::{
+    ApiError, ArchiveFormat, ChangelogEntry, CreateRemoteRepository, FileUpload, InviteCode, RemoteRepository,
+    ThisUser, TransferEncoding, UpdateChannel, UpdateRemoteRepository, User, UserWithAccess, VersionNumber,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+    Patch,
+    Delete,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Patch => "PATCH",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+impl std::fmt::Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+pub enum Body {
+    Empty,
+    Json(Vec<u8>),
+    Bytes(Vec<u8>),
+}
+
+impl Body {
+    fn json(value: &impl serde::Serialize) -> Result<Self> {
+        Ok(Body::Json(serde_json::to_vec(value)?))
+    }
+}
+
+// what came back, independent of the http library that fetched it
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub bytes: Vec<u8>,
+}
+
+impl Response {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+    // the ApiError for anything outside 2xx
+    pub fn error_for_status(self) -> Result<Self> {
+        if (200..=299).contains(&self.status) {
+            Ok(self)
+        } else {
+            Err(ApiError::from_response(self.status, &self.bytes).into())
+        }
+    }
+}
+
+pub trait FromResponse: Sized {
+    // only called for 2xx responses
+    fn from_response(response: Response) -> Result<Self>;
+}
+
+macro_rules! json_response {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl FromResponse for $ty {
+                fn from_response(response: Response) -> Result<Self> {
+                    serde_json::from_slice(&response.bytes)
+                        .map_err(|err| anyhow::anyhow!("Failed to parse response: {err}"))
+                }
+            }
+        )*
+    };
+}
+
+json_response!(
+    ThisUser,
+    User,
+    Vec<User>,
+    Vec<UserWithAccess>,
+    RemoteRepository,
+    VersionNumber,
+    Vec<ChangelogEntry>,
+);
+
+// endpoints that only answer with a status and a human readable line
+impl FromResponse for () {
+    fn from_response(_: Response) -> Result<Self> {
+        Ok(())
+    }
+}
+
+impl FromResponse for Arc<str> {
+    fn from_response(response: Response) -> Result<Self> {
+        Ok(String::from_utf8_lossy(&response.bytes).into())
+    }
+}
+
+// raw bytes, decoded if the server sent them with a transfer encoding
+impl FromResponse for Vec<u8> {
+    fn from_response(response: Response) -> Result<Self> {
+        match response.header(crate::ENCODING_HEADER) {
+            Some(encoding) => encoding.parse::<TransferEncoding>()?.decode(&response.bytes),
+            None => Ok(response.bytes),
+        }
+    }
+}
+
+pub trait Endpoint {
+    type Response: FromResponse;
+    const METHOD: Method;
+    // the route exactly as written on the server's handler
+    const ROUTE: &'static str;
+    // path and query, relative to the server url
+    fn path(&self) -> String;
+    fn headers(&self) -> Vec<(&'static str, String)> {
+        vec![]
+    }
+    // takes self so large uploads aren't copied
+    fn body(self) -> Result<Body>
+    where
+        Self: Sized,
+    {
+        Ok(Body::Empty)
+    }
+}
+
+// repository file paths are sent as they are stored, without the leading slash
+fn file_path(path: &str) -> &str {
+    path.strip_prefix('/').unwrap_or(path)
+}
+
+pub struct GetSelf;
+
+impl Endpoint for GetSelf {
+    type Response = ThisUser;
+    const METHOD: Method = Method::Get;
+    const ROUTE: &'static str = "/api/user";
+    fn path(&self) -> String {
+        "/api/user".to_string()
+    }
+}
+
+pub struct GetUser {
+    pub uuid: Uuid,
+}
+
+impl Endpoint for GetUser {
+    type Response = User;
+    const METHOD: Method = Method::Get;
+    const ROUTE: &'static str = "/api/user/{uuid}";
+    fn path(&self) -> String {
+        format!("/api/user/{}", self.uuid)
+    }
+}
+
+pub struct GetUsers;
+
+impl Endpoint for GetUsers {
+    type Response = Vec<User>;
+    const METHOD: Method = Method::Get;
+    const ROUTE: &'static str = "/api/users";
+    fn path(&self) -> String {
+        "/api/users".to_string()
+    }
+}
+
+pub struct CreateRepository {
+    pub repository: CreateRemoteRepository,
+}
+
+impl Endpoint for CreateRepository {
+    type Response = RemoteRepository;
+    const METHOD: Method = Method::Post;
+    const ROUTE: &'static str = "/api/repository";
+    fn path(&self) -> String {
+        "/api/repository".to_string()
+    }
+    fn body(self) -> Result<Body> {
+        Body::json(&self.repository)
+    }
+}
+
+pub struct GetRepository {
+    pub uuid: Uuid,
+}
+
+impl Endpoint for GetRepository {
+    type Response = RemoteRepository;
+    const METHOD: Method = Method::Get;
+    const ROUTE: &'static str = "/{uuid}";
+    fn path(&self) -> String {
+        format!("/{}", self.uuid)
+    }
+}
+
+pub struct UpdateRepository {
+    pub uuid: Uuid,
+    pub update: UpdateRemoteRepository,
+}
+
+impl Endpoint for UpdateRepository {
+    type Response = ();
+    const METHOD: Method = Method::Patch;
+    const ROUTE: &'static str = "/{uuid}";
+    fn path(&self) -> String {
+        format!("/{}", self.uuid)
+    }
+    fn body(self) -> Result<Body> {
+        Body::json(&self.update)
+    }
+}
+
+pub struct GetAccess {
+    pub uuid: Uuid,
+}
+
+impl Endpoint for GetAccess {
+    type Response = Vec<UserWithAccess>;
+    const METHOD: Method = Method::Get;
+    const ROUTE: &'static str = "/{uuid}/.pit/access";
+    fn path(&self) -> String {
+        format!("/{}/.pit/access", self.uuid)
+    }
+}
+
+pub struct SetAccessLevel {
+    pub uuid: Uuid,
+    pub user: UserWithAccess,
+}
+
+impl Endpoint for SetAccessLevel {
+    type Response = ();
+    const METHOD: Method = Method::Post;
+    const ROUTE: &'static str = "/{uuid}/.pit/user/access";
+    fn path(&self) -> String {
+        format!("/{}/.pit/user/access", self.uuid)
+    }
+    fn body(self) -> Result<Body> {
+        Body::json(&self.user)
+    }
+}
+
+pub struct RemoveAccessLevel {
+    pub uuid: Uuid,
+    pub user: Uuid,
+}
+
+impl Endpoint for RemoveAccessLevel {
+    type Response = ();
+    const METHOD: Method = Method::Delete;
+    const ROUTE: &'static str = "/{uuid}/.pit/user/access";
+    fn path(&self) -> String {
+        format!("/{}/.pit/user/access", self.uuid)
+    }
+    fn body(self) -> Result<Body> {
+        Body::json(&self.user)
+    }
+}
+
+pub struct DownloadFile {
+    pub uuid: Uuid,
+    pub path: Arc<str>,
+    // what the client can decode, the server picks one and says so in ENCODING_HEADER
+    pub accept: TransferEncoding,
+    // only these bytes of the file, always sent unencoded
+    pub range: Option<std::ops::Range<u64>>,
+}
+
+impl Endpoint for DownloadFile {
+    type Response = Vec<u8>;
+    const METHOD: Method = Method::Get;
+    const ROUTE: &'static str = "/{uuid}/{path:.*}";
+    fn path(&self) -> String {
+        format!("/{}/{}", self.uuid, file_path(&self.path))
+    }
+    fn headers(&self) -> Vec<(&'static str, String)> {
+        let mut headers = vec![(crate::ACCEPT_ENCODING_HEADER, self.accept.accept_header())];
+        if let Some(range) = &self.range {
+            headers.push((
+                "Range",
+                format!("bytes={}-{}", range.start, range.end.saturating_sub(1)),
+            ));
+        }
+        headers
+    }
+}
+
+pub struct DeleteFile {
+    pub uuid: Uuid,
+    pub path: Arc<str>,
+}
+
+impl Endpoint for DeleteFile {
+    type Response = ();
+    const METHOD: Method = Method::Delete;
+    const ROUTE: &'static str = "/{uuid}/{path:.*}";
+    fn path(&self) -> String {
+        format!("/{}/{}", self.uuid, file_path(&self.path))
+    }
+}
+
+pub struct UploadFiles {
+    pub uuid: Uuid,
+    pub files: FileUpload,
+}
+
+impl Endpoint for UploadFiles {
+    type Response = ();
+    const METHOD: Method = Method::Post;
+    const ROUTE: &'static str = "/{uuid}/.pit/upload";
+    fn path(&self) -> String {
+        format!("/{}/.pit/upload", self.uuid)
+    }
+    fn body(self) -> Result<Body> {
+        Body::json(&self.files)
+    }
+}
+
+pub struct DownloadArchive {
+    pub uuid: Uuid,
+    pub format: ArchiveFormat,
+}
+
+impl Endpoint for DownloadArchive {
+    type Response = Vec<u8>;
+    const METHOD: Method = Method::Get;
+    const ROUTE: &'static str = "/{uuid}/.pit/archive";
+    fn path(&self) -> String {
+        format!("/{}/.pit/archive?format={}", self.uuid, self.format)
+    }
+}
+
+pub struct ImportArchive {
+    pub uuid: Uuid,
+    pub archive: Vec<u8>,
+    pub strip_components: usize,
+}
+
+impl Endpoint for ImportArchive {
+    // what was imported, in words
+    type Response = Arc<str>;
+    const METHOD: Method = Method::Post;
+    const ROUTE: &'static str = "/{uuid}/.pit/import";
+    fn path(&self) -> String {
+        format!("/{}/.pit/import?strip_components={}", self.uuid, self.strip_components)
+    }
+    fn body(self) -> Result<Body> {
+        Ok(Body::Bytes(self.archive))
+    }
+}
+
+pub struct LatestVersion {
+    pub channel: UpdateChannel,
+    pub target: Arc<str>,
+}
+
+impl Endpoint for LatestVersion {
+    type Response = VersionNumber;
+    const METHOD: Method = Method::Get;
+    const ROUTE: &'static str = "/api/local/version";
+    fn path(&self) -> String {
+        format!("/api/local/version?channel={}&target={}", self.channel, self.target)
+    }
+}
+
+pub struct Changelog {
+    pub from: VersionNumber,
+    pub to: VersionNumber,
+}
+
+impl Endpoint for Changelog {
+    type Response = Vec<ChangelogEntry>;
+    const METHOD: Method = Method::Get;
+    const ROUTE: &'static str = "/api/local/changelog";
+    fn path(&self) -> String {
+        format!("/api/local/changelog?from={}&to={}", self.from, self.to)
+    }
+}
+
+pub struct DownloadUpdate {
+    pub channel: UpdateChannel,
+    pub target: Arc<str>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SignedUpdate {
+    pub executable: Vec<u8>,
+    // base64 ed25519 signature of the executable
+    pub signature: Arc<str>,
+}
+
+impl FromResponse for SignedUpdate {
+    fn from_response(response: Response) -> Result<Self> {
+        let signature = response
+            .header(crate::SIGNATURE_HEADER)
+            .map(Arc::from)
+            .ok_or_else(|| anyhow::anyhow!("Update is not signed, refusing to install it"))?;
+        Ok(SignedUpdate {
+            executable: response.bytes,
+            signature,
+        })
+    }
+}
+
+impl Endpoint for DownloadUpdate {
+    type Response = SignedUpdate;
+    const METHOD: Method = Method::Get;
+    const ROUTE: &'static str = "/api/local/update";
+    fn path(&self) -> String {
+        format!("/api/local/update?channel={}&target={}", self.channel, self.target)
+    }
+}
+
+// trades the code from an invite link for the user's api key, sent before there is a key to authenticate with
+pub struct RedeemInvite {
+    pub code: Arc<str>,
+}
+
+impl Endpoint for RedeemInvite {
+    type Response = Arc<str>;
+    const METHOD: Method = Method::Post;
+    const ROUTE: &'static str = "/api/invite";
+    fn path(&self) -> String {
+        "/api/invite".to_string()
+    }
+    fn body(self) -> Result<Body> {
+        Body::json(&InviteCode { code: self.code })
+    }
+}
+
+// routes the server has that no client calls, the installer download is opened in a browser
+pub const SERVER_ONLY_ROUTES: &[&str] = &[
+    "/",
+    "/healthz",
+    "/metrics",
+    "/api",
+    "/api/openapi.json",
+    "/api/{path:.*}",
+    "/api/invite",
+];
+
+// (method, route) of every endpoint above, for checking against the server
+pub const ENDPOINTS: &[(Method, &str)] = &[
+    (GetSelf::METHOD, GetSelf::ROUTE),
+    (GetUser::METHOD, GetUser::ROUTE),
+    (GetUsers::METHOD, GetUsers::ROUTE),
+    (CreateRepository::METHOD, CreateRepository::ROUTE),
+    (GetRepository::METHOD, GetRepository::ROUTE),
+    (UpdateRepository::METHOD, UpdateRepository::ROUTE),
+    (GetAccess::METHOD, GetAccess::ROUTE),
+    (SetAccessLevel::METHOD, SetAccessLevel::ROUTE),
+    (RemoveAccessLevel::METHOD, RemoveAccessLevel::ROUTE),
+    (DownloadFile::METHOD, DownloadFile::ROUTE),
+    (DeleteFile::METHOD, DeleteFile::ROUTE),
+    (UploadFiles::METHOD, UploadFiles::ROUTE),
+    (DownloadArchive::METHOD, DownloadArchive::ROUTE),
+    (ImportArchive::METHOD, ImportArchive::ROUTE),
+    (LatestVersion::METHOD, LatestVersion::ROUTE),
+    (Changelog::METHOD, Changelog::ROUTE),
+    (DownloadUpdate::METHOD, DownloadUpdate::ROUTE),
+    (RedeemInvite::METHOD, RedeemInvite::ROUTE),
+];
+
+#[cfg(feature = "client")]
+pub use client::Client;
+
+#[cfg(feature = "client")]
+mod client {
+    use std::{sync::Arc, time::Duration};
+
+    use anyhow::Result;
+
+    use super::{Body, Endpoint, FromResponse, Response};
+
+    // how many times a request the server throttled is retried before the 429 is handed back
+    const MAX_THROTTLE_RETRIES: u32 = 5;
+    // never sit on a request longer than this, whatever Retry-After says
+    const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+    type Callback = Box<dyn 'static + Send + FnOnce(ehttp::Result<ehttp::Response>)>;
+
+    #[derive(Debug, Clone)]
+    pub struct Client {
+        url: Arc<str>,
+        api_key: Arc<str>,
+    }
+
+    impl Client {
+        pub fn new(url: &str, api_key: &str) -> Self {
+            Self {
+                url: url.trim_end_matches('/').into(),
+                api_key: api_key.into(),
+            }
+        }
+
+        pub fn request<E: Endpoint>(&self, endpoint: E) -> Result<ehttp::Request> {
+            let mut request = ehttp::Request::get(format!("{}{}", self.url, endpoint.path()));
+            request.method = E::METHOD.as_str().to_string();
+            request
+                .headers
+                .insert("Authorization", format!("Bearer {}", self.api_key));
+            for (name, value) in endpoint.headers() {
+                request.headers.insert(name, value);
+            }
+            match endpoint.body()? {
+                Body::Empty => {}
+                Body::Json(body) => {
+                    request.headers.insert("Content-Type", "application/json");
+                    request.body = body;
+                }
+                Body::Bytes(body) => {
+                    request.headers.insert("Content-Type", "application/octet-stream");
+                    request.body = body;
+                }
+            }
+            Ok(request)
+        }
+
+        // on_done runs on ehttp's thread, not the caller's
+        pub fn fetch<E: Endpoint>(&self, endpoint: E, on_done: impl 'static + Send + FnOnce(Result<E::Response>)) {
+            match self.request(endpoint) {
+                Ok(request) => send(request, 0, Box::new(move |response| on_done(parse(response)))),
+                Err(err) => on_done(Err(err)),
+            }
+        }
+
+        // for the cli and anything else that can block
+        pub fn call<E: Endpoint>(&self, endpoint: E) -> Result<E::Response> {
+            let request = self.request(endpoint)?;
+            let mut attempt = 0;
+            loop {
+                let response = ehttp::fetch_blocking(&request);
+                if let Ok(throttled) = &response {
+                    if throttled.status == 429 && attempt < MAX_THROTTLE_RETRIES {
+                        let wait = retry_after(throttled);
+                        log::warn!(
+                            "Server is throttling {}, retrying in {}s",
+                            request.url,
+                            wait.as_secs_f32()
+                        );
+                        std::thread::sleep(wait);
+                        attempt += 1;
+                        continue;
+                    }
+                }
+                return parse(response);
+            }
+        }
+    }
+
+    // waits out a 429 for as long as the server's Retry-After asks and tries again
+    fn send(request: ehttp::Request, attempt: u32, on_done: Callback) {
+        let retry = request.clone();
+        ehttp::fetch(request, move |response| {
+            if let Ok(throttled) = &response {
+                if throttled.status == 429 && attempt < MAX_THROTTLE_RETRIES {
+                    let wait = retry_after(throttled);
+                    log::warn!(
+                        "Server is throttling {}, retrying in {}s",
+                        retry.url,
+                        wait.as_secs_f32()
+                    );
+                    // the callback runs on ehttp's own thread, sleeping here doesn't hold up a ui
+                    std::thread::sleep(wait);
+                    send(retry, attempt + 1, on_done);
+                    return;
+                }
+            }
+            on_done(response)
+        });
+    }
+
+    fn retry_after(response: &ehttp::Response) -> Duration {
+        response
+            .headers
+            .get("retry-after")
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map_or(Duration::from_secs(1), Duration::from_secs)
+            .min(MAX_RETRY_AFTER)
+    }
+
+    fn parse<T: FromResponse>(response: ehttp::Result<ehttp::Response>) -> Result<T> {
+        let response = response.map_err(|err| anyhow::anyhow!("Request failed: {err}"))?;
+        T::from_response(
+            Response {
+                status: response.status,
+                headers: response.headers.headers,
+                bytes: response.bytes,
+            }
+            .error_for_status()?,
+        )
+    }
+}
@@ -1,7 +1,9 @@
 pub use anyhow;
+pub mod api;
 use anyhow::Result;
 use base64::Engine as _;
 use rayon::prelude::*;
+pub use semver;
 use serde::{Deserialize, Serialize};
 use sha2::Digest;
 use std::{
@@ -12,16 +14,30 @@ use std::{
 use uuid::Uuid;
 
 pub const MAX_UPLOAD_SIZE: usize = 1024 * 1024 * 1024;
+// the client binary is the same for everyone, an invite hands its api key over as pitsu://invite/{code},
+// which the client redeems with the server on launch
+pub const INVITE_LINK_PREFIX: &str = "pitsu://invite/";
+
+pub fn invite_link(code: &str) -> String {
+    format!("{INVITE_LINK_PREFIX}{code}")
+}
+
+// the code out of an invite link, None for anything else that can be passed to --open
+pub fn invite_code(link: &str) -> Option<&str> {
+    let code = link.trim().strip_prefix(INVITE_LINK_PREFIX)?.trim_end_matches('/');
+    (!code.is_empty()).then_some(code)
+}
 
 lazy_static::lazy_static!(
     static ref ENGINE: base64::engine::GeneralPurpose = base64::engine::GeneralPurpose::new(
         &base64::alphabet::URL_SAFE,
         base64::engine::general_purpose::NO_PAD,
     );
-    static ref COMPRESSION: flate2::Compression = flate2::Compression::best();
 );
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[cfg_attr(feature = "openapi", schema(no_recursion))]
 #[serde(untagged)]
 pub enum File {
     Folder {
@@ -37,6 +53,7 @@ pub enum File {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct RootFolder {
     #[serde(default)]
     children: Vec<File>,
@@ -213,6 +230,19 @@ impl RootFolder {
         }
         Ok(current_folder)
     }
+    pub fn hash_of(&self, path: &str) -> Option<Arc<str>> {
+        let (folder, name) = path.rsplit_once('/').unwrap_or(("", path));
+        self.index_through(folder)
+            .ok()?
+            .children
+            .into_iter()
+            .find_map(|file| match file {
+                File::File {
+                    name: file_name, hash, ..
+                } if &*file_name == name => Some(hash),
+                _ => None,
+            })
+    }
     // pub fn iter_files<'a>(&'a self) -> FileIter<'a> {
     //     FileIter {
     //         stack: self.children.iter().collect(),
@@ -289,6 +319,221 @@ impl ChangeType {
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncAction {
+    pub action_type: ActionType,
+    pub full_path: Arc<str>,
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionType {
+    DeleteFromDisk,
+    DeleteFromRemote,
+    Upload,
+    Download,
+}
+
+impl ActionType {
+    pub fn is_transfer(&self) -> bool {
+        matches!(self, ActionType::Upload | ActionType::Download)
+    }
+    pub fn is_delete(&self) -> bool {
+        matches!(self, ActionType::DeleteFromDisk | ActionType::DeleteFromRemote)
+    }
+}
+
+impl std::fmt::Display for ActionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActionType::DeleteFromDisk => write!(f, "Delete from disk"),
+            ActionType::DeleteFromRemote => write!(f, "Delete from remote"),
+            ActionType::Upload => write!(f, "Upload"),
+            ActionType::Download => write!(f, "Download"),
+        }
+    }
+}
+
+// The full list of actions a sync would perform, computed without touching the disk or the server.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncPlan {
+    pub upload: bool,
+    pub actions: Vec<SyncAction>,
+    pub bytes_up: u64,
+    pub bytes_down: u64,
+    pub deleted: Vec<Arc<str>>,
+}
+
+impl SyncPlan {
+    // `diffs` should already have the relevant pitignore applied, `local` and `remote` are only used to look up sizes
+    pub fn new(diffs: &[Diff], upload: bool, local: &RootFolder, remote: &RootFolder) -> Self {
+        let local_sizes = local
+            .files()
+            .into_iter()
+            .map(|f| (f.full_path, f.size))
+            .collect::<std::collections::HashMap<_, _>>();
+        let remote_sizes = remote
+            .files()
+            .into_iter()
+            .map(|f| (f.full_path, f.size))
+            .collect::<std::collections::HashMap<_, _>>();
+        let mut actions = Vec::new();
+        for diff in diffs {
+            let action_type = match (diff.change_type, upload) {
+                (ChangeType::Modified, true) | (ChangeType::OnClient, true) => ActionType::Upload,
+                (ChangeType::Modified, false) | (ChangeType::OnServer, false) => ActionType::Download,
+                (ChangeType::OnServer, true) => ActionType::DeleteFromRemote,
+                (ChangeType::OnClient, false) => ActionType::DeleteFromDisk,
+            };
+            let sizes = match action_type {
+                ActionType::Upload | ActionType::DeleteFromDisk => &local_sizes,
+                ActionType::Download | ActionType::DeleteFromRemote => &remote_sizes,
+            };
+            actions.push(SyncAction {
+                action_type,
+                full_path: diff.full_path.clone(),
+                size: sizes.get(&diff.full_path).copied().unwrap_or(0),
+            });
+        }
+        let total = |action_type: ActionType| {
+            actions
+                .iter()
+                .filter(|a| a.action_type == action_type)
+                .map(|a| a.size)
+                .sum()
+        };
+        Self {
+            upload,
+            bytes_up: total(ActionType::Upload),
+            bytes_down: total(ActionType::Download),
+            deleted: actions
+                .iter()
+                .filter(|a| a.action_type.is_delete())
+                .map(|a| a.full_path.clone())
+                .collect(),
+            actions,
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+    pub fn transfer_count(&self) -> usize {
+        self.actions.iter().filter(|a| a.action_type.is_transfer()).count()
+    }
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "{} plan: {} action(s), {} up, {} down, {} deletion(s)\n",
+            if self.upload { "Upload" } else { "Download" },
+            self.actions.len(),
+            readable_size(self.bytes_up),
+            readable_size(self.bytes_down),
+            self.deleted.len(),
+        );
+        let width = self
+            .actions
+            .iter()
+            .map(|a| a.action_type.to_string().len())
+            .max()
+            .unwrap_or(0);
+        for action in &self.actions {
+            text.push_str(&format!(
+                "{:<width$}  {:>10}  {}\n",
+                action.action_type.to_string(),
+                readable_size(action.size),
+                action.full_path,
+            ));
+        }
+        text
+    }
+}
+
+pub const SIZES: &[&str] = &["B", "KB", "MB", "GB", "TB", "PB"];
+
+// the size scaled down to its unit, and the index of that unit in SIZES
+pub fn scale_size(bytes: u64) -> (f64, usize) {
+    let mut size = bytes as f64;
+    let mut index = 0;
+    while size >= 1024.0 && index < SIZES.len() - 1 {
+        size /= 1024.0;
+        index += 1;
+    }
+    (size, index)
+}
+
+pub fn readable_size(bytes: u64) -> String {
+    let (size, index) = scale_size(bytes);
+    if index == 0 {
+        format!("{bytes} {}", SIZES[index])
+    } else {
+        format!("{size:.2} {}", SIZES[index])
+    }
+}
+
+// expands %VAR%, $VAR, ${VAR} and a leading ~ in a repository's recommended path.
+// well known windows folders fall back to their closest equivalent on other platforms.
+pub fn resolve_path_template(template: &str) -> Result<PathBuf> {
+    let template = template.trim();
+    let mut resolved = String::new();
+    let mut rest = match template.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            resolved.push_str(&lookup_path_variable("HOME")?);
+            rest
+        }
+        _ => template,
+    };
+    while let Some(index) = rest.find(['%', '$']) {
+        resolved.push_str(&rest[..index]);
+        let after = &rest[index + 1..];
+        let (name, remaining) = if rest[index..].starts_with('%') {
+            let end = after
+                .find('%')
+                .ok_or_else(|| anyhow::anyhow!("Unterminated variable in {template}"))?;
+            (&after[..end], &after[end + 1..])
+        } else if let Some(braced) = after.strip_prefix('{') {
+            let end = braced
+                .find('}')
+                .ok_or_else(|| anyhow::anyhow!("Unterminated variable in {template}"))?;
+            (&braced[..end], &braced[end + 1..])
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], &after[end..])
+        };
+        if name.is_empty() {
+            return Err(anyhow::anyhow!("Empty variable in {template}"));
+        }
+        resolved.push_str(&lookup_path_variable(name)?);
+        rest = remaining;
+    }
+    resolved.push_str(rest);
+    if cfg!(windows) {
+        Ok(PathBuf::from(resolved))
+    } else {
+        Ok(PathBuf::from(resolved.replace('\\', "/")))
+    }
+}
+
+fn lookup_path_variable(name: &str) -> Result<String> {
+    if let Ok(value) = std::env::var(name).or_else(|_| std::env::var(name.to_uppercase())) {
+        return Ok(value);
+    }
+    let fallback = match name.to_lowercase().as_str() {
+        "localappdata" => dirs::data_local_dir(),
+        "appdata" => dirs::config_dir(),
+        "home" | "userprofile" => dirs::home_dir(),
+        "documents" => dirs::document_dir(),
+        "temp" | "tmp" => Some(std::env::temp_dir()),
+        _ => None,
+    };
+    fallback
+        .map(|path| path.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow::anyhow!("Unknown variable {name}"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -345,9 +590,310 @@ mod tests {
         println!("No differences found as expected.");
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_sync_plan() -> Result<()> {
+        let local_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .ok_or(anyhow::anyhow!("Failed to get parent directory"))?
+            .join("local");
+        let remote_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .ok_or(anyhow::anyhow!("Failed to get parent directory"))?
+            .join("remote");
+        let local_folder = RootFolder::ingest_folder(&local_path)?;
+        let remote_folder = RootFolder::ingest_folder(&remote_path)?;
+        let diffs = local_folder.diff(&remote_folder);
+
+        let upload = SyncPlan::new(&diffs, true, &local_folder, &remote_folder);
+        assert_eq!(upload.actions.len(), diffs.len());
+        assert_eq!(upload.bytes_down, 0, "Upload plan should not download anything");
+        assert_eq!(
+            upload.deleted.len(),
+            diffs.iter().filter(|d| d.change_type == ChangeType::OnServer).count()
+        );
+        assert_ne!(upload.bytes_up, 0, "Expected some bytes to upload");
+        assert_eq!(
+            upload.bytes_up,
+            upload
+                .actions
+                .iter()
+                .filter(|a| a.action_type == ActionType::Upload)
+                .map(|a| a.size)
+                .sum::<u64>()
+        );
+
+        let download = SyncPlan::new(&diffs, false, &local_folder, &remote_folder);
+        assert_eq!(download.bytes_up, 0, "Download plan should not upload anything");
+        assert_eq!(
+            download.deleted.len(),
+            diffs.iter().filter(|d| d.change_type == ChangeType::OnClient).count()
+        );
+        assert_eq!(
+            download.bytes_down,
+            download
+                .actions
+                .iter()
+                .filter(|a| a.action_type == ActionType::Download)
+                .map(|a| a.size)
+                .sum::<u64>()
+        );
+
+        let json = upload.to_json()?;
+        let parsed: SyncPlan = serde_json::from_str(&json)?;
+        assert_eq!(parsed.actions.len(), upload.actions.len());
+        assert_eq!(upload.to_text().lines().count(), upload.actions.len() + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pitignore_overrides() -> Result<()> {
+        let mut overrides = Pitignore::parse("# personal\nmods/*\n");
+        overrides.add_pattern("saves/slot1.sav")?;
+        overrides.add_pattern("mods/*")?;
+        assert_eq!(overrides.patterns.len(), 2, "Duplicate patterns should not be added");
+        assert!(overrides.is_ignored("mods/big/file.bin"));
+        assert!(overrides.is_ignored("saves/slot1.sav"));
+        assert!(!overrides.is_ignored("saves/slot2.sav"));
+        assert!(!overrides.is_ignored(".pitignore"));
+
+        // literal patterns only match themselves, whatever characters the name has
+        let mut literal = Pitignore::default();
+        literal.add_pattern("=data")?;
+        literal.add_pattern("=#notes*.txt")?;
+        literal.add_pattern("=!old/")?;
+        assert!(literal.is_ignored("data"));
+        assert!(!literal.is_ignored("data2.bin"));
+        assert!(!literal.is_ignored("data/inner.txt"));
+        assert!(literal.is_ignored("#notes*.txt"));
+        assert!(!literal.is_ignored("#notes-2.txt"));
+        assert!(literal.is_ignored("!old/save.sav"));
+        assert!(!literal.is_ignored("!older/save.sav"));
+
+        let diffs: Arc<[Diff]> = Arc::from(vec![
+            Diff {
+                full_path: "mods/big/file.bin".into(),
+                change_type: ChangeType::OnServer,
+            },
+            Diff {
+                full_path: "readme.txt".into(),
+                change_type: ChangeType::Modified,
+            },
+        ]);
+        assert_eq!(overrides.apply_patterns(&diffs).len(), 1);
+
+        assert!(overrides.remove_pattern("mods/*", false));
+        assert!(!overrides.remove_pattern("mods/*", false));
+        assert!(!overrides.is_ignored("mods/big/file.bin"));
+        assert!(
+            overrides.add_pattern("*a*b").is_err(),
+            "Multiple wildcards are not supported"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pitexecute() -> Result<()> {
+        let remote_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .ok_or(anyhow::anyhow!("Failed to get parent directory"))?
+            .join("remote");
+        let folder = RootFolder::ingest_folder(&remote_path)?;
+        let pitexecute = Pitexecute::parse(
+            "# tools\nqueries/*.sql | --verbose \"two words\" | src\n!queries/user.sql\nCargo.toml\nsrc/* | | ../..\n",
+        );
+        assert_eq!(
+            pitexecute.entries.len(),
+            3,
+            "Comments and escaping working directories are dropped"
+        );
+
+        let targets = pitexecute.launch_targets(&folder);
+        assert!(targets.iter().all(|t| &*t.full_path != "queries/user.sql"));
+        let access = targets
+            .iter()
+            .find(|t| &*t.full_path == "queries/access.sql")
+            .ok_or(anyhow::anyhow!("Missing queries/access.sql"))?;
+        assert_eq!(&*access.arguments, ["--verbose", "two words"]);
+        assert_eq!(&*access.working_directory, "src");
+        let cargo = targets
+            .iter()
+            .find(|t| &*t.full_path == "Cargo.toml")
+            .ok_or(anyhow::anyhow!("Missing Cargo.toml"))?;
+        assert!(cargo.arguments.is_empty());
+        assert_eq!(&*cargo.working_directory, "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve_path_template() -> Result<()> {
+        std::env::set_var("PITSU_TEST_ROOT", "/games");
+        let expected = PathBuf::from("/games/me3/profiles");
+        assert_eq!(resolve_path_template("%PITSU_TEST_ROOT%/me3/profiles")?, expected);
+        assert_eq!(resolve_path_template("${PITSU_TEST_ROOT}/me3/profiles")?, expected);
+        assert_eq!(resolve_path_template("$PITSU_TEST_ROOT/me3/profiles")?, expected);
+        if !cfg!(windows) {
+            assert_eq!(resolve_path_template("%PITSU_TEST_ROOT%\\me3\\profiles")?, expected);
+        }
+        let home = dirs::home_dir().ok_or(anyhow::anyhow!("No home directory"))?;
+        assert_eq!(
+            resolve_path_template("~/mods")?,
+            PathBuf::from(format!("{}/mods", home.display()))
+        );
+        assert!(resolve_path_template("%localappdata%/mods").is_ok());
+        assert!(resolve_path_template("%PITSU_TEST_ROOT/mods").is_err());
+        assert!(resolve_path_template("%PITSU_NOT_A_REAL_VARIABLE%/mods").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_version_number() -> Result<()> {
+        let client_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .parent()
+            .ok_or(anyhow::anyhow!("Failed to get parent directory"))?
+            .join("local");
+        let client = VersionNumber::new(&client_path)?;
+        assert_eq!(VersionNumber::from_artifact_name(&client.artifact_name()), Some(client));
+
+        let stable = VersionNumber::parse("1.2.0", "a")?;
+        let beta = VersionNumber::parse("1.3.0-beta.2", "a")?;
+        let dev = VersionNumber::parse("1.3.0-dev.7", "a")?;
+        assert!(beta.is_prerelease());
+        assert_eq!(beta.to_string(), "1.3.0-beta.2");
+        assert!(stable < beta && stable < dev);
+        assert!(beta < VersionNumber::parse("1.3.0", "a")?);
+        assert_ne!(stable, VersionNumber::parse("1.2.0", "b")?);
+        assert!(VersionNumber::parse("1.2", "a").is_err());
+
+        assert!(UpdateChannel::Stable.includes(&stable) && !UpdateChannel::Stable.includes(&beta));
+        assert!(UpdateChannel::Beta.includes(&beta) && !UpdateChannel::Beta.includes(&dev));
+        assert!(UpdateChannel::Dev.includes(&dev));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_changelog() {
+        let changelog = parse_changelog(
+            "# Changelog\n\n## 1.3.0-beta.1\n- beta things\n\n## Unreleased\n- ignored\n## v1.2.0\n- first\n- second\n",
+        );
+        let versions = changelog
+            .iter()
+            .map(|(version, entry)| (version.to_string(), entry.notes.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            versions,
+            vec![
+                ("1.3.0-beta.1".to_string(), "- beta things".to_string()),
+                ("1.2.0".to_string(), "- first\n- second".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_transfer_encoding() -> Result<()> {
+        let text = "pitsu ".repeat(1000).into_bytes();
+        for encoding in [TransferEncoding::Gzip, TransferEncoding::Zstd] {
+            let mut file = UploadFile::new("notes.txt".into(), text.clone(), encoding, encoding.default_level())?;
+            assert_eq!(file.encoding(), encoding);
+            assert_eq!(&*file.get_bytes()?, &text[..]);
+        }
+
+        // pseudo random bytes shouldn't be compressed, neither should known archive formats
+        let mut state = 0x2545f4914f6cdd1du64;
+        let noise = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect::<Vec<_>>();
+        let mut file = UploadFile::new("noise.bin".into(), noise.clone(), TransferEncoding::Zstd, 3)?;
+        assert_eq!(file.encoding(), TransferEncoding::None);
+        assert_eq!(&*file.get_bytes()?, &noise[..]);
+        assert_eq!(
+            TransferEncoding::Zstd.for_file("a/b/Video.BK2", &text),
+            TransferEncoding::None
+        );
+        assert_eq!(
+            TransferEncoding::Zstd.for_path("textures/rock.dds"),
+            TransferEncoding::None
+        );
+
+        // uploads from older clients have no encoding field and are gzip
+        let legacy: UploadFile = serde_json::from_str(r#"{"path":"a","bytes":[]}"#)?;
+        assert_eq!(legacy.encoding(), TransferEncoding::Gzip);
+
+        assert_eq!(TransferEncoding::Gzip.accept_header(), "gzip, zstd, none");
+        assert_eq!(
+            TransferEncoding::from_accept_header("br, zstd, gzip"),
+            Some(TransferEncoding::Zstd)
+        );
+        assert_eq!(TransferEncoding::from_accept_header("br"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_endpoint_path() -> Result<()> {
+        let download = api::DownloadFile {
+            uuid: Uuid::nil(),
+            path: "/a/b.txt".into(),
+            accept: TransferEncoding::Zstd,
+            range: Some(10..20),
+        };
+        assert_eq!(api::Endpoint::path(&download), format!("/{}/a/b.txt", Uuid::nil()));
+        assert!(api::Endpoint::headers(&download).contains(&("Range", "bytes=10-19".to_string())));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invite_link() {
+        let code = encode_string_base64("pitsu|code");
+        assert_eq!(invite_code(&invite_link(&code)), Some(code.as_str()));
+        // browsers like to add a trailing slash
+        assert_eq!(invite_code(&format!("{}/", invite_link(&code))), Some(code.as_str()));
+        assert_eq!(invite_code(INVITE_LINK_PREFIX), None);
+        // repository links go to --open too
+        assert_eq!(invite_code(&format!("pitsu://{}", Uuid::nil())), None);
+    }
+
+    #[test]
+    fn test_api_error() -> Result<()> {
+        let error =
+            ApiError::new(ErrorCode::NotFound, "File not found").with_details(serde_json::json!({ "path": "a/b.txt" }));
+        let body = serde_json::to_vec(&error)?;
+        assert_eq!(ApiError::from_response(404, &body), error);
+        assert_eq!(
+            error.to_string(),
+            "File not found. It may have been renamed or deleted, refresh and try again"
+        );
+
+        // details are left out when empty, codes this build doesn't know still parse
+        let bare = serde_json::to_string(&ApiError::new(ErrorCode::InvalidRequest, "Invalid version"))?;
+        assert_eq!(bare, r#"{"code":"invalid_request","message":"Invalid version"}"#);
+        let newer = ApiError::from_response(500, br#"{"code":"quota_exceeded","message":"Out of space"}"#);
+        assert_eq!(newer.code, ErrorCode::Unknown);
+        assert_eq!(&*newer.message, "Out of space");
+
+        // plain text from older servers or proxies falls back to the status
+        let legacy = ApiError::from_response(403, b"Access denied");
+        assert_eq!(legacy.code, ErrorCode::Forbidden);
+        assert_eq!(&*legacy.message, "Access denied");
+        assert_eq!(&*ApiError::from_response(502, b"").message, "HTTP 502");
+        for code in [
+            ErrorCode::RateLimited,
+            ErrorCode::Unavailable,
+            ErrorCode::PayloadTooLarge,
+            ErrorCode::Unauthorized,
+        ] {
+            assert_eq!(ErrorCode::from_status(code.status()), code);
+        }
+        Ok(())
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct RemoteRepository {
     // included in simple
     pub uuid: Uuid,
@@ -359,14 +905,22 @@ pub struct RemoteRepository {
     pub files: RootFolder,
     pub users: Vec<UserWithAccess>,
     pub pitignore: Pitignore,
+    // unresolved template, see resolve_path_template
+    #[serde(default)]
+    pub recommended_path: Option<Arc<str>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct UpdateRemoteRepository {
     pub name: Arc<str>,
+    // None keeps the current template, an empty one clears it
+    #[serde(default)]
+    pub recommended_path: Option<Arc<str>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct SimpleRemoteRepository {
     pub uuid: Uuid,
     pub name: Arc<str>,
@@ -376,12 +930,14 @@ pub struct SimpleRemoteRepository {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct User {
     pub uuid: Uuid,
     pub username: Arc<str>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct ThisUser {
     pub user: User,
     pub owned_repositories: Vec<SimpleRemoteRepository>,
@@ -389,6 +945,7 @@ pub struct ThisUser {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub enum AccessLevel {
     None = 0,
     Read = 1,
@@ -446,6 +1003,7 @@ impl std::cmp::PartialOrd for AccessLevel {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct UserWithAccess {
     #[serde(flatten)]
     pub user: User,
@@ -453,43 +1011,260 @@ pub struct UserWithAccess {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct SetAccess {
     pub user: Uuid,
     pub access_level: AccessLevel,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct CreateRemoteRepository {
     pub name: Arc<str>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct InviteCode {
+    pub code: Arc<str>,
+}
+
+// body of every error response from the server
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: Arc<str>,
+    // extra context depending on the code, e.g. {"path": ...} for not_found or {"retry_after": 3} for rate_limited
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
+    #[cfg_attr(feature = "openapi", schema(value_type = Object))]
+    pub details: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    // missing or unknown api key
+    Unauthorized,
+    // known user without enough access
+    Forbidden,
+    NotFound,
+    InvalidRequest,
+    InvalidArchive,
+    InvalidInviteCode,
+    PayloadTooLarge,
+    RateLimited,
+    // not ready yet, like a client that hasn't been built
+    Unavailable,
+    Database,
+    Storage,
+    Internal,
+    // a code from a newer server
+    #[serde(other)]
+    Unknown,
+}
+
+impl ErrorCode {
+    pub fn status(&self) -> u16 {
+        match self {
+            ErrorCode::Unauthorized => 401,
+            ErrorCode::Forbidden => 403,
+            ErrorCode::NotFound => 404,
+            ErrorCode::InvalidRequest | ErrorCode::InvalidArchive | ErrorCode::InvalidInviteCode => 400,
+            ErrorCode::PayloadTooLarge => 413,
+            ErrorCode::RateLimited => 429,
+            ErrorCode::Unavailable => 503,
+            ErrorCode::Database | ErrorCode::Storage | ErrorCode::Internal | ErrorCode::Unknown => 500,
+        }
+    }
+    // for bodies that aren't an ApiError, like ones from a proxy in front of the server
+    pub fn from_status(status: u16) -> Self {
+        match status {
+            401 => ErrorCode::Unauthorized,
+            403 => ErrorCode::Forbidden,
+            404 => ErrorCode::NotFound,
+            413 => ErrorCode::PayloadTooLarge,
+            429 => ErrorCode::RateLimited,
+            503 => ErrorCode::Unavailable,
+            400..=499 => ErrorCode::InvalidRequest,
+            _ => ErrorCode::Unknown,
+        }
+    }
+    // what the user can do about it
+    pub fn hint(&self) -> Option<&'static str> {
+        Some(match self {
+            ErrorCode::Unauthorized => "Check the API key in Settings or ask for a new invite",
+            ErrorCode::Forbidden => "Ask the repository owner for access",
+            ErrorCode::NotFound => "It may have been renamed or deleted, refresh and try again",
+            ErrorCode::InvalidArchive => "Check that the file is a valid zip or tar.gz",
+            ErrorCode::InvalidInviteCode => "Check that the whole invite code was copied",
+            ErrorCode::PayloadTooLarge => "Sync fewer files at once or add large files to .pitignore",
+            ErrorCode::RateLimited => "The server is busy, try again in a moment",
+            ErrorCode::Unavailable => "The server is still preparing this, try again later",
+            ErrorCode::Database | ErrorCode::Storage | ErrorCode::Internal => {
+                "This is a problem on the server, try again later or let the server admin know"
+            }
+            ErrorCode::InvalidRequest | ErrorCode::Unknown => return None,
+        })
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let code = match self {
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::InvalidArchive => "invalid_archive",
+            ErrorCode::InvalidInviteCode => "invalid_invite_code",
+            ErrorCode::PayloadTooLarge => "payload_too_large",
+            ErrorCode::RateLimited => "rate_limited",
+            ErrorCode::Unavailable => "unavailable",
+            ErrorCode::Database => "database",
+            ErrorCode::Storage => "storage",
+            ErrorCode::Internal => "internal",
+            ErrorCode::Unknown => "unknown",
+        };
+        write!(f, "{code}")
+    }
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<Arc<str>>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: serde_json::Value::Null,
+        }
+    }
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = details;
+        self
+    }
+    pub fn status(&self) -> u16 {
+        self.code.status()
+    }
+    // anything that isn't json from this server still becomes an ApiError, coded from the status
+    pub fn from_response(status: u16, body: &[u8]) -> Self {
+        if let Ok(error) = serde_json::from_slice::<ApiError>(body) {
+            return error;
+        }
+        let text = String::from_utf8_lossy(body);
+        let text = text.trim();
+        Self::new(
+            ErrorCode::from_status(status),
+            if text.is_empty() || text.len() > 200 {
+                format!("HTTP {status}")
+            } else {
+                text.to_string()
+            },
+        )
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.code.hint() {
+            Some(hint) => write!(f, "{}. {hint}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar.gz")]
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "application/zip",
+            ArchiveFormat::TarGz => "application/gzip",
+        }
+    }
+}
+
+impl std::fmt::Display for ArchiveFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct FileUpload {
     pub files: Vec<UploadFile>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct UploadFile {
     pub path: Arc<str>,
+    // clients from before encoding negotiation always gzipped
+    #[serde(default = "TransferEncoding::legacy")]
+    encoding: TransferEncoding,
+    #[cfg_attr(feature = "openapi", schema(value_type = Vec<u8>))]
     bytes: Arc<[u8]>,
     #[serde(skip)]
     decoded: Option<Arc<[u8]>>,
+    // set when this is one piece of a file too large to send in one request at the client's bandwidth limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub part: Option<FilePart>,
+}
+
+// where a piece goes in the whole file, the server holds pieces back until `total` bytes have arrived
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct FilePart {
+    pub offset: u64,
+    pub total: u64,
 }
 
 impl UploadFile {
-    pub fn new(path: Arc<str>, raw_bytes: Vec<u8>) -> Result<Self> {
-        let bytes = compress(&raw_bytes)?;
+    pub fn new(path: Arc<str>, raw_bytes: Vec<u8>, encoding: TransferEncoding, level: i32) -> Result<Self> {
+        let mut encoding = encoding.for_file(&path, &raw_bytes);
+        let bytes: Arc<[u8]> = match encoding.encode(&raw_bytes, level)? {
+            Some(compressed) if compressed.len() < raw_bytes.len() => compressed.into(),
+            _ => {
+                encoding = TransferEncoding::None;
+                raw_bytes.into()
+            }
+        };
         Ok(Self {
             path,
+            encoding,
             bytes,
             decoded: None,
+            part: None,
         })
     }
+    pub fn encoding(&self) -> TransferEncoding {
+        self.encoding
+    }
     pub fn get_bytes(&mut self) -> Result<Arc<[u8]>> {
         if let Some(decoded) = &self.decoded {
             return Ok(decoded.clone());
         }
-        let decoded = decompress(&self.bytes)?;
+        let decoded: Arc<[u8]> = match self.encoding {
+            TransferEncoding::None => self.bytes.clone(),
+            encoding => encoding.decode(&self.bytes)?.into(),
+        };
         self.decoded = Some(decoded.clone());
         Ok(decoded)
     }
@@ -497,25 +1272,163 @@ impl UploadFile {
         let bytes = self.bytes.len();
         let decoded = self.decoded.as_ref().map_or(0, |d| d.len());
         let path = self.path.len();
-        r#"{"path": "",bytes: ""}"#.len() + bytes + decoded + path
+        r#"{"path": "",encoding: "",bytes: ""}"#.len() + bytes + decoded + path
+    }
+}
+
+// sent by the client with the encodings it can decode, most preferred first
+pub const ACCEPT_ENCODING_HEADER: &str = "x-pitsu-accept-encoding";
+// set on a download response when the body is encoded
+pub const ENCODING_HEADER: &str = "x-pitsu-encoding";
+// base64 ed25519 signature of the served bytes, checked by the client before it replaces itself
+pub const SIGNATURE_HEADER: &str = "x-pitsu-signature";
+
+// formats that are already compressed, running them through gzip or zstd only burns time
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "zip", "7z", "rar", "gz", "tgz", "xz", "zst", "bz2", "png", "jpg", "jpeg", "webp", "gif", "mp3", "ogg", "opus",
+    "flac", "mp4", "mkv", "webm", "bk2", "usm", "ktx2", "dds",
+];
+// bits per byte above which a sample is assumed to be incompressible
+const ENTROPY_THRESHOLD: f64 = 7.5;
+const ENTROPY_SAMPLE_SIZE: usize = 64 * 1024;
+// smaller than this and the header overhead eats any gain
+const MIN_COMPRESS_SIZE: usize = 256;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum TransferEncoding {
+    None,
+    Gzip,
+    #[default]
+    Zstd,
+}
+
+impl std::fmt::Display for TransferEncoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransferEncoding::None => write!(f, "none"),
+            TransferEncoding::Gzip => write!(f, "gzip"),
+            TransferEncoding::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl std::str::FromStr for TransferEncoding {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "none" | "identity" => Ok(TransferEncoding::None),
+            "gzip" => Ok(TransferEncoding::Gzip),
+            "zstd" => Ok(TransferEncoding::Zstd),
+            other => Err(anyhow::anyhow!("Unknown transfer encoding: {other}")),
+        }
     }
 }
 
-fn compress(data: &[u8]) -> Result<Arc<[u8]>> {
-    let mut compressed_data = Vec::new();
-    let mut encoder = flate2::write::GzEncoder::new(&mut compressed_data, *COMPRESSION);
-    encoder.write_all(data)?;
-    encoder.finish()?;
-    Ok(compressed_data.into())
+impl TransferEncoding {
+    pub const ALL: [TransferEncoding; 3] = [TransferEncoding::None, TransferEncoding::Gzip, TransferEncoding::Zstd];
+    fn legacy() -> Self {
+        TransferEncoding::Gzip
+    }
+    pub fn levels(&self) -> std::ops::RangeInclusive<i32> {
+        match self {
+            TransferEncoding::None => 0..=0,
+            TransferEncoding::Gzip => 1..=9,
+            TransferEncoding::Zstd => 1..=19,
+        }
+    }
+    pub fn default_level(&self) -> i32 {
+        match self {
+            TransferEncoding::None => 0,
+            TransferEncoding::Gzip => 6,
+            TransferEncoding::Zstd => 3,
+        }
+    }
+    // value for ACCEPT_ENCODING_HEADER, this encoding first then whatever else we can decode
+    pub fn accept_header(&self) -> String {
+        std::iter::once(*self)
+            .chain(TransferEncoding::ALL.into_iter().rev().filter(|e| e != self))
+            .map(|e| e.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+    // first encoding in the list we know about, unknown entries are skipped
+    pub fn from_accept_header(header: &str) -> Option<Self> {
+        header.split(',').find_map(|e| e.parse().ok())
+    }
+    // cheap check on the extension alone, for when reading the file isn't worth it yet
+    pub fn for_path(self, path: &str) -> Self {
+        let extension = path.rsplit_once('.').map(|(_, ext)| ext.to_ascii_lowercase());
+        if extension.is_some_and(|ext| COMPRESSED_EXTENSIONS.contains(&ext.as_str())) {
+            return TransferEncoding::None;
+        }
+        self
+    }
+    // downgrades to None for files that won't get any smaller
+    pub fn for_file(self, path: &str, data: &[u8]) -> Self {
+        if self.for_path(path) == TransferEncoding::None || data.len() < MIN_COMPRESS_SIZE {
+            return TransferEncoding::None;
+        }
+        if entropy(&data[..data.len().min(ENTROPY_SAMPLE_SIZE)]) > ENTROPY_THRESHOLD {
+            return TransferEncoding::None;
+        }
+        self
+    }
+    // None for TransferEncoding::None, the caller should send the bytes as is
+    pub fn encode(&self, data: &[u8], level: i32) -> Result<Option<Vec<u8>>> {
+        let level = level.clamp(*self.levels().start(), *self.levels().end());
+        match self {
+            TransferEncoding::None => Ok(None),
+            TransferEncoding::Gzip => {
+                let mut compressed_data = Vec::new();
+                let mut encoder =
+                    flate2::write::GzEncoder::new(&mut compressed_data, flate2::Compression::new(level as u32));
+                encoder.write_all(data)?;
+                encoder.finish()?;
+                Ok(Some(compressed_data))
+            }
+            TransferEncoding::Zstd => zstd::encode_all(data, level)
+                .map(Some)
+                .map_err(|e| anyhow::anyhow!("Failed to compress data: {}", e)),
+        }
+    }
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            TransferEncoding::None => Ok(data.to_vec()),
+            TransferEncoding::Gzip => {
+                let mut decoder = flate2::read::GzDecoder::new(data);
+                let mut decompressed_data = Vec::new();
+                decoder
+                    .read_to_end(&mut decompressed_data)
+                    .map_err(|e| anyhow::anyhow!("Failed to decompress data: {}", e))?;
+                Ok(decompressed_data)
+            }
+            TransferEncoding::Zstd => {
+                zstd::decode_all(data).map_err(|e| anyhow::anyhow!("Failed to decompress data: {}", e))
+            }
+        }
+    }
 }
 
-fn decompress(compressed: &Arc<[u8]>) -> Result<Arc<[u8]>> {
-    let mut decoder = flate2::read::GzDecoder::new(&compressed[..]);
-    let mut decompressed_data = Vec::new();
-    decoder
-        .read_to_end(&mut decompressed_data)
-        .map_err(|e| anyhow::anyhow!("Failed to decompress data: {}", e))?;
-    Ok(decompressed_data.into())
+// shannon entropy in bits per byte, 8.0 is indistinguishable from random
+fn entropy(data: &[u8]) -> f64 {
+    if data.is_empty() {
+        return 0.0;
+    }
+    let mut counts = [0usize; 256];
+    for byte in data {
+        counts[*byte as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|c| **c > 0)
+        .map(|c| {
+            let p = *c as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
 }
 
 pub fn encode_base64(data: &[u8]) -> String {
@@ -538,16 +1451,20 @@ pub fn decode_string_base64(data: &str) -> Result<String> {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct VersionNumber {
     pub major: u32,
     pub minor: u32,
     pub patch: u32,
+    // semver pre-release tag ("beta.2"), empty for a release
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub pre: String,
     pub folder_hash: String,
 }
 
 impl std::fmt::Display for VersionNumber {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+        write!(f, "{}", self.semver())
     }
 }
 
@@ -557,30 +1474,18 @@ impl VersionNumber {
         if !cargo_toml_path.exists() {
             return Err(anyhow::anyhow!("Cargo.toml not found"));
         }
-        let mut file = std::fs::File::open(cargo_toml_path)?;
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)?;
-        let mut major = 0;
-        let mut minor = 0;
-        let mut patch = 0;
-        for line in contents.lines() {
-            if line.starts_with("version = ") {
-                let version = line
-                    .split('=')
-                    .nth(1)
-                    .ok_or(anyhow::anyhow!("Failed to parse version line in Cargo.toml"))?
-                    .trim();
-                let parts: Vec<&str> = version.split('.').collect();
-                if parts.len() >= 3 {
-                    major = parts[0].trim_matches('"').parse()?;
-                    minor = parts[1].trim_matches('"').parse()?;
-                    patch = parts[2].trim_matches('"').parse()?;
-                }
-            }
-        }
-        if major == 0 && minor == 0 && patch == 0 {
-            return Err(anyhow::anyhow!("Version not found in Cargo.toml"));
-        }
+        let contents = std::fs::read_to_string(cargo_toml_path)?;
+        // the first version key inside [package], dependency tables come later
+        let version = contents
+            .lines()
+            .skip_while(|line| line.trim() != "[package]")
+            .skip(1)
+            .take_while(|line| !line.trim_start().starts_with('['))
+            .find_map(|line| {
+                let (key, value) = line.split_once('=')?;
+                (key.trim() == "version").then(|| value.trim().trim_matches('"'))
+            })
+            .ok_or(anyhow::anyhow!("Version not found in Cargo.toml"))?;
         // Get a hash of the folder contents
         let mut hasher = sha2::Sha256::new();
         for entry in std::fs::read_dir(path)? {
@@ -592,38 +1497,48 @@ impl VersionNumber {
                 hasher.update(&buffer);
             }
         }
-        let folder_hash = hasher.finalize().to_vec();
-        let folder_hash = base64::engine::general_purpose::STANDARD.encode(folder_hash);
+        // url safe so it can be used in artifact paths
+        let folder_hash = encode_base64(&hasher.finalize());
+        Self::parse(version, &folder_hash)
+    }
+    pub fn parse(version: &str, folder_hash: &str) -> Result<Self> {
+        let version = semver::Version::parse(version).map_err(|e| anyhow::anyhow!("Invalid version {version}: {e}"))?;
         Ok(VersionNumber {
-            major,
-            minor,
-            patch,
-            folder_hash,
+            major: version.major.try_into()?,
+            minor: version.minor.try_into()?,
+            patch: version.patch.try_into()?,
+            pre: version.pre.to_string(),
+            folder_hash: folder_hash.to_string(),
         })
     }
-    pub fn is_dev(&self) -> bool {
-        self.folder_hash == "dev"
+    pub fn semver(&self) -> semver::Version {
+        semver::Version {
+            major: self.major.into(),
+            minor: self.minor.into(),
+            patch: self.patch.into(),
+            pre: semver::Prerelease::new(&self.pre).unwrap_or(semver::Prerelease::EMPTY),
+            build: semver::BuildMetadata::EMPTY,
+        }
+    }
+    pub fn is_prerelease(&self) -> bool {
+        !self.pre.is_empty()
+    }
+    // folder name in the server's artifact store, "{version}+{folder hash}"
+    pub fn artifact_name(&self) -> String {
+        format!("{self}+{}", self.folder_hash)
+    }
+    pub fn from_artifact_name(name: &str) -> Option<Self> {
+        let (version, folder_hash) = name.split_once('+')?;
+        Self::parse(version, folder_hash).ok()
     }
 }
 
+// semver precedence first, builds of the same version are only told apart by their folder hash
 impl std::cmp::Ord for VersionNumber {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        if self.major > other.major {
-            return std::cmp::Ordering::Greater;
-        } else if self.major < other.major {
-            return std::cmp::Ordering::Less;
-        }
-        if self.minor > other.minor {
-            return std::cmp::Ordering::Greater;
-        } else if self.minor < other.minor {
-            return std::cmp::Ordering::Less;
-        }
-        if self.patch > other.patch {
-            return std::cmp::Ordering::Greater;
-        } else if self.patch < other.patch {
-            return std::cmp::Ordering::Less;
-        }
-        std::cmp::Ordering::Equal
+        self.semver()
+            .cmp(&other.semver())
+            .then_with(|| self.folder_hash.cmp(&other.folder_hash))
     }
 }
 
@@ -637,23 +1552,180 @@ impl Eq for VersionNumber {}
 
 impl PartialEq for VersionNumber {
     fn eq(&self, other: &Self) -> bool {
-        self.major == other.major && self.minor == other.minor && self.patch == other.patch
+        self.cmp(other).is_eq()
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateChannel {
+    #[default]
+    Stable,
+    Beta,
+    Dev,
+}
+
+impl std::fmt::Display for UpdateChannel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateChannel::Stable => write!(f, "stable"),
+            UpdateChannel::Beta => write!(f, "beta"),
+            UpdateChannel::Dev => write!(f, "dev"),
+        }
+    }
+}
+
+impl UpdateChannel {
+    pub const ALL: [UpdateChannel; 3] = [UpdateChannel::Stable, UpdateChannel::Beta, UpdateChannel::Dev];
+    // stable only gets releases, beta also gets beta.N and rc.N, dev gets every build
+    pub fn includes(&self, version: &VersionNumber) -> bool {
+        match self {
+            UpdateChannel::Stable => !version.is_prerelease(),
+            UpdateChannel::Beta => {
+                !version.is_prerelease() || version.pre.starts_with("beta") || version.pre.starts_with("rc")
+            }
+            UpdateChannel::Dev => true,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ChangelogEntry {
+    pub version: Arc<str>,
+    pub notes: Arc<str>,
+}
+
+// "## 1.2.3" starts a section, everything until the next heading is its notes
+pub fn parse_changelog(contents: &str) -> Vec<(semver::Version, ChangelogEntry)> {
+    let mut entries = Vec::new();
+    let mut current: Option<(semver::Version, String)> = None;
+    for line in contents.lines() {
+        if let Some(heading) = line.strip_prefix("## ") {
+            if let Some((version, notes)) = current.take() {
+                entries.push((version, notes));
+            }
+            current = semver::Version::parse(heading.trim().trim_start_matches('v'))
+                .ok()
+                .map(|version| (version, String::new()));
+        } else if let Some((_, notes)) = &mut current {
+            notes.push_str(line);
+            notes.push('\n');
+        }
+    }
+    entries.extend(current);
+    entries
+        .into_iter()
+        .map(|(version, notes)| {
+            let entry = ChangelogEntry {
+                version: version.to_string().into(),
+                notes: notes.trim().into(),
+            };
+            (version, entry)
+        })
+        .collect()
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Pitignore {
+    // [line, pattern] pairs
+    #[cfg_attr(feature = "openapi", schema(value_type = Vec<Vec<Object>>))]
     pub patterns: Vec<(usize, PitignorePattern)>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct PitignorePattern {
     pub pattern: String,
     starts_with: Option<Arc<str>>,
     ends_with: Option<Arc<str>>,
+    // `=path` matches that one path, `=folder/` that folder and everything in it. no wildcards or comments inside
+    #[serde(default)]
+    literal: Option<Arc<str>>,
     pub negated: bool,
 }
 
+impl PitignorePattern {
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let negated = line.starts_with('!');
+        let pattern = if negated { &line[1..] } else { line };
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return None;
+        }
+        if let Some(literal) = pattern.strip_prefix('=') {
+            let literal = literal.trim_start_matches('/');
+            if literal.is_empty() {
+                return None;
+            }
+            return Some(PitignorePattern {
+                pattern: pattern.into(),
+                starts_with: None,
+                ends_with: None,
+                literal: Some(literal.into()),
+                negated,
+            });
+        }
+        let mut split = pattern.split('*');
+        let mut starts_with = None;
+        let mut ends_with = None;
+        if let Some(first) = split.next() {
+            if !first.is_empty() {
+                starts_with = Some(first.trim().into());
+            }
+        }
+        if let Some(last) = split.next() {
+            if !last.is_empty() {
+                ends_with = Some(last.trim().into());
+            }
+        }
+        if split.next().is_some() {
+            // If there are more than one split, it means there are multiple wildcards
+            // which is not supported in this implementation.
+            return None;
+        }
+        if starts_with.is_none() && ends_with.is_none() {
+            // There is no pattern at all
+            return None;
+        }
+        Some(PitignorePattern {
+            pattern: pattern.into(),
+            starts_with,
+            ends_with,
+            literal: None,
+            negated,
+        })
+    }
+    // ignores negation, callers decide what a match means
+    pub fn matches(&self, path: &str) -> bool {
+        let path = path.trim_start_matches("/");
+        if let Some(literal) = &self.literal {
+            return if literal.ends_with('/') {
+                path.starts_with(&**literal)
+            } else {
+                path == &**literal
+            };
+        }
+        if let Some(starts_with) = &self.starts_with {
+            if !path.starts_with(starts_with.trim_start_matches("/")) {
+                return false;
+            }
+        }
+        if let Some(ends_with) = &self.ends_with {
+            if !path.ends_with(ends_with.trim_start_matches("/")) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
 impl Pitignore {
     pub fn from_repository(root_folder: std::path::PathBuf) -> Result<Self> {
         let pitignore_path = root_folder.join(".pitignore");
@@ -662,62 +1734,48 @@ impl Pitignore {
         }
 
         let contents = std::fs::read_to_string(pitignore_path)?;
-        let mut patterns: Vec<(usize, PitignorePattern)> = contents
+        Ok(Self::parse(&contents))
+    }
+    pub fn parse(contents: &str) -> Self {
+        let patterns = contents
             .lines()
             .enumerate()
-            .filter_map(|(index, line)| {
-                let line = line.trim();
-                if line.is_empty() || line.starts_with('#') {
-                    return None;
-                }
-                let negated = line.starts_with('!');
-                let pattern = if negated { &line[1..] } else { line };
-                let pattern = pattern.trim();
-                if pattern.is_empty() {
-                    return None;
-                }
-                let mut split = pattern.split('*');
-                let mut starts_with = None;
-                let mut ends_with = None;
-                if let Some(first) = split.next() {
-                    if !first.is_empty() {
-                        starts_with = Some(first.trim().into());
-                    }
-                }
-                if let Some(last) = split.next() {
-                    if !last.is_empty() {
-                        ends_with = Some(last.trim().into());
-                    }
-                }
-                if split.next().is_some() {
-                    // If there are more than one split, it means there are multiple wildcards
-                    // which is not supported in this implementation.
-                    return None;
-                }
-                if starts_with.is_none() && ends_with.is_none() {
-                    // There is no pattern at all
-                    return None;
-                }
-                Some((
-                    index,
-                    PitignorePattern {
-                        pattern: pattern.into(),
-                        starts_with,
-                        ends_with,
-                        negated,
-                    },
-                ))
-            })
+            .filter_map(|(index, line)| PitignorePattern::parse(line).map(|pattern| (index, pattern)))
             .collect();
+        let mut pitignore = Self { patterns };
+        pitignore.sort();
+        pitignore
+    }
+    fn sort(&mut self) {
         // sort patterns by negated (true first) and then by pattern length (longer patterns first)
-        patterns.sort_by(|(_, p1), (_, p2)| {
+        self.patterns.sort_by(|(_, p1), (_, p2)| {
             if p1.negated != p2.negated {
                 p2.negated.cmp(&p1.negated) // true (negated) should come before false
             } else {
                 p2.pattern.len().cmp(&p1.pattern.len())
             }
         });
-        Ok(Self { patterns })
+    }
+    pub fn add_pattern(&mut self, line: &str) -> Result<()> {
+        let pattern = PitignorePattern::parse(line).ok_or(anyhow::anyhow!("Invalid pattern: {line}"))?;
+        if self.contains_pattern(&pattern.pattern, pattern.negated) {
+            return Ok(());
+        }
+        let index = self.patterns.iter().map(|(i, _)| i + 1).max().unwrap_or(0);
+        self.patterns.push((index, pattern));
+        self.sort();
+        Ok(())
+    }
+    pub fn remove_pattern(&mut self, pattern: &str, negated: bool) -> bool {
+        let len = self.patterns.len();
+        self.patterns
+            .retain(|(_, p)| !(p.pattern == pattern && p.negated == negated));
+        self.patterns.len() != len
+    }
+    pub fn contains_pattern(&self, pattern: &str, negated: bool) -> bool {
+        self.patterns
+            .iter()
+            .any(|(_, p)| p.pattern == pattern && p.negated == negated)
     }
     pub fn save_to_repository(&self, root_folder: std::path::PathBuf) -> Result<()> {
         let pitignore_path = root_folder.join(".pitignore");
@@ -791,24 +1849,7 @@ impl Pitignore {
         let mut is_ignored = false;
         // true if the file matches any non-negated pattern, override with false if it matches any negated pattern
         for (_index, pattern) in &self.patterns {
-            let mut both_match = true;
-            if let Some(starts_with) = &pattern.starts_with {
-                if !path
-                    .trim_start_matches("/")
-                    .starts_with(starts_with.trim_start_matches("/"))
-                {
-                    both_match = false;
-                }
-            }
-            if let Some(ends_with) = &pattern.ends_with {
-                if !path
-                    .trim_start_matches("/")
-                    .ends_with(ends_with.trim_start_matches("/"))
-                {
-                    both_match = false;
-                }
-            }
-            if both_match {
+            if pattern.matches(path) {
                 if pattern.negated {
                     return false; // If it matches a negated pattern, we do not ignore it ever.
                 } else {
@@ -819,3 +1860,126 @@ impl Pitignore {
         is_ignored
     }
 }
+
+// .pitexecute lists files members can launch from the app, one line each:
+// <pattern> [| <arguments> [| <working directory>]]
+// patterns work like .pitignore, a negated pattern hides matching files from every other line
+#[derive(Debug, Clone, Default)]
+pub struct Pitexecute {
+    pub entries: Vec<PitexecuteEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PitexecuteEntry {
+    pub pattern: PitignorePattern,
+    pub arguments: Arc<[String]>,
+    // relative to the repository root, defaults to the folder the target is in
+    pub working_directory: Option<Arc<str>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LaunchTarget {
+    pub full_path: Arc<str>,
+    pub arguments: Arc<[String]>,
+    pub working_directory: Arc<str>,
+}
+
+impl Pitexecute {
+    pub fn from_repository(root_folder: PathBuf) -> Result<Self> {
+        let pitexecute_path = root_folder.join(".pitexecute");
+        if !pitexecute_path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(pitexecute_path)?;
+        Ok(Self::parse(&contents))
+    }
+    pub fn parse(contents: &str) -> Self {
+        Self {
+            entries: contents.lines().filter_map(PitexecuteEntry::parse).collect(),
+        }
+    }
+    // the first line that matches a file decides its arguments, sorted by path
+    pub fn launch_targets(&self, folder: &RootFolder) -> Vec<LaunchTarget> {
+        let mut targets = Vec::new();
+        for file in folder.files() {
+            let path = file.full_path.trim_start_matches('/');
+            if self
+                .entries
+                .iter()
+                .any(|entry| entry.pattern.negated && entry.pattern.matches(path))
+            {
+                continue;
+            }
+            if let Some(entry) = self
+                .entries
+                .iter()
+                .find(|entry| !entry.pattern.negated && entry.pattern.matches(path))
+            {
+                let working_directory = entry.working_directory.clone().unwrap_or_else(|| {
+                    path.rsplit_once('/')
+                        .map(|(folder, _)| folder)
+                        .unwrap_or_default()
+                        .into()
+                });
+                targets.push(LaunchTarget {
+                    full_path: path.into(),
+                    arguments: Arc::clone(&entry.arguments),
+                    working_directory,
+                });
+            }
+        }
+        targets.sort_by(|a, b| a.full_path.cmp(&b.full_path));
+        targets
+    }
+}
+
+impl PitexecuteEntry {
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.splitn(3, '|');
+        let pattern = PitignorePattern::parse(parts.next()?)?;
+        let arguments = split_arguments(parts.next().unwrap_or_default()).into();
+        let working_directory = match parts.next().map(|folder| folder.trim().trim_matches(['/', '\\'])) {
+            None | Some("") => None,
+            // launching must never reach outside the repository
+            Some(folder) if folder.split(['/', '\\']).any(|part| part == "..") || folder.contains(':') => {
+                return None;
+            }
+            Some(folder) => Some(folder.into()),
+        };
+        Some(Self {
+            pattern,
+            arguments,
+            working_directory,
+        })
+    }
+}
+
+// whitespace separated, double quotes keep spaces inside an argument
+fn split_arguments(arguments: &str) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut in_argument = false;
+    for c in arguments.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_argument = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_argument {
+                    split.push(std::mem::take(&mut current));
+                    in_argument = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_argument = true;
+            }
+        }
+    }
+    if in_argument {
+        split.push(current);
+    }
+    split
+}